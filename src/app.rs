@@ -1,7 +1,15 @@
 use color_eyre::eyre::Result;
 use crossterm::event::KeyEvent;
-use serde::{Deserialize, Serialize};
-use std::time::Instant;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use crate::delivery::DeliveryStatus;
+use crate::network_secure::SessionId;
+use crate::protocol::MessageId;
+use crate::qr::InviteQr;
+use crate::room::RoomId;
+use crate::sas::Sas;
+use crate::transfer::Progress;
 
 /// Modos de operação da aplicação.
 #[derive(Debug, Clone, PartialEq)]
@@ -23,17 +31,45 @@ pub enum MessageState {
 /// Ações que podem ser disparadas pela UI.
 #[derive(Debug, Clone)]
 pub enum Action {
-    GenerateInvite,
-    ConnectTo(String),
-    SendMessage(String),
+    /// Gera um convite; opcionais: o contato esperado e o host anunciado
+    /// (número de `/invite -l` ou endereço)
+    GenerateInvite(Option<String>, Option<String>),
+    /// Lista os endereços das interfaces locais para convites
+    ListAddresses,
+    /// Conecta a uma URI; o apelido opcional é o contato esperado
+    ConnectTo(String, Option<String>),
+    SendMessage(SessionId, String),
     SetUsername(String),
+    TrustContact(SessionId, String),
+    /// O usuário confirmou no diálogo que o SAS confere
+    VerifyContact(SessionId, String),
+    /// O usuário indicou no diálogo que o SAS não confere
+    RejectVerification(SessionId, String),
+    ListContacts,
+    Room(RoomCommand),
+    SendRoomMessage(RoomId, String),
+    /// Propõe o envio de um arquivo ao par da conversa
+    SendFile(SessionId, PathBuf),
+    /// Aceita uma proposta de arquivo, opcionalmente em outro diretório
+    AcceptFile(String, Option<PathBuf>),
+    /// Recusa uma proposta ou cancela uma transferência
+    RejectFile(String),
+    /// Começou ou parou de digitar na conversa
+    Typing(SessionId, bool),
 }
 
-/// Representa uma mensagem de chat a ser serializada e enviada.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ChatMessage {
-    pub sender: String,
-    pub content: String,
+/// Subcomandos de `/room`; as salas são indicadas por nome ou identificador curto.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RoomCommand {
+    Create(String),
+    /// Convida o par da conversa indicada
+    Invite(String, SessionId),
+    Join(String),
+    Leave(String),
+    Kick(String, SessionId),
+    /// Coloca a sala em foco
+    Open(String),
+    List,
 }
 
 /// Representa uma mensagem para exibição na TUI.
@@ -43,6 +79,16 @@ pub struct DisplayMessage {
     pub arrival_time: Instant,
     pub state: MessageState,
     pub sender: String,
+    /// Conversa a que a mensagem pertence; `None` para avisos gerais do sistema
+    pub session: Option<SessionId>,
+    /// Sala a que a mensagem pertence
+    pub room: Option<RoomId>,
+    /// Identificador das mensagens de chat (enviadas e recebidas)
+    pub id: Option<MessageId>,
+    /// Estado de entrega das mensagens enviadas por nós
+    pub status: Option<DeliveryStatus>,
+    /// Mensagem recebida cuja leitura já foi confirmada ao par
+    pub read: bool,
 }
 
 impl DisplayMessage {
@@ -52,10 +98,77 @@ impl DisplayMessage {
             arrival_time: Instant::now(),
             state: MessageState::FadingIn,
             sender,
+            session: None,
+            room: None,
+            id: None,
+            status: None,
+            read: false,
         }
     }
 }
 
+/// Conversa com um par autenticado, uma por sessão de rede.
+#[derive(Debug, Clone)]
+pub struct Conversation {
+    /// Nome exibido: apelido do contato ou "par N"
+    pub label: String,
+    /// Chave Ed25519 do par, autenticada no handshake
+    pub remote_identity: [u8; 32],
+    pub remote_fingerprint: String,
+    /// Contato cuja chave mudou; o envio fica bloqueado até a confirmação
+    pub pending_key_change: Option<String>,
+    /// Código de verificação da sessão
+    pub sas: Sas,
+    /// O par enviou uma confirmação assinada de que o SAS confere
+    pub peer_confirmed_sas: bool,
+    /// Mensagens recebidas enquanto a conversa não estava em foco
+    pub unread: usize,
+    /// O par está digitando
+    pub typing: bool,
+    /// A conexão caiu e a sessão aguarda a retomada
+    pub reconnecting: bool,
+    /// Tempo de ida e volta medido pelo keepalive
+    pub rtt: Option<Duration>,
+    /// O par não respondeu ao último ping
+    pub degraded: bool,
+}
+
+impl Conversation {
+    pub fn new(session: SessionId, petname: Option<String>, remote_identity: [u8; 32], remote_fingerprint: String, sas: Sas) -> Self {
+        Self {
+            label: petname.unwrap_or_else(|| format!("par {}", session)),
+            remote_identity,
+            remote_fingerprint,
+            pending_key_change: None,
+            sas,
+            peer_confirmed_sas: false,
+            unread: 0,
+            typing: false,
+            reconnecting: false,
+            rtt: None,
+            degraded: false,
+        }
+    }
+
+    /// Atualiza a conversa após a retomada da sessão numa nova conexão: o
+    /// handshake novo traz outro código de verificação.
+    pub fn resume(&mut self, sas: Sas) {
+        self.sas = sas;
+        self.peer_confirmed_sas = false;
+        self.typing = false;
+        self.reconnecting = false;
+        self.degraded = false;
+    }
+}
+
+/// Sala em grupo da qual o usuário participa.
+#[derive(Debug, Clone)]
+pub struct RoomView {
+    /// Nome seguido do identificador curto
+    pub label: String,
+    pub unread: usize,
+}
+
 /// Estado geral da aplicação.
 pub struct App {
     pub should_quit: bool,
@@ -65,7 +178,22 @@ pub struct App {
     pub status_message: String,
     pub username: String,
     pub local_fingerprint: Option<String>,
-    pub remote_fingerprint: Option<String>,
+    /// Conversas abertas, uma por par conectado
+    pub conversations: BTreeMap<SessionId, Conversation>,
+    /// Conversa em foco: recebe o que é digitado e aparece no log
+    pub active_session: Option<SessionId>,
+    /// Salas em que o usuário está
+    pub rooms: BTreeMap<RoomId, RoomView>,
+    /// Sala em foco; tem precedência sobre a conversa em foco
+    pub active_room: Option<RoomId>,
+    /// Transferências de arquivo em andamento
+    pub transfers: Vec<Progress>,
+    /// Diálogo de verificação aberto para a sessão e o apelido indicados
+    pub verify_dialog: Option<(SessionId, String)>,
+    /// QR code do último convite, exibido por cima do log até ser fechado
+    pub invite_qr: Option<InviteQr>,
+    /// Conversa avisada de que estamos digitando
+    typing: Option<SessionId>,
     // Adicione outros campos de estado conforme necessário
 }
 
@@ -79,7 +207,14 @@ impl App {
             status_message: "Bem-vindo ao SAE - Secure Anonymous Echo".to_string(),
             username: "Phantom".to_string(),
             local_fingerprint: None,
-            remote_fingerprint: None,
+            conversations: BTreeMap::new(),
+            active_session: None,
+            rooms: BTreeMap::new(),
+            active_room: None,
+            transfers: Vec::new(),
+            verify_dialog: None,
+            invite_qr: None,
+            typing: None,
         }
     }
 
@@ -98,10 +233,22 @@ impl App {
         });
     }
 
-    /// Processa a entrada do teclado.
-    pub fn handle_key(&mut self, key: KeyEvent) -> Result<()> {
+    /// Processa a entrada do teclado. Enter envia a linha digitada.
+    pub fn handle_key(&mut self, key: KeyEvent) -> Result<Option<Action>> {
         use crossterm::event::{KeyCode, KeyModifiers};
 
+        if let Some((session, petname)) = self.verify_dialog.clone() {
+            return Ok(self.handle_verify_dialog_key(key.code, session, petname));
+        }
+
+        // O QR code fecha com Esc ou Enter; as demais teclas são ignoradas
+        if self.invite_qr.is_some() {
+            if matches!(key.code, KeyCode::Esc | KeyCode::Enter) {
+                self.invite_qr = None;
+            }
+            return Ok(None);
+        }
+
         match key.code {
             KeyCode::Char('c') if key.modifiers == KeyModifiers::CONTROL => {
                 self.should_quit = true;
@@ -113,14 +260,63 @@ impl App {
                 self.input.pop();
             }
             KeyCode::Enter => {
-                // O manuseio do Enter é feito no loop principal para disparar ações.
+                let action = self.handle_input()?;
+                // A mensagem enviada já encerra o "digitando" no par
+                if matches!(action, Some(Action::SendMessage(..))) {
+                    self.typing = None;
+                }
+                if action.is_some() {
+                    return Ok(action);
+                }
             }
             KeyCode::Esc => {
                 self.input.clear();
             }
+            KeyCode::Tab => {
+                self.cycle_conversation();
+            }
             _ => {}
         }
-        Ok(())
+        Ok(self.typing_update())
+    }
+
+    /// Avisa a conversa em foco quando começamos ou paramos de digitar.
+    /// Comandos não contam; ao trocar de conversa, a anterior é avisada primeiro.
+    fn typing_update(&mut self) -> Option<Action> {
+        let target = self.active_conversation()
+            .filter(|(_, conversation)| conversation.pending_key_change.is_none())
+            .map(|(session, _)| session)
+            .filter(|_| self.active_room.is_none() && !self.input.is_empty() && !self.input.starts_with('/'));
+        if target == self.typing {
+            return None;
+        }
+        if let Some(previous) = self.typing.take() {
+            return Some(Action::Typing(previous, false));
+        }
+        self.typing = target;
+        target.map(|session| Action::Typing(session, true))
+    }
+
+    /// Teclas do diálogo de verificação: S confirma, N rejeita, Esc fecha.
+    fn handle_verify_dialog_key(&mut self, code: crossterm::event::KeyCode, session: SessionId, petname: String) -> Option<Action> {
+        use crossterm::event::KeyCode;
+
+        match code {
+            KeyCode::Char('s') | KeyCode::Char('S') | KeyCode::Char('y') | KeyCode::Char('Y') => {
+                self.verify_dialog = None;
+                Some(Action::VerifyContact(session, petname))
+            }
+            KeyCode::Char('n') | KeyCode::Char('N') => {
+                self.verify_dialog = None;
+                Some(Action::RejectVerification(session, petname))
+            }
+            KeyCode::Esc => {
+                self.verify_dialog = None;
+                self.status_message = "Verificação cancelada".to_string();
+                None
+            }
+            _ => None,
+        }
     }
 
     /// Processa a entrada do usuário após o Enter.
@@ -133,14 +329,25 @@ impl App {
         }
 
         if input.starts_with('/') {
-            self.handle_command(&input)
+            return self.handle_command(&input);
+        }
+
+        if let Some(room_id) = self.active_room {
+            return Ok(Some(Action::SendRoomMessage(room_id, input)));
+        }
+
+        let Some((session, conversation)) = self.active_conversation() else {
+            self.status_message = "Não conectado. Use /invite ou /connect.".to_string();
+            return Ok(None);
+        };
+        if let Some(petname) = &conversation.pending_key_change {
+            self.status_message = format!(
+                "⚠️ Envio bloqueado: a chave de {} mudou. Use /trust {} ou /verify {}",
+                petname, petname, petname
+            );
+            Ok(None)
         } else {
-            if self.mode == AppMode::Connected {
-                Ok(Some(Action::SendMessage(input)))
-            } else {
-                self.status_message = "Não conectado. Use /invite ou /connect.".to_string();
-                Ok(None)
-            }
+            Ok(Some(Action::SendMessage(session, input)))
         }
     }
 
@@ -152,18 +359,24 @@ impl App {
                 self.should_quit = true;
                 Ok(None)
             }
+            Some(&"/invite") if matches!(parts.get(1), Some(&"-l") | Some(&"--list")) => Ok(Some(Action::ListAddresses)),
             Some(&"/invite") => {
                 self.mode = AppMode::Host;
                 self.status_message = "Gerando convite seguro...".to_string();
-                Ok(Some(Action::GenerateInvite))
+                // `/invite @<host> [apelido]` escolhe o endereço anunciado
+                let (host, petname) = match parts.get(1).and_then(|arg| arg.strip_prefix('@')) {
+                    Some(host) => (Some(host.to_string()), parts.get(2)),
+                    None => (None, parts.get(1)),
+                };
+                Ok(Some(Action::GenerateInvite(petname.map(|p| p.to_string()), host)))
             }
             Some(&"/connect") => {
                 if let Some(uri) = parts.get(1) {
                     self.mode = AppMode::Client;
                     self.status_message = format!("Conectando a {}...", uri);
-                    Ok(Some(Action::ConnectTo(uri.to_string())))
+                    Ok(Some(Action::ConnectTo(uri.to_string(), parts.get(2).map(|p| p.to_string()))))
                 } else {
-                    self.status_message = "Uso: /connect <sae://uri>".to_string();
+                    self.status_message = "Uso: /connect <sae://uri> [apelido]".to_string();
                     Ok(None)
                 }
             }
@@ -175,6 +388,79 @@ impl App {
                     Ok(None)
                 }
             }
+            Some(&"/trust") => {
+                if let Some(petname) = parts.get(1) {
+                    match self.active_session {
+                        Some(session) => Ok(Some(Action::TrustContact(session, petname.to_string()))),
+                        None => {
+                            self.status_message = "Nenhum par conectado para confiar".to_string();
+                            Ok(None)
+                        }
+                    }
+                } else {
+                    self.status_message = "Uso: /trust <apelido>".to_string();
+                    Ok(None)
+                }
+            }
+            Some(&"/verify") => {
+                if let Some(petname) = parts.get(1) {
+                    if let Some(session) = self.active_session {
+                        self.verify_dialog = Some((session, petname.to_string()));
+                        self.status_message = "Compare o código com o par: [S] confere, [N] não confere, [Esc] cancelar".to_string();
+                    } else {
+                        self.status_message = "Nenhum par conectado para verificar".to_string();
+                    }
+                    Ok(None)
+                } else {
+                    self.status_message = "Uso: /verify <apelido>".to_string();
+                    Ok(None)
+                }
+            }
+            Some(&"/contacts") => Ok(Some(Action::ListContacts)),
+            Some(&"/sessions") => {
+                self.list_conversations();
+                Ok(None)
+            }
+            Some(&"/switch") => {
+                match parts.get(1).and_then(|target| self.find_conversation(target)) {
+                    Some(session) => self.switch_to(session),
+                    None => self.status_message = "Uso: /switch <número|apelido> (veja /sessions)".to_string(),
+                }
+                Ok(None)
+            }
+            Some(&"/send") => {
+                // O caminho é o resto da linha, para aceitar espaços
+                let path = command.split_once(' ').map(|(_, path)| path.trim()).unwrap_or_default();
+                match self.active_session {
+                    Some(session) if !path.is_empty() => Ok(Some(Action::SendFile(session, PathBuf::from(path)))),
+                    Some(_) => {
+                        self.status_message = "Uso: /send <caminho>".to_string();
+                        Ok(None)
+                    }
+                    None => {
+                        self.status_message = "Nenhum par conectado para enviar o arquivo".to_string();
+                        Ok(None)
+                    }
+                }
+            }
+            Some(&"/accept") => {
+                if let Some(id) = parts.get(1) {
+                    let dir = command.splitn(3, ' ').nth(2).map(|dir| PathBuf::from(dir.trim()));
+                    Ok(Some(Action::AcceptFile(id.to_string(), dir)))
+                } else {
+                    self.status_message = "Uso: /accept <id> [diretório]".to_string();
+                    Ok(None)
+                }
+            }
+            Some(&"/reject") => {
+                if let Some(id) = parts.get(1) {
+                    Ok(Some(Action::RejectFile(id.to_string())))
+                } else {
+                    self.status_message = "Uso: /reject <id>".to_string();
+                    Ok(None)
+                }
+            }
+            Some(&"/room") => Ok(self.handle_room_command(&parts[1..])),
             _ => {
                 self.status_message = format!("Comando desconhecido: {}", command);
                 Ok(None)
@@ -182,9 +468,405 @@ impl App {
         }
     }
 
+    /// Subcomandos de `/room`. Convites e remoções usam a conversa em foco,
+    /// ou a indicada por número/apelido após o nome da sala.
+    fn handle_room_command(&mut self, args: &[&str]) -> Option<Action> {
+        let room = args.get(1).map(|room| room.to_string());
+        let command = match (args.first().copied(), room) {
+            (Some("create"), Some(name)) => RoomCommand::Create(name),
+            (Some("join"), Some(room)) => RoomCommand::Join(room),
+            (Some("leave"), Some(room)) => RoomCommand::Leave(room),
+            (Some("open"), Some(room)) => RoomCommand::Open(room),
+            (Some("list"), _) => RoomCommand::List,
+            (Some(sub @ ("invite" | "kick")), Some(room)) => {
+                let session = match args.get(2) {
+                    Some(target) => self.find_conversation(target),
+                    None => self.active_session,
+                };
+                let Some(session) = session else {
+                    self.status_message = format!("Uso: /room {} <sala> [número|apelido] (veja /sessions)", sub);
+                    return None;
+                };
+                if sub == "invite" {
+                    RoomCommand::Invite(room, session)
+                } else {
+                    RoomCommand::Kick(room, session)
+                }
+            }
+            _ => {
+                self.status_message = "Uso: /room create|invite|join|leave|kick|open|list <sala>".to_string();
+                return None;
+            }
+        };
+        Some(Action::Room(command))
+    }
+
     /// Adiciona uma mensagem à lista de exibição.
     pub fn add_message(&mut self, content: String, sender: String) {
         let message = DisplayMessage::new(content, sender);
         self.messages.push(message);
     }
+
+    /// Adiciona uma mensagem a uma conversa; fora de foco, conta como não lida.
+    pub fn add_session_message(&mut self, session: SessionId, content: String, sender: String) {
+        let mut message = DisplayMessage::new(content, sender);
+        message.session = Some(session);
+        self.messages.push(message);
+
+        if self.active_session != Some(session) {
+            if let Some(conversation) = self.conversations.get_mut(&session) {
+                conversation.unread += 1;
+            }
+        }
+    }
+
+    /// Adiciona uma mensagem nossa à conversa, aguardando envio.
+    pub fn add_outgoing_message(&mut self, session: SessionId, id: MessageId, content: String) {
+        self.add_session_message(session, content, "Você".to_string());
+        if let Some(message) = self.messages.last_mut() {
+            message.id = Some(id);
+            message.status = Some(DeliveryStatus::Pending);
+        }
+    }
+
+    /// Adiciona uma mensagem de chat do par; a leitura é confirmada quando a
+    /// conversa estiver em foco (ver `take_read_receipts`).
+    pub fn add_incoming_message(&mut self, session: SessionId, id: MessageId, content: String, sender: String) {
+        self.add_session_message(session, content, sender);
+        if let Some(message) = self.messages.last_mut() {
+            message.id = Some(id);
+        }
+    }
+
+    /// Avança o estado de entrega de uma mensagem enviada (nunca regride).
+    pub fn set_delivery_status(&mut self, id: MessageId, status: DeliveryStatus) {
+        for message in self.messages.iter_mut().filter(|msg| msg.id == Some(id)) {
+            if let Some(current) = message.status.as_mut() {
+                *current = status.max(*current);
+            }
+        }
+    }
+
+    /// Mensagens recebidas agora visíveis na conversa em foco e ainda sem
+    /// recibo de leitura; marca-as como lidas.
+    pub fn take_read_receipts(&mut self) -> Vec<(SessionId, MessageId)> {
+        let Some(active) = self.active_session.filter(|_| self.active_room.is_none()) else {
+            return Vec::new();
+        };
+        self.messages.iter_mut()
+            .filter(|msg| msg.session == Some(active) && msg.status.is_none() && !msg.read)
+            .filter_map(|msg| {
+                msg.read = true;
+                msg.id.map(|id| (active, id))
+            })
+            .collect()
+    }
+
+    /// Adiciona uma mensagem a uma sala; fora de foco, conta como não lida.
+    pub fn add_room_message(&mut self, room_id: RoomId, content: String, sender: String) {
+        let mut message = DisplayMessage::new(content, sender);
+        message.room = Some(room_id);
+        self.messages.push(message);
+
+        if self.active_room != Some(room_id) {
+            if let Some(room) = self.rooms.get_mut(&room_id) {
+                room.unread += 1;
+            }
+        }
+    }
+
+    /// Registra uma sala em que o usuário entrou.
+    pub fn open_room(&mut self, room_id: RoomId, label: String) {
+        self.rooms.insert(room_id, RoomView { label, unread: 0 });
+    }
+
+    /// Coloca a sala em foco; o que for digitado vai para ela.
+    pub fn switch_to_room(&mut self, room_id: RoomId) {
+        if let Some(room) = self.rooms.get_mut(&room_id) {
+            room.unread = 0;
+            self.status_message = format!("Sala {} - Tab ou /switch volta às conversas", room.label);
+            self.active_room = Some(room_id);
+        }
+    }
+
+    /// Remove uma sala encerrada ou abandonada e suas mensagens.
+    pub fn close_room(&mut self, room_id: RoomId) -> Option<RoomView> {
+        let room = self.rooms.remove(&room_id)?;
+        self.messages.retain(|msg| msg.room != Some(room_id));
+        if self.active_room == Some(room_id) {
+            self.active_room = None;
+        }
+        Some(room)
+    }
+
+    /// Conversa em foco, se houver.
+    pub fn active_conversation(&self) -> Option<(SessionId, &Conversation)> {
+        let session = self.active_session?;
+        self.conversations.get(&session).map(|conversation| (session, conversation))
+    }
+
+    /// Registra a conversa de um par recém-conectado. Ela só ganha o foco se
+    /// nenhuma outra estiver aberta, para não desviar o que está sendo digitado.
+    pub fn open_conversation(&mut self, session: SessionId, conversation: Conversation) {
+        let label = conversation.label.clone();
+        self.conversations.insert(session, conversation);
+        self.mode = AppMode::Connected;
+        if self.active_session.is_none() {
+            self.active_session = Some(session);
+        } else {
+            self.status_message = format!("Nova conversa com {} - use /switch {} ou Tab", label, session);
+        }
+    }
+
+    /// Remove a conversa de um par desconectado e passa o foco para outra.
+    pub fn close_conversation(&mut self, session: SessionId) -> Option<Conversation> {
+        let conversation = self.conversations.remove(&session)?;
+        self.messages.retain(|msg| msg.session != Some(session));
+        if self.verify_dialog.as_ref().is_some_and(|(dialog, _)| *dialog == session) {
+            self.verify_dialog = None;
+        }
+        if self.active_session == Some(session) {
+            self.active_session = self.conversations.keys().next().copied();
+        }
+        if self.conversations.is_empty() {
+            self.mode = AppMode::Menu;
+        }
+        Some(conversation)
+    }
+
+    /// Coloca a conversa em foco e zera as não lidas.
+    pub fn switch_to(&mut self, session: SessionId) {
+        if let Some(conversation) = self.conversations.get_mut(&session) {
+            conversation.unread = 0;
+            self.status_message = format!("Conversa com {}", conversation.label);
+            self.active_session = Some(session);
+            self.active_room = None;
+        }
+    }
+
+    /// Passa o foco para a próxima conversa (Tab), voltando ao início no fim.
+    fn cycle_conversation(&mut self) {
+        let next = match self.active_session {
+            // Saindo de uma sala, volta à conversa que estava em foco
+            Some(current) if self.active_room.is_some() => Some(current),
+            Some(current) => self.conversations.range(current + 1..).next()
+                .or_else(|| self.conversations.iter().next())
+                .map(|(session, _)| *session),
+            None => self.conversations.keys().next().copied(),
+        };
+        if let Some(session) = next {
+            self.switch_to(session);
+        }
+    }
+
+    /// Procura uma conversa pelo número da sessão ou pelo nome exibido.
+    fn find_conversation(&self, target: &str) -> Option<SessionId> {
+        if let Ok(session) = target.parse::<SessionId>() {
+            return self.conversations.contains_key(&session).then_some(session);
+        }
+        self.conversations.iter()
+            .find(|(_, conversation)| conversation.label == target)
+            .map(|(session, _)| *session)
+    }
+
+    fn list_conversations(&mut self) {
+        if self.conversations.is_empty() {
+            self.add_message("Nenhuma conversa aberta".to_string(), "Sistema".into());
+            return;
+        }
+        let lines: Vec<String> = self.conversations.iter()
+            .map(|(session, conversation)| format!(
+                "{} {} {}{}",
+                if self.active_session == Some(*session) { "▶" } else { " " },
+                session,
+                conversation.label,
+                if conversation.unread > 0 { format!(" ({} não lidas)", conversation.unread) } else { String::new() },
+            ))
+            .collect();
+        for line in lines {
+            self.add_message(line, "Conversas".into());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conversation(app: &mut App, session: SessionId, petname: &str) {
+        let sas = Sas::derive(&[session as u8; 32], &[1u8; 32], &[2u8; 32]);
+        app.open_conversation(session, Conversation::new(session, Some(petname.into()), [session as u8; 32], String::new(), sas));
+    }
+
+    #[test]
+    fn test_first_conversation_takes_focus() {
+        let mut app = App::new();
+        conversation(&mut app, 1, "alice");
+        conversation(&mut app, 2, "bob");
+
+        assert_eq!(app.active_session, Some(1));
+        assert_eq!(app.mode, AppMode::Connected);
+    }
+
+    #[test]
+    fn test_unread_counted_outside_focus() {
+        let mut app = App::new();
+        conversation(&mut app, 1, "alice");
+        conversation(&mut app, 2, "bob");
+
+        app.add_session_message(2, "oi".into(), "bob".into());
+        app.add_session_message(1, "olá".into(), "alice".into());
+        assert_eq!(app.conversations[&2].unread, 1);
+        assert_eq!(app.conversations[&1].unread, 0);
+
+        app.switch_to(2);
+        assert_eq!(app.conversations[&2].unread, 0);
+    }
+
+    #[test]
+    fn test_switch_and_cycle() {
+        let mut app = App::new();
+        conversation(&mut app, 1, "alice");
+        conversation(&mut app, 5, "bob");
+
+        assert_eq!(app.find_conversation("bob"), Some(5));
+        assert_eq!(app.find_conversation("5"), Some(5));
+        assert_eq!(app.find_conversation("3"), None);
+
+        app.cycle_conversation();
+        assert_eq!(app.active_session, Some(5));
+        app.cycle_conversation();
+        assert_eq!(app.active_session, Some(1));
+    }
+
+    #[test]
+    fn test_close_moves_focus_and_drops_messages() {
+        let mut app = App::new();
+        conversation(&mut app, 1, "alice");
+        conversation(&mut app, 2, "bob");
+        app.add_session_message(1, "oi".into(), "alice".into());
+        app.verify_dialog = Some((1, "alice".into()));
+
+        app.close_conversation(1);
+        assert_eq!(app.active_session, Some(2));
+        assert!(app.verify_dialog.is_none());
+        assert!(app.messages.iter().all(|msg| msg.session != Some(1)));
+
+        app.close_conversation(2);
+        assert_eq!(app.active_session, None);
+        assert_eq!(app.mode, AppMode::Menu);
+    }
+
+    #[test]
+    fn test_message_goes_to_active_session() {
+        let mut app = App::new();
+        conversation(&mut app, 1, "alice");
+        conversation(&mut app, 2, "bob");
+        app.switch_to(2);
+
+        app.input = "olá".into();
+        assert!(matches!(app.handle_input().unwrap(), Some(Action::SendMessage(2, msg)) if msg == "olá"));
+    }
+
+    #[test]
+    fn test_room_focus_and_commands() {
+        let mut app = App::new();
+        conversation(&mut app, 1, "alice");
+        conversation(&mut app, 2, "bob");
+        let room_id = [9u8; 16];
+        app.open_room(room_id, "geral#09090909".into());
+
+        app.input = "/room invite geral bob".into();
+        assert!(matches!(app.handle_input().unwrap(), Some(Action::Room(RoomCommand::Invite(room, 2))) if room == "geral"));
+
+        app.switch_to_room(room_id);
+        app.input = "olá, sala".into();
+        assert!(matches!(app.handle_input().unwrap(), Some(Action::SendRoomMessage(id, msg)) if id == room_id && msg == "olá, sala"));
+
+        // Tab volta para a conversa em foco antes da sala
+        app.cycle_conversation();
+        assert_eq!(app.active_room, None);
+        assert_eq!(app.active_session, Some(1));
+
+        app.add_room_message(room_id, "oi".into(), "bob".into());
+        assert_eq!(app.rooms[&room_id].unread, 1);
+        app.close_room(room_id);
+        assert!(app.messages.iter().all(|msg| msg.room.is_none()));
+    }
+
+    #[test]
+    fn test_file_commands_keep_spaces_in_paths() {
+        use std::path::Path;
+
+        let mut app = App::new();
+        app.input = "/send notas.txt".into();
+        assert!(app.handle_input().unwrap().is_none());
+
+        conversation(&mut app, 3, "alice");
+        app.input = "/send /tmp/minhas fotos/praia.png".into();
+        assert!(matches!(app.handle_input().unwrap(),
+            Some(Action::SendFile(3, path)) if path == Path::new("/tmp/minhas fotos/praia.png")));
+
+        app.input = "/accept 1a2b /home/alice/Área de Trabalho".into();
+        assert!(matches!(app.handle_input().unwrap(),
+            Some(Action::AcceptFile(id, Some(dir))) if id == "1a2b" && dir == Path::new("/home/alice/Área de Trabalho")));
+    }
+
+    #[test]
+    fn test_invite_host_choice() {
+        let mut app = App::new();
+        app.input = "/invite -l".into();
+        assert!(matches!(app.handle_input().unwrap(), Some(Action::ListAddresses)));
+
+        app.input = "/invite bob".into();
+        assert!(matches!(app.handle_input().unwrap(),
+            Some(Action::GenerateInvite(Some(petname), None)) if petname == "bob"));
+
+        app.input = "/invite @2 bob".into();
+        assert!(matches!(app.handle_input().unwrap(),
+            Some(Action::GenerateInvite(Some(petname), Some(host))) if petname == "bob" && host == "2"));
+
+        app.input = "/invite @[2001:db8::1]".into();
+        assert!(matches!(app.handle_input().unwrap(),
+            Some(Action::GenerateInvite(None, Some(host))) if host == "[2001:db8::1]"));
+    }
+
+    #[test]
+    fn test_typing_notifications() {
+        use crossterm::event::KeyCode;
+
+        let mut app = App::new();
+        conversation(&mut app, 1, "alice");
+        conversation(&mut app, 2, "bob");
+        let press = |app: &mut App, code| app.handle_key(KeyEvent::from(code)).unwrap();
+
+        assert!(matches!(press(&mut app, KeyCode::Char('o')), Some(Action::Typing(1, true))));
+        assert!(press(&mut app, KeyCode::Char('i')).is_none());
+        // Ao trocar de conversa com texto digitado, a anterior é avisada
+        assert!(matches!(press(&mut app, KeyCode::Tab), Some(Action::Typing(1, false))));
+        assert!(matches!(press(&mut app, KeyCode::Char('!')), Some(Action::Typing(2, true))));
+        assert!(matches!(press(&mut app, KeyCode::Enter), Some(Action::SendMessage(2, _))));
+        assert!(press(&mut app, KeyCode::Char('/')).is_none());
+        assert!(press(&mut app, KeyCode::Esc).is_none());
+    }
+
+    #[test]
+    fn test_delivery_status_and_read_receipts() {
+        let mut app = App::new();
+        conversation(&mut app, 1, "alice");
+        conversation(&mut app, 2, "bob");
+
+        app.add_outgoing_message(1, 10, "oi".into());
+        app.set_delivery_status(10, DeliveryStatus::Read);
+        app.set_delivery_status(10, DeliveryStatus::Delivered);
+        assert_eq!(app.messages.last().unwrap().status, Some(DeliveryStatus::Read));
+
+        // Só a conversa em foco confirma leitura, e uma única vez
+        app.add_incoming_message(1, 20, "olá".into(), "alice".into());
+        app.add_incoming_message(2, 30, "e aí".into(), "bob".into());
+        assert_eq!(app.take_read_receipts(), vec![(1, 20)]);
+        assert!(app.take_read_receipts().is_empty());
+        app.switch_to(2);
+        assert_eq!(app.take_read_receipts(), vec![(2, 30)]);
+    }
 }
@@ -0,0 +1,177 @@
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+
+const CONFIG_FILE: &str = "config.json";
+/// Porta do listener quando nem a linha de comando nem a configuração indicam outra
+pub const DEFAULT_PORT: u16 = 9001;
+
+/// Configuração lida de `$XDG_CONFIG_HOME/sae/config.json`. As opções de
+/// linha de comando têm precedência sobre ela.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub network: NetworkConfig,
+}
+
+/// Seção `network`: onde o host escuta e o que publica nos convites.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    /// Endereço local do listener, IPv4 ou IPv6
+    pub bind: Option<IpAddr>,
+    /// Porta do listener; 0 escolhe uma porta livre
+    pub port: Option<u16>,
+    /// Host ou IP publicado nos convites
+    pub advertise: Option<String>,
+}
+
+/// Endereço de escuta e host anunciado, já resolvidos.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListenConfig {
+    pub bind: SocketAddr,
+    /// Host dos convites; `None` usa o endereço da interface principal
+    pub advertise: Option<String>,
+}
+
+impl Config {
+    /// Carrega a configuração de `path`; um arquivo inexistente vale a padrão.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        match std::fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|e| ConfigError::Invalid(path.to_path_buf(), e.to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(ConfigError::Io(format!("{}: {}", path.display(), e))),
+        }
+    }
+}
+
+impl NetworkConfig {
+    /// Aplica as opções da linha de comando sobre a configuração.
+    ///
+    /// Com Tor o listener fica restrito ao loopback, já que o único caminho
+    /// de entrada é o onion service, e o convite sempre anuncia o `.onion`.
+    pub fn resolve(
+        &self,
+        bind: Option<IpAddr>,
+        port: Option<u16>,
+        advertise: Option<String>,
+        tor: bool,
+    ) -> Result<ListenConfig, ConfigError> {
+        let port = port.or(self.port).unwrap_or(DEFAULT_PORT);
+        let ip = match bind.or(self.bind) {
+            Some(ip) if tor && !ip.is_loopback() => return Err(ConfigError::PublicBindWithTor(ip)),
+            Some(ip) => ip,
+            None if tor => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            None => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        };
+
+        let advertise = if tor {
+            None
+        } else {
+            advertise.or_else(|| self.advertise.clone()).map(|host| normalize_host(&host)).transpose()?
+        };
+        Ok(ListenConfig { bind: SocketAddr::new(ip, port), advertise })
+    }
+}
+
+/// Caminho padrão da configuração, no diretório de configuração do usuário.
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("sae").join(CONFIG_FILE))
+}
+
+/// Confere um host para convite (nome, IPv4 ou IPv6, com ou sem colchetes) e
+/// o devolve sem colchetes, como o convite o guarda.
+pub fn normalize_host(host: &str) -> Result<String, ConfigError> {
+    let host = host.trim();
+    let bare = host.strip_prefix('[').and_then(|h| h.strip_suffix(']')).unwrap_or(host);
+    let valid = if bare.contains(':') {
+        bare.parse::<Ipv6Addr>().is_ok()
+    } else {
+        !bare.is_empty()
+            && bare.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+    };
+    if valid {
+        Ok(bare.to_string())
+    } else {
+        Err(ConfigError::InvalidHost(host.to_string()))
+    }
+}
+
+/// Erros da configuração de rede.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    Invalid(PathBuf, String),
+    Io(String),
+    InvalidHost(String),
+    PublicBindWithTor(IpAddr),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Invalid(path, e) => write!(f, "Configuração inválida em {}: {}", path.display(), e),
+            ConfigError::Io(e) => write!(f, "Erro ao ler a configuração: {}", e),
+            ConfigError::InvalidHost(host) => write!(f, "Host de convite inválido: '{}'", host),
+            ConfigError::PublicBindWithTor(ip) => write!(
+                f,
+                "Com --tor o host escuta apenas no loopback (bind {} exporia o listener fora do Tor)",
+                ip
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_line_overrides_config() {
+        let config: Config = serde_json::from_str(
+            r#"{"network": {"bind": "::", "port": 7000, "advertise": "chat.example.org"}}"#
+        ).unwrap();
+
+        let listen = config.network.resolve(None, None, None, false).unwrap();
+        assert_eq!(listen.bind, "[::]:7000".parse().unwrap());
+        assert_eq!(listen.advertise.as_deref(), Some("chat.example.org"));
+
+        let listen = config.network.resolve(Some("127.0.0.1".parse().unwrap()), Some(0), Some("[2001:db8::1]".into()), false).unwrap();
+        assert_eq!(listen.bind, "127.0.0.1:0".parse().unwrap());
+        assert_eq!(listen.advertise.as_deref(), Some("2001:db8::1"));
+
+        let listen = NetworkConfig::default().resolve(None, None, None, false).unwrap();
+        assert_eq!(listen.bind, SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), DEFAULT_PORT));
+        assert_eq!(listen.advertise, None);
+    }
+
+    #[test]
+    fn test_tor_keeps_listener_on_loopback() {
+        let config = NetworkConfig { advertise: Some("192.0.2.7".into()), ..NetworkConfig::default() };
+        let listen = config.resolve(None, None, None, true).unwrap();
+        assert!(listen.bind.ip().is_loopback());
+        assert_eq!(listen.advertise, None);
+
+        let public = "0.0.0.0".parse().unwrap();
+        assert_eq!(config.resolve(Some(public), None, None, true), Err(ConfigError::PublicBindWithTor(public)));
+    }
+
+    #[test]
+    fn test_invalid_hosts_and_files() {
+        assert_eq!(normalize_host("host.lan").unwrap(), "host.lan");
+        assert!(normalize_host("").is_err());
+        assert!(normalize_host("a b").is_err());
+        assert!(normalize_host("host/path").is_err());
+        assert!(normalize_host("::zz").is_err());
+
+        let dir = std::env::temp_dir().join(format!("sae-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(CONFIG_FILE);
+        assert_eq!(Config::load(&path).unwrap(), Config::default());
+        std::fs::write(&path, r#"{"network": {"prot": 1}}"#).unwrap();
+        assert!(matches!(Config::load(&path), Err(ConfigError::Invalid(..))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
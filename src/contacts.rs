@@ -0,0 +1,314 @@
+use crate::identity::get_fingerprint;
+use crate::keystore;
+use ed25519_dalek::VerifyingKey;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Armazenamento de contatos compartilhado entre a rede e o loop principal.
+pub type SharedContacts = Arc<Mutex<ContactStore>>;
+
+/// Contato conhecido: apelido local associado a uma chave de identidade Ed25519.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Contact {
+    pub petname: String,
+    #[serde(serialize_with = "serialize_key", deserialize_with = "deserialize_key")]
+    pub ed25519_key: [u8; 32],
+    /// Marcado pelo usuário após comparar o fingerprint por um canal seguro
+    pub verified: bool,
+    /// Unix timestamp do primeiro registro desta chave
+    pub added_at: u64,
+}
+
+impl Contact {
+    pub fn fingerprint(&self) -> String {
+        key_fingerprint(&self.ed25519_key)
+    }
+}
+
+/// Resultado da checagem de uma identidade recém-autenticada.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrustStatus {
+    /// Chave desconhecida e nenhum contato esperado para esta conexão
+    Unknown,
+    /// Primeiro uso: o contato esperado não existia e foi registrado (TOFU)
+    FirstUse { petname: String },
+    /// Chave já associada a um contato
+    Known { petname: String, verified: bool },
+    /// O contato esperado apresentou uma chave diferente da registrada
+    KeyChanged { petname: String, previous_key: [u8; 32] },
+}
+
+/// Contatos indexados por apelido. Com um caminho definido, cada alteração é
+/// gravada em disco; sem caminho, os contatos vivem só na memória da sessão.
+#[derive(Debug, Default)]
+pub struct ContactStore {
+    path: Option<PathBuf>,
+    contacts: BTreeMap<String, Contact>,
+}
+
+impl ContactStore {
+    /// Armazenamento efêmero, descartado ao sair.
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Carrega os contatos de `path`; um arquivo inexistente começa vazio.
+    pub fn load(path: &Path) -> Result<Self, ContactError> {
+        let contacts = match std::fs::read(path) {
+            Ok(data) => {
+                let list: Vec<Contact> = serde_json::from_slice(&data)
+                    .map_err(|_| ContactError::Corrupted(path.to_path_buf()))?;
+                list.into_iter().map(|c| (c.petname.clone(), c)).collect()
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(ContactError::Io(format!("{}: {}", path.display(), e))),
+        };
+
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            contacts,
+        })
+    }
+
+    pub fn shared(self) -> SharedContacts {
+        Arc::new(Mutex::new(self))
+    }
+
+    pub fn get(&self, petname: &str) -> Option<&Contact> {
+        self.contacts.get(petname)
+    }
+
+    pub fn find_by_key(&self, ed25519_key: &[u8; 32]) -> Option<&Contact> {
+        self.contacts.values().find(|c| c.ed25519_key == *ed25519_key)
+    }
+
+    /// Contatos em ordem alfabética de apelido.
+    pub fn list(&self) -> impl Iterator<Item = &Contact> {
+        self.contacts.values()
+    }
+
+    /// Checa a identidade de um par recém-conectado.
+    ///
+    /// `expected` é o apelido do contato que o usuário disse estar contatando.
+    /// Se ele ainda não existe, a chave é registrada (confiança no primeiro uso);
+    /// se existe com outra chave, a mudança é reportada e nada é alterado.
+    pub fn observe(&mut self, expected: Option<&str>, ed25519_key: &[u8; 32]) -> Result<TrustStatus, ContactError> {
+        if let Some(petname) = expected {
+            return match self.contacts.get(petname) {
+                Some(contact) if contact.ed25519_key == *ed25519_key => Ok(TrustStatus::Known {
+                    petname: petname.to_string(),
+                    verified: contact.verified,
+                }),
+                Some(contact) => Ok(TrustStatus::KeyChanged {
+                    petname: petname.to_string(),
+                    previous_key: contact.ed25519_key,
+                }),
+                None => {
+                    self.trust(petname, ed25519_key)?;
+                    Ok(TrustStatus::FirstUse { petname: petname.to_string() })
+                }
+            };
+        }
+
+        Ok(match self.find_by_key(ed25519_key) {
+            Some(contact) => TrustStatus::Known {
+                petname: contact.petname.clone(),
+                verified: contact.verified,
+            },
+            None => TrustStatus::Unknown,
+        })
+    }
+
+    /// Associa `ed25519_key` ao apelido. Trocar a chave de um contato remove
+    /// a marca de verificado. Retorna a chave anterior, se houver.
+    pub fn trust(&mut self, petname: &str, ed25519_key: &[u8; 32]) -> Result<Option<[u8; 32]>, ContactError> {
+        validate_petname(petname)?;
+        if let Some(other) = self.find_by_key(ed25519_key) {
+            if other.petname != petname {
+                return Err(ContactError::KeyInUse(other.petname.clone()));
+            }
+        }
+
+        let previous = self.contacts.get(petname).map(|c| c.ed25519_key);
+        if previous == Some(*ed25519_key) {
+            return Ok(previous);
+        }
+
+        self.contacts.insert(petname.to_string(), Contact {
+            petname: petname.to_string(),
+            ed25519_key: *ed25519_key,
+            verified: false,
+            added_at: now(),
+        });
+        self.save()?;
+        Ok(previous)
+    }
+
+    /// Marca o contato como verificado, desde que a chave corresponda à apresentada.
+    pub fn verify(&mut self, petname: &str, ed25519_key: &[u8; 32]) -> Result<(), ContactError> {
+        let contact = self.contacts.get_mut(petname)
+            .ok_or_else(|| ContactError::UnknownContact(petname.to_string()))?;
+        if contact.ed25519_key != *ed25519_key {
+            return Err(ContactError::KeyMismatch(petname.to_string()));
+        }
+        contact.verified = true;
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), ContactError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let list: Vec<&Contact> = self.contacts.values().collect();
+        let json = serde_json::to_vec_pretty(&list).map_err(|e| ContactError::Io(e.to_string()))?;
+        keystore::write_private(path, &json).map_err(|e| ContactError::Io(e.to_string()))
+    }
+}
+
+/// Fingerprint de uma chave Ed25519 no mesmo formato de `Identity::fingerprint`.
+pub fn key_fingerprint(ed25519_key: &[u8; 32]) -> String {
+    match VerifyingKey::from_bytes(ed25519_key) {
+        Ok(key) => get_fingerprint(&key),
+        Err(_) => "chave inválida".to_string(),
+    }
+}
+
+fn validate_petname(petname: &str) -> Result<(), ContactError> {
+    let valid = !petname.is_empty()
+        && petname.len() <= 32
+        && petname.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.');
+    if valid {
+        Ok(())
+    } else {
+        Err(ContactError::InvalidPetname(petname.to_string()))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn serialize_key<S: Serializer>(key: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&hex::encode(key))
+}
+
+fn deserialize_key<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 32], D::Error> {
+    let value = String::deserialize(deserializer)?;
+    hex::decode(&value).ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| serde::de::Error::custom("chave Ed25519 inválida"))
+}
+
+/// Erros do armazenamento de contatos.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContactError {
+    UnknownContact(String),
+    InvalidPetname(String),
+    KeyInUse(String),
+    KeyMismatch(String),
+    Corrupted(PathBuf),
+    Io(String),
+}
+
+impl std::fmt::Display for ContactError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContactError::UnknownContact(name) => write!(f, "Contato desconhecido: {}", name),
+            ContactError::InvalidPetname(name) => write!(f, "Apelido inválido: '{}' (use letras, números, '-', '_' ou '.')", name),
+            ContactError::KeyInUse(name) => write!(f, "Esta chave já pertence ao contato {}", name),
+            ContactError::KeyMismatch(name) => write!(f, "A chave do par não corresponde à registrada para {}", name),
+            ContactError::Corrupted(path) => write!(f, "Arquivo de contatos corrompido: {}", path.display()),
+            ContactError::Io(e) => write!(f, "Erro ao gravar contatos: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ContactError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::Identity;
+
+    fn key() -> [u8; 32] {
+        Identity::generate().public_key_bytes()
+    }
+
+    #[test]
+    fn test_first_use_records_contact() {
+        let mut store = ContactStore::in_memory();
+        let alice = key();
+
+        assert_eq!(store.observe(Some("alice"), &alice).unwrap(), TrustStatus::FirstUse { petname: "alice".into() });
+        assert_eq!(store.observe(None, &alice).unwrap(), TrustStatus::Known { petname: "alice".into(), verified: false });
+        assert_eq!(store.observe(None, &key()).unwrap(), TrustStatus::Unknown);
+    }
+
+    #[test]
+    fn test_key_change_detected_and_not_applied() {
+        let mut store = ContactStore::in_memory();
+        let original = key();
+        let impostor = key();
+        store.trust("alice", &original).unwrap();
+
+        assert_eq!(
+            store.observe(Some("alice"), &impostor).unwrap(),
+            TrustStatus::KeyChanged { petname: "alice".into(), previous_key: original }
+        );
+        assert_eq!(store.get("alice").unwrap().ed25519_key, original);
+    }
+
+    #[test]
+    fn test_trust_new_key_clears_verified() {
+        let mut store = ContactStore::in_memory();
+        let original = key();
+        let rotated = key();
+        store.trust("alice", &original).unwrap();
+        store.verify("alice", &original).unwrap();
+        assert!(store.get("alice").unwrap().verified);
+
+        assert_eq!(store.trust("alice", &rotated).unwrap(), Some(original));
+        let contact = store.get("alice").unwrap();
+        assert_eq!(contact.ed25519_key, rotated);
+        assert!(!contact.verified);
+    }
+
+    #[test]
+    fn test_verify_requires_matching_key() {
+        let mut store = ContactStore::in_memory();
+        let alice = key();
+        store.trust("alice", &alice).unwrap();
+
+        assert_eq!(store.verify("alice", &key()), Err(ContactError::KeyMismatch("alice".into())));
+        assert_eq!(store.verify("bob", &alice), Err(ContactError::UnknownContact("bob".into())));
+    }
+
+    #[test]
+    fn test_key_belongs_to_one_petname() {
+        let mut store = ContactStore::in_memory();
+        let alice = key();
+        store.trust("alice", &alice).unwrap();
+        assert_eq!(store.trust("bob", &alice), Err(ContactError::KeyInUse("alice".into())));
+        assert!(store.trust("não vale", &key()).is_err());
+    }
+
+    #[test]
+    fn test_persisted_store_roundtrip() {
+        let path = std::env::temp_dir()
+            .join(format!("sae-contacts-{}", hex::encode(&key()[..8])))
+            .join("contacts.json");
+        let alice = key();
+
+        let mut store = ContactStore::load(&path).unwrap();
+        store.trust("alice", &alice).unwrap();
+        store.verify("alice", &alice).unwrap();
+
+        let reloaded = ContactStore::load(&path).unwrap();
+        let contact = reloaded.get("alice").unwrap();
+        assert_eq!(contact.ed25519_key, alice);
+        assert!(contact.verified);
+    }
+}
@@ -5,7 +5,7 @@ use chacha20poly1305::{
 use hkdf::Hkdf;
 use rand::rngs::OsRng;
 use sha2::Sha256;
-use x25519_dalek::{EphemeralSecret, PublicKey, SharedSecret};
+use x25519_dalek::{PublicKey, SharedSecret, StaticSecret};
 use zeroize::ZeroizeOnDrop;
 
 const HKDF_INFO: &[u8] = b"sae-hkdf-info";
@@ -87,8 +87,11 @@ impl CryptoSession {
 }
 
 /// Gera um par de chaves efêmero X25519.
-pub fn generate_keypair() -> (EphemeralSecret, PublicKey) {
-    let secret = EphemeralSecret::random_from_rng(OsRng);
+///
+/// A chave secreta é `StaticSecret` porque, no host, ela também serve como
+/// chave de ratchet inicial da sessão.
+pub fn generate_keypair() -> (StaticSecret, PublicKey) {
+    let secret = StaticSecret::random_from_rng(OsRng);
     let public = PublicKey::from(&secret);
     (secret, public)
 }
@@ -0,0 +1,773 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+
+use crate::config::ListenConfig;
+use crate::contacts::SharedContacts;
+use crate::crypton::generate_keypair;
+use crate::delivery::Outbox;
+use crate::host::{HostError, HostListener};
+use crate::invite::Invite;
+use crate::network_secure::{resend_unacked, send_wire, NetworkEvent, NetworkManager, SessionId};
+use crate::protocol::{Message, MessageId, Receipt, SecureChannel};
+use crate::tor::TorConfig;
+
+const SOCKET_FILE: &str = "sae.sock";
+
+// Códigos de erro do JSON-RPC 2.0
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Erro da operação pedida (sessão inexistente, falha de conexão...)
+const CALL_FAILED: i64 = -32000;
+
+/// Caminho padrão do socket de controle: o diretório de runtime do usuário
+/// ou, sem ele, o diretório de dados.
+pub fn default_socket_path() -> Option<PathBuf> {
+    dirs::runtime_dir().or_else(dirs::data_dir).map(|dir| dir.join("sae").join(SOCKET_FILE))
+}
+
+/// Evento entregue aos clientes que chamaram `subscribe`, como notificação
+/// JSON-RPC `event`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DaemonEvent {
+    PeerConnected { session: SessionId, fingerprint: String, petname: Option<String>, resumed: bool, post_quantum: bool },
+    Message { session: SessionId, id: MessageId, sender: String, content: String, timestamp: u64, verified: bool },
+    Delivered { session: SessionId, id: MessageId },
+    Read { session: SessionId, id: MessageId },
+    ConnectionLost { session: SessionId },
+    SessionClosed { session: SessionId },
+    /// Um contato conhecido apresentou outra chave; o envio fica bloqueado
+    KeyChanged { session: SessionId, petname: String, fingerprint: String },
+    ConnectionFailed { reason: String },
+}
+
+/// Erro devolvido no campo `error` da resposta.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct InviteParams {
+    #[serde(default)]
+    petname: Option<String>,
+    /// Host ou IP do convite no lugar do anunciado
+    #[serde(default)]
+    host: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ConnectParams {
+    uri: String,
+    #[serde(default)]
+    petname: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SendParams {
+    session: SessionId,
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SessionParams {
+    session: SessionId,
+}
+
+/// Métodos da API de controle.
+#[derive(Debug)]
+enum Call {
+    CreateInvite(InviteParams),
+    Connect(ConnectParams),
+    Send(SendParams),
+    ListSessions,
+    Subscribe,
+    CloseSession(SessionParams),
+}
+
+impl Call {
+    fn parse(method: &str, params: Value) -> Result<Self, RpcError> {
+        // Sem `params`, os campos opcionais ficam com o padrão
+        let params = if params.is_null() { json!({}) } else { params };
+        let invalid = |e: serde_json::Error| RpcError::new(INVALID_PARAMS, format!("Parâmetros inválidos: {}", e));
+        match method {
+            "create_invite" => serde_json::from_value(params).map(Call::CreateInvite).map_err(invalid),
+            "connect" => serde_json::from_value(params).map(Call::Connect).map_err(invalid),
+            "send" => serde_json::from_value(params).map(Call::Send).map_err(invalid),
+            "list_sessions" => Ok(Call::ListSessions),
+            "subscribe" => Ok(Call::Subscribe),
+            "close_session" => serde_json::from_value(params).map(Call::CloseSession).map_err(invalid),
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("Método desconhecido: {}", method))),
+        }
+    }
+}
+
+/// Chamada de um cliente, repassada à tarefa que detém o estado do daemon.
+struct Request {
+    call: Call,
+    /// Canal de notificações da conexão que fez a chamada
+    events: UnboundedSender<DaemonEvent>,
+    reply: oneshot::Sender<Result<Value, RpcError>>,
+}
+
+/// Fim de um `connect`: a resposta ainda pendente e a sessão aberta ou o erro.
+struct Dialed {
+    reply: oneshot::Sender<Result<Value, RpcError>>,
+    result: Result<SessionId, String>,
+}
+
+/// Par de uma sessão aberta no daemon.
+struct Peer {
+    label: String,
+    remote_identity: [u8; 32],
+    fingerprint: String,
+    /// A chave do contato mudou: o envio fica bloqueado
+    key_changed: bool,
+    /// A conexão caiu e a sessão aguarda a retomada
+    reconnecting: bool,
+}
+
+/// SAE em segundo plano: as mesmas sessões da TUI (handshake Noise, Double
+/// Ratchet, fila de entrega), controladas por JSON-RPC num socket Unix.
+pub struct Daemon {
+    network: NetworkManager,
+    network_events: UnboundedReceiver<NetworkEvent>,
+    contacts: SharedContacts,
+    listen: ListenConfig,
+    tor_config: Option<TorConfig>,
+    username: String,
+    peers: BTreeMap<SessionId, Peer>,
+    channels: HashMap<SessionId, SecureChannel>,
+    outbox: Outbox,
+    host_listener: Option<HostListener>,
+    subscribers: Vec<UnboundedSender<DaemonEvent>>,
+    /// Resultados dos `connect` em andamento, discados fora do loop
+    dialed_sender: UnboundedSender<Dialed>,
+    dialed: UnboundedReceiver<Dialed>,
+}
+
+impl Daemon {
+    /// `network_events` é o receptor do canal passado a `NetworkManager::new`.
+    pub fn new(
+        network: NetworkManager,
+        network_events: UnboundedReceiver<NetworkEvent>,
+        contacts: SharedContacts,
+        listen: ListenConfig,
+        tor_config: Option<TorConfig>,
+        username: String,
+    ) -> Self {
+        let (dialed_sender, dialed) = mpsc::unbounded_channel();
+        Self {
+            network,
+            network_events,
+            contacts,
+            listen,
+            tor_config,
+            username,
+            peers: BTreeMap::new(),
+            channels: HashMap::new(),
+            outbox: Outbox::new(),
+            host_listener: None,
+            subscribers: Vec::new(),
+            dialed_sender,
+            dialed,
+        }
+    }
+
+    /// Atende o socket de controle em `path` até SIGINT ou SIGTERM; ao sair,
+    /// encerra as sessões e remove o socket.
+    pub async fn serve(mut self, path: &Path) -> Result<(), DaemonError> {
+        let listener = bind_socket(path)?;
+        let mut terminate = signal(SignalKind::terminate()).map_err(DaemonError::io)?;
+        let mut interrupt = signal(SignalKind::interrupt()).map_err(DaemonError::io)?;
+        let (request_sender, mut requests) = mpsc::unbounded_channel::<Request>();
+
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => {
+                    let Ok((stream, _)) = accepted else {
+                        continue;
+                    };
+                    // Só processos do mesmo usuário controlam o daemon
+                    if stream.peer_cred().is_ok_and(|cred| cred.uid() == current_uid()) {
+                        tokio::spawn(serve_client(stream, request_sender.clone()));
+                    }
+                }
+                Some(request) = requests.recv() => self.handle_call(request).await,
+                Some(dialed) = self.dialed.recv() => self.finish_connect(dialed),
+                Some(event) = self.network_events.recv() => self.handle_network(event).await,
+                _ = terminate.recv() => break,
+                _ = interrupt.recv() => break,
+            }
+        }
+
+        for (session, channel) in self.channels.iter_mut() {
+            let _ = send_wire(channel, &self.network, *session, Message::Close { reason: "o par saiu".to_string() }).await;
+            self.network.close_session(*session).await;
+        }
+        if let Some(listener) = self.host_listener.take() {
+            listener.close().await;
+        }
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    async fn handle_call(&mut self, Request { call, events, reply }: Request) {
+        let result = match call {
+            Call::CreateInvite(params) => self.create_invite(params).await,
+            Call::Connect(params) => return self.connect(params, reply),
+            Call::Send(params) => self.send(params).await,
+            Call::ListSessions => Ok(self.list_sessions()),
+            Call::Subscribe => {
+                self.subscribers.push(events);
+                Ok(Value::Bool(true))
+            }
+            Call::CloseSession(params) => self.close_session(params).await,
+        };
+        let _ = reply.send(result);
+    }
+
+    /// Disca e faz o handshake numa tarefa própria, para não segurar os outros
+    /// clientes e os eventos de rede; a resposta sai quando o resultado volta
+    /// por `dialed`.
+    fn connect(&self, ConnectParams { uri, petname }: ConnectParams, reply: oneshot::Sender<Result<Value, RpcError>>) {
+        if let Err(e) = Invite::parse(&uri) {
+            let _ = reply.send(Err(RpcError::new(INVALID_PARAMS, format!("Convite rejeitado: {}", e))));
+            return;
+        }
+        let mut network = self.network.clone();
+        let dialed = self.dialed_sender.clone();
+        tokio::spawn(async move {
+            let (secret, _) = generate_keypair();
+            let result = network.connect_to_host(&uri, secret, petname).await;
+            let _ = dialed.send(Dialed { reply, result });
+        });
+    }
+
+    /// Responde ao `connect`; uma falha também vai aos inscritos.
+    fn finish_connect(&mut self, Dialed { reply, result }: Dialed) {
+        let result = match result {
+            Ok(session) => Ok(json!({ "session": session })),
+            Err(reason) => {
+                self.broadcast(DaemonEvent::ConnectionFailed { reason: reason.clone() });
+                Err(RpcError::new(CALL_FAILED, reason))
+            }
+        };
+        let _ = reply.send(result);
+    }
+
+    async fn send(&mut self, SendParams { session, text }: SendParams) -> Result<Value, RpcError> {
+        let peer = self.peers.get(&session)
+            .ok_or_else(|| RpcError::new(CALL_FAILED, format!("Sessão {} não existe", session)))?;
+        if peer.key_changed {
+            return Err(RpcError::new(CALL_FAILED, format!("Envio bloqueado: a chave de {} mudou", peer.label)));
+        }
+        // Fica na fila até o ACK do par, mesmo se o envio falhar agora
+        let id: MessageId = rand::random();
+        self.outbox.push(peer.remote_identity, id, self.username.clone(), text.clone());
+        let mut sent = false;
+        if let Some(channel) = self.channels.get_mut(&session) {
+            let message = Message::Chat { id, sender: self.username.clone(), content: text };
+            sent = send_wire(channel, &self.network, session, message).await.is_ok();
+        }
+        Ok(json!({ "id": id, "status": if sent { "sent" } else { "pending" } }))
+    }
+
+    fn list_sessions(&self) -> Value {
+        let contacts = self.contacts.lock().unwrap();
+        let sessions: Vec<Value> = self.peers.iter()
+            .map(|(session, peer)| json!({
+                "session": session,
+                "peer": peer.label,
+                "fingerprint": peer.fingerprint,
+                "verified": contacts.find_by_key(&peer.remote_identity).is_some_and(|c| c.verified),
+                "reconnecting": peer.reconnecting,
+                "key_changed": peer.key_changed,
+            }))
+            .collect();
+        Value::Array(sessions)
+    }
+
+    async fn close_session(&mut self, SessionParams { session }: SessionParams) -> Result<Value, RpcError> {
+        if !self.peers.contains_key(&session) {
+            return Err(RpcError::new(CALL_FAILED, format!("Sessão {} não existe", session)));
+        }
+        if let Some(channel) = self.channels.get_mut(&session) {
+            let _ = send_wire(channel, &self.network, session, Message::Close { reason: "sessão encerrada".to_string() }).await;
+        }
+        self.network.close_session(session).await;
+        Ok(Value::Bool(true))
+    }
+
+    /// Inicia o listener no primeiro convite e emite um convite de uso único.
+    async fn create_invite(&mut self, InviteParams { petname, host }: InviteParams) -> Result<Value, RpcError> {
+        if self.host_listener.is_none() {
+            let listener = HostListener::start(&mut self.network, &self.listen, self.tor_config.as_ref()).await
+                .map_err(|e| RpcError::new(CALL_FAILED, e.to_string()))?;
+            self.host_listener = Some(listener);
+        }
+        let Some(listener) = &self.host_listener else {
+            return Err(RpcError::new(CALL_FAILED, "Listener indisponível"));
+        };
+
+        let invite = listener.create_invite(&self.network, host, petname).map_err(|e| match e {
+            HostError::OnionOnly | HostError::InvalidHost(_) => RpcError::new(INVALID_PARAMS, e.to_string()),
+            e => RpcError::new(CALL_FAILED, e.to_string()),
+        })?;
+        Ok(json!({ "uri": invite.to_uri(), "expires_in": invite.expires_in().as_secs() }))
+    }
+
+    async fn handle_network(&mut self, event: NetworkEvent) {
+        match event {
+            NetworkEvent::PeerConnected { session, ed25519_key, fingerprint, petname, post_quantum, channel, .. } => {
+                self.channels.insert(session, channel.open());
+
+                let resumed = match self.peers.get_mut(&session) {
+                    Some(peer) => {
+                        peer.reconnecting = false;
+                        true
+                    }
+                    None => {
+                        let label = petname.clone().unwrap_or_else(|| format!("par {}", session));
+                        let peer = Peer { label, remote_identity: ed25519_key, fingerprint: fingerprint.clone(), key_changed: false, reconnecting: false };
+                        self.peers.insert(session, peer);
+                        false
+                    }
+                };
+                self.broadcast(DaemonEvent::PeerConnected { session, fingerprint, petname, resumed, post_quantum });
+
+                if let Some(channel) = self.channels.get_mut(&session) {
+                    resend_unacked(channel, &self.network, session, &self.outbox, &ed25519_key).await;
+                }
+            }
+            NetworkEvent::DataReceived(session, data) => {
+                let Some(channel) = self.channels.get_mut(&session) else {
+                    return;
+                };
+                // Tipos que o daemon não trata (arquivos, salas) e mensagens
+                // corrompidas são descartados sem derrubar a sessão
+                let Ok(message) = channel.open(&data) else {
+                    return;
+                };
+                let Some(peer) = self.peers.get(&session).map(|peer| peer.remote_identity) else {
+                    return;
+                };
+                match message {
+                    Message::Chat { id, sender, content } => {
+                        let _ = send_wire(channel, &self.network, session, Message::Ack { id }).await;
+                        if self.outbox.receive(peer, id) {
+                            let verified = self.contacts.lock().unwrap()
+                                .find_by_key(&peer)
+                                .is_some_and(|contact| contact.verified);
+                            let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |t| t.as_secs());
+                            self.broadcast(DaemonEvent::Message { session, id, sender, content, timestamp, verified });
+                        }
+                    }
+                    // Um ACK repetido (de uma retransmissão) não gera outro evento
+                    Message::Ack { id } | Message::Receipt { id, receipt: Receipt::Delivered }
+                        if self.outbox.ack(&peer, id) => {
+                        self.broadcast(DaemonEvent::Delivered { session, id });
+                    }
+                    Message::Receipt { id, receipt: Receipt::Read } => {
+                        self.outbox.ack(&peer, id);
+                        self.broadcast(DaemonEvent::Read { session, id });
+                    }
+                    Message::Ping { nonce } => {
+                        let _ = send_wire(channel, &self.network, session, Message::Pong { nonce }).await;
+                    }
+                    Message::Rekey { request: true } => {
+                        let _ = send_wire(channel, &self.network, session, Message::Rekey { request: false }).await;
+                    }
+                    Message::Close { .. } => self.network.close_session(session).await,
+                    _ => {}
+                }
+            }
+            NetworkEvent::PeerDisconnected(session) => {
+                self.channels.remove(&session);
+                if self.peers.remove(&session).is_some() {
+                    self.broadcast(DaemonEvent::SessionClosed { session });
+                }
+            }
+            NetworkEvent::ConnectionLost(session) => {
+                self.channels.remove(&session);
+                if let Some(peer) = self.peers.get_mut(&session) {
+                    peer.reconnecting = true;
+                    self.broadcast(DaemonEvent::ConnectionLost { session });
+                }
+            }
+            NetworkEvent::FingerprintVerificationRequired { session, petname, fingerprint, .. } => {
+                if let Some(peer) = self.peers.get_mut(&session) {
+                    peer.key_changed = true;
+                }
+                self.broadcast(DaemonEvent::KeyChanged { session, petname, fingerprint });
+            }
+            NetworkEvent::ConnectionFailed(reason) => self.broadcast(DaemonEvent::ConnectionFailed { reason }),
+            _ => {}
+        }
+    }
+
+    /// Entrega o evento aos inscritos, esquecendo as conexões já fechadas.
+    fn broadcast(&mut self, event: DaemonEvent) {
+        self.subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
+
+/// Atende uma conexão ao socket: uma requisição JSON-RPC por linha, respostas
+/// e notificações também uma por linha.
+async fn serve_client(stream: UnixStream, requests: UnboundedSender<Request>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let (event_sender, mut events) = mpsc::unbounded_channel();
+
+    loop {
+        let output = tokio::select! {
+            line = lines.next_line() => {
+                let Ok(Some(line)) = line else {
+                    return;
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let (id, call) = parse_request(&line);
+                let result = match call {
+                    Ok(call) => {
+                        let (reply, response) = oneshot::channel();
+                        if requests.send(Request { call, events: event_sender.clone(), reply }).is_err() {
+                            return;
+                        }
+                        // Notificação: executa, mas sem resposta, nem de erro
+                        if id.is_none() {
+                            continue;
+                        }
+                        response.await.unwrap_or_else(|_| Err(RpcError::new(CALL_FAILED, "Daemon encerrado")))
+                    }
+                    Err(_) if id.is_none() => continue,
+                    Err(e) => Err(e),
+                };
+                let id = id.unwrap_or(Value::Null);
+                match result {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+                }
+            }
+            Some(event) = events.recv() => json!({ "jsonrpc": "2.0", "method": "event", "params": event }),
+        };
+        let mut line = output.to_string();
+        line.push('\n');
+        if writer.write_all(line.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Separa o `id` e a chamada de uma linha JSON-RPC 2.0. Sem o membro `id` a
+/// requisição é uma notificação (`None`), que não recebe resposta; um JSON
+/// ilegível ou sem envelope válido responde com `id` nulo.
+fn parse_request(line: &str) -> (Option<Value>, Result<Call, RpcError>) {
+    #[derive(Deserialize)]
+    struct Envelope {
+        jsonrpc: String,
+        method: String,
+        #[serde(default)]
+        params: Value,
+    }
+
+    let value = match serde_json::from_str::<Value>(line) {
+        Ok(value) => value,
+        Err(e) => return (Some(Value::Null), Err(RpcError::new(PARSE_ERROR, format!("JSON inválido: {}", e)))),
+    };
+    let id = value.get("id").cloned();
+    let envelope: Envelope = match serde_json::from_value(value) {
+        Ok(envelope) => envelope,
+        Err(e) => {
+            let id = Some(id.unwrap_or(Value::Null));
+            return (id, Err(RpcError::new(INVALID_REQUEST, format!("Requisição inválida: {}", e))));
+        }
+    };
+    if envelope.jsonrpc != "2.0" {
+        return (Some(id.unwrap_or(Value::Null)), Err(RpcError::new(INVALID_REQUEST, "Apenas JSON-RPC 2.0 é aceito")));
+    }
+    (id, Call::parse(&envelope.method, envelope.params))
+}
+
+/// Cria o socket em `path` acessível só ao usuário: o diretório é criado com
+/// 0700 (ou precisa já ser privado) e o socket fica com 0600.
+fn bind_socket(path: &Path) -> Result<UnixListener, DaemonError> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    if !dir.exists() {
+        std::fs::DirBuilder::new().recursive(true).mode(0o700).create(dir).map_err(DaemonError::io)?;
+    }
+    let metadata = std::fs::metadata(dir).map_err(DaemonError::io)?;
+    if metadata.uid() != current_uid() || metadata.mode() & 0o022 != 0 {
+        return Err(DaemonError::InsecureDirectory(dir.to_path_buf()));
+    }
+
+    // Um socket que sobrou de um daemon encerrado à força é substituído
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(DaemonError::Io(format!("{} existe e não é um socket", path.display())));
+        }
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(DaemonError::AlreadyRunning(path.to_path_buf()));
+        }
+        std::fs::remove_file(path).map_err(DaemonError::io)?;
+    }
+
+    let listener = UnixListener::bind(path).map_err(DaemonError::io)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600)).map_err(DaemonError::io)?;
+    Ok(listener)
+}
+
+fn current_uid() -> u32 {
+    // SAFETY: getuid não tem pré-condições e nunca falha
+    unsafe { libc::getuid() }
+}
+
+/// Erros ao iniciar o daemon.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DaemonError {
+    Io(String),
+    /// O diretório do socket pertence a outro usuário ou aceita escrita de outros
+    InsecureDirectory(PathBuf),
+    AlreadyRunning(PathBuf),
+}
+
+impl DaemonError {
+    fn io(e: std::io::Error) -> Self {
+        DaemonError::Io(e.to_string())
+    }
+}
+
+impl std::fmt::Display for DaemonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DaemonError::Io(e) => write!(f, "Erro no socket de controle: {}", e),
+            DaemonError::InsecureDirectory(dir) => write!(
+                f,
+                "O diretório {} não é privado (precisa ser do usuário e sem escrita para outros)",
+                dir.display()
+            ),
+            DaemonError::AlreadyRunning(path) => write!(f, "Já há um daemon atendendo em {}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for DaemonError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contacts::ContactStore;
+    use crate::handshake::PqPolicy;
+    use crate::identity::Identity;
+    use crate::network_secure::KeepaliveConfig;
+    use std::collections::VecDeque;
+    use tokio::io::Lines;
+    use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+    use tokio::time::{sleep, timeout, Duration};
+
+    /// Cliente de teste do socket de controle.
+    struct RpcClient {
+        lines: Lines<BufReader<OwnedReadHalf>>,
+        writer: OwnedWriteHalf,
+        next_id: u64,
+        /// Notificações que chegaram enquanto se esperava uma resposta
+        events: VecDeque<Value>,
+    }
+
+    impl RpcClient {
+        async fn connect(path: &Path) -> Self {
+            let stream = UnixStream::connect(path).await.unwrap();
+            let (reader, writer) = stream.into_split();
+            Self { lines: BufReader::new(reader).lines(), writer, next_id: 1, events: VecDeque::new() }
+        }
+
+        async fn read(&mut self) -> Value {
+            let line = timeout(Duration::from_secs(10), self.lines.next_line()).await
+                .expect("o daemon não respondeu").unwrap().unwrap();
+            serde_json::from_str(&line).unwrap()
+        }
+
+        /// Chama `method` e devolve a resposta inteira (`result` ou `error`).
+        async fn call(&mut self, method: &str, params: Value) -> Value {
+            let id = self.next_id;
+            self.next_id += 1;
+            let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+            self.writer.write_all(format!("{}\n", request).as_bytes()).await.unwrap();
+            loop {
+                let message = self.read().await;
+                if message["id"] == json!(id) {
+                    return message;
+                }
+                self.events.push_back(message["params"].clone());
+            }
+        }
+
+        /// Próximo evento do tipo indicado, descartando os outros.
+        async fn event(&mut self, kind: &str) -> Value {
+            loop {
+                let event = match self.events.pop_front() {
+                    Some(event) => event,
+                    None => self.read().await["params"].clone(),
+                };
+                if event["type"] == kind {
+                    return event;
+                }
+            }
+        }
+    }
+
+    fn socket_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sae-daemon-{}-{}", name, std::process::id())).join("run")
+    }
+
+    /// Daemon com identidade efêmera escutando no loopback em porta livre.
+    async fn spawn_daemon(path: PathBuf) -> tokio::task::JoinHandle<()> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let contacts = ContactStore::in_memory().shared();
+        let network = NetworkManager::new(sender, Identity::generate(), contacts.clone(), None, None, KeepaliveConfig::default(), PqPolicy::default());
+        let listen = ListenConfig { bind: "127.0.0.1:0".parse().unwrap(), advertise: Some("127.0.0.1".into()) };
+        let daemon = Daemon::new(network, receiver, contacts, listen, None, "daemon".into());
+
+        let socket = path.clone();
+        let handle = tokio::spawn(async move {
+            daemon.serve(&socket).await.unwrap();
+        });
+        for _ in 0..100 {
+            if UnixStream::connect(&path).await.is_ok() {
+                break;
+            }
+            sleep(Duration::from_millis(20)).await;
+        }
+        handle
+    }
+
+    #[tokio::test]
+    async fn test_two_daemons_exchange_messages() {
+        let dir = socket_dir("pair");
+        let (alice_path, bob_path) = (dir.join("alice.sock"), dir.join("bob.sock"));
+        let alice_daemon = spawn_daemon(alice_path.clone()).await;
+        let bob_daemon = spawn_daemon(bob_path.clone()).await;
+
+        // Socket e diretório só do usuário
+        assert_eq!(std::fs::metadata(&alice_path).unwrap().mode() & 0o777, 0o600);
+        assert_eq!(std::fs::metadata(&dir).unwrap().mode() & 0o777, 0o700);
+
+        let mut alice = RpcClient::connect(&alice_path).await;
+        let mut bob = RpcClient::connect(&bob_path).await;
+        assert_eq!(alice.call("subscribe", Value::Null).await["result"], json!(true));
+        assert_eq!(bob.call("subscribe", Value::Null).await["result"], json!(true));
+
+        let invite = alice.call("create_invite", json!({ "petname": "bob" })).await;
+        let uri = invite["result"]["uri"].as_str().unwrap().to_string();
+        assert!(uri.starts_with("sae://127.0.0.1:"));
+
+        let connected = bob.call("connect", json!({ "uri": uri })).await;
+        let session = connected["result"]["session"].as_u64().unwrap();
+        let peer = alice.event("peer_connected").await;
+        assert_eq!(bob.event("peer_connected").await["session"], json!(session));
+
+        let sent = bob.call("send", json!({ "session": session, "text": "olá alice" })).await;
+        assert_eq!(sent["result"]["status"], "sent");
+        let message = alice.event("message").await;
+        assert_eq!(message["content"], "olá alice");
+        assert_eq!(message["sender"], "daemon");
+        assert_eq!(message["session"], peer["session"]);
+        assert_eq!(message["verified"], false);
+        assert_eq!(bob.event("delivered").await["id"], sent["result"]["id"]);
+
+        let sessions = alice.call("list_sessions", Value::Null).await["result"].clone();
+        assert_eq!(sessions.as_array().unwrap().len(), 1);
+        assert_eq!(sessions[0]["peer"], "bob");
+
+        // Fechar de um lado encerra a sessão nos dois
+        let alice_session = peer["session"].clone();
+        assert_eq!(alice.call("close_session", json!({ "session": alice_session })).await["result"], json!(true));
+        assert_eq!(bob.event("session_closed").await["session"], json!(session));
+        assert_eq!(alice.event("session_closed").await["session"], alice_session);
+
+        alice_daemon.abort();
+        bob_daemon.abort();
+        let _ = std::fs::remove_dir_all(dir.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_connect_does_not_block_other_calls() {
+        let dir = socket_dir("dialing");
+        let path = dir.join(SOCKET_FILE);
+        let daemon = spawn_daemon(path.clone()).await;
+
+        // Host que aceita a conexão TCP e nunca conclui o handshake
+        let silent = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = silent.local_addr().unwrap().port();
+        let _accepted = tokio::spawn(async move {
+            let (_stream, _) = silent.accept().await.unwrap();
+            sleep(Duration::from_secs(60)).await;
+        });
+        let invite = Invite::new(&Identity::generate(), "127.0.0.1", port, None, None, false, crate::invite::DEFAULT_INVITE_TTL);
+
+        let mut dialing = RpcClient::connect(&path).await;
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": "connect", "params": { "uri": invite.to_uri() } });
+        dialing.writer.write_all(format!("{}\n", request).as_bytes()).await.unwrap();
+        sleep(Duration::from_millis(200)).await;
+
+        let mut client = RpcClient::connect(&path).await;
+        let sessions = timeout(Duration::from_secs(2), client.call("list_sessions", Value::Null)).await
+            .expect("o connect em andamento segurou o daemon");
+        assert_eq!(sessions["result"], json!([]));
+
+        daemon.abort();
+        let _ = std::fs::remove_dir_all(dir.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_rpc_errors_and_stale_socket() {
+        let dir = socket_dir("errors");
+        let path = dir.join(SOCKET_FILE);
+        let daemon = spawn_daemon(path.clone()).await;
+        let mut client = RpcClient::connect(&path).await;
+
+        assert_eq!(client.call("reboot", Value::Null).await["error"]["code"], json!(METHOD_NOT_FOUND));
+        assert_eq!(client.call("send", json!({ "text": "sem sessão" })).await["error"]["code"], json!(INVALID_PARAMS));
+        assert_eq!(client.call("send", json!({ "session": 42, "text": "oi" })).await["error"]["code"], json!(CALL_FAILED));
+        assert_eq!(client.call("connect", json!({ "uri": "sae://x" })).await["error"]["code"], json!(INVALID_PARAMS));
+        client.writer.write_all(b"{nada\n").await.unwrap();
+        assert_eq!(client.read().await["error"]["code"], json!(PARSE_ERROR));
+
+        // Notificações (sem `id`) não recebem resposta, nem de erro
+        client.writer.write_all(b"{\"jsonrpc\":\"2.0\",\"method\":\"reboot\"}\n").await.unwrap();
+        client.writer.write_all(b"{\"jsonrpc\":\"2.0\",\"method\":\"list_sessions\"}\n").await.unwrap();
+        let answered = client.call("list_sessions", Value::Null).await;
+        assert_eq!(answered["id"], json!(client.next_id - 1));
+        assert!(client.events.is_empty());
+
+        // Um segundo daemon no mesmo socket é recusado enquanto o primeiro atende
+        assert_eq!(bind_socket(&path).err(), Some(DaemonError::AlreadyRunning(path.clone())));
+        daemon.abort();
+        let _ = daemon.await;
+        // O socket órfão de um daemon abortado é reaproveitado
+        assert!(bind_socket(&path).is_ok());
+
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o777)).unwrap();
+        assert_eq!(bind_socket(&path).err(), Some(DaemonError::InsecureDirectory(dir.clone())));
+        let _ = std::fs::remove_dir_all(dir.parent().unwrap());
+    }
+}
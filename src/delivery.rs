@@ -0,0 +1,142 @@
+use crate::protocol::{Message, MessageId};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Máximo de mensagens sem ACK guardadas por par (as mais antigas saem primeiro)
+const MAX_PENDING: usize = 1000;
+/// Identificadores recentes lembrados por par para descartar retransmissões
+const MAX_SEEN: usize = 1024;
+
+/// Chave Ed25519 do par: a fila sobrevive à troca de sessão numa reconexão.
+pub type PeerKey = [u8; 32];
+
+/// Estado de entrega de uma mensagem enviada, em ordem de progresso.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DeliveryStatus {
+    /// Ainda não saiu pela conexão
+    Pending,
+    /// Enviada, sem confirmação do par
+    Sent,
+    /// O par decifrou e confirmou com um ACK
+    Delivered,
+    /// O par exibiu a mensagem na conversa em foco
+    Read,
+}
+
+impl DeliveryStatus {
+    pub fn icon(self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "⏳",
+            DeliveryStatus::Sent => "✓",
+            DeliveryStatus::Delivered => "✓✓",
+            DeliveryStatus::Read => "👁",
+        }
+    }
+}
+
+/// Mensagem de chat aguardando o ACK do par.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PendingChat {
+    id: MessageId,
+    sender: String,
+    content: String,
+}
+
+/// Filas de entrega por par: o que enviamos e ainda não foi confirmado, e o
+/// que já recebemos, para que uma retransmissão não apareça duas vezes.
+#[derive(Default)]
+pub struct Outbox {
+    pending: HashMap<PeerKey, VecDeque<PendingChat>>,
+    seen: HashMap<PeerKey, (VecDeque<MessageId>, HashSet<MessageId>)>,
+}
+
+impl Outbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Guarda uma mensagem até o ACK do par.
+    pub fn push(&mut self, peer: PeerKey, id: MessageId, sender: String, content: String) {
+        let queue = self.pending.entry(peer).or_default();
+        if queue.len() == MAX_PENDING {
+            queue.pop_front();
+        }
+        queue.push_back(PendingChat { id, sender, content });
+    }
+
+    /// Remove a mensagem confirmada; `false` se o ACK não corresponde a nada pendente.
+    pub fn ack(&mut self, peer: &PeerKey, id: MessageId) -> bool {
+        let Some(queue) = self.pending.get_mut(peer) else {
+            return false;
+        };
+        let before = queue.len();
+        queue.retain(|chat| chat.id != id);
+        let acked = queue.len() != before;
+        if queue.is_empty() {
+            self.pending.remove(peer);
+        }
+        acked
+    }
+
+    /// Mensagens ainda sem ACK, na ordem de envio, para retransmitir após reconectar.
+    pub fn unacked(&self, peer: &PeerKey) -> Vec<Message> {
+        self.pending.get(peer).into_iter().flatten()
+            .map(|chat| Message::Chat { id: chat.id, sender: chat.sender.clone(), content: chat.content.clone() })
+            .collect()
+    }
+
+    /// Nenhuma mensagem aguarda ACK de nenhum par.
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Registra uma mensagem recebida; `false` se ela já tinha chegado antes.
+    pub fn receive(&mut self, peer: PeerKey, id: MessageId) -> bool {
+        let (order, ids) = self.seen.entry(peer).or_default();
+        if !ids.insert(id) {
+            return false;
+        }
+        order.push_back(id);
+        if order.len() > MAX_SEEN {
+            if let Some(oldest) = order.pop_front() {
+                ids.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unacked_survive_until_ack() {
+        let mut outbox = Outbox::new();
+        let peer = [1u8; 32];
+        outbox.push(peer, 1, "Phantom".into(), "um".into());
+        outbox.push(peer, 2, "Phantom".into(), "dois".into());
+
+        assert!(outbox.ack(&peer, 1));
+        assert!(!outbox.ack(&peer, 1));
+        assert!(!outbox.ack(&[2u8; 32], 2));
+        assert_eq!(outbox.unacked(&peer), vec![Message::Chat { id: 2, sender: "Phantom".into(), content: "dois".into() }]);
+        assert!(outbox.ack(&peer, 2));
+        assert!(outbox.unacked(&peer).is_empty());
+        assert!(outbox.is_empty());
+    }
+
+    #[test]
+    fn test_retransmission_deduplicated() {
+        let mut outbox = Outbox::new();
+        let peer = [1u8; 32];
+        assert!(outbox.receive(peer, 7));
+        assert!(!outbox.receive(peer, 7));
+        // Outro par pode usar o mesmo identificador
+        assert!(outbox.receive([2u8; 32], 7));
+
+        for id in 100..100 + MAX_SEEN as u64 {
+            outbox.receive(peer, id);
+        }
+        assert!(outbox.receive(peer, 7));
+    }
+}
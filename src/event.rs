@@ -9,7 +9,14 @@ pub enum Event {
     Key(KeyEvent),
     Tick,
     Network(crate::network_secure::NetworkEvent),
-    Resize(u16, u16),
+    /// Ação que não veio do teclado, como a inicial de `sae host` e `sae connect`
+    Action(crate::app::Action),
+    /// Linha lida da entrada padrão no modo `--no-tui`
+    Input(String),
+    /// Fim da entrada padrão no modo `--no-tui`
+    InputClosed,
+    /// Terminal redimensionado; o próximo desenho já usa o tamanho novo
+    Resize,
 }
 
 pub struct EventHandler {
@@ -40,8 +47,8 @@ impl EventHandler {
                             CrosstermEvent::Key(key) if key.kind == KeyEventKind::Press => {
                                 event_sender.send(Event::Key(key)).unwrap();
                             }
-                            CrosstermEvent::Resize(w, h) => {
-                                event_sender.send(Event::Resize(w, h)).unwrap();
+                            CrosstermEvent::Resize(..) => {
+                                event_sender.send(Event::Resize).unwrap();
                             }
                             _ => {}
                         }
@@ -52,6 +59,25 @@ impl EventHandler {
         
         Self { sender, receiver }
     }
+
+    /// Sem terminal (`--no-tui`): só os ticks; a entrada chega por `sender()`.
+    pub fn headless(tick_rate: Duration) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        let event_sender = sender.clone();
+
+        tokio::spawn(async move {
+            let mut tick_interval = interval(tick_rate);
+            loop {
+                tick_interval.tick().await;
+                if event_sender.send(Event::Tick).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Self { sender, receiver }
+    }
     
     pub async fn next(&mut self) -> Result<Event> {
         self.receiver.recv().await
@@ -0,0 +1,880 @@
+use crate::identity::{get_fingerprint, verify_signature, Identity, SignatureError};
+use crate::invite::TOKEN_LEN;
+use crate::mlkem::{self, DecapsulationKey, SharedSecret};
+use crate::protocol::{negotiate_version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+use crate::sas::Sas;
+use ed25519_dalek::{Signature, VerifyingKey};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use snow::{Builder, HandshakeState};
+use std::sync::Arc;
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroizing;
+
+const PROLOGUE: &[u8] = b"SAE-Noise-v1";
+const IDENTITY_CONTEXT: &[u8] = b"sae-noise-identity-v1";
+const HKDF_INFO_ROOT: &[u8] = b"sae-noise-root-key";
+const HKDF_INFO_RESUME: &[u8] = b"sae-noise-resume-ticket";
+const MAX_NOISE_MESSAGE: usize = 65535;
+/// Payload de identidade: chave Ed25519 (32) + assinatura (64)
+const IDENTITY_PAYLOAD_LEN: usize = 96;
+/// Versões do protocolo de mensagens aceitas: mínima (1) + máxima (1)
+const VERSIONS_LEN: usize = 2;
+/// Bit do byte de padrão que anuncia a troca híbrida X25519 + ML-KEM-768
+const HYBRID_FLAG: u8 = 0x80;
+
+/// Política de uso da troca de chaves pós-quântica.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PqPolicy {
+    /// Só X25519, mesmo quando o par suporta ML-KEM
+    Off,
+    /// Híbrido quando o par suporta, clássico caso contrário
+    #[default]
+    Prefer,
+    /// Recusa pares sem ML-KEM
+    Require,
+}
+
+impl std::str::FromStr for PqPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "off" => Ok(PqPolicy::Off),
+            "prefer" => Ok(PqPolicy::Prefer),
+            "require" => Ok(PqPolicy::Require),
+            _ => Err(format!("política pós-quântica inválida: {} (use off, prefer ou require)", value)),
+        }
+    }
+}
+
+/// Padrão Noise usado no handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandshakePattern {
+    /// Noise XX: primeiro contato, nenhuma chave estática conhecida de antemão
+    FirstContact,
+    /// Noise IK: o convite já traz a chave estática X25519 do host
+    KnownResponder,
+}
+
+impl HandshakePattern {
+    fn id(self) -> u8 {
+        match self {
+            HandshakePattern::FirstContact => 1,
+            HandshakePattern::KnownResponder => 2,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(HandshakePattern::FirstContact),
+            2 => Some(HandshakePattern::KnownResponder),
+            _ => None,
+        }
+    }
+
+    fn noise_params(self) -> &'static str {
+        match self {
+            HandshakePattern::FirstContact => "Noise_XX_25519_ChaChaPoly_SHA256",
+            HandshakePattern::KnownResponder => "Noise_IK_25519_ChaChaPoly_SHA256",
+        }
+    }
+
+    /// Nome curto do padrão para exibição.
+    pub fn name(self) -> &'static str {
+        match self {
+            HandshakePattern::FirstContact => "XX",
+            HandshakePattern::KnownResponder => "IK",
+        }
+    }
+
+    /// Byte que abre a primeira mensagem: o padrão e, se híbrido, `HYBRID_FLAG`.
+    fn header(self, hybrid: bool) -> u8 {
+        if hybrid { self.id() | HYBRID_FLAG } else { self.id() }
+    }
+}
+
+/// O prólogo inclui o byte de padrão: trocá-lo em trânsito, inclusive para
+/// remover o ML-KEM, quebra o handshake.
+fn prologue(header: u8) -> Vec<u8> {
+    let mut prologue = PROLOGUE.to_vec();
+    prologue.push(header);
+    prologue
+}
+
+/// Resultado de um handshake concluído e autenticado.
+#[derive(Debug, Clone)]
+pub struct HandshakeOutcome {
+    /// Chave raiz do Double Ratchet, derivada do split do Noise e do transcript
+    pub root_key: [u8; 32],
+    /// Chave estática X25519 do par (chave de ratchet inicial do respondedor)
+    pub remote_static: [u8; 32],
+    /// Chave de identidade Ed25519 do par
+    pub remote_identity: [u8; 32],
+    /// Fingerprint da identidade Ed25519 do par
+    pub fingerprint: String,
+    /// Código de verificação curto derivado do transcript
+    pub sas: Sas,
+    pub pattern: HandshakePattern,
+    /// Versão do protocolo de mensagens negociada com o par
+    pub protocol_version: u8,
+    /// Ticket para retomar a sessão numa nova conexão, conhecido pelos dois lados
+    pub resume_ticket: [u8; TOKEN_LEN],
+    /// Chaves derivadas também de um segredo ML-KEM-768 (troca híbrida)
+    pub post_quantum: bool,
+}
+
+impl HandshakeOutcome {
+    /// Padrão e troca de chaves para exibição, como "IK + ML-KEM-768".
+    pub fn key_exchange(&self) -> String {
+        if self.post_quantum {
+            format!("{} + ML-KEM-768", self.pattern.name())
+        } else {
+            self.pattern.name().to_string()
+        }
+    }
+}
+
+/// Máquina de estados do handshake Noise, independente de transporte.
+///
+/// Cada mensagem que carrega a chave estática do remetente leva também a sua
+/// identidade Ed25519 e uma assinatura sobre o hash do transcript até aquele
+/// ponto e a chave estática, ligando a identidade a esta sessão específica.
+/// Junto vai o intervalo de versões do protocolo de mensagens aceito pelo
+/// remetente; a versão usada é a maior comum aos dois lados.
+///
+/// Na troca híbrida o iniciador põe uma chave ML-KEM-768 efêmera à frente do
+/// payload da primeira mensagem, e o respondedor devolve o cifrado à frente
+/// do payload da sua primeira mensagem. O segredo encapsulado entra na chave
+/// raiz junto com o split do Noise: quebrar só o X25519 não basta.
+pub struct NoiseHandshake {
+    state: Option<HandshakeState>,
+    pattern: Option<HandshakePattern>,
+    local_static: Zeroizing<[u8; 32]>,
+    identity: Arc<Identity>,
+    initiator: bool,
+    message_index: usize,
+    remote_identity: Option<[u8; 32]>,
+    /// Token de convite enviado pelo iniciador junto com a sua identidade
+    token: Option<[u8; TOKEN_LEN]>,
+    remote_token: Option<[u8; TOKEN_LEN]>,
+    /// Intervalo local de versões (mínima, máxima)
+    versions: (u8, u8),
+    protocol_version: Option<u8>,
+    /// Troca híbrida: pedida pelo iniciador, ou anunciada na primeira mensagem
+    hybrid: bool,
+    /// Respondedor que recusa iniciadores sem ML-KEM
+    require_post_quantum: bool,
+    /// Chave ML-KEM efêmera do iniciador
+    kem_key: Option<DecapsulationKey>,
+    /// Cifrado a enviar na primeira resposta do respondedor
+    kem_ciphertext: Option<Vec<u8>>,
+    kem_secret: Option<SharedSecret>,
+}
+
+impl NoiseHandshake {
+    /// Cria o lado iniciador. Com a chave estática do host usa IK, senão XX.
+    ///
+    /// O `token` do convite segue cifrado no payload de identidade do iniciador.
+    /// Com `post_quantum` a troca é híbrida; só deve ser pedida a um par que
+    /// anuncia suporte, pois respondedores antigos recusam o byte de padrão.
+    pub fn initiator(
+        local_static: &StaticSecret,
+        remote_static: Option<[u8; 32]>,
+        identity: Arc<Identity>,
+        token: Option<[u8; TOKEN_LEN]>,
+        post_quantum: bool,
+    ) -> Result<Self, HandshakeError> {
+        let local_static = Zeroizing::new(local_static.to_bytes());
+        let pattern = match remote_static {
+            Some(_) => HandshakePattern::KnownResponder,
+            None => HandshakePattern::FirstContact,
+        };
+
+        let params = pattern.noise_params().parse()
+            .map_err(|e: snow::Error| HandshakeError::Noise(e.to_string()))?;
+        let prologue = prologue(pattern.header(post_quantum));
+        let mut builder = Builder::new(params)
+            .local_private_key(local_static.as_ref())
+            .prologue(&prologue);
+        if let Some(remote) = &remote_static {
+            builder = builder.remote_public_key(remote);
+        }
+        let state = builder.build_initiator()
+            .map_err(|e| HandshakeError::Noise(e.to_string()))?;
+
+        Ok(Self {
+            state: Some(state),
+            pattern: Some(pattern),
+            local_static,
+            identity,
+            initiator: true,
+            message_index: 0,
+            remote_identity: None,
+            token,
+            remote_token: None,
+            versions: (MIN_PROTOCOL_VERSION, PROTOCOL_VERSION),
+            protocol_version: None,
+            hybrid: post_quantum,
+            require_post_quantum: false,
+            kem_key: post_quantum.then(DecapsulationKey::generate),
+            kem_ciphertext: None,
+            kem_secret: None,
+        })
+    }
+
+    /// Cria o lado respondedor; o padrão e o uso de ML-KEM são lidos da
+    /// primeira mensagem. Com `require_post_quantum` o handshake clássico é recusado.
+    pub fn responder(local_static: &StaticSecret, identity: Arc<Identity>, require_post_quantum: bool) -> Self {
+        Self {
+            state: None,
+            pattern: None,
+            local_static: Zeroizing::new(local_static.to_bytes()),
+            identity,
+            initiator: false,
+            message_index: 0,
+            remote_identity: None,
+            token: None,
+            remote_token: None,
+            versions: (MIN_PROTOCOL_VERSION, PROTOCOL_VERSION),
+            protocol_version: None,
+            hybrid: false,
+            require_post_quantum,
+            kem_key: None,
+            kem_ciphertext: None,
+            kem_secret: None,
+        }
+    }
+
+    /// Indica se é a vez deste lado escrever.
+    pub fn is_my_turn(&self) -> bool {
+        self.state.as_ref().is_some_and(|state| state.is_my_turn())
+    }
+
+    /// Token de convite apresentado pelo iniciador, assim que recebido.
+    pub fn remote_token(&self) -> Option<[u8; TOKEN_LEN]> {
+        self.remote_token
+    }
+
+    /// Identidade Ed25519 do par, assim que autenticada.
+    pub fn remote_identity(&self) -> Option<[u8; 32]> {
+        self.remote_identity
+    }
+
+    /// Indica se todas as mensagens do padrão já foram trocadas.
+    pub fn is_finished(&self) -> bool {
+        self.state.as_ref().is_some_and(|state| state.is_handshake_finished())
+    }
+
+    /// Produz a próxima mensagem do handshake.
+    pub fn write_message(&mut self) -> Result<Vec<u8>, HandshakeError> {
+        let carries_identity = self.carries_identity();
+        let local_public = PublicKey::from(&StaticSecret::from(*self.local_static)).to_bytes();
+        let state = self.state.as_mut().ok_or(HandshakeError::OutOfOrder)?;
+
+        let mut payload = match (&self.kem_key, &self.kem_ciphertext) {
+            (Some(key), _) if self.message_index == 0 => key.encapsulation_key().to_vec(),
+            (_, Some(ciphertext)) if self.message_index == 1 => ciphertext.clone(),
+            _ => Vec::new(),
+        };
+        if carries_identity {
+            let signature = self.identity.sign(&identity_transcript(state.get_handshake_hash(), &local_public));
+            payload.extend_from_slice(&self.identity.public_key_bytes());
+            payload.extend_from_slice(&signature.to_bytes());
+            payload.extend_from_slice(&[self.versions.0, self.versions.1]);
+            if let Some(token) = &self.token {
+                payload.extend_from_slice(token);
+            }
+        }
+
+        let mut message = vec![0u8; MAX_NOISE_MESSAGE];
+        let len = state.write_message(&payload, &mut message)
+            .map_err(|e| HandshakeError::Noise(e.to_string()))?;
+        message.truncate(len);
+
+        if self.initiator && self.message_index == 0 {
+            let pattern = self.pattern.ok_or(HandshakeError::OutOfOrder)?;
+            message.insert(0, pattern.header(self.hybrid));
+        }
+        self.message_index += 1;
+
+        Ok(message)
+    }
+
+    /// Processa uma mensagem do par, verificando a identidade quando presente.
+    pub fn read_message(&mut self, message: &[u8]) -> Result<(), HandshakeError> {
+        let body = if !self.initiator && self.message_index == 0 {
+            let (&header, rest) = message.split_first().ok_or(HandshakeError::InvalidPattern)?;
+            let pattern = HandshakePattern::from_id(header & !HYBRID_FLAG).ok_or(HandshakeError::InvalidPattern)?;
+            self.hybrid = header & HYBRID_FLAG != 0;
+            if self.require_post_quantum && !self.hybrid {
+                return Err(HandshakeError::PostQuantumRequired);
+            }
+            let params = pattern.noise_params().parse()
+                .map_err(|e: snow::Error| HandshakeError::Noise(e.to_string()))?;
+            let prologue = prologue(header);
+            let state = Builder::new(params)
+                .local_private_key(self.local_static.as_ref())
+                .prologue(&prologue)
+                .build_responder()
+                .map_err(|e| HandshakeError::Noise(e.to_string()))?;
+            self.state = Some(state);
+            self.pattern = Some(pattern);
+            rest
+        } else {
+            message
+        };
+
+        let carries_identity = self.carries_identity();
+        let state = self.state.as_mut().ok_or(HandshakeError::OutOfOrder)?;
+        let transcript_before = state.get_handshake_hash().to_vec();
+
+        let mut payload = vec![0u8; MAX_NOISE_MESSAGE];
+        let len = state.read_message(body, &mut payload)
+            .map_err(|e| HandshakeError::Noise(e.to_string()))?;
+        payload.truncate(len);
+
+        // Material ML-KEM à frente do payload: a chave na primeira mensagem, o cifrado na segunda
+        let kem_len = match (self.hybrid, self.message_index) {
+            (true, 0) => mlkem::PUBLIC_KEY_LEN,
+            (true, 1) => mlkem::CIPHERTEXT_LEN,
+            _ => 0,
+        };
+        if payload.len() < kem_len {
+            return Err(HandshakeError::UnexpectedPayload);
+        }
+        let kem_material = payload.drain(..kem_len).collect::<Vec<u8>>();
+
+        if carries_identity {
+            let remote_static: [u8; 32] = state.get_remote_static()
+                .and_then(|key| key.try_into().ok())
+                .ok_or(HandshakeError::MissingRemoteStatic)?;
+            // Só o iniciador pode anexar um token após as versões
+            let (identity_payload, rest) = match payload.len() {
+                len if len == IDENTITY_PAYLOAD_LEN + VERSIONS_LEN => payload.split_at(IDENTITY_PAYLOAD_LEN),
+                len if len == IDENTITY_PAYLOAD_LEN + VERSIONS_LEN + TOKEN_LEN && !self.initiator => {
+                    payload.split_at(IDENTITY_PAYLOAD_LEN)
+                }
+                _ => return Err(HandshakeError::UnexpectedPayload),
+            };
+            let (versions, token) = rest.split_at(VERSIONS_LEN);
+            let remote_identity = verify_identity_payload(identity_payload, &transcript_before, &remote_static)?;
+            let remote_versions = (versions[0], versions[1]);
+            let version = negotiate_version(self.versions, remote_versions)
+                .ok_or(HandshakeError::IncompatibleVersion(remote_versions.0, remote_versions.1))?;
+            self.remote_identity = Some(remote_identity);
+            self.remote_token = token.try_into().ok();
+            self.protocol_version = Some(version);
+        } else if !payload.is_empty() {
+            return Err(HandshakeError::UnexpectedPayload);
+        }
+
+        if kem_len > 0 {
+            let secret = if self.initiator {
+                let key = self.kem_key.take().ok_or(HandshakeError::OutOfOrder)?;
+                key.decapsulate(&kem_material)
+            } else {
+                mlkem::encapsulate(&kem_material).map(|(ciphertext, secret)| {
+                    self.kem_ciphertext = Some(ciphertext);
+                    secret
+                })
+            };
+            self.kem_secret = Some(secret.map_err(|_| HandshakeError::UnexpectedPayload)?);
+        }
+        self.message_index += 1;
+
+        Ok(())
+    }
+
+    /// Finaliza o handshake e deriva a chave raiz da sessão.
+    pub fn finish(mut self) -> Result<HandshakeOutcome, HandshakeError> {
+        if !self.is_finished() {
+            return Err(HandshakeError::Incomplete);
+        }
+        let pattern = self.pattern.ok_or(HandshakeError::Incomplete)?;
+        let remote_identity = self.remote_identity.ok_or(HandshakeError::Incomplete)?;
+        let protocol_version = self.protocol_version.ok_or(HandshakeError::Incomplete)?;
+        let state = self.state.as_mut().ok_or(HandshakeError::Incomplete)?;
+
+        let remote_static: [u8; 32] = state.get_remote_static()
+            .and_then(|key| key.try_into().ok())
+            .ok_or(HandshakeError::MissingRemoteStatic)?;
+        let handshake_hash: [u8; 32] = state.get_handshake_hash().try_into()
+            .map_err(|_| HandshakeError::Incomplete)?;
+
+        // Chave raiz = HKDF(transcript, k1 || k2 [|| segredo ML-KEM]) sobre as duas chaves do Split()
+        let (k1, k2) = state.dangerously_get_raw_split();
+        let mut ikm = Zeroizing::new(Vec::with_capacity(64 + mlkem::SHARED_SECRET_LEN));
+        ikm.extend_from_slice(&k1);
+        ikm.extend_from_slice(&k2);
+        if self.hybrid {
+            let kem_secret = self.kem_secret.as_ref().ok_or(HandshakeError::Incomplete)?;
+            ikm.extend_from_slice(kem_secret.as_ref());
+        }
+        let hkdf = Hkdf::<Sha256>::new(Some(&handshake_hash), ikm.as_ref());
+        let mut root_key = [0u8; 32];
+        hkdf.expand(HKDF_INFO_ROOT, &mut root_key)
+            .expect("HKDF expand failed");
+        let mut resume_ticket = [0u8; TOKEN_LEN];
+        hkdf.expand(HKDF_INFO_RESUME, &mut resume_ticket)
+            .expect("HKDF expand failed");
+
+        let verifying_key = VerifyingKey::from_bytes(&remote_identity)
+            .map_err(|_| HandshakeError::Identity(SignatureError::InvalidPublicKey))?;
+
+        Ok(HandshakeOutcome {
+            root_key,
+            remote_static,
+            remote_identity,
+            fingerprint: get_fingerprint(&verifying_key),
+            sas: Sas::derive(&handshake_hash, &self.identity.public_key_bytes(), &remote_identity),
+            pattern,
+            protocol_version,
+            resume_ticket,
+            post_quantum: self.hybrid,
+        })
+    }
+
+    /// No XX a primeira mensagem só carrega a chave efêmera, em claro.
+    fn carries_identity(&self) -> bool {
+        !(self.pattern == Some(HandshakePattern::FirstContact) && self.message_index == 0)
+    }
+}
+
+/// Dados assinados pela identidade: contexto || hash do transcript || chave estática.
+fn identity_transcript(handshake_hash: &[u8], static_key: &[u8; 32]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(IDENTITY_CONTEXT.len() + handshake_hash.len() + 32);
+    data.extend_from_slice(IDENTITY_CONTEXT);
+    data.extend_from_slice(handshake_hash);
+    data.extend_from_slice(static_key);
+    data
+}
+
+fn verify_identity_payload(
+    payload: &[u8],
+    handshake_hash: &[u8],
+    remote_static: &[u8; 32],
+) -> Result<[u8; 32], HandshakeError> {
+    if payload.len() != IDENTITY_PAYLOAD_LEN {
+        return Err(HandshakeError::Identity(SignatureError::InvalidSignature));
+    }
+    let identity_key: [u8; 32] = payload[..32].try_into().unwrap();
+    let signature_bytes: [u8; 64] = payload[32..].try_into().unwrap();
+
+    let verifying_key = VerifyingKey::from_bytes(&identity_key)
+        .map_err(|_| HandshakeError::Identity(SignatureError::InvalidPublicKey))?;
+    let signature = Signature::from_bytes(&signature_bytes);
+    verify_signature(&verifying_key, &identity_transcript(handshake_hash, remote_static), &signature)
+        .map_err(HandshakeError::Identity)?;
+
+    Ok(identity_key)
+}
+
+/// Erros do handshake Noise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HandshakeError {
+    Noise(String),
+    InvalidPattern,
+    OutOfOrder,
+    MissingRemoteStatic,
+    UnexpectedPayload,
+    Identity(SignatureError),
+    /// O par só aceita as versões (mínima, máxima), sem interseção com as locais
+    IncompatibleVersion(u8, u8),
+    Incomplete,
+    /// A política local exige ML-KEM e o par iniciou um handshake clássico
+    PostQuantumRequired,
+}
+
+impl std::fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HandshakeError::Noise(e) => write!(f, "Falha no protocolo Noise: {}", e),
+            HandshakeError::InvalidPattern => write!(f, "Padrão de handshake desconhecido"),
+            HandshakeError::OutOfOrder => write!(f, "Mensagem de handshake fora de ordem"),
+            HandshakeError::MissingRemoteStatic => write!(f, "Chave estática do par ausente"),
+            HandshakeError::UnexpectedPayload => write!(f, "Payload inesperado no handshake"),
+            HandshakeError::Identity(e) => write!(f, "Identidade do par inválida: {}", e),
+            HandshakeError::IncompatibleVersion(min, max) => write!(
+                f, "Versão de protocolo incompatível: o par aceita {}-{}, suportamos {}-{}",
+                min, max, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+            ),
+            HandshakeError::Incomplete => write!(f, "Handshake incompleto"),
+            HandshakeError::PostQuantumRequired => write!(f, "O par não suporta a troca de chaves pós-quântica exigida"),
+        }
+    }
+}
+
+impl std::error::Error for HandshakeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::OsRng;
+
+    /// Executa o handshake completo em memória, entregando as mensagens em ordem.
+    fn run(
+        mut initiator: NoiseHandshake,
+        mut responder: NoiseHandshake,
+    ) -> Result<(HandshakeOutcome, HandshakeOutcome), HandshakeError> {
+        let mut initiator_turn = true;
+        while !(initiator.is_finished() && responder.is_finished()) {
+            let (from, to) = if initiator_turn {
+                (&mut initiator, &mut responder)
+            } else {
+                (&mut responder, &mut initiator)
+            };
+            let message = from.write_message()?;
+            to.read_message(&message)?;
+            initiator_turn = !initiator_turn;
+        }
+        Ok((initiator.finish()?, responder.finish()?))
+    }
+
+    fn keys() -> (StaticSecret, StaticSecret, Arc<Identity>, Arc<Identity>) {
+        (
+            StaticSecret::random_from_rng(OsRng),
+            StaticSecret::random_from_rng(OsRng),
+            Arc::new(Identity::generate()),
+            Arc::new(Identity::generate()),
+        )
+    }
+
+    #[test]
+    fn test_xx_first_contact() {
+        let (client_static, host_static, client_id, host_id) = keys();
+        let initiator = NoiseHandshake::initiator(&client_static, None, client_id.clone(), None, false).unwrap();
+        let responder = NoiseHandshake::responder(&host_static, host_id.clone(), false);
+
+        let (client, host) = run(initiator, responder).unwrap();
+        assert_eq!(client.pattern, HandshakePattern::FirstContact);
+        assert_eq!(host.pattern, HandshakePattern::FirstContact);
+        assert_eq!(client.root_key, host.root_key);
+        assert_eq!(client.remote_static, PublicKey::from(&host_static).to_bytes());
+        assert_eq!(host.remote_static, PublicKey::from(&client_static).to_bytes());
+        assert_eq!(client.remote_identity, host_id.public_key_bytes());
+        assert_eq!(host.remote_identity, client_id.public_key_bytes());
+        assert_eq!(host.fingerprint, client_id.fingerprint());
+        assert_eq!(client.sas, host.sas);
+        assert_eq!(client.resume_ticket, host.resume_ticket);
+        assert_ne!(client.resume_ticket[..], client.root_key[..TOKEN_LEN]);
+    }
+
+    #[test]
+    fn test_ik_known_responder() {
+        let (client_static, host_static, client_id, host_id) = keys();
+        let host_public = PublicKey::from(&host_static).to_bytes();
+        let initiator = NoiseHandshake::initiator(&client_static, Some(host_public), client_id, None, false).unwrap();
+        let responder = NoiseHandshake::responder(&host_static, host_id.clone(), false);
+
+        let (client, host) = run(initiator, responder).unwrap();
+        assert_eq!(client.pattern, HandshakePattern::KnownResponder);
+        assert_eq!(host.pattern, HandshakePattern::KnownResponder);
+        assert_eq!(client.root_key, host.root_key);
+        assert_eq!(client.remote_identity, host_id.public_key_bytes());
+    }
+
+    #[test]
+    fn test_ik_wrong_host_key_fails() {
+        let (client_static, host_static, client_id, host_id) = keys();
+        let impostor = PublicKey::from(&StaticSecret::random_from_rng(OsRng)).to_bytes();
+        let initiator = NoiseHandshake::initiator(&client_static, Some(impostor), client_id, None, false).unwrap();
+        let responder = NoiseHandshake::responder(&host_static, host_id, false);
+
+        assert!(matches!(run(initiator, responder), Err(HandshakeError::Noise(_))));
+    }
+
+    #[test]
+    fn test_sessions_have_distinct_root_keys() {
+        let (client_static, host_static, client_id, host_id) = keys();
+        let first = run(
+            NoiseHandshake::initiator(&client_static, None, client_id.clone(), None, false).unwrap(),
+            NoiseHandshake::responder(&host_static, host_id.clone(), false),
+        ).unwrap();
+        let second = run(
+            NoiseHandshake::initiator(&client_static, None, client_id, None, false).unwrap(),
+            NoiseHandshake::responder(&host_static, host_id, false),
+        ).unwrap();
+
+        // Mesmas chaves estáticas, efêmeras novas: transcript e raiz diferentes
+        assert_ne!(first.0.root_key, second.0.root_key);
+    }
+
+    #[test]
+    fn test_replayed_responder_message_rejected() {
+        let (client_static, host_static, client_id, host_id) = keys();
+
+        // Sessão capturada pelo atacante
+        let mut captured_client = NoiseHandshake::initiator(&client_static, None, client_id.clone(), None, false).unwrap();
+        let mut captured_host = NoiseHandshake::responder(&host_static, host_id.clone(), false);
+        let msg1 = captured_client.write_message().unwrap();
+        captured_host.read_message(&msg1).unwrap();
+        let captured_msg2 = captured_host.write_message().unwrap();
+
+        // A resposta assinada do host não serve para uma nova sessão do cliente
+        let mut victim = NoiseHandshake::initiator(&client_static, None, client_id, None, false).unwrap();
+        victim.write_message().unwrap();
+        assert!(victim.read_message(&captured_msg2).is_err());
+    }
+
+    #[test]
+    fn test_protocol_version_negotiated() {
+        let (client_static, host_static, client_id, host_id) = keys();
+        let mut initiator = NoiseHandshake::initiator(&client_static, None, client_id.clone(), None, false).unwrap();
+        let mut responder = NoiseHandshake::responder(&host_static, host_id.clone(), false);
+        initiator.versions = (1, 4);
+        responder.versions = (1, 3);
+        let (client, host) = run(initiator, responder).unwrap();
+        assert_eq!((client.protocol_version, host.protocol_version), (3, 3));
+
+        // Sem versão em comum o iniciador desiste ao ler a identidade do host
+        let mut initiator = NoiseHandshake::initiator(&client_static, None, client_id, None, false).unwrap();
+        let responder = NoiseHandshake::responder(&host_static, host_id, false);
+        initiator.versions = (4, 5);
+        assert_eq!(run(initiator, responder).unwrap_err(), HandshakeError::IncompatibleVersion(1, PROTOCOL_VERSION));
+    }
+
+    #[test]
+    fn test_identity_signature_bound_to_transcript() {
+        let identity = Identity::generate();
+        let static_key = [5u8; 32];
+        let transcript = [6u8; 32];
+
+        let mut payload = identity.public_key_bytes().to_vec();
+        payload.extend_from_slice(&identity.sign(&identity_transcript(&transcript, &static_key)).to_bytes());
+
+        assert!(verify_identity_payload(&payload, &transcript, &static_key).is_ok());
+        assert!(verify_identity_payload(&payload, &[7u8; 32], &static_key).is_err());
+        assert!(verify_identity_payload(&payload, &transcript, &[8u8; 32]).is_err());
+    }
+
+    #[test]
+    fn test_token_delivered_to_responder() {
+        for known_responder in [false, true] {
+            let (client_static, host_static, client_id, host_id) = keys();
+            let host_public = known_responder.then(|| PublicKey::from(&host_static).to_bytes());
+            let mut initiator = NoiseHandshake::initiator(&client_static, host_public, client_id, Some([6u8; TOKEN_LEN]), false).unwrap();
+            let mut responder = NoiseHandshake::responder(&host_static, host_id, false);
+
+            // No IK o token chega já na primeira mensagem, antes da resposta do host
+            loop {
+                responder.read_message(&initiator.write_message().unwrap()).unwrap();
+                if responder.remote_token().is_some() {
+                    break;
+                }
+                initiator.read_message(&responder.write_message().unwrap()).unwrap();
+            }
+            assert_eq!(responder.remote_token(), Some([6u8; TOKEN_LEN]));
+            assert_eq!(responder.is_finished(), !known_responder);
+        }
+    }
+
+    #[test]
+    fn test_hybrid_post_quantum() {
+        for known_responder in [false, true] {
+            let (client_static, host_static, client_id, host_id) = keys();
+            let host_public = known_responder.then(|| PublicKey::from(&host_static).to_bytes());
+            let initiator = NoiseHandshake::initiator(&client_static, host_public, client_id, None, true).unwrap();
+            let responder = NoiseHandshake::responder(&host_static, host_id, true);
+
+            let (client, host) = run(initiator, responder).unwrap();
+            assert!(client.post_quantum && host.post_quantum);
+            assert_eq!(client.root_key, host.root_key);
+            assert_eq!(client.resume_ticket, host.resume_ticket);
+            assert_eq!(client.sas, host.sas);
+        }
+    }
+
+    #[test]
+    fn test_post_quantum_policy_and_downgrade() {
+        let (client_static, host_static, client_id, host_id) = keys();
+
+        // Iniciador clássico: aceito por padrão, recusado quando o host exige ML-KEM
+        let (client, host) = run(
+            NoiseHandshake::initiator(&client_static, None, client_id.clone(), None, false).unwrap(),
+            NoiseHandshake::responder(&host_static, host_id.clone(), false),
+        ).unwrap();
+        assert!(!client.post_quantum && !host.post_quantum);
+        let mut initiator = NoiseHandshake::initiator(&client_static, None, client_id.clone(), None, false).unwrap();
+        let mut responder = NoiseHandshake::responder(&host_static, host_id.clone(), true);
+        assert_eq!(
+            responder.read_message(&initiator.write_message().unwrap()).unwrap_err(),
+            HandshakeError::PostQuantumRequired
+        );
+
+        // Um atacante que tira o bit híbrido e a chave ML-KEM quebra o transcript
+        let host_public = PublicKey::from(&host_static).to_bytes();
+        let mut initiator = NoiseHandshake::initiator(&client_static, Some(host_public), client_id, None, true).unwrap();
+        let mut responder = NoiseHandshake::responder(&host_static, host_id, false);
+        let message = initiator.write_message().unwrap();
+        let mut stripped = vec![message[0] & !HYBRID_FLAG];
+        stripped.extend_from_slice(&message[1..]);
+        assert!(responder.read_message(&stripped).is_err());
+    }
+
+    #[test]
+    fn test_policy_parsing() {
+        assert_eq!("off".parse(), Ok(PqPolicy::Off));
+        assert_eq!("require".parse(), Ok(PqPolicy::Require));
+        assert_eq!(PqPolicy::default(), PqPolicy::Prefer);
+        assert!("sempre".parse::<PqPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_unknown_pattern_rejected() {
+        let (_, host_static, _, host_id) = keys();
+        let mut responder = NoiseHandshake::responder(&host_static, host_id, false);
+        assert_eq!(responder.read_message(&[9u8; 33]).unwrap_err(), HandshakeError::InvalidPattern);
+    }
+
+    /// Chaves e prólogo de um vetor: estática e efêmera de cada lado.
+    struct VectorKeys<'a> {
+        prologue: &'a [u8],
+        initiator_static: [u8; 32],
+        initiator_ephemeral: [u8; 32],
+        responder_static: [u8; 32],
+        responder_ephemeral: [u8; 32],
+    }
+
+    /// Executa o padrão bruto do snow com chaves efêmeras fixas. Depois das
+    /// `handshake_len` mensagens do handshake, as seguintes vão pelo modo de
+    /// transporte, alternando o remetente como nos vetores do cacophony.
+    /// Retorna as mensagens, o hash do handshake e as chaves do Split().
+    fn run_vector(
+        pattern: HandshakePattern,
+        keys: &VectorKeys,
+        handshake_len: usize,
+        payloads: &[&[u8]],
+    ) -> (Vec<String>, String, String, String) {
+        let responder_public = PublicKey::from(&StaticSecret::from(keys.responder_static)).to_bytes();
+        let mut builder = Builder::new(pattern.noise_params().parse().unwrap())
+            .local_private_key(&keys.initiator_static)
+            .prologue(keys.prologue)
+            .fixed_ephemeral_key_for_testing_only(&keys.initiator_ephemeral);
+        if pattern == HandshakePattern::KnownResponder {
+            builder = builder.remote_public_key(&responder_public);
+        }
+        let mut initiator = builder.build_initiator().unwrap();
+        let mut responder = Builder::new(pattern.noise_params().parse().unwrap())
+            .local_private_key(&keys.responder_static)
+            .prologue(keys.prologue)
+            .fixed_ephemeral_key_for_testing_only(&keys.responder_ephemeral)
+            .build_responder()
+            .unwrap();
+
+        let mut messages = Vec::new();
+        let mut buffer = vec![0u8; MAX_NOISE_MESSAGE];
+        let mut read = vec![0u8; MAX_NOISE_MESSAGE];
+        for (i, payload) in payloads[..handshake_len].iter().enumerate() {
+            let (from, to) = if i % 2 == 0 { (&mut initiator, &mut responder) } else { (&mut responder, &mut initiator) };
+            let len = from.write_message(payload, &mut buffer).unwrap();
+            let read_len = to.read_message(&buffer[..len], &mut read).unwrap();
+            assert_eq!(&read[..read_len], *payload);
+            messages.push(hex::encode(&buffer[..len]));
+        }
+
+        let hash = hex::encode(initiator.get_handshake_hash());
+        let (k1, k2) = initiator.dangerously_get_raw_split();
+        let mut initiator = initiator.into_transport_mode().unwrap();
+        let mut responder = responder.into_transport_mode().unwrap();
+        for (i, payload) in payloads.iter().enumerate().skip(handshake_len) {
+            let (from, to) = if i % 2 == 0 { (&mut initiator, &mut responder) } else { (&mut responder, &mut initiator) };
+            let len = from.write_message(payload, &mut buffer).unwrap();
+            let read_len = to.read_message(&buffer[..len], &mut read).unwrap();
+            assert_eq!(&read[..read_len], *payload);
+            messages.push(hex::encode(&buffer[..len]));
+        }
+        (messages, hash, hex::encode(k1), hex::encode(k2))
+    }
+
+    /// Chaves e payloads dos vetores do cacophony (os mesmos do snow).
+    fn cacophony_keys() -> VectorKeys<'static> {
+        VectorKeys {
+            prologue: b"John Galt",
+            initiator_static: hex_key("e61ef9919cde45dd5f82166404bd08e38bceb5dfdfded0a34c8df7ed542214d1"),
+            initiator_ephemeral: hex_key("893e28b9dc6ca8d611ab664754b8ceb7bac5117349a4439a6b0569da977c464a"),
+            responder_static: hex_key("4a3acbfdb163dec651dfa3194dece676d437029c62a408b4c5ea9114246e4893"),
+            responder_ephemeral: hex_key("bbdb4cdbd309f1a1f2e1456967fe288cadd6f712d65dc7b7793d5e63da6b375b"),
+        }
+    }
+
+    const CACOPHONY_PAYLOADS: [&[u8]; 6] = [
+        b"Ludwig von Mises",
+        b"Murray Rothbard",
+        b"F. A. Hayek",
+        b"Carl Menger",
+        b"Jean-Baptiste Say",
+        b"Eugen B\xf6hm von Bawerk",
+    ];
+
+    fn hex_key(value: &str) -> [u8; 32] {
+        hex::decode(value).unwrap().try_into().unwrap()
+    }
+
+    #[test]
+    fn test_noise_xx_spec_vector() {
+        let (messages, hash, _, _) = run_vector(HandshakePattern::FirstContact, &cacophony_keys(), 3, &CACOPHONY_PAYLOADS);
+        assert_eq!(messages, vec![
+            "ca35def5ae56cec33dc2036731ab14896bc4c75dbb07a61f879f8e3afa4c79444c756477696720766f6e204d69736573",
+            "95ebc60d2b1fa672c1f46a8aa265ef51bfe38e7ccb39ec5be34069f14480884381cbad1f276e038c48378ffce2b65285e08d6b68aaa3629a5a8639392490e5b9bd5269c2f1e4f488ed8831161f19b7815528f8982ffe09be9b5c412f8a0db50f8814c7194e83f23dbd8d162c9326ad",
+            "c7195ffacac1307ff99046f219750fc47693e23c3cb08b89c2af808b444850a80ae475b9df0f169ae80a89be0865b57f58c9fea0d4ec82a286427402f113e4b6ae769a1d95941d49b25030",
+            "96763ed773f8e47bb3712f0e29b3060ffc956ffc146cee53d5e1df",
+            "3e40f15f6f3a46ae446b253bf8b1d9ffb6ed9b174d272328ff91a7e2e5c79c07f5",
+            "eb3f3515110702e047a6c9da4478b6ead94873c11c0f2d710ddb3f09fce024b3a58502ae3f",
+        ]);
+        assert_eq!(hash, "c8e5f64e846193be2a834104c2a009868d6c9f3bd3c186299888b488b2f1f58e");
+    }
+
+    #[test]
+    fn test_noise_ik_spec_vector() {
+        let (messages, hash, _, _) = run_vector(HandshakePattern::KnownResponder, &cacophony_keys(), 2, &CACOPHONY_PAYLOADS);
+        assert_eq!(messages, vec![
+            "ca35def5ae56cec33dc2036731ab14896bc4c75dbb07a61f879f8e3afa4c7944718da798efbcd91528520204f904b9bd6c7413dccdc214d951e15253e39987f18146e8cd0873654207148333479d4d16c289f0294b29960a72f48e0b7bba2e89083169825e59642148d492020664ccf7",
+            "95ebc60d2b1fa672c1f46a8aa265ef51bfe38e7ccb39ec5be34069f1448088435361e70b2ed446e6c9ec387d1d6b3b840f194e373979d241b203c4acafccf5",
+            "050e9f3c8fac16b68dbce8f8c4bfbf6617c897f9ada4aa29aa19c8",
+            "344233a6cabb7141d80f3da2fedc311d9646bbb0f505afe403a667",
+            "62cdeeb172ad7ade7aa7d9e069da5790f12331bfa00177787a1d0810c67dc3b2b4",
+            "029bead1b40992327044d409d9a1f3ad8f36c3c452775d557e18bbeb2e8dfcead32d514024",
+        ]);
+        assert_eq!(hash, "0b0f68fb0c27e03ce9b97565995ed4838cc0581b762ef72b062f6a546419fad7");
+    }
+
+    /// Chaves fixas e prólogo SAE: valores congelados para detectar mudanças
+    /// no prólogo, no byte de padrão ou na derivação a partir do Split().
+    fn sae_keys(prologue: &[u8]) -> VectorKeys<'_> {
+        VectorKeys {
+            prologue,
+            initiator_static: [1u8; 32],
+            initiator_ephemeral: [2u8; 32],
+            responder_static: [3u8; 32],
+            responder_ephemeral: [4u8; 32],
+        }
+    }
+
+    #[test]
+    fn test_sae_xx_regression() {
+        let prologue = prologue(HandshakePattern::FirstContact.header(false));
+        let (messages, hash, k1, k2) = run_vector(HandshakePattern::FirstContact, &sae_keys(&prologue), 3, &[b"", b"resp", b"init"]);
+        assert_eq!(messages, vec![
+            "ce8d3ad1ccb633ec7b70c17814a5c76ecd029685050d344745ba05870e587d59",
+            "ac01b2209e86354fb853237b5de0f4fab13c7fcbf433a61c019369617fecf10bb719b14d19eaf5ca91c89748c5ce8668e6864f05ee2367682b3e0c2fd086f0b4bb5721959b140b4377afc74962b05d1dd86cd8122e1b0fa427c6171733a268c1ac89df12",
+            "539a5cf3ae8a0a9134b32bfa775a1522db3558a1351ed4101989b8b88aa6a6f0b4ea2f0dc21cf813f3847c9496429c7b2e0cdd5f22fc3ea565b8386d52b9ec8536630ce7",
+        ]);
+        assert_eq!(hash, "482948dfcb24b54e3d7092fd52455e1ffd009009be503b840284f075f348b689");
+        assert_eq!(k1, "013155fc61857be164b5250358a116758f5d20cf2d867365b23a4e428020d685");
+        assert_eq!(k2, "59b596b4192418d0f86a61c07a944f38a15c30dda5b4659b5500eacd809cd853");
+    }
+
+    #[test]
+    fn test_sae_ik_regression() {
+        let prologue = prologue(HandshakePattern::KnownResponder.header(false));
+        let (messages, hash, k1, k2) = run_vector(HandshakePattern::KnownResponder, &sae_keys(&prologue), 2, &[b"init", b"resp"]);
+        assert_eq!(messages, vec![
+            "ce8d3ad1ccb633ec7b70c17814a5c76ecd029685050d344745ba05870e587d59d5ea48b87c259312e4124f5b8ab09dbabfeeac5b1a722c5a59f272aaf5046006b6c95f2e8a4d7b5f302cb8bfe01eb6cd42b1a06c61b4562b77d20bbafbd8d3635a640aa4",
+            "ac01b2209e86354fb853237b5de0f4fab13c7fcbf433a61c019369617fecf10ba573d91dd52784d6f031990776a38b4b75b23e1f",
+        ]);
+        assert_eq!(hash, "aa5c7f36da774a4fb413ef776a8b325637013a7f6a09e0e03930e21e07908492");
+        assert_eq!(k1, "2ceea2107a23f496fca89ca3b1d442c9fb73c9d1b97aad6b1503e5010f48276d");
+        assert_eq!(k2, "dcc2c18489c2f28ce858ba981f58743615582c90d5a4554803eda05d754223c2");
+    }
+}
@@ -0,0 +1,133 @@
+use std::net::SocketAddr;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::config::{self, ConfigError, ListenConfig};
+use crate::crypton;
+use crate::interfaces;
+use crate::invite::Invite;
+use crate::network_secure::NetworkManager;
+use crate::tor::{OnionService, TorConfig};
+
+/// Listener do host, iniciado no primeiro convite e mantido para os
+/// seguintes: chave estática Noise, endereço anunciado nos convites e, com
+/// `--tor`, o onion service que o publica. Usado pela TUI e pelo daemon.
+pub struct HostListener {
+    secret: StaticSecret,
+    invite_host: String,
+    /// Porta efetivamente aberta, mesmo com `--port 0`
+    port: u16,
+    onion_service: Option<OnionService>,
+}
+
+impl HostListener {
+    /// Abre o listener em `listen.bind` com uma chave estática nova. O host
+    /// dos convites é o anunciado na configuração, o endereço .onion com Tor
+    /// ou, sem nenhum dos dois, o melhor endereço local.
+    pub async fn start(network: &mut NetworkManager, listen: &ListenConfig, tor_config: Option<&TorConfig>) -> Result<Self, HostError> {
+        let (secret, _) = crypton::generate_keypair();
+        let addr = network.start_host(listen.bind, secret.clone()).await.map_err(HostError::Bind)?;
+
+        let (invite_host, onion_service) = match tor_config {
+            Some(tor_config) => {
+                let service = OnionService::create(tor_config, addr.port(), addr).await
+                    .map_err(|e| HostError::Onion(e.to_string()))?;
+                (service.onion_address(), Some(service))
+            }
+            None => (listen.advertise.clone().unwrap_or_else(|| default_invite_host(addr)), None),
+        };
+        Ok(Self { secret, invite_host, port: addr.port(), onion_service })
+    }
+
+    /// Fingerprint da chave estática X25519 publicada nos convites.
+    pub fn fingerprint(&self) -> String {
+        crypton::get_fingerprint(&PublicKey::from(&self.secret))
+    }
+
+    /// Host usado nos convites quando nenhum outro é escolhido.
+    pub fn invite_host(&self) -> &str {
+        &self.invite_host
+    }
+
+    pub fn is_onion(&self) -> bool {
+        self.onion_service.is_some()
+    }
+
+    /// Emite um convite de uso único para este listener. `host` (já
+    /// normalizado) substitui o anunciado só neste convite; com Tor o convite
+    /// usa sempre o endereço .onion.
+    pub fn create_invite(&self, network: &NetworkManager, host: Option<String>, petname: Option<String>) -> Result<Invite, HostError> {
+        let invite_host = match host {
+            Some(_) if self.is_onion() => return Err(HostError::OnionOnly),
+            Some(host) => config::normalize_host(&host).map_err(HostError::InvalidHost)?,
+            None => self.invite_host.clone(),
+        };
+        let public = PublicKey::from(&self.secret).to_bytes();
+        Ok(network.create_invite(&invite_host, self.port, Some(public), petname))
+    }
+
+    /// Remove o onion service, se houver; o listener fecha com o processo.
+    pub async fn close(self) {
+        if let Some(service) = self.onion_service {
+            let _ = service.close().await;
+        }
+    }
+}
+
+fn default_invite_host(addr: SocketAddr) -> String {
+    let addresses = interfaces::local_addresses().unwrap_or_default();
+    interfaces::default_advertise(addr.ip(), &addresses)
+}
+
+/// Erros ao abrir o listener ou emitir um convite.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostError {
+    Bind(String),
+    Onion(String),
+    /// Com Tor o convite não aceita outro host
+    OnionOnly,
+    InvalidHost(ConfigError),
+}
+
+impl std::fmt::Display for HostError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HostError::Bind(e) => write!(f, "Erro ao iniciar host: {}", e),
+            HostError::Onion(e) => write!(f, "Erro ao criar onion service: {}", e),
+            HostError::OnionOnly => write!(f, "Com --tor o convite usa sempre o endereço .onion"),
+            HostError::InvalidHost(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for HostError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contacts::ContactStore;
+    use crate::handshake::PqPolicy;
+    use crate::identity::Identity;
+    use crate::network_secure::KeepaliveConfig;
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn test_invites_use_advertised_or_chosen_host() {
+        let (sender, _events) = mpsc::unbounded_channel();
+        let mut network = NetworkManager::new(sender, Identity::generate(), ContactStore::in_memory().shared(), None, None, KeepaliveConfig::default(), PqPolicy::default());
+        let listen = ListenConfig { bind: "127.0.0.1:0".parse().unwrap(), advertise: Some("sae.example".to_string()) };
+        let listener = HostListener::start(&mut network, &listen, None).await.unwrap();
+        assert!(!listener.is_onion());
+
+        let invite = listener.create_invite(&network, None, None).unwrap();
+        assert_eq!(invite.host, "sae.example");
+        assert_ne!(invite.port, 0);
+        assert_eq!(invite.noise_key, Some(PublicKey::from(&listener.secret).to_bytes()));
+
+        let invite = listener.create_invite(&network, Some("[::1]".to_string()), None).unwrap();
+        assert_eq!(invite.host, "::1");
+        assert!(matches!(
+            listener.create_invite(&network, Some("host inválido".to_string()), None),
+            Err(HostError::InvalidHost(_))
+        ));
+    }
+}
@@ -0,0 +1,123 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Endereço IP de uma interface de rede local.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceAddr {
+    pub name: String,
+    pub ip: IpAddr,
+}
+
+/// Endereços das interfaces ativas, os roteáveis primeiro e IPv4 antes de
+/// IPv6. IPv6 link-local fica de fora: sem o escopo da interface ele não
+/// serve num convite.
+pub fn local_addresses() -> io::Result<Vec<InterfaceAddr>> {
+    let mut addresses: Vec<InterfaceAddr> = interface_addresses()?
+        .into_iter()
+        .filter(|addr| !addr.ip.is_unspecified() && !is_link_local_v6(&addr.ip))
+        .collect();
+    addresses.sort_by_key(|addr| (addr.ip.is_loopback(), addr.ip.is_ipv6()));
+    addresses.dedup_by(|a, b| a.ip == b.ip);
+    Ok(addresses)
+}
+
+/// Host anunciado quando nada foi configurado: o próprio endereço de bind ou,
+/// com bind em todas as interfaces, o primeiro endereço roteável da família.
+pub fn default_advertise(bind: IpAddr, addresses: &[InterfaceAddr]) -> String {
+    if !bind.is_unspecified() {
+        return bind.to_string();
+    }
+    // `::` costuma aceitar também IPv4; `0.0.0.0` só IPv4
+    addresses.iter()
+        .find(|addr| !addr.ip.is_loopback() && (bind.is_ipv6() || addr.ip.is_ipv4()))
+        .map(|addr| addr.ip.to_string())
+        .unwrap_or_else(|| match bind {
+            IpAddr::V4(_) => Ipv4Addr::LOCALHOST.to_string(),
+            IpAddr::V6(_) => Ipv6Addr::LOCALHOST.to_string(),
+        })
+}
+
+fn is_link_local_v6(ip: &IpAddr) -> bool {
+    matches!(ip, IpAddr::V6(ip) if ip.segments()[0] & 0xffc0 == 0xfe80)
+}
+
+#[cfg(unix)]
+fn interface_addresses() -> io::Result<Vec<InterfaceAddr>> {
+    use std::ffi::CStr;
+
+    let mut head: *mut libc::ifaddrs = std::ptr::null_mut();
+    // SAFETY: em caso de sucesso `head` aponta para uma lista alocada pela
+    // libc, percorrida só para leitura e liberada com freeifaddrs abaixo.
+    if unsafe { libc::getifaddrs(&mut head) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut addresses = Vec::new();
+    let mut cursor = head;
+    while !cursor.is_null() {
+        // SAFETY: os nós da lista são válidos até freeifaddrs
+        let entry = unsafe { &*cursor };
+        cursor = entry.ifa_next;
+        if entry.ifa_addr.is_null() || entry.ifa_flags & libc::IFF_UP as libc::c_uint == 0 {
+            continue;
+        }
+        // SAFETY: `sa_family` indica qual estrutura sockaddr está por trás do ponteiro
+        let ip = match unsafe { (*entry.ifa_addr).sa_family } as libc::c_int {
+            libc::AF_INET => {
+                let addr = unsafe { &*(entry.ifa_addr as *const libc::sockaddr_in) };
+                IpAddr::V4(Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)))
+            }
+            libc::AF_INET6 => {
+                let addr = unsafe { &*(entry.ifa_addr as *const libc::sockaddr_in6) };
+                IpAddr::V6(Ipv6Addr::from(addr.sin6_addr.s6_addr))
+            }
+            _ => continue,
+        };
+        // SAFETY: `ifa_name` é uma string C terminada em zero
+        let name = unsafe { CStr::from_ptr(entry.ifa_name) }.to_string_lossy().into_owned();
+        addresses.push(InterfaceAddr { name, ip });
+    }
+
+    // SAFETY: `head` veio de getifaddrs e não é usado depois daqui
+    unsafe { libc::freeifaddrs(head) };
+    Ok(addresses)
+}
+
+/// Sem getifaddrs, descobre ao menos o endereço da rota padrão: "conectar"
+/// um socket UDP não envia pacotes, só escolhe a interface de saída.
+#[cfg(not(unix))]
+fn interface_addresses() -> io::Result<Vec<InterfaceAddr>> {
+    let socket = std::net::UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect("192.0.2.1:9")?;
+    Ok(vec![
+        InterfaceAddr { name: "padrão".to_string(), ip: socket.local_addr()?.ip() },
+        InterfaceAddr { name: "lo".to_string(), ip: IpAddr::V4(Ipv4Addr::LOCALHOST) },
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(name: &str, ip: &str) -> InterfaceAddr {
+        InterfaceAddr { name: name.to_string(), ip: ip.parse().unwrap() }
+    }
+
+    #[test]
+    fn test_local_addresses_include_loopback_last() {
+        let addresses = local_addresses().unwrap();
+        assert!(addresses.iter().any(|a| a.ip.is_loopback()));
+        assert!(!addresses.iter().any(|a| is_link_local_v6(&a.ip)));
+        let first_loopback = addresses.iter().position(|a| a.ip.is_loopback()).unwrap();
+        assert!(addresses[first_loopback..].iter().all(|a| a.ip.is_loopback()));
+    }
+
+    #[test]
+    fn test_default_advertise() {
+        let addresses = [addr("eth0", "2001:db8::5"), addr("wlan0", "192.168.1.20"), addr("lo", "127.0.0.1")];
+        assert_eq!(default_advertise("0.0.0.0".parse().unwrap(), &addresses), "192.168.1.20");
+        assert_eq!(default_advertise("::".parse().unwrap(), &addresses), "2001:db8::5");
+        assert_eq!(default_advertise("10.0.0.3".parse().unwrap(), &addresses), "10.0.0.3");
+        assert_eq!(default_advertise("0.0.0.0".parse().unwrap(), &addresses[2..]), "127.0.0.1");
+    }
+}
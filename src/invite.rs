@@ -0,0 +1,390 @@
+use crate::identity::{verify_signature, Identity};
+use ed25519_dalek::{Signature, VerifyingKey};
+use rand::{rngs::OsRng, RngCore};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/// Versão atual do formato de convite.
+pub const INVITE_VERSION: u8 = 1;
+/// Validade padrão de um convite.
+pub const DEFAULT_INVITE_TTL: Duration = Duration::from_secs(15 * 60);
+const INVITE_CONTEXT: &[u8] = b"sae-invite-v1";
+pub const TOKEN_LEN: usize = 16;
+/// Valor do parâmetro `kem` para hosts que aceitam a troca híbrida
+const KEM_ALGORITHM: &str = "mlkem768";
+
+/// Tokens de convite pendentes, compartilhados entre o host e o loop principal.
+pub type SharedInvites = Arc<Mutex<InviteTokens>>;
+
+/// Transporte anunciado no convite.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Tcp,
+    Onion,
+}
+
+impl Transport {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Transport::Tcp => "tcp",
+            Transport::Onion => "onion",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "tcp" => Some(Transport::Tcp),
+            "onion" => Some(Transport::Onion),
+            _ => None,
+        }
+    }
+}
+
+/// Convite de uso único assinado pela identidade Ed25519 do host.
+///
+/// Formato da URI (v1):
+/// `sae://<host>:<porta>?v=1&transport=tcp|onion&id=<ed25519>&pubkey=<x25519>&token=<hex>&exp=<unix>[&tls=<sha256>][&kem=mlkem768]&sig=<ed25519>`
+///
+/// `pubkey` é opcional: sem ele o cliente usa Noise XX e confere a identidade
+/// do host contra `id` após o handshake. `kem` anuncia a troca híbrida com
+/// ML-KEM-768; a chave KEM em si é efêmera e segue no handshake, já que com
+/// 1184 bytes tornaria o convite e o QR code impraticáveis.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invite {
+    pub version: u8,
+    pub host: String,
+    pub port: u16,
+    pub transport: Transport,
+    /// Chave de identidade Ed25519 do host
+    pub identity_key: [u8; 32],
+    /// Chave estática Noise (X25519) do host
+    pub noise_key: Option<[u8; 32]>,
+    /// Segredo de uso único apresentado pelo cliente no handshake
+    pub token: [u8; TOKEN_LEN],
+    /// Unix timestamp a partir do qual o convite não vale mais
+    pub expires_at: u64,
+    /// Fingerprint SHA256 do certificado TLS do host
+    pub tls_pin: Option<String>,
+    /// O host aceita X25519 + ML-KEM-768
+    pub post_quantum: bool,
+    signature: [u8; 64],
+}
+
+impl Invite {
+    /// Cria e assina um convite novo, com token aleatório.
+    pub fn new(
+        identity: &Identity,
+        host: &str,
+        port: u16,
+        noise_key: Option<[u8; 32]>,
+        tls_pin: Option<String>,
+        post_quantum: bool,
+        ttl: Duration,
+    ) -> Self {
+        let mut token = [0u8; TOKEN_LEN];
+        OsRng.fill_bytes(&mut token);
+        let transport = if host.ends_with(".onion") { Transport::Onion } else { Transport::Tcp };
+
+        let mut invite = Self {
+            version: INVITE_VERSION,
+            host: host.to_string(),
+            port,
+            transport,
+            identity_key: identity.public_key_bytes(),
+            noise_key,
+            token,
+            expires_at: now() + ttl.as_secs(),
+            tls_pin,
+            post_quantum,
+            signature: [0u8; 64],
+        };
+        invite.signature = identity.sign(&invite.signed_data()).to_bytes();
+        invite
+    }
+
+    /// Valida e decodifica uma URI de convite, conferindo assinatura e validade.
+    pub fn parse(uri: &str) -> Result<Self, InviteError> {
+        Self::parse_at(uri, now())
+    }
+
+    fn parse_at(uri: &str, now: u64) -> Result<Self, InviteError> {
+        let url = Url::parse(uri).map_err(|_| InviteError::InvalidUri)?;
+        if url.scheme() != "sae" {
+            return Err(InviteError::InvalidUri);
+        }
+        let host = url.host_str().ok_or(InviteError::MissingField("host"))?;
+        let host = host.trim_matches(|c| c == '[' || c == ']').to_string();
+        let port = url.port().ok_or(InviteError::MissingField("porta"))?;
+
+        let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        let field = |name: &'static str| params.get(name).ok_or(InviteError::MissingField(name));
+
+        let version: u8 = field("v")?.parse().map_err(|_| InviteError::InvalidField("v"))?;
+        if version != INVITE_VERSION {
+            return Err(InviteError::UnsupportedVersion(version));
+        }
+        let transport = Transport::parse(field("transport")?).ok_or(InviteError::InvalidField("transport"))?;
+        if (transport == Transport::Onion) != host.ends_with(".onion") {
+            return Err(InviteError::InvalidField("transport"));
+        }
+
+        let invite = Self {
+            version,
+            port,
+            transport,
+            identity_key: decode_hex(field("id")?, "id")?,
+            noise_key: params.get("pubkey").map(|v| decode_hex(v, "pubkey")).transpose()?,
+            token: decode_hex(field("token")?, "token")?,
+            expires_at: field("exp")?.parse().map_err(|_| InviteError::InvalidField("exp"))?,
+            tls_pin: params.get("tls").cloned(),
+            post_quantum: match params.get("kem").map(String::as_str) {
+                None => false,
+                Some(KEM_ALGORITHM) => true,
+                Some(_) => return Err(InviteError::InvalidField("kem")),
+            },
+            signature: decode_hex(field("sig")?, "sig")?,
+            host,
+        };
+
+        let verifying_key = VerifyingKey::from_bytes(&invite.identity_key)
+            .map_err(|_| InviteError::InvalidField("id"))?;
+        verify_signature(&verifying_key, &invite.signed_data(), &Signature::from_bytes(&invite.signature))
+            .map_err(|_| InviteError::BadSignature)?;
+
+        if now >= invite.expires_at {
+            return Err(InviteError::Expired);
+        }
+        Ok(invite)
+    }
+
+    /// Tempo restante até a expiração.
+    pub fn expires_in(&self) -> Duration {
+        Duration::from_secs(self.expires_at.saturating_sub(now()))
+    }
+
+    /// Serializa o convite como URI `sae://`.
+    pub fn to_uri(&self) -> String {
+        let host = if self.host.contains(':') { format!("[{}]", self.host) } else { self.host.clone() };
+        let mut uri = format!(
+            "sae://{}:{}?v={}&transport={}&id={}",
+            host, self.port, self.version, self.transport.as_str(), hex::encode(self.identity_key)
+        );
+        if let Some(noise_key) = &self.noise_key {
+            uri.push_str(&format!("&pubkey={}", hex::encode(noise_key)));
+        }
+        uri.push_str(&format!("&token={}&exp={}", hex::encode(self.token), self.expires_at));
+        if let Some(pin) = &self.tls_pin {
+            uri.push_str(&format!("&tls={}", pin));
+        }
+        if self.post_quantum {
+            uri.push_str(&format!("&kem={}", KEM_ALGORITHM));
+        }
+        uri.push_str(&format!("&sig={}", hex::encode(self.signature)));
+        uri
+    }
+
+    /// Codificação canônica coberta pela assinatura; campos de tamanho
+    /// variável levam prefixo de comprimento. O algoritmo KEM só entra quando
+    /// anunciado, mantendo a assinatura de convites clássicos.
+    fn signed_data(&self) -> Vec<u8> {
+        let mut data = INVITE_CONTEXT.to_vec();
+        data.push(self.version);
+        push_field(&mut data, self.host.as_bytes());
+        data.extend_from_slice(&self.port.to_be_bytes());
+        push_field(&mut data, self.transport.as_str().as_bytes());
+        data.extend_from_slice(&self.identity_key);
+        push_field(&mut data, self.noise_key.as_ref().map(|k| &k[..]).unwrap_or(&[]));
+        data.extend_from_slice(&self.token);
+        data.extend_from_slice(&self.expires_at.to_be_bytes());
+        push_field(&mut data, self.tls_pin.as_deref().unwrap_or("").as_bytes());
+        if self.post_quantum {
+            push_field(&mut data, KEM_ALGORITHM.as_bytes());
+        }
+        data
+    }
+}
+
+/// Registro de tokens emitidos pelo host. Cada token vale uma única conexão
+/// e apenas até a expiração do convite.
+#[derive(Debug, Default)]
+pub struct InviteTokens {
+    pending: HashMap<[u8; TOKEN_LEN], PendingInvite>,
+    redeemed: HashSet<[u8; TOKEN_LEN]>,
+}
+
+#[derive(Debug)]
+struct PendingInvite {
+    expires_at: u64,
+    /// Contato esperado por quem emitiu o convite (`/invite <apelido>`)
+    petname: Option<String>,
+}
+
+impl InviteTokens {
+    pub fn shared() -> SharedInvites {
+        Arc::new(Mutex::new(Self::default()))
+    }
+
+    /// Registra o token do convite, associado ao contato esperado, se houver.
+    pub fn issue(&mut self, invite: &Invite, petname: Option<String>) {
+        self.pending.insert(invite.token, PendingInvite { expires_at: invite.expires_at, petname });
+    }
+
+    /// Consome o token; a partir daqui ele não aceita outra conexão.
+    /// Retorna o contato esperado para quem apresentou o token.
+    pub fn redeem(&mut self, token: &[u8; TOKEN_LEN]) -> Result<Option<String>, InviteError> {
+        self.redeem_at(token, now())
+    }
+
+    fn redeem_at(&mut self, token: &[u8; TOKEN_LEN], now: u64) -> Result<Option<String>, InviteError> {
+        // Tokens vencidos saem do registro mesmo que nunca tenham sido usados
+        self.pending.retain(|_, pending| now < pending.expires_at);
+
+        if self.redeemed.contains(token) {
+            return Err(InviteError::TokenAlreadyUsed);
+        }
+        match self.pending.remove(token) {
+            Some(pending) => {
+                self.redeemed.insert(*token);
+                Ok(pending.petname)
+            }
+            None => Err(InviteError::UnknownToken),
+        }
+    }
+}
+
+fn push_field(data: &mut Vec<u8>, field: &[u8]) {
+    data.extend_from_slice(&(field.len() as u16).to_be_bytes());
+    data.extend_from_slice(field);
+}
+
+fn decode_hex<const N: usize>(value: &str, name: &'static str) -> Result<[u8; N], InviteError> {
+    hex::decode(value).ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(InviteError::InvalidField(name))
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Erros de validação de convites.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InviteError {
+    InvalidUri,
+    UnsupportedVersion(u8),
+    MissingField(&'static str),
+    InvalidField(&'static str),
+    BadSignature,
+    Expired,
+    UnknownToken,
+    TokenAlreadyUsed,
+}
+
+impl std::fmt::Display for InviteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InviteError::InvalidUri => write!(f, "URI de convite inválida"),
+            InviteError::UnsupportedVersion(v) => write!(f, "Versão de convite não suportada: {}", v),
+            InviteError::MissingField(name) => write!(f, "Campo ausente no convite: {}", name),
+            InviteError::InvalidField(name) => write!(f, "Campo inválido no convite: {}", name),
+            InviteError::BadSignature => write!(f, "⚠️ Assinatura do convite inválida - convite adulterado!"),
+            InviteError::Expired => write!(f, "Convite expirado"),
+            InviteError::UnknownToken => write!(f, "Token de convite desconhecido ou expirado"),
+            InviteError::TokenAlreadyUsed => write!(f, "Token de convite já utilizado"),
+        }
+    }
+}
+
+impl std::error::Error for InviteError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(identity: &Identity) -> Invite {
+        Invite::new(identity, "127.0.0.1", 9001, Some([7u8; 32]), Some("ab".repeat(32)), true, DEFAULT_INVITE_TTL)
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let identity = Identity::generate();
+        let invite = sample(&identity);
+        let parsed = Invite::parse(&invite.to_uri()).unwrap();
+
+        assert_eq!(parsed, invite);
+        assert_eq!(parsed.identity_key, identity.public_key_bytes());
+        assert_eq!(parsed.transport, Transport::Tcp);
+        assert!(parsed.post_quantum);
+        assert!(parsed.expires_in() <= DEFAULT_INVITE_TTL && parsed.expires_in() > Duration::ZERO);
+    }
+
+    #[test]
+    fn test_ipv6_and_onion_hosts() {
+        let identity = Identity::generate();
+        let v6 = Invite::new(&identity, "::1", 9001, None, None, false, DEFAULT_INVITE_TTL);
+        assert_eq!(Invite::parse(&v6.to_uri()).unwrap().host, "::1");
+
+        let onion_host = format!("{}.onion", "a".repeat(56));
+        let onion = Invite::new(&identity, &onion_host, 9001, None, None, false, DEFAULT_INVITE_TTL);
+        assert_eq!(Invite::parse(&onion.to_uri()).unwrap().transport, Transport::Onion);
+    }
+
+    #[test]
+    fn test_tampered_fields_rejected() {
+        let invite = sample(&Identity::generate());
+        let uri = invite.to_uri();
+
+        let other_host = uri.replace("127.0.0.1", "10.0.0.1");
+        assert_eq!(Invite::parse(&other_host), Err(InviteError::BadSignature));
+
+        let later = uri.replace(&format!("exp={}", invite.expires_at), &format!("exp={}", invite.expires_at + 3600));
+        assert_eq!(Invite::parse(&later), Err(InviteError::BadSignature));
+
+        let no_pin = uri.replace(&format!("&tls={}", "ab".repeat(32)), "");
+        assert_eq!(Invite::parse(&no_pin), Err(InviteError::BadSignature));
+
+        // Remover o anúncio de ML-KEM para forçar o handshake clássico
+        let no_kem = uri.replace("&kem=mlkem768", "");
+        assert_eq!(Invite::parse(&no_kem), Err(InviteError::BadSignature));
+        let other_kem = uri.replace("kem=mlkem768", "kem=mlkem1024");
+        assert_eq!(Invite::parse(&other_kem), Err(InviteError::InvalidField("kem")));
+
+        // Trocar a identidade junto com a chave não ajuda sem a chave privada
+        let impostor = Identity::generate();
+        let swapped = uri.replace(&hex::encode(invite.identity_key), &hex::encode(impostor.public_key_bytes()));
+        assert_eq!(Invite::parse(&swapped), Err(InviteError::BadSignature));
+    }
+
+    #[test]
+    fn test_expired_and_malformed() {
+        let invite = sample(&Identity::generate());
+        assert_eq!(Invite::parse_at(&invite.to_uri(), invite.expires_at), Err(InviteError::Expired));
+
+        assert_eq!(Invite::parse("sae://127.0.0.1:9001?pubkey=00"), Err(InviteError::MissingField("v")));
+        assert_eq!(Invite::parse("http://127.0.0.1:9001"), Err(InviteError::InvalidUri));
+        let v2 = invite.to_uri().replace("v=1", "v=2");
+        assert_eq!(Invite::parse(&v2), Err(InviteError::UnsupportedVersion(2)));
+    }
+
+    #[test]
+    fn test_token_is_single_use() {
+        let invite = sample(&Identity::generate());
+        let mut tokens = InviteTokens::default();
+        tokens.issue(&invite, Some("alice".into()));
+
+        assert_eq!(tokens.redeem(&invite.token), Ok(Some("alice".into())));
+        assert_eq!(tokens.redeem(&invite.token), Err(InviteError::TokenAlreadyUsed));
+        assert_eq!(tokens.redeem(&[0u8; TOKEN_LEN]), Err(InviteError::UnknownToken));
+    }
+
+    #[test]
+    fn test_expired_token_rejected() {
+        let invite = sample(&Identity::generate());
+        let mut tokens = InviteTokens::default();
+        tokens.issue(&invite, None);
+
+        assert_eq!(tokens.redeem_at(&invite.token, invite.expires_at), Err(InviteError::UnknownToken));
+    }
+}
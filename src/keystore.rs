@@ -0,0 +1,409 @@
+use crate::identity::Identity;
+use argon2::{Algorithm, Argon2, Params, Version};
+use ed25519_dalek::VerifyingKey;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+const KEYSTORE_VERSION: u8 = 1;
+const KEYSTORE_FILE: &str = "identity.json";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Parâmetros do Argon2id usados para derivar a chave do keystore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    /// Memória em KiB
+    pub m_cost: u32,
+    /// Número de passadas
+    pub t_cost: u32,
+    /// Paralelismo
+    pub p_cost: u32,
+}
+
+impl Default for KdfParams {
+    /// 64 MiB e 3 passadas: custo relevante para ataque de dicionário offline.
+    fn default() -> Self {
+        Self {
+            m_cost: 64 * 1024,
+            t_cost: 3,
+            p_cost: 1,
+        }
+    }
+}
+
+impl KdfParams {
+    /// Limites aceitos: os parâmetros vêm do arquivo e chegam ao Argon2 antes
+    /// de qualquer autenticação, então um arquivo forjado não pode pedir
+    /// gigabytes de memória nem um custo fraco demais.
+    const MIN: KdfParams = KdfParams { m_cost: 8 * 1024, t_cost: 1, p_cost: 1 };
+    const MAX: KdfParams = KdfParams { m_cost: 4 * 1024 * 1024, t_cost: 64, p_cost: 16 };
+
+    fn is_within_bounds(&self) -> bool {
+        (Self::MIN.m_cost..=Self::MAX.m_cost).contains(&self.m_cost)
+            && (Self::MIN.t_cost..=Self::MAX.t_cost).contains(&self.t_cost)
+            && (Self::MIN.p_cost..=Self::MAX.p_cost).contains(&self.p_cost)
+    }
+}
+
+/// Arquivo de identidade cifrado: a chave Ed25519 fica protegida com
+/// ChaCha20-Poly1305 sob uma chave derivada da senha via Argon2id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KeystoreFile {
+    version: u8,
+    kdf: String,
+    params: KdfParams,
+    salt: String,
+    nonce: String,
+    /// Chave pública em claro (autenticada como AAD) para exportação
+    public_key: String,
+    ciphertext: String,
+}
+
+impl KeystoreFile {
+    /// Dados autenticados: versão, parâmetros, sal e chave pública.
+    /// Alterar qualquer um deles invalida o arquivo.
+    fn associated_data(&self) -> Vec<u8> {
+        let mut aad = vec![self.version];
+        aad.extend_from_slice(self.kdf.as_bytes());
+        aad.extend_from_slice(&self.params.m_cost.to_be_bytes());
+        aad.extend_from_slice(&self.params.t_cost.to_be_bytes());
+        aad.extend_from_slice(&self.params.p_cost.to_be_bytes());
+        aad.extend_from_slice(self.salt.as_bytes());
+        aad.extend_from_slice(self.public_key.as_bytes());
+        aad
+    }
+}
+
+/// Caminho padrão do keystore: `$XDG_DATA_HOME/sae/identity.json`
+/// (ou `~/.local/share/sae/identity.json`).
+pub fn default_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("sae").join(KEYSTORE_FILE))
+}
+
+/// Cria um novo keystore com uma identidade recém-gerada.
+/// Nunca sobrescreve um arquivo existente.
+pub fn create(path: &Path, passphrase: &str, params: KdfParams) -> Result<Identity, KeystoreError> {
+    if path.exists() {
+        return Err(KeystoreError::AlreadyExists(path.to_path_buf()));
+    }
+    let identity = Identity::generate();
+    save(path, &identity, passphrase, params)?;
+    Ok(identity)
+}
+
+/// Decifra o keystore e retorna a identidade.
+pub fn unlock(path: &Path, passphrase: &str) -> Result<Identity, KeystoreError> {
+    let file = read(path)?;
+    if file.version != KEYSTORE_VERSION || file.kdf != "argon2id" {
+        return Err(KeystoreError::UnsupportedVersion(file.version));
+    }
+
+    let salt = decode_field(&file.salt, SALT_LEN)?;
+    let nonce = decode_field(&file.nonce, NONCE_LEN)?;
+    let ciphertext = hex::decode(&file.ciphertext).map_err(|_| KeystoreError::Corrupted)?;
+
+    let key = derive_key(passphrase, &salt, file.params)?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key.as_ref()));
+    let aad = file.associated_data();
+    let plaintext = Zeroizing::new(
+        cipher
+            .decrypt(Nonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: &aad })
+            .map_err(|_| KeystoreError::WrongPassphrase)?,
+    );
+
+    let secret: [u8; 32] = plaintext.as_slice().try_into().map_err(|_| KeystoreError::Corrupted)?;
+    let secret = Zeroizing::new(secret);
+    let identity = Identity::from_secret_bytes(&secret);
+
+    // A chave pública em claro precisa corresponder à chave decifrada
+    if hex::encode(identity.public_key_bytes()) != file.public_key {
+        return Err(KeystoreError::Corrupted);
+    }
+
+    Ok(identity)
+}
+
+/// Substitui a identidade do keystore por uma nova, com a mesma senha.
+/// Retorna a identidade nova; a antiga deixa de existir em disco.
+pub fn rotate(path: &Path, passphrase: &str) -> Result<Identity, KeystoreError> {
+    let params = read(path)?.params;
+    // Confirma a senha antes de descartar a identidade atual
+    unlock(path, passphrase)?;
+
+    let identity = Identity::generate();
+    save(path, &identity, passphrase, params)?;
+    Ok(identity)
+}
+
+/// Exporta a chave pública do keystore, sem exigir a senha.
+pub fn export_public_key(path: &Path) -> Result<VerifyingKey, KeystoreError> {
+    let file = read(path)?;
+    let bytes: [u8; 32] = decode_field(&file.public_key, 32)?.try_into().unwrap();
+    VerifyingKey::from_bytes(&bytes).map_err(|_| KeystoreError::Corrupted)
+}
+
+fn save(path: &Path, identity: &Identity, passphrase: &str, params: KdfParams) -> Result<(), KeystoreError> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let mut file = KeystoreFile {
+        version: KEYSTORE_VERSION,
+        kdf: "argon2id".to_string(),
+        params,
+        salt: hex::encode(salt),
+        nonce: hex::encode(nonce),
+        public_key: hex::encode(identity.public_key_bytes()),
+        ciphertext: String::new(),
+    };
+
+    let key = derive_key(passphrase, &salt, params)?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key.as_ref()));
+    let aad = file.associated_data();
+    let secret = identity.secret_bytes();
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: secret.as_ref(), aad: &aad })
+        .map_err(|_| KeystoreError::Corrupted)?;
+    file.ciphertext = hex::encode(ciphertext);
+
+    let json = serde_json::to_vec_pretty(&file).map_err(|e| KeystoreError::Io(e.to_string()))?;
+    write_private(path, &json)
+}
+
+fn read(path: &Path) -> Result<KeystoreFile, KeystoreError> {
+    let data = fs::read(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => KeystoreError::NotFound(path.to_path_buf()),
+        _ => KeystoreError::Io(format!("{}: {}", path.display(), e)),
+    })?;
+    serde_json::from_slice(&data).map_err(|_| KeystoreError::Corrupted)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], params: KdfParams) -> Result<Zeroizing<[u8; 32]>, KeystoreError> {
+    if !params.is_within_bounds() {
+        return Err(KeystoreError::InvalidKdfParams(params));
+    }
+    let params = Params::new(params.m_cost, params.t_cost, params.p_cost, Some(32))
+        .map_err(|e| KeystoreError::Kdf(e.to_string()))?;
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+
+    let mut key = Zeroizing::new([0u8; 32]);
+    argon2
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+        .map_err(|e| KeystoreError::Kdf(e.to_string()))?;
+    Ok(key)
+}
+
+fn decode_field(value: &str, len: usize) -> Result<Vec<u8>, KeystoreError> {
+    match hex::decode(value) {
+        Ok(bytes) if bytes.len() == len => Ok(bytes),
+        _ => Err(KeystoreError::Corrupted),
+    }
+}
+
+/// Grava o arquivo de forma atômica (arquivo temporário + rename), com
+/// permissão 0600 e diretório 0700 em sistemas Unix.
+pub(crate) fn write_private(path: &Path, data: &[u8]) -> Result<(), KeystoreError> {
+    let io_err = |e: std::io::Error| KeystoreError::Io(format!("{}: {}", path.display(), e));
+
+    // Só o diretório criado aqui recebe 0700; um diretório existente (como
+    // /tmp ou $HOME) não é alterado
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty() && !dir.exists()) {
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+        builder.create(dir).map_err(io_err)?;
+    }
+
+    // O modo 0600 só vale na criação: um .tmp que sobrou manteria o modo antigo
+    let tmp_path = path.with_extension("tmp");
+    match fs::remove_file(&tmp_path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(io_err(e)),
+        _ => {}
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(&tmp_path).map_err(io_err)?;
+    file.write_all(data).map_err(io_err)?;
+    file.sync_all().map_err(io_err)?;
+    fs::rename(&tmp_path, path).map_err(io_err)
+}
+
+/// Erros do keystore de identidade.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeystoreError {
+    NotFound(PathBuf),
+    AlreadyExists(PathBuf),
+    WrongPassphrase,
+    Corrupted,
+    UnsupportedVersion(u8),
+    InvalidKdfParams(KdfParams),
+    Kdf(String),
+    Io(String),
+}
+
+impl std::fmt::Display for KeystoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeystoreError::NotFound(path) => write!(f, "Identidade não encontrada em {} (use --create-identity)", path.display()),
+            KeystoreError::AlreadyExists(path) => write!(f, "Já existe uma identidade em {}", path.display()),
+            KeystoreError::WrongPassphrase => write!(f, "Senha incorreta ou arquivo de identidade adulterado"),
+            KeystoreError::Corrupted => write!(f, "Arquivo de identidade corrompido"),
+            KeystoreError::UnsupportedVersion(v) => write!(f, "Versão de keystore não suportada: {}", v),
+            KeystoreError::InvalidKdfParams(p) => write!(
+                f,
+                "Parâmetros de KDF fora dos limites aceitos (m_cost={}, t_cost={}, p_cost={})",
+                p.m_cost, p.t_cost, p.p_cost
+            ),
+            KeystoreError::Kdf(e) => write!(f, "Falha na derivação de chave: {}", e),
+            KeystoreError::Io(e) => write!(f, "Erro de E/S no keystore: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for KeystoreError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parâmetros mínimos aceitos, para os testes não levarem segundos cada.
+    const TEST_PARAMS: KdfParams = KdfParams::MIN;
+
+    fn temp_path() -> PathBuf {
+        let mut name = [0u8; 8];
+        OsRng.fill_bytes(&mut name);
+        std::env::temp_dir()
+            .join(format!("sae-keystore-{}", hex::encode(name)))
+            .join(KEYSTORE_FILE)
+    }
+
+    #[test]
+    fn test_create_and_unlock() {
+        let path = temp_path();
+        let created = create(&path, "correct horse", TEST_PARAMS).unwrap();
+        let unlocked = unlock(&path, "correct horse").unwrap();
+
+        assert_eq!(created.public_key_bytes(), unlocked.public_key_bytes());
+        assert_eq!(created.fingerprint(), unlocked.fingerprint());
+        assert_eq!(export_public_key(&path).unwrap().to_bytes(), created.public_key_bytes());
+    }
+
+    #[test]
+    fn test_wrong_passphrase_rejected() {
+        let path = temp_path();
+        create(&path, "correct horse", TEST_PARAMS).unwrap();
+        assert_eq!(unlock(&path, "battery staple").err(), Some(KeystoreError::WrongPassphrase));
+    }
+
+    #[test]
+    fn test_create_never_overwrites() {
+        let path = temp_path();
+        create(&path, "pass", TEST_PARAMS).unwrap();
+        assert!(matches!(create(&path, "pass", TEST_PARAMS), Err(KeystoreError::AlreadyExists(_))));
+    }
+
+    #[test]
+    fn test_tampered_public_key_rejected() {
+        let path = temp_path();
+        create(&path, "pass", TEST_PARAMS).unwrap();
+
+        let mut file = read(&path).unwrap();
+        file.public_key = hex::encode(Identity::generate().public_key_bytes());
+        fs::write(&path, serde_json::to_vec(&file).unwrap()).unwrap();
+
+        assert_eq!(unlock(&path, "pass").err(), Some(KeystoreError::WrongPassphrase));
+    }
+
+    #[test]
+    fn test_kdf_params_out_of_bounds_rejected() {
+        let path = temp_path();
+        create(&path, "pass", TEST_PARAMS).unwrap();
+        let original = read(&path).unwrap();
+
+        for params in [
+            KdfParams { m_cost: u32::MAX, ..TEST_PARAMS },
+            KdfParams { t_cost: 1000, ..TEST_PARAMS },
+            KdfParams { p_cost: 255, ..TEST_PARAMS },
+            KdfParams { m_cost: 64, ..TEST_PARAMS },
+            KdfParams { t_cost: 0, ..TEST_PARAMS },
+        ] {
+            let mut file = original.clone();
+            file.params = params;
+            fs::write(&path, serde_json::to_vec(&file).unwrap()).unwrap();
+            assert_eq!(unlock(&path, "pass").err(), Some(KeystoreError::InvalidKdfParams(params)));
+        }
+
+        assert!(KdfParams::default().is_within_bounds());
+        let weak = KdfParams { m_cost: 64, ..TEST_PARAMS };
+        assert_eq!(create(&temp_path(), "pass", weak).err(), Some(KeystoreError::InvalidKdfParams(weak)));
+    }
+
+    #[test]
+    fn test_rotate_replaces_identity() {
+        let path = temp_path();
+        let original = create(&path, "pass", TEST_PARAMS).unwrap();
+
+        assert_eq!(rotate(&path, "wrong").err(), Some(KeystoreError::WrongPassphrase));
+        let rotated = rotate(&path, "pass").unwrap();
+
+        assert_ne!(original.public_key_bytes(), rotated.public_key_bytes());
+        assert_eq!(unlock(&path, "pass").unwrap().public_key_bytes(), rotated.public_key_bytes());
+    }
+
+    #[test]
+    fn test_missing_file() {
+        assert!(matches!(unlock(&temp_path(), "pass"), Err(KeystoreError::NotFound(_))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = temp_path();
+        create(&path, "pass", TEST_PARAMS).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let dir_mode = fs::metadata(path.parent().unwrap()).unwrap().permissions().mode();
+        assert_eq!(dir_mode & 0o777, 0o700);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_existing_directory_and_stale_tmp() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = temp_path();
+        let dir = path.parent().unwrap();
+        fs::create_dir_all(dir).unwrap();
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o755)).unwrap();
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, b"sobra").unwrap();
+        fs::set_permissions(&tmp_path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        write_private(&path, b"dados").unwrap();
+
+        assert_eq!(fs::metadata(dir).unwrap().permissions().mode() & 0o777, 0o755);
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(fs::read(&path).unwrap(), b"dados");
+    }
+}
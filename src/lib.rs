@@ -0,0 +1,47 @@
+//! SAE - Secure Anonymous Echo como biblioteca.
+//!
+//! A TUI (`sae`, feature `tui`) é um dos consumidores; bots e outras
+//! interfaces usam as mesmas peças:
+//!
+//! - [`Session`]: handshake Noise, Double Ratchet e padding sobre qualquer
+//!   [`Transport`], para quem cuida da própria conexão;
+//! - [`transport`]: WebSocket, TCP com prefixo de tamanho, socket Unix e
+//!   duplex em memória, atrás de [`Listener`] e [`Connector`];
+//! - [`NetworkManager`]: host, convites, reconexão e keepalive sobre
+//!   qualquer desses transportes (WebSocket/TLS/Tor para os convites), com os
+//!   eventos tipados de [`NetworkEvent`], e [`host::HostListener`] para o
+//!   listener dos convites;
+//! - [`daemon::Daemon`]: o mesmo controlado por JSON-RPC num socket Unix.
+
+pub mod config;
+pub mod contacts;
+pub mod crypton;
+#[cfg(unix)]
+pub mod daemon;
+pub mod delivery;
+pub mod handshake;
+pub mod host;
+pub mod identity;
+pub mod interfaces;
+pub mod invite;
+pub mod keystore;
+pub mod mlkem;
+pub mod network_secure;
+pub mod padding;
+pub mod protocol;
+pub mod qr;
+pub mod ratchet;
+pub mod room;
+pub mod sas;
+pub mod session;
+pub mod tls;
+pub mod tor;
+pub mod transfer;
+pub mod transport;
+
+pub use identity::Identity;
+pub use invite::Invite;
+pub use network_secure::{KeepaliveConfig, NetworkEvent, NetworkManager, SessionId};
+pub use protocol::{Message, MessageId};
+pub use session::{Session, SessionError};
+pub use transport::{memory_pair, Connection, Connector, Listener, Transport};
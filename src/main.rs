@@ -1,41 +1,388 @@
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{eyre, Result};
 use tokio::time::Duration;
 use tokio::sync::mpsc;
-use std::net::SocketAddr;
-use clap::Parser;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use clap::{Parser, Subcommand};
 
 mod app;
-mod crypton;
 mod event;
-mod identity;
-mod network;
-mod network_secure;
-mod padding;
-mod ratchet;
-mod tor;
+mod pipe;
 mod tui;
 mod ui;
 
-use app::{App, AppMode, Action, ChatMessage};
+#[cfg(unix)]
+use sae::daemon;
+use sae::{config, contacts, crypton, delivery, handshake, host, identity, interfaces, invite, keystore};
+use sae::{network_secure, protocol, qr, room, sas, tls, tor, transfer};
+
+use app::{App, AppMode, Action, Conversation, RoomCommand};
+use config::Config;
+use contacts::{key_fingerprint, ContactStore, SharedContacts};
 use crypton::generate_keypair;
+use delivery::{DeliveryStatus, Outbox};
 use event::{Event, EventHandler};
-use network_secure::{NetworkManager, NetworkEvent};
-use padding::{add_padding, remove_padding};
-use ratchet::RatchetSession;
+use handshake::PqPolicy;
+use host::{HostError, HostListener};
+use identity::Identity;
+use invite::Invite;
+use network_secure::{resend_unacked, send_wire, KeepaliveConfig, NetworkManager, NetworkEvent, Resent, SessionId};
+use protocol::{ChannelError, Message, MessageId, ProtocolError, Receipt, SecureChannel};
+use pipe::Pipe;
+use qr::InviteQr;
+use room::{Outgoing, RoomError, RoomEvent, RoomManager, RoomOutput};
+use sas::VerifiedNotice;
+use transfer::{FileWire, TransferEvent, TransferManager};
 use ui::TuiManager;
-use x25519_dalek::{PublicKey, EphemeralSecret};
 
 /// SAE - Secure Anonymous Echo: Mensageiro criptografado e efêmero
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Ativa TLS/WSS para conexões seguras
     #[arg(long, default_value_t = false)]
     tls: bool,
 
+    /// Certificado PEM do host para TLS (padrão: autoassinado gerado por sessão)
+    #[arg(long, requires_all = ["tls", "tls_key"])]
+    tls_cert: Option<PathBuf>,
+
+    /// Chave privada PKCS#8 (PEM) correspondente a --tls-cert
+    #[arg(long, requires_all = ["tls", "tls_cert"])]
+    tls_key: Option<PathBuf>,
+
     /// Ativa anonimato via Tor (requer Tor rodando em 127.0.0.1:9050)
-    #[arg(long, default_value_t = false)]
+    #[arg(long, alias = "stealth", default_value_t = false)]
     tor: bool,
+
+    /// Porta de controle do Tor usada para publicar o onion service do /invite
+    #[arg(long, default_value_t = 9051, requires = "tor")]
+    tor_control_port: u16,
+
+    /// Senha da porta de controle do Tor (HashedControlPassword)
+    #[arg(long, requires = "tor")]
+    tor_password: Option<String>,
+
+    /// Arquivo de cookie da porta de controle (padrão: informado pelo Tor)
+    #[arg(long, requires = "tor")]
+    tor_cookie: Option<PathBuf>,
+
+    /// Desbloqueia a identidade persistente (padrão: identidade efêmera por sessão)
+    #[arg(long, default_value_t = false)]
+    identity: bool,
+
+    /// Arquivo do keystore (padrão: $XDG_DATA_HOME/sae/identity.json)
+    #[arg(long)]
+    identity_file: Option<PathBuf>,
+
+    /// Cria uma identidade persistente cifrada com senha e a usa nesta sessão
+    #[arg(long, default_value_t = false, conflicts_with_all = ["rotate_identity", "export_identity"])]
+    create_identity: bool,
+
+    /// Substitui a identidade persistente por uma nova (os pares precisarão reverificar)
+    #[arg(long, default_value_t = false, conflicts_with = "export_identity")]
+    rotate_identity: bool,
+
+    /// Exibe a chave pública e o fingerprint da identidade persistente e sai
+    #[arg(long, default_value_t = false)]
+    export_identity: bool,
+
+    /// Grava também o QR code de cada convite gerado com /invite neste arquivo PNG
+    #[arg(long, value_name = "ARQUIVO")]
+    qr_png: Option<PathBuf>,
+
+    /// Diretório padrão dos arquivos aceitos com /accept (padrão: pasta de downloads)
+    #[arg(long, value_name = "DIRETÓRIO")]
+    download_dir: Option<PathBuf>,
+
+    /// Arquivo de configuração (padrão: $XDG_CONFIG_HOME/sae/config.json)
+    #[arg(long, value_name = "ARQUIVO")]
+    config: Option<PathBuf>,
+
+    /// Endereço local do listener do host, IPv4 ou IPv6 (padrão: 0.0.0.0; com --tor, 127.0.0.1)
+    #[arg(long, value_name = "IP")]
+    bind: Option<IpAddr>,
+
+    /// Porta do listener do host; 0 escolhe uma porta livre (padrão: 9001)
+    #[arg(long, value_name = "PORTA")]
+    port: Option<u16>,
+
+    /// Host ou IP publicado nos convites (padrão: endereço da interface principal)
+    #[arg(long, value_name = "HOST", conflicts_with = "tor")]
+    advertise: Option<String>,
+
+    /// Intervalo entre pings de keepalive, em segundos
+    #[arg(long, value_name = "SEGUNDOS", default_value_t = 15, value_parser = clap::value_parser!(u64).range(1..))]
+    keepalive: u64,
+
+    /// Silêncio do par, em segundos, após o qual a conexão é dada como perdida
+    #[arg(long, value_name = "SEGUNDOS", default_value_t = 60, value_parser = clap::value_parser!(u64).range(1..))]
+    idle_timeout: u64,
+
+    /// Prazo, em segundos, para concluir o handshake de uma conexão nova
+    #[arg(long, value_name = "SEGUNDOS", default_value_t = 15, value_parser = clap::value_parser!(u64).range(1..))]
+    handshake_timeout: u64,
+
+    /// Troca de chaves pós-quântica: off, prefer (híbrida quando o par suporta) ou require
+    #[arg(long, value_name = "POLÍTICA", default_value = "prefer")]
+    pq: PqPolicy,
+
+    /// Sem TUI: lê mensagens da entrada padrão e escreve as recebidas em JSON no stdout
+    #[arg(long, default_value_t = false)]
+    no_tui: bool,
+}
+
+/// Subcomandos; sem nenhum, a TUI abre sem ação inicial.
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Inicia o host e já gera um convite
+    Host {
+        /// Contato esperado em quem usar o convite
+        petname: Option<String>,
+    },
+    /// Abre a TUI no modo cliente, à espera de /connect
+    Client,
+    /// Conecta imediatamente a um convite sae://
+    Connect {
+        uri: String,
+        /// Contato esperado do outro lado
+        petname: Option<String>,
+    },
+    /// Cria a identidade persistente cifrada com senha e sai
+    Keygen {
+        /// Substitui a identidade existente por uma nova
+        #[arg(long)]
+        rotate: bool,
+    },
+    /// Exibe a chave pública e o fingerprint da identidade persistente e sai
+    Fingerprint,
+    /// Confere assinatura, versão e validade de um convite sem conectar
+    VerifyInvite {
+        uri: String,
+    },
+    /// Roda em segundo plano, controlado por JSON-RPC num socket Unix
+    #[cfg(unix)]
+    Daemon {
+        /// Socket de controle (padrão: $XDG_RUNTIME_DIR/sae/sae.sock)
+        #[arg(long, value_name = "ARQUIVO")]
+        socket: Option<PathBuf>,
+        /// Nome de usuário nas mensagens enviadas
+        #[arg(long, default_value = "Phantom")]
+        name: String,
+    },
+}
+
+impl Args {
+    fn persistent_identity(&self) -> bool {
+        self.identity || self.identity_file.is_some() || self.create_identity
+            || self.rotate_identity || self.export_identity
+    }
+
+    fn keepalive(&self) -> KeepaliveConfig {
+        KeepaliveConfig {
+            ping_interval: Duration::from_secs(self.keepalive),
+            idle_timeout: Duration::from_secs(self.idle_timeout),
+            handshake_timeout: Duration::from_secs(self.handshake_timeout),
+        }
+    }
+}
+
+/// Configuração de `--config` ou do diretório de configuração do usuário.
+fn load_config(args: &Args) -> Result<Config> {
+    match args.config.clone().or_else(config::default_path) {
+        Some(path) => Ok(Config::load(&path)?),
+        None => Ok(Config::default()),
+    }
+}
+
+/// Caminho do keystore: `--identity-file` ou o diretório de dados do usuário.
+fn identity_path(args: &Args) -> Result<PathBuf> {
+    match &args.identity_file {
+        Some(path) => Ok(path.clone()),
+        None => keystore::default_path()
+            .ok_or_else(|| eyre!("Não foi possível determinar o diretório de dados (use --identity-file)")),
+    }
+}
+
+/// Lê a senha do keystore de `SAE_PASSPHRASE` ou do terminal.
+fn read_passphrase(prompt: &str) -> Result<String> {
+    if let Ok(passphrase) = std::env::var("SAE_PASSPHRASE") {
+        return Ok(passphrase);
+    }
+    Ok(rpassword::prompt_password(prompt)?)
+}
+
+/// Resolve a identidade Ed25519 da sessão antes de a TUI assumir o terminal.
+///
+/// Sem flags de identidade o comportamento é o original: uma identidade
+/// efêmera, descartada ao sair.
+fn resolve_identity(args: &Args) -> Result<Identity> {
+    if !args.persistent_identity() {
+        return Ok(Identity::generate());
+    }
+
+    let path = identity_path(args)?;
+
+    if args.create_identity {
+        return create_identity(&path);
+    }
+    if args.rotate_identity {
+        return rotate_identity(&path);
+    }
+
+    let passphrase = read_passphrase(&format!("Senha da identidade ({}): ", path.display()))?;
+
+    let identity = Identity::load(&path, &passphrase)?;
+    eprintln!("✓ Identidade desbloqueada: {}", identity.fingerprint());
+    Ok(identity)
+}
+
+/// Cria o keystore em `path` com uma senha nova, confirmada no terminal.
+fn create_identity(path: &Path) -> Result<Identity> {
+    let passphrase = read_passphrase("Nova senha da identidade: ")?;
+    if std::env::var("SAE_PASSPHRASE").is_err() && read_passphrase("Confirme a senha: ")? != passphrase {
+        return Err(eyre!("As senhas não conferem"));
+    }
+    if passphrase.is_empty() {
+        return Err(eyre!("A senha da identidade não pode ser vazia"));
+    }
+    let identity = keystore::create(path, &passphrase, keystore::KdfParams::default())?;
+    eprintln!("✓ Identidade criada em {}", path.display());
+    eprintln!("  Fingerprint: {}", identity.fingerprint());
+    Ok(identity)
+}
+
+fn rotate_identity(path: &Path) -> Result<Identity> {
+    let passphrase = read_passphrase(&format!("Senha da identidade ({}): ", path.display()))?;
+    let identity = keystore::rotate(path, &passphrase)?;
+    eprintln!("✓ Identidade substituída em {}", path.display());
+    eprintln!("  Novo fingerprint: {}", identity.fingerprint());
+    Ok(identity)
+}
+
+/// `sae fingerprint` (ou `--export-identity`): chave pública do keystore, sem pedir senha.
+fn print_fingerprint(args: &Args) -> Result<()> {
+    let public_key = keystore::export_public_key(&identity_path(args)?)?;
+    println!("ed25519:{}", hex::encode(public_key.as_bytes()));
+    println!("fingerprint:{}", identity::get_fingerprint(&public_key));
+    Ok(())
+}
+
+/// `sae verify-invite`: valida o convite e mostra o que ele anuncia.
+fn verify_invite(uri: &str) -> Result<()> {
+    let invite = Invite::parse(uri).map_err(|e| eyre!("Convite inválido: {}", e))?;
+    println!("✓ Convite válido (assinatura e validade conferidas)");
+    println!("host:{}", invite.host);
+    println!("porta:{}", invite.port);
+    println!("transporte:{}", invite.transport.as_str());
+    println!("fingerprint:{}", key_fingerprint(&invite.identity_key));
+    println!("handshake:{}", if invite.noise_key.is_some() { "IK" } else { "XX" });
+    println!("kem:{}", if invite.post_quantum { "x25519+mlkem768" } else { "x25519" });
+    if let Some(pin) = &invite.tls_pin {
+        println!("tls:{}", pin);
+    }
+    println!("expira_em:{}s", invite.expires_in().as_secs());
+    Ok(())
+}
+
+/// Host de um convite escolhido com `/invite @<escolha>`: o número de um
+/// endereço de `/invite -l` ou um host/IP qualquer.
+fn resolve_invite_host(choice: &str) -> Result<String> {
+    if let Ok(index) = choice.parse::<usize>() {
+        let addresses = interfaces::local_addresses()?;
+        return index.checked_sub(1)
+            .and_then(|index| addresses.get(index))
+            .map(|address| address.ip.to_string())
+            .ok_or_else(|| eyre!("Endereço {} não existe (veja /invite -l)", index));
+    }
+    Ok(config::normalize_host(choice)?)
+}
+
+/// Entrega mensagens de sala pelo canal par a par de cada membro.
+async fn send_room(
+    outgoing: Vec<Outgoing>,
+    app: &mut App,
+    channels: &mut HashMap<SessionId, SecureChannel>,
+    network: &NetworkManager,
+) {
+    for Outgoing { to, message } in outgoing {
+        let session = app.conversations.iter()
+            .find(|(_, conversation)| conversation.remote_identity == to && conversation.pending_key_change.is_none())
+            .map(|(session, _)| *session);
+        let Some((session, channel)) = session.and_then(|session| channels.get_mut(&session).map(|c| (session, c))) else {
+            app.add_message(
+                format!("⚠️ Mensagem de sala não entregue: sem conexão confiável com {}", key_fingerprint(&to)),
+                "Sistema".into()
+            );
+            continue;
+        };
+        if let Err(e) = send_wire(channel, network, session, Message::Room(message)).await {
+            app.add_message(format!("❌ {}", e), "Sistema".into());
+        }
+    }
+}
+
+/// Troca de chaves usada na sessão, para as mensagens de conexão.
+fn key_exchange_label(post_quantum: bool) -> &'static str {
+    if post_quantum { "X25519 + ML-KEM-768" } else { "só X25519, sem proteção pós-quântica" }
+}
+
+/// Nome de um membro de sala: conversa aberta, contato salvo ou fingerprint.
+fn member_label(app: &App, contacts: &SharedContacts, key: &[u8; 32]) -> String {
+    if let Some(conversation) = app.conversations.values().find(|c| c.remote_identity == *key) {
+        return conversation.label.clone();
+    }
+    if let Some(contact) = contacts.lock().unwrap().find_by_key(key) {
+        return contact.petname.clone();
+    }
+    key_fingerprint(key)[..8].to_string()
+}
+
+/// Mostra o resultado de uma operação de sala.
+fn show_room_event(app: &mut App, rooms: &RoomManager, contacts: &SharedContacts, event: RoomEvent) {
+    match event {
+        RoomEvent::Invited { room_id, name } => {
+            app.add_message(
+                format!("📨 Convite para a sala {}#{} - use /room join {}", name, room::short_id(&room_id), room::short_id(&room_id)),
+                "Sistema".into()
+            );
+        }
+        RoomEvent::Joined { room_id } => {
+            if let Some(room) = rooms.get(&room_id) {
+                app.open_room(room_id, room.label());
+                app.add_room_message(room_id, format!("✓ Você entrou na sala {}", room.label()), "Sistema".into());
+                app.status_message = format!("Entrou na sala {} - /room open {}", room.label(), room::short_id(&room_id));
+            }
+        }
+        RoomEvent::MembersChanged { room_id, added, removed } => {
+            for key in added.iter().filter(|key| **key != rooms.local_key()) {
+                let label = member_label(app, contacts, key);
+                app.add_room_message(room_id, format!("➕ {} entrou", label), "Sistema".into());
+            }
+            for key in &removed {
+                let label = member_label(app, contacts, key);
+                app.add_room_message(room_id, format!("➖ {} saiu (chaves renovadas)", label), "Sistema".into());
+            }
+        }
+        RoomEvent::Removed { room_id } => {
+            if let Some(room) = app.close_room(room_id) {
+                app.add_message(format!("Você foi removido da sala {}", room.label), "Sistema".into());
+            }
+        }
+        RoomEvent::Closed { room_id } => {
+            if let Some(room) = app.close_room(room_id) {
+                app.add_message(format!("A sala {} foi encerrada pelo criador", room.label), "Sistema".into());
+            }
+        }
+        RoomEvent::Message { room_id, sender, text } => {
+            let label = member_label(app, contacts, &sender);
+            app.add_room_message(room_id, text, label);
+        }
+    }
 }
 
 #[tokio::main]
@@ -44,28 +391,81 @@ async fn main() -> Result<()> {
 
     let args = Args::parse();
 
-    // Verifica disponibilidade do Tor se solicitado
-    if args.tor {
-        let tor_config = tor::TorConfig::default();
+    // Subcomandos de linha de comando: respondem e saem sem abrir a TUI
+    match &args.command {
+        Some(Command::Keygen { rotate: false }) => return create_identity(&identity_path(&args)?).map(|_| ()),
+        Some(Command::Keygen { rotate: true }) => return rotate_identity(&identity_path(&args)?).map(|_| ()),
+        Some(Command::Fingerprint) => return print_fingerprint(&args),
+        Some(Command::VerifyInvite { uri }) => return verify_invite(uri),
+        _ if args.export_identity => return print_fingerprint(&args),
+        _ => {}
+    }
+    let identity = resolve_identity(&args)?;
+    let listen = load_config(&args)?.network.resolve(args.bind, args.port, args.advertise.clone(), args.tor)?;
+    // Com identidade persistente os contatos ficam ao lado do keystore;
+    // no modo efêmero valem só para esta sessão.
+    let contacts = if args.persistent_identity() {
+        let path = identity_path(&args)?.with_file_name("contacts.json");
+        ContactStore::load(&path)?
+    } else {
+        ContactStore::in_memory()
+    }.shared();
+
+    // Verifica disponibilidade do Tor se solicitado. Sem Tor acessível o SAE
+    // não inicia: cair silenciosamente para a clearnet exporia o IP do usuário.
+    let tor_config = if args.tor {
+        let tor_config = tor::TorConfig {
+            control_port: args.tor_control_port,
+            control_password: args.tor_password.clone(),
+            cookie_file: args.tor_cookie.clone(),
+            ..tor::TorConfig::default()
+        };
         let tor_status = tor::get_tor_status(&tor_config).await;
 
         if !tor_status.is_available() {
-            if let Some(msg) = tor_status.message() {
-                eprintln!("⚠️  Tor não está disponível:\n{}", msg);
-                eprintln!("\nIniciando sem Tor...\n");
-            }
-        } else {
-            eprintln!("✓ Tor SOCKS5 disponível em {}", tor_config.proxy_addr());
+            return Err(eyre!("Tor não está disponível:\n{}", tor_status.message().unwrap_or_default()));
         }
+        eprintln!("✓ Tor SOCKS5 disponível em {}", tor_config.proxy_addr());
+        Some(tor_config)
+    } else {
+        None
+    };
+
+    let tls_identity = if args.tls {
+        let identity = match (&args.tls_cert, &args.tls_key) {
+            (Some(cert), Some(key)) => tls::TlsIdentity::from_pem_files(cert, key)?,
+            _ => tls::TlsIdentity::generate_self_signed()?,
+        };
+        Some(identity)
+    } else {
+        None
+    };
+
+    #[cfg(unix)]
+    if let Some(Command::Daemon { socket, name }) = args.command.clone() {
+        let socket = socket.or_else(daemon::default_socket_path)
+            .ok_or_else(|| eyre!("Não foi possível determinar o caminho do socket (use --socket)"))?;
+        let (network_sender, network_receiver) = mpsc::unbounded_channel::<NetworkEvent>();
+        let network = NetworkManager::new(network_sender, identity, contacts.clone(), tls_identity, tor_config.clone(), args.keepalive(), args.pq);
+        eprintln!("✓ Identidade Ed25519: {}", network.local_fingerprint());
+        eprintln!("✓ Socket de controle: {}", socket.display());
+        let daemon = daemon::Daemon::new(network, network_receiver, contacts, listen, tor_config, name);
+        return Ok(daemon.serve(&socket).await?);
     }
 
     let mut app = App::new();
-    let mut tui = TuiManager::new()?;
-    tui.init()?;
-
-    let mut events = EventHandler::new(Duration::from_millis(100));
+    // Com --no-tui a entrada padrão substitui o teclado e o stdout, a tela
+    let (mut tui, mut pipe, mut events) = if args.no_tui {
+        let events = EventHandler::headless(Duration::from_millis(100));
+        pipe::spawn_stdin_reader(events.sender());
+        (None, Some(Pipe::new()), events)
+    } else {
+        let mut tui = TuiManager::new()?;
+        tui.init()?;
+        (Some(tui), None, EventHandler::new(Duration::from_millis(100)))
+    };
     let (network_sender, mut network_receiver) = mpsc::unbounded_channel::<NetworkEvent>();
-    let mut network = NetworkManager::new(network_sender, args.tls);
+    let mut network = NetworkManager::new(network_sender, identity, contacts.clone(), tls_identity, tor_config.clone(), args.keepalive(), args.pq);
 
     // Exibe fingerprint local da identidade
     let local_id_fingerprint = network.local_fingerprint();
@@ -76,6 +476,9 @@ async fn main() -> Result<()> {
 
     if args.tls {
         app.add_message("🔒 TLS/WSS: ATIVADO".to_string(), "Sistema".into());
+        if let Some(tls_fp) = network.tls_fingerprint() {
+            app.add_message(format!("🔒 Certificado TLS (SHA256): {}", tls_fp), "Sistema".into());
+        }
     }
     if args.tor {
         app.add_message("🧅 Modo Tor: ATIVADO".to_string(), "Sistema".into());
@@ -88,208 +491,742 @@ async fn main() -> Result<()> {
         }
     });
 
-    let mut ratchet_session: Option<RatchetSession> = None;
-    let mut secret_key: Option<EphemeralSecret> = None;
+    // Salas em grupo sobre as conversas abertas
+    let mut rooms = RoomManager::new(network.identity());
+    // Transferências de arquivo e diretório padrão para as aceitas
+    let mut transfers = TransferManager::new();
+    let download_dir = args.download_dir.clone()
+        .or_else(dirs::download_dir)
+        .unwrap_or_else(|| PathBuf::from("."));
+    // Canal cifrado (ratchet e versão do protocolo) de cada conversa aberta
+    let mut channels: HashMap<SessionId, SecureChannel> = HashMap::new();
+    // Mensagens de chat sem ACK, reenviadas quando o par reconecta
+    let mut outbox = Outbox::new();
+    // Listener do host (e onion service com --tor), iniciado no primeiro /invite
+    // e mantido para os seguintes
+    let mut host_listener: Option<HostListener> = None;
+
+    // `sae host` e `sae connect` começam pela ação equivalente a /invite e /connect
+    let startup = match args.command.clone() {
+        Some(Command::Host { petname }) => {
+            app.mode = AppMode::Host;
+            Some(Action::GenerateInvite(petname, None))
+        }
+        Some(Command::Connect { uri, petname }) => {
+            app.mode = AppMode::Client;
+            Some(Action::ConnectTo(uri, petname))
+        }
+        Some(Command::Client) => {
+            app.mode = AppMode::Client;
+            app.status_message = "Use /connect <sae://uri> [apelido]".to_string();
+            None
+        }
+        _ => None,
+    };
+    if let Some(action) = startup {
+        events.sender().send(Event::Action(action))?;
+    }
 
     while !app.should_quit {
-        tui.draw(&mut app)?;
+        // Confirma a leitura do que ficou visível na conversa em foco
+        for (session, id) in app.take_read_receipts() {
+            if let Some(channel) = channels.get_mut(&session) {
+                let _ = send_wire(channel, &network, session, Message::Receipt { id, receipt: Receipt::Read }).await;
+            }
+        }
 
-        match events.next().await? {
-            Event::Key(key) if key.code == crossterm::event::KeyCode::Enter => {
+        if let Some(tui) = tui.as_mut() {
+            tui.draw(&mut app)?;
+        }
+        if let Some(pipe) = pipe.as_mut() {
+            pipe.flush(&mut app, &contacts)?;
+            // Linhas da entrada padrão passam pelo mesmo caminho do que é digitado na TUI
+            while let Some(line) = pipe.next_line(&app) {
+                app.input = line;
                 if let Some(action) = app.handle_input()? {
-                    match action {
-                        Action::GenerateInvite => {
-                            let (secret, public) = generate_keypair();
-                            let pubkey_bytes = public.to_bytes();
-                            let fingerprint = crypton::get_fingerprint(&public);
+                    events.sender().send(Event::Action(action))?;
+                }
+            }
+            if pipe.finished(outbox.is_empty()) {
+                app.should_quit = true;
+                continue;
+            }
+        }
+
+        // Teclas viram ações da UI; subcomandos da linha de comando chegam já como ações
+        let event = match events.next().await? {
+            Event::Key(key) => app.handle_key(key)?.map(Event::Action),
+            Event::Input(line) => {
+                if let Some(pipe) = pipe.as_mut() {
+                    pipe.push_line(line);
+                }
+                None
+            }
+            Event::InputClosed => {
+                if let Some(pipe) = pipe.as_mut() {
+                    pipe.close_input();
+                }
+                None
+            }
+            event => Some(event),
+        };
+        match event {
+            Some(Event::Action(action)) => {
+                match action {
+                    Action::GenerateInvite(petname, chosen_host) => {
+                        if host_listener.is_none() {
+                            let listener = match HostListener::start(&mut network, &listen, tor_config.as_ref()).await {
+                                Ok(listener) => listener,
+                                Err(e) => {
+                                    app.add_message(format!("❌ {}", e), "Sistema".into());
+                                    if matches!(e, HostError::Onion(_)) {
+                                        app.status_message = "Convite não gerado: onion service indisponível".to_string();
+                                    }
+                                    continue;
+                                }
+                            };
+                            let fingerprint = listener.fingerprint();
                             app.local_fingerprint = Some(fingerprint.clone());
                             app.add_message(
                                 format!("🔑 Fingerprint X25519: {}", fingerprint),
                                 "Sistema".into()
                             );
+                            if listener.is_onion() {
+                                app.add_message(format!("🧅 Onion service publicado: {}", listener.invite_host()), "Sistema".into());
+                            } else if listen.advertise.is_none() {
+                                app.add_message(
+                                    format!("🌐 Anunciando {} - veja outros endereços com /invite -l", listener.invite_host()),
+                                    "Sistema".into()
+                                );
+                            }
+                            host_listener = Some(listener);
+                        }
+                        let Some(listener) = &host_listener else {
+                            continue;
+                        };
+
+                        // O host escolhido vale só para este convite; com --tor o convite recusa
+                        let chosen_host = match chosen_host {
+                            Some(choice) if !listener.is_onion() => match resolve_invite_host(&choice) {
+                                Ok(host) => Some(host),
+                                Err(e) => {
+                                    app.add_message(format!("❌ {}", e), "Sistema".into());
+                                    continue;
+                                }
+                            },
+                            chosen_host => chosen_host,
+                        };
+                        let invite_uri = match listener.create_invite(&network, chosen_host, petname) {
+                            Ok(invite) => invite.to_uri(),
+                            Err(e) => {
+                                app.add_message(format!("❌ {}", e), "Sistema".into());
+                                continue;
+                            }
+                        };
+                        app.add_message(format!("📨 Convite gerado: {}", invite_uri), "Sistema".into());
+                        app.add_message(
+                            "⏳ Válido por 15 minutos e para uma única conexão".to_string(),
+                            "Sistema".into()
+                        );
+                        match InviteQr::new(&invite_uri) {
+                            Ok(qr) => {
+                                if let Some(path) = &args.qr_png {
+                                    match qr.write_png(path) {
+                                        Ok(()) => app.add_message(format!("🖼️ QR code salvo em {}", path.display()), "Sistema".into()),
+                                        Err(e) => app.add_message(format!("❌ {}", e), "Sistema".into()),
+                                    }
+                                }
+                                app.invite_qr = Some(qr);
+                            }
+                            Err(e) => app.add_message(format!("❌ {}", e), "Sistema".into()),
+                        }
+                        app.status_message = "Aguardando conexão...".to_string();
+                    }
+                    Action::ListAddresses => {
+                        match interfaces::local_addresses() {
+                            Ok(addresses) => {
+                                app.add_message("🌐 Endereços locais para convites:".to_string(), "Sistema".into());
+                                for (index, address) in addresses.iter().enumerate() {
+                                    app.add_message(format!("  {}) {} {}", index + 1, address.name, address.ip), "Sistema".into());
+                                }
+                                app.add_message("Use /invite @<número|endereço> [apelido]".to_string(), "Sistema".into());
+                            }
+                            Err(e) => app.add_message(format!("❌ Não foi possível listar as interfaces: {}", e), "Sistema".into()),
+                        }
+                    }
+                    Action::ConnectTo(uri, petname) => {
+                        let invite = match Invite::parse(&uri) {
+                            Ok(invite) => invite,
+                            Err(e) => {
+                                app.add_message(format!("❌ Convite rejeitado: {}", e), "Sistema".into());
+                                continue;
+                            }
+                        };
 
-                            secret_key = Some(secret);
-                            let addr: SocketAddr = "0.0.0.0:9001".parse().unwrap();
-                            let invite_uri = format!("sae://{}:{}?pubkey={}", "127.0.0.1", addr.port(), hex::encode(pubkey_bytes));
-                            app.add_message(format!("📨 Convite gerado: {}", invite_uri), "Sistema".into());
-                            app.status_message = "Aguardando conexão...".to_string();
+                        let (secret, public) = generate_keypair();
+                        app.local_fingerprint = Some(crypton::get_fingerprint(&public));
+                        app.add_message(
+                            format!("🔑 Seu fingerprint X25519: {}", app.local_fingerprint.as_ref().unwrap()),
+                            "Sistema".into()
+                        );
+                        app.add_message(
+                            format!("🪪 Identidade do host no convite: {}", key_fingerprint(&invite.identity_key)),
+                            "Sistema".into()
+                        );
 
-                            if let Err(e) = network.start_host(addr, pubkey_bytes).await {
-                                app.add_message(format!("❌ Erro ao iniciar host: {}", e), "Sistema".into());
+                        if let Err(e) = network.connect_to_host(&uri, secret, petname).await {
+                            app.add_message(format!("❌ Erro de conexão: {}", e), "Sistema".into());
+                        }
+                    }
+                    Action::SendMessage(session, msg) => {
+                        let Some(remote_identity) = app.conversations.get(&session).map(|c| c.remote_identity) else {
+                            continue;
+                        };
+                        // Fica na fila até o ACK do par, mesmo se o envio falhar agora
+                        let id: MessageId = rand::random();
+                        app.add_outgoing_message(session, id, msg.clone());
+                        outbox.push(remote_identity, id, app.username.clone(), msg.clone());
+                        if let Some(channel) = channels.get_mut(&session) {
+                            let chat_msg = Message::Chat { id, sender: app.username.clone(), content: msg };
+                            match send_wire(channel, &network, session, chat_msg).await {
+                                Ok(()) => app.set_delivery_status(id, DeliveryStatus::Sent),
+                                Err(e) => app.status_message = e.to_string(),
                             }
                         }
-                        Action::ConnectTo(uri) => {
-                            if let Ok(parsed_uri) = url::Url::parse(&uri) {
-                                let their_pubkey_hex = parsed_uri.query_pairs()
-                                    .find_map(|(key, value)| if key == "pubkey" { Some(value) } else { None })
-                                    .ok_or("Chave pública não encontrada na URI");
-
-                                if let Ok(hex) = their_pubkey_hex {
-                                    match hex::decode(hex.as_ref()) {
-                                        Ok(their_pubkey_bytes) => {
-                                            if their_pubkey_bytes.len() == 32 {
-                                                let their_public_key = PublicKey::from(
-                                                    <[u8; 32]>::try_from(their_pubkey_bytes).unwrap()
-                                                );
-
-                                                let (secret, public) = generate_keypair();
-                                                app.local_fingerprint = Some(crypton::get_fingerprint(&public));
-                                                app.remote_fingerprint = Some(crypton::get_fingerprint(&their_public_key));
-
-                                                app.add_message(
-                                                    format!("🔑 Seu fingerprint X25519: {}", app.local_fingerprint.as_ref().unwrap()),
-                                                    "Sistema".into()
-                                                );
-                                                app.add_message(
-                                                    format!("🔑 Fingerprint do par X25519: {}", app.remote_fingerprint.as_ref().unwrap()),
-                                                    "Sistema".into()
-                                                );
-
-                                                secret_key = Some(secret);
-                                                // Deixa para criar o ratchet_session quando peer conectar
-                                                // (não podemos consumir secret aqui)
-
-                                                if let Err(e) = network.connect_to_host(&uri, public.to_bytes()).await {
-                                                    app.add_message(format!("❌ Erro de conexão: {}", e), "Sistema".into());
-                                                }
-                                            } else {
-                                                app.add_message("❌ Chave pública inválida (tamanho incorreto)".to_string(), "Sistema".into());
-                                            }
-                                        }
-                                        Err(_) => {
-                                            app.add_message("❌ Erro ao decodificar chave pública".to_string(), "Sistema".into());
-                                        }
+                    }
+                    Action::SendRoomMessage(room_id, msg) => {
+                        match rooms.encrypt(&room_id, &msg) {
+                            Ok(outgoing) => {
+                                send_room(outgoing, &mut app, &mut channels, &network).await;
+                                app.add_room_message(room_id, msg, "Você".to_string());
+                            }
+                            Err(e) => app.status_message = format!("❌ {}", e),
+                        }
+                    }
+                    Action::Room(command) => {
+                        let member = |session: SessionId| app.conversations.get(&session).map(|c| c.remote_identity);
+                        let result = match command {
+                            RoomCommand::Create(name) => {
+                                let room_id = rooms.create(&name);
+                                let label = rooms.get(&room_id).map(|room| room.label()).unwrap_or_default();
+                                app.open_room(room_id, label.clone());
+                                app.switch_to_room(room_id);
+                                app.add_room_message(room_id, format!("🏠 Sala {} criada - convide com /room invite {} <par>", label, name), "Sistema".into());
+                                Ok(RoomOutput::default())
+                            }
+                            RoomCommand::Invite(room, session) => match member(session) {
+                                Some(invitee) => rooms.find(&room)
+                                    .and_then(|room_id| rooms.invite(&room_id, invitee))
+                                    .map(|outgoing| RoomOutput { outgoing: vec![outgoing], event: None }),
+                                None => Err(RoomError::NotMember),
+                            },
+                            RoomCommand::Join(room) => rooms.find(&room)
+                                .and_then(|room_id| rooms.join(&room_id))
+                                .map(|outgoing| {
+                                    app.status_message = "Aguardando o criador da sala...".to_string();
+                                    RoomOutput { outgoing: vec![outgoing], event: None }
+                                }),
+                            RoomCommand::Leave(room) => rooms.find(&room).and_then(|room_id| {
+                                let outgoing = rooms.leave(&room_id)?;
+                                if let Some(room) = app.close_room(room_id) {
+                                    app.add_message(format!("Você saiu da sala {}", room.label), "Sistema".into());
+                                }
+                                Ok(RoomOutput { outgoing, event: None })
+                            }),
+                            RoomCommand::Kick(room, session) => match member(session) {
+                                Some(key) => rooms.find(&room).and_then(|room_id| rooms.kick(&room_id, &key)),
+                                None => Err(RoomError::NotMember),
+                            },
+                            RoomCommand::Open(room) => rooms.find(&room).map(|room_id| {
+                                if app.rooms.contains_key(&room_id) {
+                                    app.switch_to_room(room_id);
+                                } else {
+                                    app.status_message = format!("Convite pendente: use /room join {}", room);
+                                }
+                                RoomOutput::default()
+                            }),
+                            RoomCommand::List => {
+                                let list = rooms.list();
+                                if list.is_empty() {
+                                    app.add_message("Nenhuma sala".to_string(), "Sistema".into());
+                                }
+                                for (room_id, label, pending) in list {
+                                    let members = rooms.get(&room_id).map_or(0, |room| room.members.members.len());
+                                    let line = if pending {
+                                        format!("{} (convite pendente)", label)
+                                    } else {
+                                        format!("{} {} ({} membros)", if app.active_room == Some(room_id) { "▶" } else { " " }, label, members)
+                                    };
+                                    app.add_message(line, "Salas".into());
+                                }
+                                Ok(RoomOutput::default())
+                            }
+                        };
+                        match result {
+                            Ok(output) => {
+                                send_room(output.outgoing, &mut app, &mut channels, &network).await;
+                                if let Some(event) = output.event {
+                                    show_room_event(&mut app, &rooms, &contacts, event);
+                                }
+                            }
+                            Err(e) => app.status_message = format!("❌ {}", e),
+                        }
+                    }
+                    Action::SendFile(session, path) => {
+                        let Some(channel) = channels.get_mut(&session) else {
+                            continue;
+                        };
+                        if app.conversations.get(&session).is_some_and(|c| c.pending_key_change.is_some()) {
+                            app.status_message = "⚠️ Envio bloqueado: a chave do par mudou".to_string();
+                            continue;
+                        }
+                        match transfers.offer(session, &path) {
+                            Ok(offer) => {
+                                let FileWire::Offer(details) = &offer else {
+                                    continue;
+                                };
+                                let line = format!(
+                                    "📎 Oferecendo {} ({}) - aguardando o par aceitar [{}]",
+                                    details.name, transfer::format_size(details.size), transfer::short_id(&details.id)
+                                );
+                                match send_wire(channel, &network, session, Message::File(offer)).await {
+                                    Ok(()) => app.add_session_message(session, line, "Sistema".into()),
+                                    Err(e) => app.status_message = e.to_string(),
+                                }
+                            }
+                            Err(e) => app.status_message = format!("❌ {}", e),
+                        }
+                    }
+                    Action::AcceptFile(id, dir) => {
+                        let dir = dir.unwrap_or_else(|| download_dir.clone());
+                        match transfers.find(&id).and_then(|id| transfers.accept(&id, &dir)) {
+                            Ok((session, accept)) => {
+                                if let Some(channel) = channels.get_mut(&session) {
+                                    if let Err(e) = send_wire(channel, &network, session, Message::File(accept)).await {
+                                        app.status_message = e.to_string();
+                                        continue;
                                     }
                                 }
+                                app.add_session_message(session, format!("📥 Recebendo em {}", dir.display()), "Sistema".into());
                             }
+                            Err(e) => app.status_message = format!("❌ {}", e),
                         }
-                        Action::SendMessage(msg) => {
-                            if let Some(session) = &mut ratchet_session {
-                                let chat_msg = ChatMessage {
-                                    sender: app.username.clone(),
-                                    content: msg.clone()
+                    }
+                    Action::RejectFile(id) => {
+                        match transfers.find(&id).and_then(|id| transfers.cancel(&id)) {
+                            Ok((session, cancel, name)) => {
+                                if let Some(channel) = channels.get_mut(&session) {
+                                    let _ = send_wire(channel, &network, session, Message::File(cancel)).await;
+                                }
+                                app.add_session_message(session, format!("✗ Transferência de {} cancelada", name), "Sistema".into());
+                            }
+                            Err(e) => app.status_message = format!("❌ {}", e),
+                        }
+                    }
+                    Action::Typing(session, active) => {
+                        if let Some(channel) = channels.get_mut(&session) {
+                            let _ = send_wire(channel, &network, session, Message::Typing { active }).await;
+                        }
+                    }
+                    Action::SetUsername(name) => {
+                        app.username = name;
+                        app.status_message = format!("Nome de usuário alterado para: {}", app.username);
+                    }
+                    Action::TrustContact(session, petname) => {
+                        let Some(remote_identity) = app.conversations.get(&session).map(|c| c.remote_identity) else {
+                            app.status_message = "Nenhum par conectado para confiar".to_string();
+                            continue;
+                        };
+                        let result = contacts.lock().unwrap().trust(&petname, &remote_identity);
+                        match result {
+                            Ok(previous) => {
+                                if let Some(conversation) = app.conversations.get_mut(&session) {
+                                    if conversation.pending_key_change.as_deref() == Some(petname.as_str()) {
+                                        conversation.pending_key_change = None;
+                                    }
+                                    conversation.label = petname.clone();
+                                }
+                                let message = match previous {
+                                    Some(previous) if previous != remote_identity => format!(
+                                        "🔑 Nova chave aceita para {} (não verificada): {}",
+                                        petname, contacts::key_fingerprint(&remote_identity)
+                                    ),
+                                    _ => format!("🔑 Contato {} salvo (não verificado)", petname),
                                 };
-                                let plaintext = serde_json::to_vec(&chat_msg).unwrap();
-
-                                // Adiciona padding para ofuscar tamanho
-                                let padded = add_padding(&plaintext);
-
-                                // Criptografa com ratchet (PFS + proteção replay)
-                                match session.encrypt(&padded) {
-                                    Ok(ratchet_msg) => {
-                                        let encrypted_bytes = ratchet_msg.to_bytes();
-                                        if network.send_message(encrypted_bytes).await.is_ok() {
-                                            app.add_message(msg, "Você".to_string());
-                                        } else {
-                                            app.status_message = "Falha ao enviar mensagem".to_string();
-                                        }
+                                app.add_session_message(session, message, "Sistema".into());
+                            }
+                            Err(e) => app.add_message(format!("❌ {}", e), "Sistema".into()),
+                        }
+                    }
+                    Action::VerifyContact(session, petname) => {
+                        let Some(conversation) = app.conversations.get(&session) else {
+                            app.status_message = "Nenhum par conectado para verificar".to_string();
+                            continue;
+                        };
+                        let remote_identity = conversation.remote_identity;
+                        let sas = conversation.sas;
+                        let key_changed = conversation.pending_key_change.as_deref() == Some(petname.as_str());
+
+                        // Verificar um contato cuja chave mudou também aceita a chave nova
+                        let result = {
+                            let mut store = contacts.lock().unwrap();
+                            let accept_key = store.get(&petname).is_none() || key_changed;
+                            if accept_key {
+                                store.trust(&petname, &remote_identity).map(|_| ())
+                            } else {
+                                Ok(())
+                            }.and_then(|_| store.verify(&petname, &remote_identity))
+                        };
+
+                        match result {
+                            Ok(()) => {
+                                let mut peer_confirmed = false;
+                                if let Some(conversation) = app.conversations.get_mut(&session) {
+                                    if key_changed {
+                                        conversation.pending_key_change = None;
                                     }
-                                    Err(_) => app.status_message = "Erro de criptografia".to_string(),
+                                    conversation.label = petname.clone();
+                                    peer_confirmed = conversation.peer_confirmed_sas;
+                                }
+                                app.add_session_message(
+                                    session,
+                                    format!("✓ {} marcado como verificado ({})", petname, contacts::key_fingerprint(&remote_identity)),
+                                    "Sistema".into()
+                                );
+
+                                // Avisa o par com uma confirmação assinada, ligada a esta sessão
+                                if let Some(channel) = channels.get_mut(&session) {
+                                    let notice = VerifiedNotice::new(&network.identity(), &sas, &remote_identity);
+                                    if let Err(e) = send_wire(channel, &network, session, Message::Verified(notice)).await {
+                                        app.add_message(format!("❌ {}", e), "Sistema".into());
+                                    }
+                                }
+                                if peer_confirmed {
+                                    app.status_message = format!("✓ Verificação mútua concluída com {}", petname);
                                 }
                             }
+                            Err(e) => app.add_message(format!("❌ {}", e), "Sistema".into()),
+                        }
+                    }
+                    Action::RejectVerification(session, petname) => {
+                        // Códigos diferentes: cada lado fez o handshake com outra pessoa
+                        if let Some(conversation) = app.conversations.get_mut(&session) {
+                            conversation.pending_key_change = Some(petname.clone());
+                        }
+                        app.add_session_message(
+                            session,
+                            "⚠️  O CÓDIGO DE SEGURANÇA NÃO CONFERE! Possível ataque MITM.".to_string(),
+                            "AVISO".into()
+                        );
+                        app.add_session_message(
+                            session,
+                            format!("Envio bloqueado. Encerre a sessão ou confirme {} por outro canal.", petname),
+                            "AVISO".into()
+                        );
+                        app.status_message = "⚠️ SAS não confere - envio bloqueado".to_string();
+                    }
+                    Action::ListContacts => {
+                        let lines: Vec<String> = contacts.lock().unwrap().list()
+                            .map(|c| format!(
+                                "{} {} {}",
+                                if c.verified { "✓" } else { "?" },
+                                c.petname,
+                                c.fingerprint()
+                            ))
+                            .collect();
+                        if lines.is_empty() {
+                            app.add_message("Nenhum contato salvo".to_string(), "Sistema".into());
                         }
-                        Action::SetUsername(name) => {
-                            app.username = name;
-                            app.status_message = format!("Nome de usuário alterado para: {}", app.username);
+                        for line in lines {
+                            app.add_message(line, "Contatos".into());
                         }
                     }
                 }
             }
-            Event::Key(key) => {
-                app.handle_key(key)?;
-            }
-            Event::Tick => {
+            Some(Event::Tick) => {
                 app.tick();
+
+                // Envia a próxima leva de blocos das transferências aceitas
+                match transfers.pump(transfer::CHUNKS_PER_TICK) {
+                    Ok(messages) => {
+                        for (session, message) in messages {
+                            if let Some(channel) = channels.get_mut(&session) {
+                                if let Err(e) = send_wire(channel, &network, session, Message::File(message)).await {
+                                    app.status_message = e.to_string();
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => app.add_message(format!("❌ {}", e), "Sistema".into()),
+                }
+                app.transfers = transfers.progress();
             }
-            Event::Network(net_event) => {
+            Some(Event::Network(net_event)) => {
                 match net_event {
-                    network_secure::NetworkEvent::PeerConnected { public_key, ed25519_key, fingerprint } => {
-                        if let Some(sk) = secret_key.take() {
-                            let their_pk = PublicKey::from(public_key);
+                    network_secure::NetworkEvent::PeerConnected { session, ed25519_key, fingerprint, petname, sas, protocol_version, post_quantum, channel, .. } => {
+                        channels.insert(session, channel.open());
+
+                        if let Some(conversation) = app.conversations.get_mut(&session) {
+                            conversation.resume(sas);
+                            app.add_session_message(session, format!("↻ Sessão retomada (protocolo v{}, {})", protocol_version, key_exchange_label(post_quantum)), "Sistema".into());
+                            if app.active_session == Some(session) {
+                                app.status_message = "Conexão restabelecida".to_string();
+                            }
+                        } else {
+                            app.open_conversation(session, Conversation::new(session, petname, ed25519_key, fingerprint.clone(), sas));
 
                             // Exibe fingerprints de ambas as identidades
-                            app.add_message(
-                                format!("✓ Par conectado!"),
+                            app.add_session_message(session, format!("✓ Par conectado! (protocolo v{}, {})", protocol_version, key_exchange_label(post_quantum)), "Sistema".into());
+                            app.add_session_message(
+                                session,
+                                format!("🔐 Identidade Ed25519 do par: {}", fingerprint),
                                 "Sistema".into()
                             );
-                            app.add_message(
-                                format!("🔐 Identidade Ed25519 do par: {}", fingerprint),
+                            app.add_session_message(
+                                session,
+                                "Compare o código de segurança com o par: /verify <apelido>".to_string(),
                                 "Sistema".into()
                             );
+                            if app.active_session == Some(session) {
+                                app.status_message = "Conexão segura e autenticada estabelecida!".to_string();
+                            }
+                        }
 
-                            let shared_secret = sk.diffie_hellman(&their_pk);
-                            ratchet_session = Some(RatchetSession::new(shared_secret.as_bytes()));
-                            app.mode = AppMode::Connected;
-                            app.status_message = "Conexão segura e autenticada estabelecida!".to_string();
-
-                            // Armazena fingerprints para verificação
-                            app.remote_fingerprint = Some(fingerprint);
+                        // Reenvia o que o par não confirmou, inclusive o que ficou
+                        // na fila enquanto a conexão estava caída
+                        let Some(channel) = channels.get_mut(&session) else {
+                            continue;
+                        };
+                        let resent = resend_unacked(channel, &network, session, &outbox, &ed25519_key).await;
+                        if !resent.is_empty() {
+                            app.add_session_message(
+                                session,
+                                format!("↻ Reenviando {} mensagem(ns) não confirmada(s)", resent.len()),
+                                "Sistema".into()
+                            );
+                        }
+                        for Resent { id, content, sent } in resent {
+                            // Mensagens de uma sessão anterior voltam a aparecer nesta conversa
+                            if !app.messages.iter().any(|msg| msg.session == Some(session) && msg.id == Some(id)) {
+                                app.add_outgoing_message(session, id, content);
+                            }
+                            if sent {
+                                app.set_delivery_status(id, DeliveryStatus::Sent);
+                            }
                         }
                     }
-                    network_secure::NetworkEvent::DataReceived(data) => {
-                        if let Some(session) = &mut ratchet_session {
-                            // Converte bytes para RatchetMessage
-                            match ratchet::RatchetMessage::from_bytes(&data) {
-                                Ok(ratchet_msg) => {
-                                    // Descriptografa com verificação de replay
-                                    match session.decrypt(&ratchet_msg) {
-                                        Ok(padded_data) => {
-                                            // Remove padding
-                                            match remove_padding(&padded_data) {
-                                                Ok(plaintext) => {
-                                                    if let Ok(msg) = serde_json::from_slice::<ChatMessage>(&plaintext) {
-                                                        app.add_message(msg.content, msg.sender);
-                                                    }
-                                                }
-                                                Err(_) => app.add_message(
-                                                    "❌ Erro ao remover padding".to_string(),
-                                                    "Sistema".into()
-                                                ),
-                                            }
+                    network_secure::NetworkEvent::DataReceived(session, data) => {
+                        let Some(channel) = channels.get_mut(&session) else {
+                            continue;
+                        };
+                        let message = match channel.open(&data) {
+                            Ok(message) => message,
+                            // Tipos novos de versões futuras são ignorados, não derrubam a sessão
+                            Err(ChannelError::Protocol(e @ ProtocolError::UnknownType(_))) => {
+                                app.add_session_message(session, format!("⚠️ Mensagem ignorada: {}", e), "Sistema".into());
+                                continue;
+                            }
+                            Err(e) => {
+                                app.add_session_message(session, format!("❌ {}", e), "Sistema".into());
+                                continue;
+                            }
+                        };
+
+                        let Some(peer) = app.conversations.get(&session).map(|c| c.remote_identity) else {
+                            continue;
+                        };
+
+                        match message {
+                            Message::Chat { id, sender, content } => {
+                                // ACK cifrado sempre, inclusive para retransmissões já vistas
+                                let _ = send_wire(channel, &network, session, Message::Ack { id }).await;
+                                if let Some(conversation) = app.conversations.get_mut(&session) {
+                                    conversation.typing = false;
+                                }
+                                if outbox.receive(peer, id) {
+                                    app.add_incoming_message(session, id, content, sender);
+                                }
+                            }
+                            Message::Ack { id } | Message::Receipt { id, receipt: Receipt::Delivered } => {
+                                outbox.ack(&peer, id);
+                                app.set_delivery_status(id, DeliveryStatus::Delivered);
+                            }
+                            Message::Receipt { id, receipt: Receipt::Read } => {
+                                outbox.ack(&peer, id);
+                                app.set_delivery_status(id, DeliveryStatus::Read);
+                            }
+                            Message::Typing { active } => {
+                                if let Some(conversation) = app.conversations.get_mut(&session) {
+                                    conversation.typing = active;
+                                }
+                            }
+                            Message::Ping { nonce } => {
+                                let _ = send_wire(channel, &network, session, Message::Pong { nonce }).await;
+                            }
+                            // A resposta abre uma cadeia de envio nova, com passo DH
+                            Message::Rekey { request: true } => {
+                                let _ = send_wire(channel, &network, session, Message::Rekey { request: false }).await;
+                            }
+                            Message::Close { reason } => {
+                                let label = app.conversations.get(&session).map(|c| c.label.clone()).unwrap_or_default();
+                                app.add_session_message(session, format!("{} encerrou a sessão: {}", label, reason), "Sistema".into());
+                                network.close_session(session).await;
+                            }
+                            Message::Rekey { request: false } | Message::Pong { .. } => {}
+                            Message::Verified(notice) => {
+                                let local_key = network.identity().public_key_bytes();
+                                let valid = app.conversations.get_mut(&session).is_some_and(|conversation| {
+                                    let valid = notice.verify(&conversation.sas, &conversation.remote_identity, &local_key);
+                                    conversation.peer_confirmed_sas |= valid;
+                                    valid
+                                });
+                                if valid {
+                                    app.add_session_message(
+                                        session,
+                                        "✓ O par confirmou que o código de segurança confere".to_string(),
+                                        "Sistema".into()
+                                    );
+                                } else {
+                                    app.add_session_message(
+                                        session,
+                                        "⚠️ Confirmação de verificação com assinatura inválida".to_string(),
+                                        "AVISO".into()
+                                    );
+                                }
+                            }
+                            Message::File(message) => {
+                                let output = match transfers.handle(session, message) {
+                                    Ok(output) => output,
+                                    Err(e) => {
+                                        app.add_session_message(session, format!("❌ {}", e), "Sistema".into());
+                                        continue;
+                                    }
+                                };
+                                if let Some(channel) = channels.get_mut(&session) {
+                                    for reply in output.replies {
+                                        if let Err(e) = send_wire(channel, &network, session, Message::File(reply)).await {
+                                            app.status_message = e.to_string();
                                         }
-                                        Err(e) => app.add_message(
-                                            format!("❌ {}", e),
-                                            "Sistema".into()
-                                        ),
                                     }
                                 }
-                                Err(_) => app.add_message(
-                                    "❌ Formato de mensagem inválido".to_string(),
-                                    "Sistema".into()
-                                ),
+                                let line = match output.event {
+                                    Some(TransferEvent::Offered { id, name, size }) => {
+                                        let short = transfer::short_id(&id);
+                                        app.status_message = format!("📎 Arquivo recebido: /accept {} ou /reject {}", short, short);
+                                        format!(
+                                            "📎 O par quer enviar {} ({}). /accept {} [diretório] para salvar em {}, /reject {} para recusar",
+                                            name, transfer::format_size(size), short, download_dir.display(), short
+                                        )
+                                    }
+                                    Some(TransferEvent::Accepted { name }) => format!("📤 Enviando {}...", name),
+                                    Some(TransferEvent::Cancelled { name }) => format!("✗ O par cancelou a transferência de {}", name),
+                                    Some(TransferEvent::Completed { name, path: Some(path), .. }) => {
+                                        format!("✓ {} recebido e conferido (SHA256): {}", name, path.display())
+                                    }
+                                    Some(TransferEvent::Completed { name, path: None, verified: true }) => {
+                                        format!("✓ {} entregue e conferido pelo par", name)
+                                    }
+                                    Some(TransferEvent::Completed { name, path: None, verified: false }) => {
+                                        format!("⚠️ {}: hash do arquivo não confere, transferência descartada", name)
+                                    }
+                                    None => continue,
+                                };
+                                app.add_session_message(session, line, "Sistema".into());
+                            }
+                            Message::Room(message) => {
+                                // O remetente imediato é o par autenticado desta sessão
+                                match rooms.handle(peer, message) {
+                                    Ok(output) => {
+                                        send_room(output.outgoing, &mut app, &mut channels, &network).await;
+                                        if let Some(event) = output.event {
+                                            show_room_event(&mut app, &rooms, &contacts, event);
+                                        }
+                                    }
+                                    Err(e) => app.add_session_message(session, format!("⚠️ Sala: {}", e), "AVISO".into()),
+                                }
                             }
                         }
                     }
-                    network_secure::NetworkEvent::PeerDisconnected => {
-                        app.mode = AppMode::Menu;
-                        app.status_message = "Par desconectado.".to_string();
-                        ratchet_session = None;
-                        app.remote_fingerprint = None;
+                    network_secure::NetworkEvent::PeerDisconnected(session) => {
+                        channels.remove(&session);
+                        for name in transfers.drop_session(session) {
+                            app.add_message(format!("✗ Transferência de {} interrompida", name), "Sistema".into());
+                        }
+                        if let Some(conversation) = app.close_conversation(session) {
+                            app.add_message(format!("Par desconectado: {}", conversation.label), "Sistema".into());
+                            app.status_message = format!("{} desconectou.", conversation.label);
+                        }
+                    }
+                    network_secure::NetworkEvent::ConnectionLost(session) => {
+                        // Sem canal, o que for enviado fica na fila até a retomada
+                        channels.remove(&session);
+                        for name in transfers.drop_session(session) {
+                            app.add_message(format!("✗ Transferência de {} interrompida", name), "Sistema".into());
+                        }
+                        if let Some(conversation) = app.conversations.get_mut(&session) {
+                            conversation.reconnecting = true;
+                            conversation.typing = false;
+                            let label = conversation.label.clone();
+                            app.add_session_message(session, "⚠️ Conexão perdida - aguardando a retomada da sessão".to_string(), "Sistema".into());
+                            app.status_message = format!("Conexão com {} perdida", label);
+                        }
+                    }
+                    network_secure::NetworkEvent::Latency { session, rtt } => {
+                        if let Some(conversation) = app.conversations.get_mut(&session) {
+                            conversation.rtt = Some(rtt);
+                            conversation.degraded = false;
+                        }
+                    }
+                    network_secure::NetworkEvent::ConnectionDegraded { session, silent_for } => {
+                        if let Some(conversation) = app.conversations.get_mut(&session) {
+                            conversation.degraded = true;
+                            app.add_session_message(
+                                session,
+                                format!("⚠️ Conexão instável: sem resposta do par há {}s", silent_for.as_secs()),
+                                "Sistema".into()
+                            );
+                        }
+                    }
+                    network_secure::NetworkEvent::Reconnecting { session, attempt, delay } => {
+                        if app.active_session == Some(session) {
+                            app.status_message = format!("Reconectando (tentativa {}) em {}s...", attempt, delay.as_secs());
+                        }
                     }
                     network_secure::NetworkEvent::ConnectionEstablished => {
                         app.status_message = "Estabelecendo handshake autenticado...".to_string();
                     }
                     network_secure::NetworkEvent::ConnectionFailed(err) => {
-                        app.mode = AppMode::Menu;
+                        if app.conversations.is_empty() {
+                            app.mode = AppMode::Menu;
+                        }
                         app.status_message = format!("❌ Falha na conexão: {}", err);
                         app.add_message(format!("❌ {}", err), "Sistema".into());
                     }
                     network_secure::NetworkEvent::Log(msg) => {
                         app.add_message(msg, "Sistema".into());
                     }
-                    network_secure::NetworkEvent::FingerprintVerificationRequired { fingerprint, ed25519_key } => {
+                    network_secure::NetworkEvent::FingerprintVerificationRequired { session, petname, fingerprint, previous_fingerprint } => {
+                        if let Some(conversation) = app.conversations.get_mut(&session) {
+                            conversation.pending_key_change = Some(petname.clone());
+                        }
+                        // Avisos gerais: aparecem mesmo com outra conversa em foco
+                        app.add_message(
+                            format!("⚠️  A CHAVE DE {} MUDOU! Possível ataque MITM.", petname.to_uppercase()),
+                            "AVISO".into()
+                        );
                         app.add_message(
-                            format!("⚠️  VERIFICAÇÃO NECESSÁRIA!"),
+                            format!("Fingerprint anterior: {}", previous_fingerprint),
                             "AVISO".into()
                         );
                         app.add_message(
-                            format!("Fingerprint Ed25519: {}", fingerprint),
+                            format!("Fingerprint apresentado: {}", fingerprint),
                             "AVISO".into()
                         );
                         app.add_message(
-                            "VERIFIQUE por um canal seguro (telefone, pessoalmente, etc)".to_string(),
+                            format!("VERIFIQUE por um canal seguro antes de usar /trust {} ou /verify {}", petname, petname),
                             "AVISO".into()
                         );
+                        app.status_message = format!("⚠️ Envio bloqueado: chave de {} mudou", petname);
                     }
                 }
             }
@@ -297,6 +1234,21 @@ async fn main() -> Result<()> {
         }
     }
 
-    tui.restore()?;
+    // Avisa os pares de que a sessão foi encerrada de propósito
+    for (session, channel) in channels.iter_mut() {
+        let _ = send_wire(channel, &network, *session, Message::Close { reason: "o par saiu".to_string() }).await;
+        network.close_session(*session).await;
+    }
+
+    if let Some(listener) = host_listener.take() {
+        listener.close().await;
+    }
+
+    if let Some(tui) = tui.as_mut() {
+        tui.restore()?;
+    }
+    if let Some(pipe) = pipe.as_mut() {
+        pipe.flush(&mut app, &contacts)?;
+    }
     Ok(())
 }
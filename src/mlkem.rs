@@ -0,0 +1,160 @@
+//! ML-KEM-768 (FIPS 203), o lado pós-quântico da troca de chaves híbrida.
+//!
+//! Camada fina sobre o crate `ml-kem` (RustCrypto), com a interface em bytes
+//! usada pelo handshake e a verificação de módulo da chave pública que o
+//! crate não faz.
+
+use ml_kem::kem::{Decapsulate, Encapsulate};
+use ml_kem::{Ciphertext, EncodedSizeUser, KemCore, MlKem768};
+use rand::rngs::OsRng;
+use zeroize::Zeroizing;
+
+type KemDecapsulationKey = <MlKem768 as KemCore>::DecapsulationKey;
+type KemEncapsulationKey = <MlKem768 as KemCore>::EncapsulationKey;
+
+const Q: u16 = 3329;
+/// t̂ codificado: 3 polinômios de 256 coeficientes de 12 bits
+const T_HAT_LEN: usize = 384 * 3;
+/// Chave de encapsulamento: t̂ codificado e a semente ρ da matriz
+pub const PUBLIC_KEY_LEN: usize = T_HAT_LEN + 32;
+pub const CIPHERTEXT_LEN: usize = 1088;
+pub const SHARED_SECRET_LEN: usize = 32;
+/// Semente d || z da qual a chave de decapsulamento é derivada
+pub const SEED_LEN: usize = 64;
+
+/// Segredo compartilhado, apagado da memória ao sair de escopo.
+pub type SharedSecret = Zeroizing<[u8; SHARED_SECRET_LEN]>;
+
+/// Chave de decapsulamento ML-KEM-768, apagada da memória ao sair de escopo.
+pub struct DecapsulationKey {
+    key: KemDecapsulationKey,
+    encapsulation_key: Vec<u8>,
+}
+
+impl DecapsulationKey {
+    /// Par de chaves novo, aleatório.
+    pub fn generate() -> Self {
+        let (key, _) = MlKem768::generate(&mut OsRng);
+        Self::wrap(key)
+    }
+
+    /// ML-KEM.KeyGen_internal a partir da semente d || z, para os vetores de teste.
+    #[cfg(test)]
+    fn from_seed(seed: &[u8; SEED_LEN]) -> Self {
+        let d: [u8; 32] = std::array::from_fn(|i| seed[i]);
+        let z: [u8; 32] = std::array::from_fn(|i| seed[32 + i]);
+        let (key, _) = MlKem768::generate_deterministic(&ml_kem::B32::from(d), &ml_kem::B32::from(z));
+        Self::wrap(key)
+    }
+
+    fn wrap(key: KemDecapsulationKey) -> Self {
+        let encapsulation_key = key.encapsulation_key().as_bytes().to_vec();
+        Self { key, encapsulation_key }
+    }
+
+    /// Chave pública, enviada ao par para que ele encapsule um segredo.
+    pub fn encapsulation_key(&self) -> &[u8] {
+        &self.encapsulation_key
+    }
+
+    /// Recupera o segredo encapsulado pelo par. Um cifrado adulterado não dá
+    /// erro: resulta num segredo pseudoaleatório (rejeição implícita), e o
+    /// handshake falha adiante sem revelar o motivo.
+    pub fn decapsulate(&self, ciphertext: &[u8]) -> Result<SharedSecret, KemError> {
+        let ciphertext = Ciphertext::<MlKem768>::try_from(ciphertext).map_err(|_| KemError::InvalidCiphertext)?;
+        let shared = self.key.decapsulate(&ciphertext).map_err(|_| KemError::InvalidCiphertext)?;
+        Ok(Zeroizing::new(shared.into()))
+    }
+}
+
+/// Encapsula um segredo novo para a chave pública do par; retorna o cifrado a
+/// enviar e o segredo compartilhado.
+pub fn encapsulate(encapsulation_key: &[u8]) -> Result<(Vec<u8>, SharedSecret), KemError> {
+    let key = parse_encapsulation_key(encapsulation_key)?;
+    let (ciphertext, shared) = key.encapsulate(&mut OsRng).map_err(|_| KemError::InvalidEncapsulationKey)?;
+    Ok((ciphertext.to_vec(), Zeroizing::new(shared.into())))
+}
+
+/// Verificação de módulo do FIPS 203: todo coeficiente de t̂ precisa estar abaixo de q.
+fn parse_encapsulation_key(bytes: &[u8]) -> Result<KemEncapsulationKey, KemError> {
+    let encoded = bytes.try_into().map_err(|_| KemError::InvalidEncapsulationKey)?;
+    let in_range = bytes[..T_HAT_LEN].chunks(3).all(|b| {
+        let (b0, b1, b2) = (b[0] as u16, b[1] as u16, b[2] as u16);
+        b0 | ((b1 & 0x0f) << 8) < Q && ((b1 >> 4) | (b2 << 4)) < Q
+    });
+    if !in_range {
+        return Err(KemError::InvalidEncapsulationKey);
+    }
+    Ok(KemEncapsulationKey::from_bytes(encoded))
+}
+
+/// Erros do ML-KEM: só entradas mal formadas; cifrados adulterados caem na
+/// rejeição implícita.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KemError {
+    InvalidEncapsulationKey,
+    InvalidCiphertext,
+}
+
+impl std::fmt::Display for KemError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KemError::InvalidEncapsulationKey => write!(f, "Chave pública ML-KEM inválida"),
+            KemError::InvalidCiphertext => write!(f, "Cifrado ML-KEM com tamanho inválido"),
+        }
+    }
+}
+
+impl std::error::Error for KemError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ml_kem::{EncapsulateDeterministic, B32};
+    use sha2::{Digest, Sha256};
+
+    fn sha256(data: &[u8]) -> String {
+        hex::encode(Sha256::digest(data))
+    }
+
+    /// Vetores conferidos com o ML-KEM-768 do OpenSSL (via `cryptography`):
+    /// chave pública derivada da semente, decapsulamento do nosso cifrado
+    /// determinístico e rejeição implícita de um cifrado adulterado.
+    #[test]
+    fn test_fips203_vectors() {
+        let seed: [u8; SEED_LEN] = std::array::from_fn(|i| i as u8);
+        let key = DecapsulationKey::from_seed(&seed);
+        assert_eq!(sha256(key.encapsulation_key()), "0b7934c83125c788995e2ba6bd761e33046b3e40571be53e023309a29f398cc9");
+
+        let encapsulation_key = parse_encapsulation_key(key.encapsulation_key()).unwrap();
+        let (ciphertext, secret) = encapsulation_key.encapsulate_deterministic(&B32::from([0x42; 32])).unwrap();
+        let (ciphertext, secret): (Vec<u8>, SharedSecret) = (ciphertext.to_vec(), Zeroizing::new(secret.into()));
+        assert_eq!(sha256(&ciphertext), "9c7b2f8d05c70575ec03ed8f93b7bb298e1506b97e54e5e885748965b1466f1c");
+        assert_eq!(hex::encode(*secret), "b83e7f23b33f909715c7a50b0d4b1f6684d53e1f4b9056f803b29f058ccb5566");
+        assert_eq!(key.decapsulate(&ciphertext).unwrap(), secret);
+
+        let mut tampered = ciphertext.clone();
+        tampered[0] ^= 1;
+        assert_eq!(
+            hex::encode(*key.decapsulate(&tampered).unwrap()),
+            "3816af13752429d4e8b800fd2c691b3254d09ed953cf287c99453d3d8057b41e"
+        );
+    }
+
+    #[test]
+    fn test_random_roundtrip_and_malformed_input() {
+        let key = DecapsulationKey::generate();
+        let (ciphertext, secret) = encapsulate(key.encapsulation_key()).unwrap();
+        assert_eq!(ciphertext.len(), CIPHERTEXT_LEN);
+        assert_eq!(key.decapsulate(&ciphertext).unwrap(), secret);
+        assert_ne!(DecapsulationKey::generate().decapsulate(&ciphertext).unwrap(), secret);
+
+        assert_eq!(key.decapsulate(&ciphertext[1..]), Err(KemError::InvalidCiphertext));
+        assert_eq!(encapsulate(&key.encapsulation_key()[1..]).unwrap_err(), KemError::InvalidEncapsulationKey);
+        // Coeficiente 0xfff ≥ q no início de t̂
+        let mut out_of_range = key.encapsulation_key().to_vec();
+        out_of_range[0] = 0xff;
+        out_of_range[1] |= 0x0f;
+        assert_eq!(encapsulate(&out_of_range).unwrap_err(), KemError::InvalidEncapsulationKey);
+    }
+}
@@ -1,326 +1,1653 @@
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::net::{TcpListener, TcpStream};
+use std::future::Future;
+use std::time::{Duration, Instant};
+use futures::future::BoxFuture;
+use tokio::net::TcpStream;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::Mutex;
-use tokio_websockets::{Message, ServerBuilder, ClientBuilder};
-use futures_util::{SinkExt, StreamExt};
-use url::Url;
-use crate::identity::{AuthenticatedHandshake, Identity};
+use tokio::time::MissedTickBehavior;
+use tokio_websockets::ClientBuilder;
+use x25519_dalek::StaticSecret;
+use crate::contacts::{key_fingerprint, ContactError, SharedContacts, TrustStatus};
+use crate::delivery::{Outbox, PeerKey};
+use crate::handshake::{HandshakeError, HandshakeOutcome, NoiseHandshake, PqPolicy};
+use crate::identity::Identity;
+use crate::invite::{Invite, InviteTokens, SharedInvites, DEFAULT_INVITE_TTL, TOKEN_LEN};
+use crate::protocol::{self, ChannelError, MessageId, SecureChannel};
+use crate::ratchet::{RatchetSession, Role};
+use crate::sas::Sas;
+use crate::tls::{self, MaybeTlsStream, TlsIdentity};
+use crate::tor::{self, TorConfig};
+use crate::transport::{self, Connection, Connector, Frame, FrameSink, FrameStream, Listener, WebSocketListener};
+
+/// Lado de envio da conexão de uma sessão, em qualquer backend.
+type BoxedSink = Box<dyn FrameSink>;
+
+/// Espera antes da primeira tentativa de reconexão do cliente
+const RECONNECT_BASE_DELAY: Duration = Duration::from_secs(1);
+/// Teto do backoff exponencial entre tentativas
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+/// Tentativas de reconexão antes de desistir da sessão
+const RECONNECT_ATTEMPTS: u32 = 10;
+/// Por quanto tempo o host aguarda a retomada de uma sessão caída
+const RESUME_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// Intervalos do keepalive WebSocket e prazos das conexões.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepaliveConfig {
+    /// Intervalo entre pings; um ping sem pong até o próximo degrada a conexão
+    pub ping_interval: Duration,
+    /// Silêncio do par a partir do qual a conexão é dada como perdida
+    pub idle_timeout: Duration,
+    /// Prazo para TLS, WebSocket e handshake Noise de uma conexão nova
+    pub handshake_timeout: Duration,
+}
+
+impl Default for KeepaliveConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval: Duration::from_secs(15),
+            idle_timeout: Duration::from_secs(60),
+            handshake_timeout: Duration::from_secs(15),
+        }
+    }
+}
+
+/// Identificador local de uma conexão autenticada, único no processo.
+pub type SessionId = u64;
 
 /// Eventos de rede enviados para o loop principal da aplicação.
 #[derive(Debug, Clone)]
 pub enum NetworkEvent {
-    DataReceived(Vec<u8>),
+    DataReceived(SessionId, Vec<u8>),
     PeerConnected {
+        session: SessionId,
+        /// Chave estática X25519 do par, autenticada pelo handshake Noise
         public_key: [u8; 32],
         ed25519_key: [u8; 32],
         fingerprint: String,
+        /// Contato associado à chave, se já conhecido
+        petname: Option<String>,
+        /// Chave raiz do Double Ratchet derivada do handshake
+        root_key: [u8; 32],
+        /// Código de verificação da sessão (emojis e número de segurança)
+        sas: Sas,
+        /// Papel local na sessão: o host responde, o cliente inicia
+        role: Role,
+        /// Versão do protocolo de mensagens negociada no handshake
+        protocol_version: u8,
+        /// Chave raiz derivada de X25519 e ML-KEM-768 (troca híbrida)
+        post_quantum: bool,
+        /// Chaves para abrir o canal cifrado da sessão com `ChannelKeys::open`
+        channel: Box<ChannelKeys>,
+    },
+    /// Sessão encerrada de vez: pelo par, localmente ou sem retomada possível
+    PeerDisconnected(SessionId),
+    /// A conexão caiu sem fechamento explícito; a sessão aguarda a retomada
+    ConnectionLost(SessionId),
+    /// Tempo de ida e volta medido pelo último ping da sessão
+    Latency {
+        session: SessionId,
+        rtt: Duration,
+    },
+    /// O par não respondeu ao último ping: a conexão pode ter caído
+    ConnectionDegraded {
+        session: SessionId,
+        silent_for: Duration,
+    },
+    /// O cliente vai tentar retomar a sessão após `delay`
+    Reconnecting {
+        session: SessionId,
+        attempt: u32,
+        delay: Duration,
     },
-    PeerDisconnected,
     ConnectionEstablished,
     ConnectionFailed(String),
     Log(String),
-    /// Solicita confirmação do fingerprint do peer antes de prosseguir
+    /// Um contato conhecido apresentou outra chave: o envio fica bloqueado
+    /// até o usuário confirmar a nova chave (`/trust` ou `/verify`)
     FingerprintVerificationRequired {
+        session: SessionId,
+        petname: String,
         fingerprint: String,
-        ed25519_key: [u8; 32],
+        previous_fingerprint: String,
     },
 }
 
+/// Chaves de uma sessão recém-autenticada, para abrir o canal cifrado com o
+/// par: a chave raiz do handshake, a chave estática local (a do host ou a da
+/// conexão de saída) e a do par. Fora do `Debug`, por serem segredos.
+#[derive(Clone)]
+pub struct ChannelKeys {
+    role: Role,
+    root_key: [u8; 32],
+    local_static: StaticSecret,
+    remote_static: [u8; 32],
+    protocol_version: u8,
+}
+
+impl ChannelKeys {
+    /// Canal na versão negociada. A chave estática do respondedor é a chave de
+    /// ratchet inicial; numa retomada o ratchet recomeça com a chave raiz nova.
+    pub fn open(&self) -> SecureChannel {
+        let ratchet = RatchetSession::new(self.role, &self.root_key, self.local_static.clone(), self.remote_static);
+        SecureChannel::new(ratchet, self.protocol_version)
+    }
+}
+
+impl std::fmt::Debug for ChannelKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChannelKeys")
+            .field("role", &self.role)
+            .field("protocol_version", &self.protocol_version)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Canais de envio das sessões abertas, indexados por `SessionId`.
+///
+/// Cada conexão que assume uma sessão recebe uma geração nova: a tarefa de
+/// uma conexão antiga, substituída numa retomada, não mexe na sessão.
+#[derive(Default)]
+struct SessionTable {
+    next_id: AtomicU64,
+    next_generation: AtomicU64,
+    /// Geração da conexão atual e seu canal de envio (`None` enquanto caída)
+    sinks: Mutex<HashMap<SessionId, (u64, Option<BoxedSink>)>>,
+    /// Sessões encerradas de vez, já informadas à aplicação
+    finished: std::sync::Mutex<HashSet<SessionId>>,
+}
+
+impl SessionTable {
+    /// Associa o canal de envio a uma sessão nova ou, em uma retomada, à existente.
+    async fn attach(&self, session: Option<SessionId>, sink: BoxedSink) -> (SessionId, u64) {
+        let session = session.unwrap_or_else(|| self.next_id.fetch_add(1, Ordering::Relaxed) + 1);
+        let generation = self.next_generation.fetch_add(1, Ordering::Relaxed);
+        self.sinks.lock().await.insert(session, (generation, Some(sink)));
+        (session, generation)
+    }
+
+    /// Solta o canal se ele ainda for o da conexão `generation`; `false` se a
+    /// sessão foi fechada localmente ou assumida por outra conexão.
+    async fn detach(&self, session: SessionId, generation: u64) -> bool {
+        match self.sinks.lock().await.get_mut(&session) {
+            Some((current, sink)) if *current == generation => sink.take().is_some(),
+            _ => false,
+        }
+    }
+
+    /// Envia um quadro de controle pela conexão `generation`, se ela ainda
+    /// for a atual; falhas aparecem no lado de leitura.
+    async fn send_control(&self, session: SessionId, generation: u64, frame: Frame) {
+        if let Some((current, Some(sink))) = self.sinks.lock().await.get_mut(&session) {
+            if *current == generation {
+                let _ = sink.send(frame).await;
+            }
+        }
+    }
+
+    async fn take(&self, session: SessionId) -> Option<BoxedSink> {
+        self.sinks.lock().await.get_mut(&session).and_then(|(_, sink)| sink.take())
+    }
+
+    async fn generation(&self, session: SessionId) -> Option<u64> {
+        self.sinks.lock().await.get(&session).map(|(generation, _)| *generation)
+    }
+
+    /// Marca a sessão como encerrada; `true` apenas na primeira vez, para que
+    /// a aplicação receba um único `PeerDisconnected`.
+    async fn finish(&self, session: SessionId) -> bool {
+        self.sinks.lock().await.remove(&session);
+        self.finished.lock().unwrap().insert(session)
+    }
+
+    fn is_finished(&self, session: SessionId) -> bool {
+        self.finished.lock().unwrap().contains(&session)
+    }
+}
+
+/// Tickets de retomada do host. Cada sessão aceita deixa um ticket, derivado
+/// do handshake, que vale uma reconexão do mesmo par (mesma identidade)
+/// enquanto a conexão está ativa ou até `RESUME_WINDOW` após a queda.
+#[derive(Default)]
+struct ResumeTickets {
+    tickets: HashMap<[u8; TOKEN_LEN], ResumeTicket>,
+}
+
+struct ResumeTicket {
+    session: SessionId,
+    identity: [u8; 32],
+    /// Contato esperado pelo convite original
+    petname: Option<String>,
+    /// Prazo para a retomada; `None` enquanto a conexão está ativa
+    expires_at: Option<Instant>,
+}
+
+impl ResumeTickets {
+    fn issue(&mut self, ticket: [u8; TOKEN_LEN], session: SessionId, identity: [u8; 32], petname: Option<String>) {
+        self.revoke(session);
+        self.tickets.insert(ticket, ResumeTicket { session, identity, petname, expires_at: None });
+    }
+
+    /// Começa a contar o prazo de retomada após a queda da conexão.
+    fn suspend(&mut self, ticket: &[u8; TOKEN_LEN]) {
+        if let Some(entry) = self.tickets.get_mut(ticket) {
+            entry.expires_at = Some(Instant::now() + RESUME_WINDOW);
+        }
+    }
+
+    /// Consome o ticket apresentado por `identity`; retorna a sessão a retomar
+    /// e o contato esperado.
+    fn redeem(&mut self, ticket: &[u8; TOKEN_LEN], identity: &[u8; 32]) -> Option<(SessionId, Option<String>)> {
+        let now = Instant::now();
+        self.tickets.retain(|_, entry| entry.expires_at.is_none_or(|deadline| now < deadline));
+        if self.tickets.get(ticket)?.identity != *identity {
+            return None;
+        }
+        self.tickets.remove(ticket).map(|entry| (entry.session, entry.petname))
+    }
+
+    fn expire(&mut self, ticket: &[u8; TOKEN_LEN]) {
+        self.tickets.remove(ticket);
+    }
+
+    fn revoke(&mut self, session: SessionId) {
+        self.tickets.retain(|_, entry| entry.session != session);
+    }
+}
+
+type SharedTickets = Arc<std::sync::Mutex<ResumeTickets>>;
+
+/// Como o host admitiu o par: por um convite ou retomando uma sessão.
+enum Admission {
+    Invite(Option<String>),
+    Resume(SessionId, Option<String>),
+}
+
+/// Como uma sessão volta depois que a conexão cai.
+#[derive(Clone)]
+enum Resume {
+    /// O cliente disca de novo para o host e apresenta o ticket
+    Dial(DialTarget),
+    /// O host aguarda o cliente por até `RESUME_WINDOW`
+    Await,
+}
+
+/// Conector e chaves do host para a reconexão do cliente.
+#[derive(Clone)]
+struct DialTarget {
+    connector: Arc<dyn Connector>,
+    /// Chave estática Noise do host: a retomada usa sempre IK
+    host_static: [u8; 32],
+    host_identity: [u8; 32],
+    local_static: StaticSecret,
+    petname: Option<String>,
+    /// A sessão original foi híbrida: a retomada também é
+    post_quantum: bool,
+}
+
+/// Abre conexões de saída: TCP direto ou via Tor, TLS opcional e WebSocket.
+#[derive(Clone)]
+struct Dialer {
+    event_sender: UnboundedSender<NetworkEvent>,
+    /// Proxy SOCKS5 do Tor por onde passam todas as conexões de saída (`--tor`)
+    tor_config: Option<TorConfig>,
+    use_tls: bool,
+    handshake_timeout: Duration,
+}
+
+impl Dialer {
+    /// Conecta ao host e conclui o handshake WebSocket.
+    ///
+    /// `tls_pin` é o SHA256 do certificado do host vindo do convite; com ele
+    /// a conexão usa TLS mesmo sem `--tls`.
+    async fn dial(&self, host: &str, port: u16, tls_pin: Option<&str>) -> Result<Connection, String> {
+        let addr = if host.contains(':') { format!("[{}]:{}", host, port) } else { format!("{}:{}", host, port) };
+        let stream = self.open_stream(host, port).await?;
+        within(self.handshake_timeout, self.upgrade(stream, host, &addr, tls_pin)).await
+    }
+
+    /// TLS (opcional) e handshake WebSocket sobre a conexão TCP aberta.
+    async fn upgrade(&self, stream: TcpStream, host: &str, addr: &str, tls_pin: Option<&str>) -> Result<Connection, String> {
+        let use_tls = self.use_tls || tls_pin.is_some();
+        let protocol = if use_tls { "wss" } else { "ws" };
+        let ws_uri = format!("{}://{}", protocol, addr);
+
+        let _ = self.event_sender.send(NetworkEvent::Log(
+            format!("Conectando via {}...", protocol)
+        ));
+
+        let stream = if use_tls {
+            let tls_stream = tls::connect(stream, host, tls_pin).await
+                .map_err(|e| e.to_string())?;
+            if tls_pin.is_some() {
+                let _ = self.event_sender.send(NetworkEvent::Log(
+                    "✓ Certificado TLS confere com o pin do convite".to_string()
+                ));
+            }
+            MaybeTlsStream::Tls(Box::new(tls_stream))
+        } else {
+            MaybeTlsStream::Plain(stream)
+        };
+
+        let (ws_stream, _) = ClientBuilder::from_uri(ws_uri.parse().unwrap())
+            .connect_on(stream)
+            .await
+            .map_err(|e| format!("Falha no handshake WebSocket: {}", e))?;
+        Ok(transport::websocket(ws_stream))
+    }
+
+    /// Abre a conexão TCP de saída, via Tor quando `--tor` está ativo.
+    ///
+    /// Com Tor não há fallback: se o proxy falhar a conexão falha, em vez de
+    /// expor o IP local conectando diretamente.
+    async fn open_stream(&self, host: &str, port: u16) -> Result<TcpStream, String> {
+        match &self.tor_config {
+            Some(tor_config) => {
+                let _ = self.event_sender.send(NetworkEvent::Log(
+                    format!("🧅 Conectando a {}:{} via Tor ({})...", host, port, tor_config.proxy_addr())
+                ));
+                tor::connect_via_tor(host, port, tor_config).await
+                    .map_err(|e| format!("Falha ao conectar via Tor (sem fallback para clearnet): {}", e))
+            }
+            None if host.ends_with(".onion") => {
+                Err("Endereços .onion exigem o modo Tor (--tor)".to_string())
+            }
+            None => TcpStream::connect((host, port)).await
+                .map_err(|e| format!("Falha ao conectar: {}", e)),
+        }
+    }
+}
+
+/// Conector dos convites: o host e o pin TLS vêm do convite, o resto do `Dialer`.
+struct InviteConnector {
+    dialer: Dialer,
+    host: String,
+    port: u16,
+    tls_pin: Option<String>,
+}
+
+impl Connector for InviteConnector {
+    fn connect(&self) -> BoxFuture<'_, Result<Connection, String>> {
+        Box::pin(self.dialer.dial(&self.host, self.port, self.tls_pin.as_deref()))
+    }
+}
+
+/// Estado compartilhado entre o gerenciador e as tarefas de cada conexão.
+#[derive(Clone)]
+struct PeerContext {
+    event_sender: UnboundedSender<NetworkEvent>,
+    sessions: Arc<SessionTable>,
+    contacts: SharedContacts,
+    identity: Arc<Identity>,
+    tickets: SharedTickets,
+    keepalive: KeepaliveConfig,
+    pq_policy: PqPolicy,
+}
+
+impl PeerContext {
+    /// Registra uma sessão autenticada, nova ou retomada, e avisa a aplicação.
+    async fn attach(
+        &self,
+        session: Option<SessionId>,
+        outcome: HandshakeOutcome,
+        expected_contact: Option<&str>,
+        role: Role,
+        local_static: &StaticSecret,
+        sink: BoxedSink,
+    ) -> (SessionId, u64) {
+        let status = self.contacts.lock().unwrap().observe(expected_contact, &outcome.remote_identity);
+        let remote_identity = outcome.remote_identity;
+
+        let (session, generation) = self.sessions.attach(session, sink).await;
+        if role == Role::Responder {
+            self.tickets.lock().unwrap()
+                .issue(outcome.resume_ticket, session, remote_identity, expected_contact.map(str::to_string));
+        }
+        let _ = self.event_sender.send(peer_connected(session, outcome, role, local_static, known_petname(&status)));
+        report_trust(session, status, &remote_identity, &self.event_sender);
+        (session, generation)
+    }
+
+    /// Registra a sessão e passa a repassar as mensagens recebidas em uma
+    /// tarefa própria, que também cuida da retomada se a conexão cair.
+    #[allow(clippy::too_many_arguments)]
+    async fn open_session(
+        &self,
+        session: Option<SessionId>,
+        outcome: HandshakeOutcome,
+        expected_contact: Option<&str>,
+        role: Role,
+        local_static: &StaticSecret,
+        connection: Connection,
+        resume: Resume,
+    ) -> SessionId {
+        let ticket = outcome.resume_ticket;
+        let (session, generation) = self.attach(session, outcome, expected_contact, role, local_static, connection.sink).await;
+
+        let context = self.clone();
+        tokio::spawn(async move {
+            context.run_session(session, generation, connection.stream, ticket, resume).await;
+        });
+        session
+    }
+
+    /// Repassa as mensagens até a conexão cair. Sem um encerramento explícito
+    /// do par, o cliente reconecta e o host aguarda a retomada.
+    async fn run_session(
+        &self,
+        session: SessionId,
+        mut generation: u64,
+        mut stream: Box<dyn FrameStream>,
+        mut ticket: [u8; TOKEN_LEN],
+        resume: Resume,
+    ) {
+        loop {
+            let closed_by_peer = self.receive_loop(session, generation, stream).await;
+            // Fechada localmente ou já assumida por outra conexão
+            if !self.sessions.detach(session, generation).await {
+                return;
+            }
+            if closed_by_peer {
+                self.finish(session).await;
+                return;
+            }
+            let _ = self.event_sender.send(NetworkEvent::ConnectionLost(session));
+
+            match &resume {
+                Resume::Dial(target) => match self.reconnect(session, target, &ticket).await {
+                    Some((next_generation, next_stream, next_ticket)) => {
+                        generation = next_generation;
+                        stream = next_stream;
+                        ticket = next_ticket;
+                    }
+                    None => {
+                        self.finish(session).await;
+                        return;
+                    }
+                },
+                Resume::Await => {
+                    self.tickets.lock().unwrap().suspend(&ticket);
+                    tokio::time::sleep(RESUME_WINDOW).await;
+                    self.tickets.lock().unwrap().expire(&ticket);
+                    // Nenhuma conexão nova assumiu a sessão: o par não voltou a tempo
+                    if self.sessions.generation(session).await == Some(generation) {
+                        self.finish(session).await;
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Disca de novo para o host com backoff exponencial e retoma a sessão com
+    /// o ticket. O novo handshake renova a chave raiz do ratchet.
+    async fn reconnect(
+        &self,
+        session: SessionId,
+        target: &DialTarget,
+        ticket: &[u8; TOKEN_LEN],
+    ) -> Option<(u64, Box<dyn FrameStream>, [u8; TOKEN_LEN])> {
+        for attempt in 1..=RECONNECT_ATTEMPTS {
+            let delay = reconnect_delay(attempt);
+            if self.event_sender.send(NetworkEvent::Reconnecting { session, attempt, delay }).is_err() {
+                return None;
+            }
+            tokio::time::sleep(delay).await;
+            if self.sessions.is_finished(session) {
+                return None;
+            }
+
+            match self.resume_with(target, ticket).await {
+                Ok((outcome, connection)) if !self.sessions.is_finished(session) => {
+                    let next_ticket = outcome.resume_ticket;
+                    let (_, generation) = self
+                        .attach(Some(session), outcome, target.petname.as_deref(), Role::Initiator, &target.local_static, connection.sink)
+                        .await;
+                    return Some((generation, connection.stream, next_ticket));
+                }
+                Ok(_) => return None,
+                Err(e) => {
+                    let _ = self.event_sender.send(NetworkEvent::Log(
+                        format!("Falha ao retomar a sessão {}: {}", session, e)
+                    ));
+                }
+            }
+        }
+        None
+    }
+
+    async fn resume_with(
+        &self,
+        target: &DialTarget,
+        ticket: &[u8; TOKEN_LEN],
+    ) -> Result<(HandshakeOutcome, Connection), String> {
+        let mut connection = self.dial(target.connector.as_ref()).await?;
+        let handshake = NoiseHandshake::initiator(&target.local_static, Some(target.host_static), self.identity.clone(), Some(*ticket), target.post_quantum)
+            .map_err(|e| e.to_string())?;
+        let (outcome, _) = within(self.keepalive.handshake_timeout, run_handshake(handshake, &mut connection, None)).await?;
+        if outcome.remote_identity != target.host_identity {
+            return Err("⚠️ Identidade do host mudou - possível ataque MITM!".to_string());
+        }
+        Ok((outcome, connection))
+    }
+
+    /// Abre uma conexão de saída pelo conector, em qualquer backend.
+    async fn dial(&self, connector: &dyn Connector) -> Result<Connection, String> {
+        let connection = connector.connect().await?;
+        let _ = self.event_sender.send(NetworkEvent::ConnectionEstablished);
+        Ok(connection)
+    }
+
+    /// Repassa as mensagens recebidas até a conexão acabar; `true` se o par a
+    /// fechou explicitamente (ou a aplicação largou o receptor de eventos),
+    /// `false` se ela caiu.
+    ///
+    /// A cada `ping_interval` vai um ping: o pong mede o RTT, um ping sem
+    /// resposta até o próximo degrada a conexão e nenhum quadro do par
+    /// durante `idle_timeout` a dá como perdida.
+    async fn receive_loop(&self, session: SessionId, generation: u64, mut stream: Box<dyn FrameStream>) -> bool {
+        let keepalive = self.keepalive;
+        let mut ticker = tokio::time::interval(keepalive.ping_interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ticker.reset();
+        let mut last_seen = Instant::now();
+        let mut pending_ping: Option<(u64, Instant)> = None;
+        let mut nonce = 0u64;
+        let mut degraded = false;
+
+        loop {
+            tokio::select! {
+                frame = stream.recv() => {
+                    let Ok(Some(frame)) = frame else {
+                        return false;
+                    };
+                    last_seen = Instant::now();
+                    match frame {
+                        Frame::Data(data) => {
+                            // Ninguém mais recebe os eventos: encerra a sessão
+                            if self.event_sender.send(NetworkEvent::DataReceived(session, data)).is_err() {
+                                return true;
+                            }
+                        }
+                        Frame::Close => return true,
+                        // Só os backends sem ping próprio (o WebSocket responde sozinho)
+                        Frame::Ping(nonce) => self.sessions.send_control(session, generation, Frame::Pong(nonce)).await,
+                        Frame::Pong(nonce) => {
+                            if let Some((expected, sent_at)) = pending_ping {
+                                if nonce == expected {
+                                    pending_ping = None;
+                                    degraded = false;
+                                    let _ = self.event_sender.send(NetworkEvent::Latency { session, rtt: sent_at.elapsed() });
+                                }
+                            }
+                        }
+                    }
+                }
+                _ = ticker.tick() => {
+                    if self.event_sender.is_closed() {
+                        return true;
+                    }
+                    let silent_for = last_seen.elapsed();
+                    if silent_for >= keepalive.idle_timeout {
+                        let _ = self.event_sender.send(NetworkEvent::Log(
+                            format!("Par da sessão {} sem resposta há {}s", session, silent_for.as_secs())
+                        ));
+                        return false;
+                    }
+                    if pending_ping.is_some() && !degraded {
+                        degraded = true;
+                        let _ = self.event_sender.send(NetworkEvent::ConnectionDegraded { session, silent_for });
+                    }
+                    nonce += 1;
+                    self.sessions.send_control(session, generation, Frame::Ping(nonce)).await;
+                    // Um pong atrasado do ping anterior não conta mais
+                    pending_ping = Some((nonce, Instant::now()));
+                }
+            }
+        }
+    }
+
+    /// Encerra a sessão de vez; a aplicação é avisada uma única vez.
+    async fn finish(&self, session: SessionId) {
+        self.tickets.lock().unwrap().revoke(session);
+        if self.sessions.finish(session).await {
+            let _ = self.event_sender.send(NetworkEvent::PeerDisconnected(session));
+        }
+    }
+}
+
+/// Aplica o prazo do handshake a uma etapa da conexão.
+async fn within<T>(limit: Duration, step: impl Future<Output = Result<T, String>>) -> Result<T, String> {
+    tokio::time::timeout(limit, step).await
+        .map_err(|_| format!("Tempo esgotado: handshake não concluído em {}s", limit.as_secs()))?
+}
+
+/// Espera antes da tentativa `attempt` (a partir de 1): dobra a cada falha, até o teto.
+fn reconnect_delay(attempt: u32) -> Duration {
+    RECONNECT_BASE_DELAY
+        .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+        .min(RECONNECT_MAX_DELAY)
+}
+
 /// Gerencia as conexões de rede com TLS e autenticação mútua.
+///
+/// Os clones compartilham sessões, convites e tickets: servem para discar
+/// numa tarefa à parte sem segurar quem detém o original.
+#[derive(Clone)]
 pub struct NetworkManager {
-    sender: Arc<Mutex<Option<futures_util::stream::SplitSink<tokio_websockets::WebSocketStream<TcpStream>, Message>>>>,
+    sessions: Arc<SessionTable>,
     event_sender: UnboundedSender<NetworkEvent>,
     identity: Arc<Identity>,
-    use_tls: bool,
+    /// Certificado do host quando TLS está ativo (`--tls`)
+    tls_identity: Option<Arc<TlsIdentity>>,
+    /// Proxy SOCKS5 do Tor por onde passam todas as conexões de saída (`--tor`)
+    tor_config: Option<TorConfig>,
+    /// Contatos conhecidos, consultados após cada handshake (TOFU)
+    contacts: SharedContacts,
+    /// Tokens dos convites emitidos por este host, cada um de uso único
+    invites: SharedInvites,
+    /// Tickets de retomada das sessões aceitas por este host
+    tickets: SharedTickets,
+    /// Keepalive e prazos aplicados a todas as conexões
+    keepalive: KeepaliveConfig,
+    /// Uso da troca de chaves híbrida com ML-KEM-768 (`--pq`)
+    pq_policy: PqPolicy,
 }
 
 impl NetworkManager {
-    pub fn new(event_sender: UnboundedSender<NetworkEvent>, use_tls: bool) -> Self {
-        let identity = Identity::generate();
-
+    /// `identity` é a identidade Ed25519 da sessão: efêmera por padrão, ou
+    /// carregada do keystore com `--identity`. `pq_policy` decide se os
+    /// convites anunciam ML-KEM e se pares sem ele são aceitos.
+    pub fn new(
+        event_sender: UnboundedSender<NetworkEvent>,
+        identity: Identity,
+        contacts: SharedContacts,
+        tls_identity: Option<TlsIdentity>,
+        tor_config: Option<TorConfig>,
+        keepalive: KeepaliveConfig,
+        pq_policy: PqPolicy,
+    ) -> Self {
         Self {
-            sender: Arc::new(Mutex::new(None)),
+            sessions: Arc::new(SessionTable::default()),
             event_sender,
             identity: Arc::new(identity),
-            use_tls,
+            tls_identity: tls_identity.map(Arc::new),
+            tor_config,
+            contacts,
+            invites: InviteTokens::shared(),
+            tickets: Arc::new(std::sync::Mutex::new(ResumeTickets::default())),
+            keepalive,
+            pq_policy,
         }
     }
 
+    /// Emite um convite assinado de uso único para este host.
+    ///
+    /// `noise_key` é a chave estática passada a `start_host`; sem ela o
+    /// cliente fará um handshake XX. `petname` é o contato esperado em quem
+    /// usar o convite: se ele já for conhecido com outra chave, a conexão gera
+    /// `FingerprintVerificationRequired`. Salvo com `--pq off`, o convite
+    /// anuncia a troca híbrida com ML-KEM-768.
+    pub fn create_invite(&self, host: &str, port: u16, noise_key: Option<[u8; 32]>, petname: Option<String>) -> Invite {
+        let post_quantum = self.pq_policy != PqPolicy::Off;
+        let invite = Invite::new(&self.identity, host, port, noise_key, self.tls_fingerprint(), post_quantum, DEFAULT_INVITE_TTL);
+        self.invites.lock().unwrap().issue(&invite, petname);
+        invite
+    }
+
+    /// Identidade local, usada para assinar mensagens de controle.
+    pub fn identity(&self) -> Arc<Identity> {
+        self.identity.clone()
+    }
+
     /// Retorna o fingerprint da identidade local.
     pub fn local_fingerprint(&self) -> String {
         self.identity.fingerprint()
     }
 
+    /// Retorna o fingerprint do certificado TLS do host, usado como pin no convite.
+    pub fn tls_fingerprint(&self) -> Option<String> {
+        self.tls_identity.as_ref().map(|identity| identity.fingerprint())
+    }
+
+    fn use_tls(&self) -> bool {
+        self.tls_identity.is_some()
+    }
+
+    fn peer_context(&self) -> PeerContext {
+        PeerContext {
+            event_sender: self.event_sender.clone(),
+            sessions: self.sessions.clone(),
+            contacts: self.contacts.clone(),
+            identity: self.identity.clone(),
+            tickets: self.tickets.clone(),
+            keepalive: self.keepalive,
+            pq_policy: self.pq_policy,
+        }
+    }
+
+    fn dialer(&self) -> Dialer {
+        Dialer {
+            event_sender: self.event_sender.clone(),
+            tor_config: self.tor_config.clone(),
+            use_tls: self.use_tls(),
+            handshake_timeout: self.keepalive.handshake_timeout,
+        }
+    }
+
     /// Inicia um servidor host com autenticação mútua.
-    pub async fn start_host(&mut self, addr: SocketAddr, local_public_key: [u8; 32]) -> Result<(), String> {
-        let listener = TcpListener::bind(addr).await.map_err(|e| e.to_string())?;
+    ///
+    /// `local_static` é a chave estática Noise do host, publicada no convite.
+    /// O listener continua aceitando conexões; cada par autenticado vira uma
+    /// sessão independente. Retorna o endereço aberto, com a porta escolhida
+    /// pelo sistema quando `addr` pede a porta 0.
+    pub async fn start_host(&mut self, addr: SocketAddr, local_static: StaticSecret) -> Result<SocketAddr, String> {
+        let acceptor = match &self.tls_identity {
+            Some(identity) => Some(identity.acceptor().map_err(|e| e.to_string())?),
+            None => None,
+        };
+        let listener = WebSocketListener::bind(addr, acceptor).await.map_err(|e| format!("{}: {}", addr, e))?;
+        let addr = listener.local_addr().map_err(|e| e.to_string())?;
+        self.serve(listener, local_static);
+        Ok(addr)
+    }
 
-        let protocol = if self.use_tls { "wss" } else { "ws" };
-        self.event_sender.send(NetworkEvent::Log(
-            format!("Host escutando em {}://{}", protocol, addr)
-        )).unwrap();
+    /// Aceita pares por qualquer `Listener` (WebSocket, TCP, socket Unix ou
+    /// em memória), com o mesmo handshake e as mesmas sessões de `start_host`.
+    pub fn serve(&self, mut listener: impl Listener + 'static, local_static: StaticSecret) {
+        let _ = self.event_sender.send(NetworkEvent::Log(
+            format!("Host escutando em {}", listener.local_description())
+        ));
 
-        let event_sender = self.event_sender.clone();
-        let sender_clone = self.sender.clone();
+        let context = self.peer_context();
         let identity = self.identity.clone();
+        let invites = self.invites.clone();
 
         tokio::spawn(async move {
-            if let Ok((stream, peer_addr)) = listener.accept().await {
-                event_sender.send(NetworkEvent::Log(
-                    format!("Conexão recebida de {}", peer_addr)
-                )).unwrap();
-
-                let ws_stream = ServerBuilder::new()
-                    .accept(stream)
-                    .await
-                    .map_err(|e| e.to_string());
-
-                match ws_stream {
-                    Ok(ws) => {
-                        let (mut ws_sender, mut ws_receiver) = ws.split();
-
-                        // 1. Cria handshake autenticado
-                        let handshake = AuthenticatedHandshake::new(local_public_key, &identity);
-                        let handshake_bytes = match serde_json::to_vec(&handshake) {
-                            Ok(b) => b,
-                            Err(e) => {
-                                event_sender.send(NetworkEvent::ConnectionFailed(
-                                    format!("Erro ao serializar handshake: {}", e)
-                                )).unwrap();
-                                return;
-                            }
-                        };
-
-                        // 2. Envia handshake autenticado
-                        if ws_sender.send(Message::binary(handshake_bytes)).await.is_err() {
-                            event_sender.send(NetworkEvent::ConnectionFailed(
-                                "Falha ao enviar handshake".to_string()
-                            )).unwrap();
-                            return;
-                        }
-
-                        // 3. Recebe e verifica handshake do cliente
-                        if let Some(Ok(msg)) = ws_receiver.next().await {
-                            let peer_handshake_bytes = msg.as_payload().to_vec();
-
-                            match serde_json::from_slice::<AuthenticatedHandshake>(&peer_handshake_bytes) {
-                                Ok(peer_handshake) => {
-                                    // Verifica a assinatura
-                                    match peer_handshake.verify() {
-                                        Ok(_peer_verifying_key) => {
-                                            let peer_x25519 = match peer_handshake.x25519_key_array() {
-                                                Ok(k) => k,
-                                                Err(e) => {
-                                                    event_sender.send(NetworkEvent::ConnectionFailed(
-                                                        format!("Erro ao processar chave X25519: {}", e)
-                                                    )).unwrap();
-                                                    return;
-                                                }
-                                            };
-                                            let peer_ed25519 = match peer_handshake.ed25519_key_array() {
-                                                Ok(k) => k,
-                                                Err(e) => {
-                                                    event_sender.send(NetworkEvent::ConnectionFailed(
-                                                        format!("Erro ao processar chave Ed25519: {}", e)
-                                                    )).unwrap();
-                                                    return;
-                                                }
-                                            };
-
-                                            // Calcula fingerprint
-                                            let fingerprint = match peer_handshake.fingerprint() {
-                                                Ok(fp) => fp,
-                                                Err(e) => {
-                                                    event_sender.send(NetworkEvent::ConnectionFailed(
-                                                        format!("Erro ao calcular fingerprint: {}", e)
-                                                    )).unwrap();
-                                                    return;
-                                                }
-                                            };
-
-                                            event_sender.send(NetworkEvent::Log(
-                                                format!("✓ Assinatura verificada! Fingerprint: {}", fingerprint)
-                                            )).unwrap();
-
-                                            event_sender.send(NetworkEvent::PeerConnected {
-                                                public_key: peer_x25519,
-                                                ed25519_key: peer_ed25519,
-                                                fingerprint,
-                                            }).unwrap();
-
-                                            *sender_clone.lock().await = Some(ws_sender);
-
-                                            // Loop para receber mensagens
-                                            while let Some(msg) = ws_receiver.next().await {
-                                                match msg {
-                                                    Ok(m) if m.is_binary() => {
-                                                        let data = m.as_payload();
-                                                        event_sender.send(NetworkEvent::DataReceived(data.to_vec())).unwrap();
-                                                    }
-                                                    Ok(m) if m.is_close() => {
-                                                        event_sender.send(NetworkEvent::PeerDisconnected).unwrap();
-                                                        break;
-                                                    }
-                                                    Err(_) => {
-                                                        event_sender.send(NetworkEvent::PeerDisconnected).unwrap();
-                                                        break;
-                                                    }
-                                                    _ => {}
-                                                }
-                                            }
-                                        }
-                                        Err(e) => {
-                                            event_sender.send(NetworkEvent::ConnectionFailed(
-                                                format!("⚠️ ASSINATURA INVÁLIDA: {} - Possível ataque MITM!", e)
-                                            )).unwrap();
-                                        }
-                                    }
-                                }
-                                Err(e) => {
-                                    event_sender.send(NetworkEvent::ConnectionFailed(
-                                        format!("Handshake inválido: {}", e)
-                                    )).unwrap();
-                                }
-                            }
-                        } else {
-                            event_sender.send(NetworkEvent::ConnectionFailed(
-                                "Falha ao receber handshake".to_string()
-                            )).unwrap();
-                        }
-                    }
+            loop {
+                let incoming = match listener.accept().await {
+                    Ok(incoming) => incoming,
                     Err(e) => {
-                        event_sender.send(NetworkEvent::ConnectionFailed(
-                            format!("Erro no WebSocket: {}", e)
-                        )).unwrap();
+                        let _ = context.event_sender.send(NetworkEvent::Log(
+                            format!("Falha ao aceitar conexão: {}", e)
+                        ));
+                        continue;
                     }
+                };
+                // Sem quem receba os eventos, o listener para de aceitar
+                if context.event_sender.send(NetworkEvent::Log(
+                    format!("Conexão recebida de {}", incoming.peer)
+                )).is_err() {
+                    return;
                 }
-                *sender_clone.lock().await = None;
+
+                let context = context.clone();
+                let local_static = local_static.clone();
+                let identity = identity.clone();
+                let invites = invites.clone();
+                tokio::spawn(async move {
+                    let limit = context.keepalive.handshake_timeout;
+                    let result = within(limit, accept_peer(incoming.connecting, &local_static, identity, &invites, &context)).await;
+                    if let Err(e) = result {
+                        let _ = context.event_sender.send(NetworkEvent::ConnectionFailed(e));
+                    }
+                });
             }
         });
-
-        Ok(())
     }
 
     /// Conecta-se a um host usando a URI de convite com autenticação.
-    pub async fn connect_to_host(&mut self, uri: &str, local_public_key: [u8; 32]) -> Result<(), String> {
-        let parsed_uri = Url::parse(uri).map_err(|_| "URI de convite inválida".to_string())?;
-        let host = parsed_uri.host_str().ok_or("Host inválido na URI".to_string())?;
-        let port = parsed_uri.port().ok_or("Porta inválida na URI".to_string())?;
-        let addr = format!("{}:{}", host, port);
+    ///
+    /// `petname` é o contato esperado do outro lado; retorna a sessão aberta.
+    /// Se a conexão cair depois, a sessão é retomada com o ticket do handshake.
+    pub async fn connect_to_host(&mut self, uri: &str, local_static: StaticSecret, petname: Option<String>) -> Result<SessionId, String> {
+        // O convite é validado (assinatura, versão e validade) antes de qualquer conexão
+        let invite = Invite::parse(uri).map_err(|e| e.to_string())?;
+        let connector = InviteConnector {
+            dialer: self.dialer(),
+            host: invite.host.clone(),
+            port: invite.port,
+            tls_pin: invite.tls_pin.clone(),
+        };
+        self.connect_with(Arc::new(connector), &invite, local_static, petname).await
+    }
 
-        let stream = TcpStream::connect(&addr).await
-            .map_err(|e| format!("Falha ao conectar: {}", e))?;
+    /// Como `connect_to_host`, mas pelo `Connector` dado em vez do endereço do
+    /// convite; as reconexões da sessão usam o mesmo conector. O convite já
+    /// deve ter sido validado. A troca é híbrida quando o convite anuncia
+    /// ML-KEM, salvo com `--pq off`; com `--pq require`, convites sem ele são recusados.
+    pub async fn connect_with(
+        &mut self,
+        connector: Arc<dyn Connector>,
+        invite: &Invite,
+        local_static: StaticSecret,
+        petname: Option<String>,
+    ) -> Result<SessionId, String> {
+        if self.pq_policy == PqPolicy::Require && !invite.post_quantum {
+            return Err(format!("Conexão recusada: {}", HandshakeError::PostQuantumRequired));
+        }
+        let post_quantum = invite.post_quantum && self.pq_policy != PqPolicy::Off;
+        let context = self.peer_context();
+        let mut connection = context.dial(connector.as_ref()).await?;
 
-        let protocol = if self.use_tls { "wss" } else { "ws" };
-        let ws_uri = format!("{}://{}", protocol, addr);
+        // IK quando o convite traz a chave estática do host, XX no primeiro contato
+        let handshake = NoiseHandshake::initiator(&local_static, invite.noise_key, self.identity.clone(), Some(invite.token), post_quantum)
+            .map_err(|e| e.to_string())?;
+        let (outcome, _) = within(self.keepalive.handshake_timeout, run_handshake(handshake, &mut connection, None)).await?;
 
-        self.event_sender.send(NetworkEvent::Log(
-            format!("Conectando via {}...", protocol)
-        )).unwrap();
+        // A identidade autenticada no handshake precisa ser a que assinou o convite
+        if outcome.remote_identity != invite.identity_key {
+            return Err("⚠️ Identidade do host não corresponde ao convite - possível ataque MITM!".to_string());
+        }
 
-        let (ws_stream, _) = ClientBuilder::from_uri(ws_uri.parse().unwrap())
-            .connect_on(stream)
-            .await
-            .map_err(|e| format!("Falha no handshake WebSocket: {}", e))?;
+        let _ = self.event_sender.send(NetworkEvent::Log(
+            format!("✓ Handshake Noise {} com o host concluído! Fingerprint: {}", outcome.key_exchange(), outcome.fingerprint)
+        ));
 
-        self.event_sender.send(NetworkEvent::ConnectionEstablished).unwrap();
-
-        let (mut ws_sender, mut ws_receiver) = ws_stream.split();
-
-        // 1. Recebe handshake autenticado do host
-        if let Some(Ok(msg)) = ws_receiver.next().await {
-            let peer_handshake_bytes = msg.as_payload().to_vec();
-
-            match serde_json::from_slice::<AuthenticatedHandshake>(&peer_handshake_bytes) {
-                Ok(peer_handshake) => {
-                    // Verifica a assinatura do host
-                    match peer_handshake.verify() {
-                        Ok(_peer_verifying_key) => {
-                            let peer_x25519 = match peer_handshake.x25519_key_array() {
-                                Ok(k) => k,
-                                Err(e) => return Err(format!("Erro ao processar chave X25519: {}", e)),
-                            };
-                            let peer_ed25519 = match peer_handshake.ed25519_key_array() {
-                                Ok(k) => k,
-                                Err(e) => return Err(format!("Erro ao processar chave Ed25519: {}", e)),
-                            };
-
-                            let fingerprint = match peer_handshake.fingerprint() {
-                                Ok(fp) => fp,
-                                Err(e) => return Err(format!("Erro ao calcular fingerprint: {}", e)),
-                            };
-
-                            self.event_sender.send(NetworkEvent::Log(
-                                format!("✓ Assinatura do host verificada! Fingerprint: {}", fingerprint)
-                            )).unwrap();
-
-                            // 2. Envia nosso handshake autenticado
-                            let handshake = AuthenticatedHandshake::new(local_public_key, &self.identity);
-                            let handshake_bytes = serde_json::to_vec(&handshake)
-                                .map_err(|e| format!("Erro ao serializar handshake: {}", e))?;
-
-                            if ws_sender.send(Message::binary(handshake_bytes)).await.is_err() {
-                                return Err("Falha ao enviar handshake".to_string());
-                            }
+        let target = DialTarget {
+            connector,
+            host_static: outcome.remote_static,
+            host_identity: outcome.remote_identity,
+            local_static: local_static.clone(),
+            petname: petname.clone(),
+            post_quantum,
+        };
+        let session = context
+            .open_session(None, outcome, petname.as_deref(), Role::Initiator, &local_static, connection, Resume::Dial(target))
+            .await;
+        Ok(session)
+    }
 
-                            self.event_sender.send(NetworkEvent::PeerConnected {
-                                public_key: peer_x25519,
-                                ed25519_key: peer_ed25519,
-                                fingerprint,
-                            }).unwrap();
-
-                            *self.sender.lock().await = Some(ws_sender);
-                            let event_sender = self.event_sender.clone();
-                            let sender_clone = self.sender.clone();
-
-                            // Loop para receber mensagens
-                            tokio::spawn(async move {
-                                while let Some(msg) = ws_receiver.next().await {
-                                    match msg {
-                                        Ok(m) if m.is_binary() => {
-                                            let data = m.as_payload();
-                                            event_sender.send(NetworkEvent::DataReceived(data.to_vec())).unwrap();
-                                        }
-                                        Ok(m) if m.is_close() => {
-                                            event_sender.send(NetworkEvent::PeerDisconnected).unwrap();
-                                            break;
-                                        }
-                                        Err(_) => {
-                                            event_sender.send(NetworkEvent::PeerDisconnected).unwrap();
-                                            break;
-                                        }
-                                        _ => {}
-                                    }
-                                }
-                                *sender_clone.lock().await = None;
-                            });
-                        }
-                        Err(e) => {
-                            return Err(format!("⚠️ ASSINATURA DO HOST INVÁLIDA: {} - NÃO CONECTE!", e));
-                        }
+    /// Envia uma mensagem criptografada para o par da sessão indicada.
+    pub async fn send_message(&self, session: SessionId, data: Vec<u8>) -> Result<(), &'static str> {
+        if let Some((_, Some(sender))) = self.sessions.sinks.lock().await.get_mut(&session) {
+            sender.send(Frame::Data(data)).await
+                .map_err(|_| "Falha ao enviar mensagem")?;
+            Ok(())
+        } else {
+            Err("Não conectado")
+        }
+    }
+
+    /// Fecha a conexão da sessão e descarta a retomada: o par recebe um
+    /// fechamento explícito e não tenta reconectar.
+    pub async fn close_session(&self, session: SessionId) {
+        if let Some(mut sender) = self.sessions.take(session).await {
+            let _ = sender.send(Frame::Close).await;
+        }
+        self.peer_context().finish(session).await;
+    }
+}
+
+/// Cifra a mensagem no canal da sessão e envia ao par. Se a cadeia de envio
+/// ficou longa sem resposta, pede em seguida um passo DH ao par.
+pub async fn send_wire(
+    channel: &mut SecureChannel,
+    network: &NetworkManager,
+    session: SessionId,
+    message: protocol::Message,
+) -> Result<(), &'static str> {
+    let mut messages = vec![message];
+    if channel.wants_rekey() {
+        messages.push(protocol::Message::Rekey { request: true });
+    }
+    for message in messages {
+        let data = channel.seal(message).map_err(|e| match e {
+            ChannelError::Padding(_) => "Mensagem muito grande para enviar",
+            _ => "Erro de criptografia",
+        })?;
+        network.send_message(session, data).await
+            .map_err(|_| "Falha ao enviar mensagem")?;
+    }
+    Ok(())
+}
+
+/// Mensagem de chat retransmitida por `resend_unacked`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resent {
+    pub id: MessageId,
+    pub content: String,
+    /// Saiu pela conexão; senão continua na fila para a próxima retomada
+    pub sent: bool,
+}
+
+/// Reenvia ao par o que ele não confirmou, inclusive o que ficou na fila
+/// enquanto a conexão estava caída ou veio de uma sessão anterior.
+pub async fn resend_unacked(
+    channel: &mut SecureChannel,
+    network: &NetworkManager,
+    session: SessionId,
+    outbox: &Outbox,
+    peer: &PeerKey,
+) -> Vec<Resent> {
+    let mut resent = Vec::new();
+    for message in outbox.unacked(peer) {
+        let protocol::Message::Chat { id, content, .. } = &message else {
+            continue;
+        };
+        let (id, content) = (*id, content.clone());
+        let sent = send_wire(channel, network, session, message).await.is_ok();
+        resent.push(Resent { id, content, sent });
+    }
+    resent
+}
+
+/// Conclui a conexão recebida pelo host (TLS e WebSocket, conforme o
+/// backend) e o handshake Noise.
+async fn accept_peer(
+    connecting: BoxFuture<'static, Result<Connection, String>>,
+    local_static: &StaticSecret,
+    identity: Arc<Identity>,
+    invites: &SharedInvites,
+    context: &PeerContext,
+) -> Result<(), String> {
+    let mut connection = connecting.await?;
+
+    // Handshake Noise: o padrão (XX ou IK) é escolhido pelo cliente
+    let handshake = NoiseHandshake::responder(local_static, identity, context.pq_policy == PqPolicy::Require);
+    let admitted = Some((invites, &context.tickets));
+    let (outcome, admission) = run_handshake(handshake, &mut connection, admitted).await?;
+
+    let _ = context.event_sender.send(NetworkEvent::Log(
+        format!("✓ Handshake Noise {} concluído! Fingerprint: {}", outcome.key_exchange(), outcome.fingerprint)
+    ));
+    let (session, expected_contact) = match admission {
+        Some(Admission::Resume(session, petname)) => (Some(session), petname),
+        Some(Admission::Invite(petname)) => (None, petname),
+        None => (None, None),
+    };
+    context
+        .open_session(session, outcome, expected_contact.as_deref(), Role::Responder, local_static, connection, Resume::Await)
+        .await;
+    Ok(())
+}
+
+/// Conduz o handshake Noise sobre a conexão até concluir ou falhar.
+///
+/// Com `admission` (lado host), o token apresentado pelo cliente é consumido
+/// assim que chega: primeiro como ticket de retomada da mesma identidade,
+/// depois como convite. Um token ausente, expirado ou reutilizado encerra a
+/// conexão. Junto do resultado vem como o par foi admitido.
+async fn run_handshake(
+    mut handshake: NoiseHandshake,
+    connection: &mut Connection,
+    admission: Option<(&SharedInvites, &SharedTickets)>,
+) -> Result<(HandshakeOutcome, Option<Admission>), String> {
+    let mut admitted = None;
+    while !handshake.is_finished() {
+        if handshake.is_my_turn() {
+            let message = handshake.write_message().map_err(|e| e.to_string())?;
+            connection.sink.send(Frame::Data(message)).await
+                .map_err(|_| "Falha ao enviar handshake".to_string())?;
+        } else {
+            let message = match connection.stream.recv().await {
+                Ok(Some(Frame::Data(message))) => message,
+                _ => return Err("Falha ao receber handshake".to_string()),
+            };
+            handshake.read_message(&message).map_err(|e| match e {
+                HandshakeError::PostQuantumRequired => format!("Conexão recusada: {}", e),
+                e => format!("⚠️ HANDSHAKE INVÁLIDO: {} - Possível ataque MITM!", e),
+            })?;
+
+            if let (Some((invites, tickets)), Some(token), None) = (admission, handshake.remote_token(), &admitted) {
+                let resumed = handshake.remote_identity()
+                    .and_then(|identity| tickets.lock().unwrap().redeem(&token, &identity));
+                admitted = Some(match resumed {
+                    Some((session, petname)) => Admission::Resume(session, petname),
+                    None => Admission::Invite(
+                        invites.lock().unwrap().redeem(&token).map_err(|e| format!("Conexão recusada: {}", e))?
+                    ),
+                });
+            }
+        }
+    }
+    if admission.is_some() && admitted.is_none() {
+        return Err("Conexão recusada: nenhum token de convite apresentado".to_string());
+    }
+    let outcome = handshake.finish().map_err(|e| e.to_string())?;
+    Ok((outcome, admitted))
+}
+
+/// Apelido já associado à identidade do par, qualquer que seja o status.
+fn known_petname(status: &Result<TrustStatus, ContactError>) -> Option<String> {
+    match status {
+        Ok(TrustStatus::FirstUse { petname })
+        | Ok(TrustStatus::Known { petname, .. })
+        | Ok(TrustStatus::KeyChanged { petname, .. }) => Some(petname.clone()),
+        Ok(TrustStatus::Unknown) | Err(_) => None,
+    }
+}
+
+/// Informa à aplicação o resultado da checagem da identidade contra os contatos.
+fn report_trust(
+    session: SessionId,
+    status: Result<TrustStatus, ContactError>,
+    ed25519_key: &[u8; 32],
+    event_sender: &UnboundedSender<NetworkEvent>,
+) {
+    let event = match status {
+        Ok(TrustStatus::Unknown) => NetworkEvent::Log(
+            "Par desconhecido: use /trust <apelido> para salvar o contato".to_string()
+        ),
+        Ok(TrustStatus::FirstUse { petname }) => NetworkEvent::Log(
+            format!("Novo contato '{}' registrado no primeiro uso - confirme com /verify {}", petname, petname)
+        ),
+        Ok(TrustStatus::Known { petname, verified: true }) => NetworkEvent::Log(
+            format!("✓ Contato verificado: {}", petname)
+        ),
+        Ok(TrustStatus::Known { petname, verified: false }) => NetworkEvent::Log(
+            format!("Contato conhecido (não verificado): {}", petname)
+        ),
+        Ok(TrustStatus::KeyChanged { petname, previous_key }) => NetworkEvent::FingerprintVerificationRequired {
+            session,
+            petname,
+            fingerprint: key_fingerprint(ed25519_key),
+            previous_fingerprint: key_fingerprint(&previous_key),
+        },
+        Err(e) => NetworkEvent::Log(format!("❌ {}", e)),
+    };
+    let _ = event_sender.send(event);
+}
+
+fn peer_connected(
+    session: SessionId,
+    outcome: HandshakeOutcome,
+    role: Role,
+    local_static: &StaticSecret,
+    petname: Option<String>,
+) -> NetworkEvent {
+    let channel = Box::new(ChannelKeys {
+        role,
+        root_key: outcome.root_key,
+        local_static: local_static.clone(),
+        remote_static: outcome.remote_static,
+        protocol_version: outcome.protocol_version,
+    });
+    NetworkEvent::PeerConnected {
+        session,
+        public_key: outcome.remote_static,
+        ed25519_key: outcome.remote_identity,
+        fingerprint: outcome.fingerprint,
+        petname,
+        root_key: outcome.root_key,
+        sas: outcome.sas,
+        role,
+        protocol_version: outcome.protocol_version,
+        post_quantum: outcome.post_quantum,
+        channel,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contacts::ContactStore;
+    use crate::tor::test_socks;
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc::{self, UnboundedReceiver};
+    use tokio::time::{timeout, Duration};
+    use x25519_dalek::PublicKey;
+
+    async fn free_port() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    /// Aguarda um evento que satisfaça `pred`, ou falha após 5 segundos.
+    async fn wait_for(rx: &mut UnboundedReceiver<NetworkEvent>, pred: impl Fn(&NetworkEvent) -> bool) -> bool {
+        timeout(Duration::from_secs(5), async {
+            while let Some(event) = rx.recv().await {
+                if pred(&event) {
+                    return true;
+                }
+            }
+            false
+        }).await.unwrap_or(false)
+    }
+
+    /// Aguarda o PeerConnected e retorna a chave raiz derivada do handshake.
+    async fn root_key(rx: &mut UnboundedReceiver<NetworkEvent>) -> Option<[u8; 32]> {
+        timeout(Duration::from_secs(5), async {
+            while let Some(event) = rx.recv().await {
+                if let NetworkEvent::PeerConnected { root_key, .. } = event {
+                    return Some(root_key);
+                }
+            }
+            None
+        }).await.ok().flatten()
+    }
+
+    async fn spawn_host(port: u16) -> (NetworkManager, UnboundedReceiver<NetworkEvent>) {
+        spawn_host_with(port, KeepaliveConfig::default()).await
+    }
+
+    async fn spawn_host_with(port: u16, keepalive: KeepaliveConfig) -> (NetworkManager, UnboundedReceiver<NetworkEvent>) {
+        let (host_tx, host_rx) = mpsc::unbounded_channel();
+        let mut host = NetworkManager::new(host_tx, Identity::generate(), ContactStore::in_memory().shared(), None, None, keepalive, PqPolicy::default());
+        let addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+        host.start_host(addr, StaticSecret::from([1u8; 32])).await.unwrap();
+        (host, host_rx)
+    }
+
+    fn host_public() -> [u8; 32] {
+        PublicKey::from(&StaticSecret::from([1u8; 32])).to_bytes()
+    }
+
+    fn invite(host: &NetworkManager, port: u16) -> String {
+        host.create_invite("127.0.0.1", port, Some(host_public()), None).to_uri()
+    }
+
+    #[tokio::test]
+    async fn test_client_connects_through_socks_proxy() {
+        let port = free_port().await;
+        let (host, mut host_rx) = spawn_host(port).await;
+        let (tor_config, targets) = test_socks::spawn().await;
+
+        let (client_tx, _client_rx) = mpsc::unbounded_channel();
+        let mut client = NetworkManager::new(client_tx, Identity::generate(), ContactStore::in_memory().shared(), None, Some(tor_config), KeepaliveConfig::default(), PqPolicy::default());
+        client.connect_to_host(&invite(&host, port), StaticSecret::from([2u8; 32]), None).await.unwrap();
+
+        assert!(wait_for(&mut host_rx, |e| matches!(e, NetworkEvent::PeerConnected { .. })).await);
+        assert_eq!(*targets.lock().unwrap(), vec![format!("127.0.0.1:{}", port)]);
+    }
+
+    #[tokio::test]
+    async fn test_host_responds_and_client_initiates() {
+        let port = free_port().await;
+        let (host, mut host_rx) = spawn_host(port).await;
+
+        let (client_tx, mut client_rx) = mpsc::unbounded_channel();
+        let mut client = NetworkManager::new(client_tx, Identity::generate(), ContactStore::in_memory().shared(), None, None, KeepaliveConfig::default(), PqPolicy::default());
+        client.connect_to_host(&invite(&host, port), StaticSecret::from([2u8; 32]), None).await.unwrap();
+
+        assert!(wait_for(&mut host_rx, |e| matches!(
+            e,
+            NetworkEvent::PeerConnected { role: Role::Responder, public_key, .. }
+                if *public_key == PublicKey::from(&StaticSecret::from([2u8; 32])).to_bytes()
+        )).await);
+        assert!(wait_for(&mut client_rx, |e| matches!(
+            e,
+            NetworkEvent::PeerConnected { role: Role::Initiator, public_key, .. } if *public_key == host_public()
+        )).await);
+    }
+
+    /// Aguarda o PeerConnected e abre o canal cifrado da sessão.
+    async fn open_channel(rx: &mut UnboundedReceiver<NetworkEvent>) -> Option<(SessionId, SecureChannel)> {
+        timeout(Duration::from_secs(5), async {
+            while let Some(event) = rx.recv().await {
+                if let NetworkEvent::PeerConnected { session, channel, .. } = event {
+                    return Some((session, channel.open()));
+                }
+            }
+            None
+        }).await.ok().flatten()
+    }
+
+    #[tokio::test]
+    async fn test_channel_keys_open_matching_channels_and_resend() {
+        let port = free_port().await;
+        let (host, mut host_rx) = spawn_host(port).await;
+
+        let (client_tx, mut client_rx) = mpsc::unbounded_channel();
+        let mut client = NetworkManager::new(client_tx, Identity::generate(), ContactStore::in_memory().shared(), None, None, KeepaliveConfig::default(), PqPolicy::default());
+        client.connect_to_host(&invite(&host, port), StaticSecret::from([2u8; 32]), None).await.unwrap();
+
+        let (session, mut client_channel) = open_channel(&mut client_rx).await.unwrap();
+        let (_, mut host_channel) = open_channel(&mut host_rx).await.unwrap();
+
+        let mut outbox = Outbox::new();
+        let peer = [7u8; 32];
+        outbox.push(peer, 1, "alice".to_string(), "pendente".to_string());
+        let resent = resend_unacked(&mut client_channel, &client, session, &outbox, &peer).await;
+        assert_eq!(resent, vec![Resent { id: 1, content: "pendente".to_string(), sent: true }]);
+
+        let data = timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(NetworkEvent::DataReceived(_, data)) = host_rx.recv().await {
+                    return data;
+                }
+            }
+        }).await.unwrap();
+        assert!(matches!(
+            host_channel.open(&data).unwrap(),
+            protocol::Message::Chat { id: 1, content, .. } if content == "pendente"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_first_contact_without_pubkey_agrees_on_root_key() {
+        let port = free_port().await;
+        let (host, mut host_rx) = spawn_host(port).await;
+
+        let (client_tx, mut client_rx) = mpsc::unbounded_channel();
+        let mut client = NetworkManager::new(client_tx, Identity::generate(), ContactStore::in_memory().shared(), None, None, KeepaliveConfig::default(), PqPolicy::default());
+        let uri = host.create_invite("127.0.0.1", port, None, None).to_uri();
+        client.connect_to_host(&uri, StaticSecret::from([2u8; 32]), None).await.unwrap();
+
+        let host_root = root_key(&mut host_rx).await.unwrap();
+        let client_root = root_key(&mut client_rx).await.unwrap();
+        assert_eq!(host_root, client_root);
+    }
+
+    #[tokio::test]
+    async fn test_known_contact_with_new_key_requires_verification() {
+        let contacts = ContactStore::in_memory().shared();
+
+        // Primeiro contato registra a chave do host sob o apelido "host"
+        let port = free_port().await;
+        let (host, _first_rx) = spawn_host(port).await;
+        let (client_tx, mut client_rx) = mpsc::unbounded_channel();
+        let mut client = NetworkManager::new(client_tx, Identity::generate(), contacts.clone(), None, None, KeepaliveConfig::default(), PqPolicy::default());
+        client.connect_to_host(&invite(&host, port), StaticSecret::from([2u8; 32]), Some("host".to_string())).await.unwrap();
+        assert!(wait_for(&mut client_rx, |e| matches!(e, NetworkEvent::Log(msg) if msg.starts_with("Novo contato 'host'"))).await);
+
+        // Outro host (outra identidade) se apresentando como o mesmo contato
+        let port = free_port().await;
+        let (host, _second_rx) = spawn_host(port).await;
+        let (client_tx, mut client_rx) = mpsc::unbounded_channel();
+        let mut client = NetworkManager::new(client_tx, Identity::generate(), contacts.clone(), None, None, KeepaliveConfig::default(), PqPolicy::default());
+        client.connect_to_host(&invite(&host, port), StaticSecret::from([2u8; 32]), Some("host".to_string())).await.unwrap();
+        assert!(wait_for(&mut client_rx, |e| matches!(
+            e,
+            NetworkEvent::FingerprintVerificationRequired { petname, .. } if petname == "host"
+        )).await);
+    }
+
+    #[tokio::test]
+    async fn test_wrong_host_key_in_invite_rejected() {
+        let port = free_port().await;
+        let (host, mut host_rx) = spawn_host(port).await;
+
+        // Convite assinado, mas com uma chave Noise que não é a do host
+        let uri = host.create_invite("127.0.0.1", port, Some([0x11u8; 32]), None).to_uri();
+        let (client_tx, _client_rx) = mpsc::unbounded_channel();
+        let mut client = NetworkManager::new(client_tx, Identity::generate(), ContactStore::in_memory().shared(), None, None, KeepaliveConfig::default(), PqPolicy::default());
+        assert!(client.connect_to_host(&uri, StaticSecret::from([2u8; 32]), None).await.is_err());
+        assert!(wait_for(&mut host_rx, |e| matches!(e, NetworkEvent::ConnectionFailed(_))).await);
+    }
+
+    #[tokio::test]
+    async fn test_reused_invite_rejected() {
+        let port = free_port().await;
+        let (host, mut host_rx) = spawn_host(port).await;
+        let uri = invite(&host, port);
+
+        let (client_tx, _client_rx) = mpsc::unbounded_channel();
+        let mut client = NetworkManager::new(client_tx, Identity::generate(), ContactStore::in_memory().shared(), None, None, KeepaliveConfig::default(), PqPolicy::default());
+        client.connect_to_host(&uri, StaticSecret::from([2u8; 32]), None).await.unwrap();
+        assert!(wait_for(&mut host_rx, |e| matches!(e, NetworkEvent::PeerConnected { .. })).await);
+
+        // O listener continua aberto, mas o mesmo convite não vale uma segunda vez
+        let (client_tx, _client_rx) = mpsc::unbounded_channel();
+        let mut replay = NetworkManager::new(client_tx, Identity::generate(), ContactStore::in_memory().shared(), None, None, KeepaliveConfig::default(), PqPolicy::default());
+        assert!(replay.connect_to_host(&uri, StaticSecret::from([3u8; 32]), None).await.is_err());
+        assert!(wait_for(&mut host_rx, |e| matches!(
+            e,
+            NetworkEvent::ConnectionFailed(msg) if msg.contains("já utilizado")
+        )).await);
+    }
+
+    #[tokio::test]
+    async fn test_host_keeps_concurrent_sessions_apart() {
+        let port = free_port().await;
+        let (host, mut host_rx) = spawn_host(port).await;
+
+        let mut clients = Vec::new();
+        let mut host_sessions = Vec::new();
+        for seed in [2u8, 3u8] {
+            let (client_tx, client_rx) = mpsc::unbounded_channel();
+            let mut client = NetworkManager::new(client_tx, Identity::generate(), ContactStore::in_memory().shared(), None, None, KeepaliveConfig::default(), PqPolicy::default());
+            let session = client.connect_to_host(&invite(&host, port), StaticSecret::from([seed; 32]), None).await.unwrap();
+
+            let connected = timeout(Duration::from_secs(5), async {
+                loop {
+                    if let Some(NetworkEvent::PeerConnected { session, .. }) = host_rx.recv().await {
+                        return session;
                     }
                 }
-                Err(e) => {
-                    return Err(format!("Handshake do host inválido: {}", e));
+            }).await.unwrap();
+            host_sessions.push(connected);
+            clients.push((client, client_rx, session));
+        }
+        assert_ne!(host_sessions[0], host_sessions[1]);
+
+        // Cada sessão do host chega apenas ao seu cliente
+        host.send_message(host_sessions[1], b"para o segundo".to_vec()).await.unwrap();
+        let (_, second_rx, _) = &mut clients[1];
+        assert!(wait_for(second_rx, |e| matches!(e, NetworkEvent::DataReceived(_, data) if data == b"para o segundo")).await);
+        let (_, first_rx, _) = &mut clients[0];
+        assert!(first_rx.try_recv().map_or(true, |e| !matches!(e, NetworkEvent::DataReceived(..))));
+
+        // E o que cada cliente envia chega marcado com a sessão certa
+        let (first, _, first_session) = &clients[0];
+        first.send_message(*first_session, b"do primeiro".to_vec()).await.unwrap();
+        let expected = host_sessions[0];
+        assert!(wait_for(&mut host_rx, |e| matches!(
+            e,
+            NetworkEvent::DataReceived(session, data) if *session == expected && data == b"do primeiro"
+        )).await);
+    }
+
+    #[tokio::test]
+    async fn test_invite_from_other_identity_rejected() {
+        let port = free_port().await;
+        let (_host, _host_rx) = spawn_host(port).await;
+
+        // Convite bem assinado, mas por uma identidade que não é a do host
+        let uri = Invite::new(&Identity::generate(), "127.0.0.1", port, None, None, false, DEFAULT_INVITE_TTL).to_uri();
+        let (client_tx, _client_rx) = mpsc::unbounded_channel();
+        let mut client = NetworkManager::new(client_tx, Identity::generate(), ContactStore::in_memory().shared(), None, None, KeepaliveConfig::default(), PqPolicy::default());
+        let result = client.connect_to_host(&uri, StaticSecret::from([2u8; 32]), None).await;
+        assert!(result.unwrap_err().contains("não corresponde ao convite"));
+    }
+
+    #[tokio::test]
+    async fn test_tor_failure_does_not_fall_back_to_clearnet() {
+        let port = free_port().await;
+        let (host, mut host_rx) = spawn_host(port).await;
+
+        // Proxy apontando para uma porta sem ninguém escutando
+        let tor_config = TorConfig {
+            socks_addr: "127.0.0.1".to_string(),
+            socks_port: free_port().await,
+            ..TorConfig::default()
+        };
+        let (client_tx, _client_rx) = mpsc::unbounded_channel();
+        let mut client = NetworkManager::new(client_tx, Identity::generate(), ContactStore::in_memory().shared(), None, Some(tor_config), KeepaliveConfig::default(), PqPolicy::default());
+
+        let result = client.connect_to_host(&invite(&host, port), StaticSecret::from([2u8; 32]), None).await;
+        assert!(result.unwrap_err().contains("Tor"));
+
+        // O host não pode ter recebido nenhuma conexão direta
+        let received = timeout(Duration::from_millis(300), async {
+            wait_for(&mut host_rx, |e| matches!(e, NetworkEvent::Log(msg) if msg.starts_with("Conexão recebida"))).await
+        }).await.unwrap_or(false);
+        assert!(!received);
+    }
+
+    /// Repassa conexões para `target`; abortar as tarefas derruba as conexões
+    /// abertas sem fechamento WebSocket, como uma queda de rede.
+    async fn spawn_relay(target: u16) -> (u16, Arc<std::sync::Mutex<Vec<tokio::task::JoinHandle<()>>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let links = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let relay_links = links.clone();
+        tokio::spawn(async move {
+            while let Ok((mut inbound, _)) = listener.accept().await {
+                let link = tokio::spawn(async move {
+                    let mut outbound = TcpStream::connect(("127.0.0.1", target)).await.unwrap();
+                    let _ = tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await;
+                });
+                relay_links.lock().unwrap().push(link);
+            }
+        });
+        (port, links)
+    }
+
+    /// Aguarda o PeerConnected e retorna a sessão.
+    async fn connected_session(rx: &mut UnboundedReceiver<NetworkEvent>) -> Option<SessionId> {
+        timeout(Duration::from_secs(5), async {
+            while let Some(event) = rx.recv().await {
+                if let NetworkEvent::PeerConnected { session, .. } = event {
+                    return Some(session);
                 }
             }
-        } else {
-            return Err("Falha ao receber handshake do host".to_string());
+            None
+        }).await.ok().flatten()
+    }
+
+    #[tokio::test]
+    async fn test_dropped_connection_resumes_same_session() {
+        let port = free_port().await;
+        let (host, mut host_rx) = spawn_host(port).await;
+        let (relay_port, links) = spawn_relay(port).await;
+
+        let (client_tx, mut client_rx) = mpsc::unbounded_channel();
+        let mut client = NetworkManager::new(client_tx, Identity::generate(), ContactStore::in_memory().shared(), None, None, KeepaliveConfig::default(), PqPolicy::default());
+        let uri = host.create_invite("127.0.0.1", relay_port, Some(host_public()), None).to_uri();
+        let session = client.connect_to_host(&uri, StaticSecret::from([2u8; 32]), None).await.unwrap();
+        let host_session = connected_session(&mut host_rx).await.unwrap();
+        assert_eq!(connected_session(&mut client_rx).await, Some(session));
+
+        // Queda de rede: nenhum dos lados recebe um fechamento explícito
+        for link in links.lock().unwrap().drain(..) {
+            link.abort();
         }
+        assert!(wait_for(&mut host_rx, |e| matches!(e, NetworkEvent::ConnectionLost(s) if *s == host_session)).await);
+        assert!(wait_for(&mut client_rx, |e| matches!(e, NetworkEvent::Reconnecting { attempt: 1, .. })).await);
 
-        Ok(())
+        // O cliente volta com o ticket e os dois lados mantêm a mesma sessão
+        assert_eq!(connected_session(&mut host_rx).await, Some(host_session));
+        assert_eq!(connected_session(&mut client_rx).await, Some(session));
+        client.send_message(session, b"de volta".to_vec()).await.unwrap();
+        assert!(wait_for(&mut host_rx, |e| matches!(
+            e,
+            NetworkEvent::DataReceived(s, data) if *s == host_session && data == b"de volta"
+        )).await);
+
+        // Um encerramento explícito não é retomado
+        client.close_session(session).await;
+        assert!(wait_for(&mut host_rx, |e| matches!(e, NetworkEvent::PeerDisconnected(s) if *s == host_session)).await);
+        assert!(client.send_message(session, b"depois".to_vec()).await.is_err());
     }
 
-    /// Envia uma mensagem criptografada para o par conectado.
-    pub async fn send_message(&self, data: Vec<u8>) -> Result<(), &'static str> {
-        if let Some(sender) = &mut *self.sender.lock().await {
-            sender.send(Message::binary(data)).await
-                .map_err(|_| "Falha ao enviar mensagem")?;
-            Ok(())
-        } else {
-            Err("Não conectado")
+    #[test]
+    fn test_resume_ticket_bound_to_identity() {
+        let mut tickets = ResumeTickets::default();
+        let ticket = [7u8; TOKEN_LEN];
+        tickets.issue(ticket, 3, [1u8; 32], Some("alice".to_string()));
+
+        // Outra identidade com o mesmo ticket não retoma a sessão
+        assert_eq!(tickets.redeem(&ticket, &[2u8; 32]), None);
+        assert_eq!(tickets.redeem(&ticket, &[1u8; 32]), Some((3, Some("alice".to_string()))));
+        // Uso único
+        assert_eq!(tickets.redeem(&ticket, &[1u8; 32]), None);
+
+        // Um ticket novo da mesma sessão substitui o anterior
+        tickets.issue(ticket, 3, [1u8; 32], None);
+        tickets.issue([8u8; TOKEN_LEN], 3, [1u8; 32], None);
+        assert_eq!(tickets.redeem(&ticket, &[1u8; 32]), None);
+
+        assert_eq!(reconnect_delay(1), RECONNECT_BASE_DELAY);
+        assert_eq!(reconnect_delay(3), RECONNECT_BASE_DELAY * 4);
+        assert_eq!(reconnect_delay(RECONNECT_ATTEMPTS), RECONNECT_MAX_DELAY);
+    }
+
+    /// Keepalive curto para os testes de detecção de queda.
+    fn fast_keepalive() -> KeepaliveConfig {
+        KeepaliveConfig {
+            ping_interval: Duration::from_millis(100),
+            idle_timeout: Duration::from_millis(500),
+            handshake_timeout: Duration::from_millis(500),
+        }
+    }
+
+    /// Repassa conexões para `target` até `freeze`: daí em diante as conexões
+    /// abertas ficam mudas, sem erro nem fechamento, como um TCP que sumiu.
+    async fn spawn_frozen_relay(target: u16) -> (u16, Arc<tokio::sync::Notify>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let freeze = Arc::new(tokio::sync::Notify::new());
+        let relay_freeze = freeze.clone();
+        tokio::spawn(async move {
+            while let Ok((mut inbound, _)) = listener.accept().await {
+                let freeze = relay_freeze.clone();
+                tokio::spawn(async move {
+                    let mut outbound = TcpStream::connect(("127.0.0.1", target)).await.unwrap();
+                    let frozen = tokio::select! {
+                        _ = tokio::io::copy_bidirectional(&mut inbound, &mut outbound) => false,
+                        _ = freeze.notified() => true,
+                    };
+                    if frozen {
+                        std::future::pending::<()>().await;
+                    }
+                });
+            }
+        });
+        (port, freeze)
+    }
+
+    #[tokio::test]
+    async fn test_keepalive_measures_rtt() {
+        let port = free_port().await;
+        let (host, mut host_rx) = spawn_host_with(port, fast_keepalive()).await;
+
+        let (client_tx, mut client_rx) = mpsc::unbounded_channel();
+        let mut client = NetworkManager::new(client_tx, Identity::generate(), ContactStore::in_memory().shared(), None, None, fast_keepalive(), PqPolicy::default());
+        let session = client.connect_to_host(&invite(&host, port), StaticSecret::from([2u8; 32]), None).await.unwrap();
+
+        assert!(wait_for(&mut client_rx, |e| matches!(
+            e,
+            NetworkEvent::Latency { session: s, rtt } if *s == session && *rtt < Duration::from_secs(1)
+        )).await);
+        assert!(wait_for(&mut host_rx, |e| matches!(e, NetworkEvent::Latency { .. })).await);
+    }
+
+    #[tokio::test]
+    async fn test_host_without_event_receiver_ends_sessions() {
+        let port = free_port().await;
+        let (host, host_rx) = spawn_host_with(port, fast_keepalive()).await;
+
+        let (client_tx, mut client_rx) = mpsc::unbounded_channel();
+        let mut client = NetworkManager::new(client_tx, Identity::generate(), ContactStore::in_memory().shared(), None, None, fast_keepalive(), PqPolicy::default());
+        let session = client.connect_to_host(&invite(&host, port), StaticSecret::from([2u8; 32]), None).await.unwrap();
+
+        // Quem usava a biblioteca largou o receptor: as tarefas do host
+        // encerram a sessão em vez de entrar em pânico
+        drop(host_rx);
+        assert!(wait_for(&mut client_rx, |e| matches!(e, NetworkEvent::ConnectionLost(s) if *s == session)).await);
+        assert!(client.connect_to_host(&invite(&host, port), StaticSecret::from([3u8; 32]), None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_silent_peer_detected_as_lost() {
+        let port = free_port().await;
+        let (host, mut host_rx) = spawn_host_with(port, fast_keepalive()).await;
+        let (relay_port, freeze) = spawn_frozen_relay(port).await;
+
+        let (client_tx, mut client_rx) = mpsc::unbounded_channel();
+        let mut client = NetworkManager::new(client_tx, Identity::generate(), ContactStore::in_memory().shared(), None, None, fast_keepalive(), PqPolicy::default());
+        let uri = host.create_invite("127.0.0.1", relay_port, Some(host_public()), None).to_uri();
+        let session = client.connect_to_host(&uri, StaticSecret::from([2u8; 32]), None).await.unwrap();
+        assert!(wait_for(&mut client_rx, |e| matches!(e, NetworkEvent::Latency { .. })).await);
+
+        // Nenhum dos lados recebe erro ou fechamento: só o keepalive percebe
+        freeze.notify_waiters();
+        assert!(wait_for(&mut client_rx, |e| matches!(e, NetworkEvent::ConnectionDegraded { session: s, .. } if *s == session)).await);
+        assert!(wait_for(&mut client_rx, |e| matches!(e, NetworkEvent::ConnectionLost(s) if *s == session)).await);
+        assert!(wait_for(&mut host_rx, |e| matches!(e, NetworkEvent::ConnectionLost(_))).await);
+    }
+
+    #[tokio::test]
+    async fn test_stalled_handshake_times_out() {
+        let port = free_port().await;
+        let (_host, mut host_rx) = spawn_host_with(port, fast_keepalive()).await;
+
+        // Conexão TCP que nunca inicia o WebSocket
+        let _stalled = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        assert!(wait_for(&mut host_rx, |e| matches!(
+            e,
+            NetworkEvent::ConnectionFailed(msg) if msg.contains("Tempo esgotado")
+        )).await);
+    }
+
+    #[tokio::test]
+    async fn test_ipv6_host_on_random_port() {
+        let (host_tx, mut host_rx) = mpsc::unbounded_channel();
+        let mut host = NetworkManager::new(host_tx, Identity::generate(), ContactStore::in_memory().shared(), None, None, KeepaliveConfig::default(), PqPolicy::default());
+        let addr = host.start_host("[::1]:0".parse().unwrap(), StaticSecret::from([1u8; 32])).await.unwrap();
+        assert_ne!(addr.port(), 0);
+
+        let uri = host.create_invite("::1", addr.port(), Some(host_public()), None).to_uri();
+        let (client_tx, _client_rx) = mpsc::unbounded_channel();
+        let mut client = NetworkManager::new(client_tx, Identity::generate(), ContactStore::in_memory().shared(), None, None, KeepaliveConfig::default(), PqPolicy::default());
+        client.connect_to_host(&uri, StaticSecret::from([2u8; 32]), None).await.unwrap();
+        assert!(wait_for(&mut host_rx, |e| matches!(e, NetworkEvent::PeerConnected { .. })).await);
+    }
+
+    #[tokio::test]
+    async fn test_peers_over_memory_transport() {
+        let (host_tx, mut host_rx) = mpsc::unbounded_channel();
+        let host = NetworkManager::new(host_tx, Identity::generate(), ContactStore::in_memory().shared(), None, None, fast_keepalive(), PqPolicy::default());
+        let (listener, connector) = transport::memory_listener();
+        host.serve(listener, StaticSecret::from([1u8; 32]));
+
+        let (client_tx, mut client_rx) = mpsc::unbounded_channel();
+        let mut client = NetworkManager::new(client_tx, Identity::generate(), ContactStore::in_memory().shared(), None, None, fast_keepalive(), PqPolicy::default());
+        let invite = host.create_invite("memória", 0, Some(host_public()), None);
+        let session = client.connect_with(Arc::new(connector), &invite, StaticSecret::from([2u8; 32]), None).await.unwrap();
+        let host_session = connected_session(&mut host_rx).await.unwrap();
+
+        client.send_message(session, b"sem sockets".to_vec()).await.unwrap();
+        assert!(wait_for(&mut host_rx, |e| matches!(
+            e,
+            NetworkEvent::DataReceived(s, data) if *s == host_session && data == b"sem sockets"
+        )).await);
+        // Keepalive pelos quadros de ping do próprio transporte
+        assert!(wait_for(&mut client_rx, |e| matches!(e, NetworkEvent::Latency { session: s, .. } if *s == session)).await);
+
+        client.close_session(session).await;
+        assert!(wait_for(&mut host_rx, |e| matches!(e, NetworkEvent::PeerDisconnected(s) if *s == host_session)).await);
+    }
+
+    #[tokio::test]
+    async fn test_post_quantum_policy() {
+        let manager = |policy| {
+            let (tx, rx) = mpsc::unbounded_channel();
+            (NetworkManager::new(tx, Identity::generate(), ContactStore::in_memory().shared(), None, None, KeepaliveConfig::default(), policy), rx)
+        };
+        let serve = |host: &NetworkManager| {
+            let (listener, connector) = transport::memory_listener();
+            host.serve(listener, StaticSecret::from([1u8; 32]));
+            Arc::new(connector)
+        };
+
+        // Padrão dos dois lados: troca híbrida
+        let (host, mut host_rx) = manager(PqPolicy::Prefer);
+        let connector = serve(&host);
+        let (mut client, mut client_rx) = manager(PqPolicy::Prefer);
+        let invite = host.create_invite("memória", 0, Some(host_public()), None);
+        assert!(invite.post_quantum);
+        client.connect_with(connector, &invite, StaticSecret::from([2u8; 32]), None).await.unwrap();
+        for rx in [&mut host_rx, &mut client_rx] {
+            assert!(wait_for(rx, |e| matches!(e, NetworkEvent::PeerConnected { post_quantum: true, .. })).await);
         }
+
+        // Host sem ML-KEM: aceito por padrão, recusado com --pq require antes de conectar
+        let (host, mut host_rx) = manager(PqPolicy::Off);
+        let connector = serve(&host);
+        let invite = host.create_invite("memória", 0, Some(host_public()), None);
+        assert!(!invite.post_quantum);
+        let (mut strict, _strict_rx) = manager(PqPolicy::Require);
+        assert!(strict.connect_with(connector.clone(), &invite, StaticSecret::from([2u8; 32]), None).await.is_err());
+        let (mut client, _client_rx) = manager(PqPolicy::Prefer);
+        client.connect_with(connector, &invite, StaticSecret::from([2u8; 32]), None).await.unwrap();
+        assert!(wait_for(&mut host_rx, |e| matches!(e, NetworkEvent::PeerConnected { post_quantum: false, .. })).await);
+
+        // Host que exige ML-KEM recusa o cliente clássico no handshake
+        let (host, mut host_rx) = manager(PqPolicy::Require);
+        let connector = serve(&host);
+        let invite = host.create_invite("memória", 0, Some(host_public()), None);
+        let (mut classic, _classic_rx) = manager(PqPolicy::Off);
+        assert!(classic.connect_with(connector, &invite, StaticSecret::from([2u8; 32]), None).await.is_err());
+        assert!(wait_for(&mut host_rx, |e| matches!(
+            e,
+            NetworkEvent::ConnectionFailed(reason) if reason.starts_with("Conexão recusada")
+        )).await);
+    }
+
+    #[tokio::test]
+    async fn test_onion_requires_tor() {
+        let (client_tx, _client_rx) = mpsc::unbounded_channel();
+        let mut client = NetworkManager::new(client_tx, Identity::generate(), ContactStore::in_memory().shared(), None, None, KeepaliveConfig::default(), PqPolicy::default());
+
+        let onion = format!("{}.onion", "a".repeat(56));
+        let uri = Invite::new(&Identity::generate(), &onion, 9001, Some(host_public()), None, false, DEFAULT_INVITE_TTL).to_uri();
+        let result = client.connect_to_host(&uri, StaticSecret::from([2u8; 32]), None).await;
+        assert!(result.unwrap_err().contains("--tor"));
     }
 }
@@ -2,28 +2,30 @@ use rand::{Rng, rngs::OsRng};
 
 /// Tamanhos de bloco de padding para ofuscar tamanhos de mensagens
 const PADDING_BLOCKS: &[usize] = &[128, 256, 512, 1024, 2048, 4096];
+/// Bytes do cabeçalho com o tamanho original
+const LENGTH_PREFIX: usize = 4;
+/// Maior mensagem aceita (cabe com folga em um frame WebSocket)
+pub const MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
 
 /// Adiciona padding aleatório à mensagem para ofuscar o tamanho real
-pub fn add_padding(data: &[u8]) -> Vec<u8> {
+pub fn add_padding(data: &[u8]) -> Result<Vec<u8>, PaddingError> {
     let original_len = data.len();
+    if original_len > MAX_MESSAGE_SIZE {
+        return Err(PaddingError::TooLarge);
+    }
 
     // Encontra o próximo tamanho de bloco que acomoda os dados
     let padded_size = PADDING_BLOCKS
         .iter()
-        .find(|&&size| size >= original_len + 2) // +2 para armazenar o tamanho original
+        .find(|&&size| size >= original_len + LENGTH_PREFIX)
         .copied()
-        .unwrap_or(((original_len + 2 + 4095) / 4096) * 4096); // Arredonda para múltiplo de 4096
+        .unwrap_or((original_len + LENGTH_PREFIX).div_ceil(4096) * 4096); // Arredonda para múltiplo de 4096
 
-    let padding_len = padded_size - original_len - 2;
+    let padding_len = padded_size - original_len - LENGTH_PREFIX;
 
-    // Formato: [tamanho_original: u16][dados][padding_aleatório]
+    // Formato: [tamanho_original: u32][dados][padding_aleatório]
     let mut padded = Vec::with_capacity(padded_size);
-
-    // Armazena o tamanho original (máx 65535 bytes)
-    if original_len > u16::MAX as usize {
-        panic!("Mensagem muito grande para padding");
-    }
-    padded.extend_from_slice(&(original_len as u16).to_le_bytes());
+    padded.extend_from_slice(&(original_len as u32).to_le_bytes());
 
     // Adiciona dados originais
     padded.extend_from_slice(data);
@@ -33,36 +35,38 @@ pub fn add_padding(data: &[u8]) -> Vec<u8> {
     let random_padding: Vec<u8> = (0..padding_len).map(|_| rng.gen()).collect();
     padded.extend_from_slice(&random_padding);
 
-    padded
+    Ok(padded)
 }
 
 /// Remove o padding e retorna os dados originais
 pub fn remove_padding(padded_data: &[u8]) -> Result<Vec<u8>, PaddingError> {
-    if padded_data.len() < 2 {
+    let Some((prefix, rest)) = padded_data.split_first_chunk::<LENGTH_PREFIX>() else {
         return Err(PaddingError::InvalidPadding);
-    }
+    };
 
     // Lê o tamanho original
-    let original_len = u16::from_le_bytes([padded_data[0], padded_data[1]]) as usize;
+    let original_len = u32::from_le_bytes(*prefix) as usize;
 
-    if original_len + 2 > padded_data.len() {
+    if original_len > rest.len() {
         return Err(PaddingError::InvalidPadding);
     }
 
     // Extrai dados originais
-    Ok(padded_data[2..2 + original_len].to_vec())
+    Ok(rest[..original_len].to_vec())
 }
 
 /// Erro de padding
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PaddingError {
     InvalidPadding,
+    TooLarge,
 }
 
 impl std::fmt::Display for PaddingError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             PaddingError::InvalidPadding => write!(f, "Padding inválido"),
+            PaddingError::TooLarge => write!(f, "Mensagem muito grande para padding"),
         }
     }
 }
@@ -76,7 +80,7 @@ mod tests {
     #[test]
     fn test_padding_roundtrip() {
         let original = b"Hello, World!";
-        let padded = add_padding(original);
+        let padded = add_padding(original).unwrap();
         let unpadded = remove_padding(&padded).unwrap();
 
         assert_eq!(original, unpadded.as_slice());
@@ -90,9 +94,9 @@ mod tests {
         let msg2 = b"abc";
         let msg3 = b"abcdefghijklmnop";
 
-        let padded1 = add_padding(msg1);
-        let padded2 = add_padding(msg2);
-        let padded3 = add_padding(msg3);
+        let padded1 = add_padding(msg1).unwrap();
+        let padded2 = add_padding(msg2).unwrap();
+        let padded3 = add_padding(msg3).unwrap();
 
         // Todas devem ter o mesmo tamanho (128 bytes - o menor bloco)
         assert_eq!(padded1.len(), 128);
@@ -103,7 +107,7 @@ mod tests {
     #[test]
     fn test_padding_larger_messages() {
         let msg = vec![0u8; 500]; // 500 bytes
-        let padded = add_padding(&msg);
+        let padded = add_padding(&msg).unwrap();
 
         // Deve arredondar para 512 bytes
         assert_eq!(padded.len(), 512);
@@ -114,7 +118,19 @@ mod tests {
 
     #[test]
     fn test_invalid_padding() {
-        let invalid = vec![0xFF, 0xFF, 0, 0]; // Tamanho inválido
+        let invalid = vec![0xFF, 0xFF, 0, 0, 0]; // Tamanho inválido
         assert!(remove_padding(&invalid).is_err());
+        assert!(remove_padding(&[1, 0]).is_err());
+    }
+
+    #[test]
+    fn test_padding_beyond_u16() {
+        // Blocos de arquivo passam de 65535 bytes
+        let msg = vec![7u8; 100_000];
+        let padded = add_padding(&msg).unwrap();
+        assert_eq!(padded.len() % 4096, 0);
+        assert_eq!(remove_padding(&padded).unwrap(), msg);
+
+        assert_eq!(add_padding(&vec![0u8; MAX_MESSAGE_SIZE + 1]), Err(PaddingError::TooLarge));
     }
 }
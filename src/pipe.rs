@@ -0,0 +1,174 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::app::{App, DisplayMessage};
+use crate::contacts::SharedContacts;
+use crate::event::Event;
+use crate::network_secure::SessionId;
+
+/// Mensagem de chat recebida, como sai no stdout: uma linha JSON por mensagem.
+#[derive(Debug, Serialize)]
+struct ChatLine<'a> {
+    session: SessionId,
+    sender: &'a str,
+    content: &'a str,
+    /// Segundos desde a época Unix em que a mensagem foi decifrada
+    timestamp: u64,
+    /// A chave do par foi verificada (/verify) e pertence ao contato
+    verified: bool,
+}
+
+/// Front end de `--no-tui`: linhas da entrada padrão fazem o papel do que
+/// seria digitado na TUI, mensagens recebidas vão para o stdout em JSON e o
+/// resto (avisos, status) para o stderr.
+pub struct Pipe {
+    /// Linhas de texto que esperam uma conversa em foco para serem enviadas
+    pending: VecDeque<String>,
+    input_closed: bool,
+    last_status: String,
+}
+
+/// Lê a entrada padrão linha a linha; o fim dela vira `Event::InputClosed`.
+pub fn spawn_stdin_reader(sender: UnboundedSender<Event>) {
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if sender.send(Event::Input(line)).is_err() {
+                return;
+            }
+        }
+        let _ = sender.send(Event::InputClosed);
+    });
+}
+
+impl Pipe {
+    pub fn new() -> Self {
+        Self { pending: VecDeque::new(), input_closed: false, last_status: String::new() }
+    }
+
+    pub fn push_line(&mut self, line: String) {
+        self.pending.push_back(line);
+    }
+
+    pub fn close_input(&mut self) {
+        self.input_closed = true;
+    }
+
+    /// Próxima linha pronta para virar entrada do `App`. Comandos passam na
+    /// hora; texto espera uma conversa ou sala em foco, para que o que foi
+    /// redirecionado antes da conexão não se perca.
+    pub fn next_line(&mut self, app: &App) -> Option<String> {
+        let ready = self.pending.front().is_some_and(|line| {
+            line.trim_start().starts_with('/') || app.active_session.is_some() || app.active_room.is_some()
+        });
+        if ready { self.pending.pop_front() } else { None }
+    }
+
+    /// A entrada acabou e tudo o que foi lido já saiu; `delivered` indica que
+    /// não há mensagens aguardando ACK.
+    pub fn finished(&self, delivered: bool) -> bool {
+        self.input_closed && self.pending.is_empty() && delivered
+    }
+
+    /// Escreve as mensagens novas do `App` e as descarta: sem tela, não há o
+    /// que esmaecer depois.
+    pub fn flush(&mut self, app: &mut App, contacts: &SharedContacts) -> io::Result<()> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |t| t.as_secs());
+        let mut stdout = io::stdout().lock();
+        let mut stderr = io::stderr().lock();
+
+        for message in app.messages.drain(..) {
+            match chat_session(&message) {
+                Some(session) => {
+                    let verified = app.conversations.get(&session).is_some_and(|conversation| {
+                        contacts.lock().unwrap()
+                            .find_by_key(&conversation.remote_identity)
+                            .is_some_and(|contact| contact.verified)
+                    });
+                    let line = ChatLine {
+                        session,
+                        sender: &message.sender,
+                        content: &message.content,
+                        timestamp,
+                        verified,
+                    };
+                    serde_json::to_writer(&mut stdout, &line)?;
+                    writeln!(stdout)?;
+                }
+                // O eco do que nós mesmos enviamos não interessa a quem lê o stdout
+                None if message.status.is_some() => {}
+                None => writeln!(stderr, "{}", system_line(&message))?,
+            }
+        }
+        stdout.flush()?;
+
+        if app.status_message != self.last_status {
+            self.last_status = app.status_message.clone();
+            writeln!(stderr, "[status] {}", self.last_status)?;
+        }
+        Ok(())
+    }
+}
+
+/// Sessão de uma mensagem de chat recebida de um par; `None` para avisos,
+/// mensagens nossas e mensagens de sala.
+fn chat_session(message: &DisplayMessage) -> Option<SessionId> {
+    match (message.session, message.id, message.status) {
+        (Some(session), Some(_), None) => Some(session),
+        _ => None,
+    }
+}
+
+/// Aviso em texto, com a conversa ou sala de origem quando houver.
+fn system_line(message: &DisplayMessage) -> String {
+    match (message.session, message.room) {
+        (Some(session), _) => format!("[{}#{}] {}", message.sender, session, message.content),
+        (None, Some(room_id)) => format!("[{}@{}] {}", message.sender, crate::room::short_id(&room_id), message.content),
+        (None, None) => format!("[{}] {}", message.sender, message.content),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delivery::DeliveryStatus;
+
+    #[test]
+    fn test_text_waits_for_conversation() {
+        let mut app = App::new();
+        let mut pipe = Pipe::new();
+        pipe.push_line("olá".into());
+        pipe.push_line("/quit".into());
+
+        assert_eq!(pipe.next_line(&app), None);
+        app.active_session = Some(1);
+        assert_eq!(pipe.next_line(&app).as_deref(), Some("olá"));
+        assert_eq!(pipe.next_line(&app).as_deref(), Some("/quit"));
+
+        assert!(!pipe.finished(true));
+        pipe.close_input();
+        assert!(!pipe.finished(false));
+        assert!(pipe.finished(true));
+    }
+
+    #[test]
+    fn test_only_incoming_chat_goes_to_stdout() {
+        let mut incoming = DisplayMessage::new("oi".into(), "alice".into());
+        incoming.session = Some(3);
+        incoming.id = Some(9);
+        assert_eq!(chat_session(&incoming), Some(3));
+
+        let mut outgoing = incoming.clone();
+        outgoing.status = Some(DeliveryStatus::Pending);
+        assert_eq!(chat_session(&outgoing), None);
+
+        let mut notice = DisplayMessage::new("↻ Sessão retomada".into(), "Sistema".into());
+        notice.session = Some(3);
+        assert_eq!(chat_session(&notice), None);
+        assert_eq!(system_line(&notice), "[Sistema#3] ↻ Sessão retomada");
+    }
+}
@@ -0,0 +1,469 @@
+use crate::padding::{add_padding, remove_padding, PaddingError};
+use crate::ratchet::{RatchetError, RatchetMessage, RatchetSession};
+use crate::room::RoomWire;
+use crate::sas::VerifiedNotice;
+use crate::transfer::FileWire;
+
+/// Versão do protocolo de mensagens falada por este binário.
+pub const PROTOCOL_VERSION: u8 = 1;
+/// Versão mais antiga que ainda aceitamos negociar.
+pub const MIN_PROTOCOL_VERSION: u8 = 1;
+
+/// Mensagens seguidas na mesma cadeia de envio antes de pedir um passo DH ao par
+const REKEY_AFTER: u64 = 500;
+
+/// Identificador de uma mensagem de chat, usado por ACKs e recibos.
+pub type MessageId = u64;
+
+/// Tipos do envelope; o byte de tipo vem logo após a versão.
+mod kind {
+    pub const CHAT: u8 = 0x01;
+    pub const ACK: u8 = 0x02;
+    pub const TYPING: u8 = 0x03;
+    pub const RECEIPT: u8 = 0x04;
+    pub const FILE_CHUNK: u8 = 0x05;
+    pub const REKEY: u8 = 0x06;
+    pub const CLOSE: u8 = 0x07;
+    pub const PING: u8 = 0x08;
+    pub const PONG: u8 = 0x09;
+    pub const VERIFIED: u8 = 0x0a;
+    pub const ROOM: u8 = 0x0b;
+    pub const FILE_CONTROL: u8 = 0x0c;
+}
+
+/// Recibo de uma mensagem de chat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Receipt {
+    Delivered,
+    Read,
+}
+
+/// Conteúdo cifrado trocado entre os pares: chat ou mensagens de controle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Chat { id: MessageId, sender: String, content: String },
+    /// Confirmação de que o envelope com este id chegou
+    Ack { id: MessageId },
+    /// O par começou ou parou de digitar
+    Typing { active: bool },
+    Receipt { id: MessageId, receipt: Receipt },
+    /// Pedido (ou resposta) de passo DH: o par responde e as duas cadeias trocam de chave
+    Rekey { request: bool },
+    /// Encerramento da sessão pelo par, com o motivo
+    Close { reason: String },
+    Ping { nonce: u64 },
+    Pong { nonce: u64 },
+    /// O remetente confirmou que o SAS da sessão confere
+    Verified(VerifiedNotice),
+    /// Controle ou conteúdo de sala em grupo
+    Room(RoomWire),
+    /// Transferência de arquivo (blocos usam o tipo file-chunk)
+    File(FileWire),
+}
+
+/// Envelope versionado: `[versão: u8][tipo: u8][corpo]`, inteiros em big-endian.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    pub version: u8,
+    pub message: Message,
+}
+
+impl Envelope {
+    pub fn new(version: u8, message: Message) -> Self {
+        Self { version, message }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.u8(self.version);
+        match &self.message {
+            Message::Chat { id, sender, content } => {
+                w.u8(kind::CHAT);
+                w.u64(*id);
+                w.string(sender);
+                w.string(content);
+            }
+            Message::Ack { id } => {
+                w.u8(kind::ACK);
+                w.u64(*id);
+            }
+            Message::Typing { active } => {
+                w.u8(kind::TYPING);
+                w.bool(*active);
+            }
+            Message::Receipt { id, receipt } => {
+                w.u8(kind::RECEIPT);
+                w.u64(*id);
+                w.u8(match receipt {
+                    Receipt::Delivered => 0,
+                    Receipt::Read => 1,
+                });
+            }
+            Message::Rekey { request } => {
+                w.u8(kind::REKEY);
+                w.bool(*request);
+            }
+            Message::Close { reason } => {
+                w.u8(kind::CLOSE);
+                w.string(reason);
+            }
+            Message::Ping { nonce } => {
+                w.u8(kind::PING);
+                w.u64(*nonce);
+            }
+            Message::Pong { nonce } => {
+                w.u8(kind::PONG);
+                w.u64(*nonce);
+            }
+            Message::Verified(notice) => {
+                w.u8(kind::VERIFIED);
+                notice.encode(&mut w);
+            }
+            Message::Room(room) => {
+                w.u8(kind::ROOM);
+                room.encode(&mut w);
+            }
+            Message::File(FileWire::Chunk(chunk)) => {
+                w.u8(kind::FILE_CHUNK);
+                chunk.encode(&mut w);
+            }
+            Message::File(file) => {
+                w.u8(kind::FILE_CONTROL);
+                file.encode(&mut w);
+            }
+        }
+        w.into_bytes()
+    }
+
+    /// Decodifica um envelope. Versões fora do intervalo suportado e tipos
+    /// desconhecidos são erros distintos, para a interface poder explicá-los.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProtocolError> {
+        let mut r = Reader::new(bytes);
+        let version = r.u8()?;
+        if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version) {
+            return Err(ProtocolError::UnsupportedVersion(version));
+        }
+        let message = match r.u8()? {
+            kind::CHAT => Message::Chat { id: r.u64()?, sender: r.string()?, content: r.string()? },
+            kind::ACK => Message::Ack { id: r.u64()? },
+            kind::TYPING => Message::Typing { active: r.bool()? },
+            kind::RECEIPT => Message::Receipt {
+                id: r.u64()?,
+                receipt: match r.u8()? {
+                    0 => Receipt::Delivered,
+                    1 => Receipt::Read,
+                    _ => return Err(ProtocolError::Malformed),
+                },
+            },
+            kind::REKEY => Message::Rekey { request: r.bool()? },
+            kind::CLOSE => Message::Close { reason: r.string()? },
+            kind::PING => Message::Ping { nonce: r.u64()? },
+            kind::PONG => Message::Pong { nonce: r.u64()? },
+            kind::VERIFIED => Message::Verified(VerifiedNotice::decode(&mut r)?),
+            kind::ROOM => Message::Room(RoomWire::decode(&mut r)?),
+            kind::FILE_CHUNK => Message::File(FileWire::Chunk(crate::transfer::FileChunk::decode(&mut r)?)),
+            kind::FILE_CONTROL => Message::File(FileWire::decode(&mut r)?),
+            other => return Err(ProtocolError::UnknownType(other)),
+        };
+        r.finish()?;
+        Ok(Self { version, message })
+    }
+}
+
+/// Escolhe a maior versão comum aos dois intervalos `(mínima, máxima)`.
+pub fn negotiate_version(local: (u8, u8), remote: (u8, u8)) -> Option<u8> {
+    let version = local.1.min(remote.1);
+    (version >= local.0.max(remote.0)).then_some(version)
+}
+
+/// Canal cifrado de uma sessão: envelope na versão negociada, padding e
+/// Double Ratchet, nesta ordem.
+pub struct SecureChannel {
+    ratchet: RatchetSession,
+    version: u8,
+    /// Cadeia de envio para a qual já pedimos um passo DH
+    rekey_requested: bool,
+}
+
+impl SecureChannel {
+    pub fn new(ratchet: RatchetSession, version: u8) -> Self {
+        Self { ratchet, version, rekey_requested: false }
+    }
+
+    /// Serializa, aplica padding e cifra uma mensagem.
+    pub fn seal(&mut self, message: Message) -> Result<Vec<u8>, ChannelError> {
+        let plaintext = Envelope::new(self.version, message).to_bytes();
+        // Adiciona padding para ofuscar tamanho
+        let padded = add_padding(&plaintext).map_err(ChannelError::Padding)?;
+        // Criptografa com ratchet (PFS + proteção replay)
+        let ratchet_msg = self.ratchet.encrypt(&padded).map_err(ChannelError::Ratchet)?;
+        Ok(ratchet_msg.to_bytes())
+    }
+
+    /// Decifra, remove o padding e decodifica uma mensagem recebida.
+    pub fn open(&mut self, data: &[u8]) -> Result<Message, ChannelError> {
+        // Converte bytes e descriptografa com verificação de replay
+        let ratchet_msg = RatchetMessage::from_bytes(data).map_err(ChannelError::Ratchet)?;
+        let padded = self.ratchet.decrypt(&ratchet_msg).map_err(ChannelError::Ratchet)?;
+        let plaintext = remove_padding(&padded).map_err(ChannelError::Padding)?;
+        let envelope = Envelope::from_bytes(&plaintext).map_err(ChannelError::Protocol)?;
+        if envelope.version != self.version {
+            return Err(ChannelError::Protocol(ProtocolError::UnsupportedVersion(envelope.version)));
+        }
+        // Uma mensagem do par abre uma cadeia de envio nova na próxima resposta
+        self.rekey_requested = false;
+        Ok(envelope.message)
+    }
+
+    /// Indica, uma vez por cadeia, que ela ficou longa sem resposta do par e
+    /// vale enviar `Message::Rekey { request: true }`.
+    pub fn wants_rekey(&mut self) -> bool {
+        if self.rekey_requested || self.ratchet.sending_chain_length() < REKEY_AFTER {
+            return false;
+        }
+        self.rekey_requested = true;
+        true
+    }
+}
+
+/// Codificação binária de um tipo de corpo do envelope.
+pub trait WireEncode {
+    fn encode(&self, w: &mut Writer);
+}
+
+/// Decodificação binária de um tipo de corpo do envelope.
+pub trait WireDecode: Sized {
+    fn decode(r: &mut Reader) -> Result<Self, ProtocolError>;
+}
+
+/// Escritor binário: inteiros big-endian, bytes e textos com tamanho u32.
+#[derive(Default)]
+pub struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    pub fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.buf.push(value as u8);
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    /// Bytes de tamanho fixo, sem prefixo.
+    pub fn array(&mut self, value: &[u8]) {
+        self.buf.extend_from_slice(value);
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.u32(value.len() as u32);
+        self.buf.extend_from_slice(value);
+    }
+
+    pub fn string(&mut self, value: &str) {
+        self.bytes(value.as_bytes());
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Leitor binário correspondente a `Writer`; nunca lê além do fim.
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ProtocolError> {
+        if len > self.data.len() {
+            return Err(ProtocolError::Truncated);
+        }
+        let (head, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, ProtocolError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, ProtocolError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(ProtocolError::Malformed),
+        }
+    }
+
+    pub fn u32(&mut self) -> Result<u32, ProtocolError> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, ProtocolError> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N], ProtocolError> {
+        Ok(self.take(N)?.try_into().expect("tamanho conferido em take"))
+    }
+
+    pub fn bytes(&mut self) -> Result<Vec<u8>, ProtocolError> {
+        let len = self.u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    pub fn string(&mut self) -> Result<String, ProtocolError> {
+        String::from_utf8(self.bytes()?).map_err(|_| ProtocolError::Malformed)
+    }
+
+    /// Quantidade de itens de uma lista, limitada pelo que ainda cabe nos dados.
+    pub fn count(&mut self, item_len: usize) -> Result<usize, ProtocolError> {
+        let count = self.u32()? as usize;
+        if count.saturating_mul(item_len) > self.data.len() {
+            return Err(ProtocolError::Truncated);
+        }
+        Ok(count)
+    }
+
+    /// Confere que não sobraram bytes após o corpo.
+    pub fn finish(self) -> Result<(), ProtocolError> {
+        if self.data.is_empty() { Ok(()) } else { Err(ProtocolError::Malformed) }
+    }
+}
+
+/// Erros de decodificação do envelope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolError {
+    UnsupportedVersion(u8),
+    UnknownType(u8),
+    Truncated,
+    Malformed,
+}
+
+impl std::fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProtocolError::UnsupportedVersion(v) => write!(
+                f, "Versão de protocolo {} não suportada (suportadas: {}-{})", v, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+            ),
+            ProtocolError::UnknownType(t) => write!(f, "Tipo de mensagem desconhecido: 0x{:02x}", t),
+            ProtocolError::Truncated => write!(f, "Mensagem truncada"),
+            ProtocolError::Malformed => write!(f, "Mensagem malformada"),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+/// Erros do canal cifrado, por camada.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelError {
+    Ratchet(RatchetError),
+    Padding(PaddingError),
+    Protocol(ProtocolError),
+}
+
+impl std::fmt::Display for ChannelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChannelError::Ratchet(e) => write!(f, "{}", e),
+            ChannelError::Padding(e) => write!(f, "{}", e),
+            ChannelError::Protocol(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ChannelError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ratchet::Role;
+    use crate::transfer::FileChunk;
+    use x25519_dalek::{PublicKey, StaticSecret};
+
+    fn roundtrip(message: Message) {
+        let bytes = Envelope::new(PROTOCOL_VERSION, message.clone()).to_bytes();
+        assert_eq!(Envelope::from_bytes(&bytes).unwrap().message, message);
+        // Qualquer truncamento é detectado
+        assert!(Envelope::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_envelope_roundtrip() {
+        roundtrip(Message::Chat { id: 42, sender: "Phantom".into(), content: "olá, ✓".into() });
+        roundtrip(Message::Ack { id: u64::MAX });
+        roundtrip(Message::Typing { active: true });
+        roundtrip(Message::Receipt { id: 7, receipt: Receipt::Read });
+        roundtrip(Message::Rekey { request: false });
+        roundtrip(Message::Close { reason: "até mais".into() });
+        roundtrip(Message::Ping { nonce: 9 });
+        roundtrip(Message::Pong { nonce: 9 });
+        roundtrip(Message::File(FileWire::Chunk(FileChunk { id: [3u8; 16], index: 2, data: vec![0xab; 70_000] })));
+        roundtrip(Message::File(FileWire::Complete { id: [4u8; 16], verified: true }));
+    }
+
+    #[test]
+    fn test_chunk_is_compact() {
+        let chunk = FileChunk { id: [3u8; 16], index: 0, data: vec![0u8; 1000] };
+        let bytes = Envelope::new(PROTOCOL_VERSION, Message::File(FileWire::Chunk(chunk))).to_bytes();
+        // versão + tipo + id + índice + tamanho + dados
+        assert_eq!(bytes.len(), 1 + 1 + 16 + 4 + 4 + 1000);
+    }
+
+    #[test]
+    fn test_unknown_type_and_version() {
+        assert_eq!(Envelope::from_bytes(&[PROTOCOL_VERSION, 0x7f]), Err(ProtocolError::UnknownType(0x7f)));
+        assert_eq!(Envelope::from_bytes(&[PROTOCOL_VERSION + 1, kind::PING]), Err(ProtocolError::UnsupportedVersion(PROTOCOL_VERSION + 1)));
+        assert_eq!(Envelope::from_bytes(&[]), Err(ProtocolError::Truncated));
+
+        let mut bytes = Envelope::new(PROTOCOL_VERSION, Message::Ping { nonce: 1 }).to_bytes();
+        bytes.push(0);
+        assert_eq!(Envelope::from_bytes(&bytes), Err(ProtocolError::Malformed));
+        // Lista com tamanho declarado maior que os dados não aloca
+        assert_eq!(Reader::new(&[0xff, 0xff, 0xff, 0xff]).count(4), Err(ProtocolError::Truncated));
+    }
+
+    #[test]
+    fn test_version_negotiation() {
+        assert_eq!(negotiate_version((1, 1), (1, 1)), Some(1));
+        assert_eq!(negotiate_version((1, 3), (2, 5)), Some(3));
+        assert_eq!(negotiate_version((1, 2), (3, 4)), None);
+    }
+
+    #[test]
+    fn test_channel_roundtrip_and_rekey() {
+        let bob_secret = StaticSecret::random_from_rng(rand::rngs::OsRng);
+        let bob_public = PublicKey::from(&bob_secret).to_bytes();
+        let root = [5u8; 32];
+        let mut alice = SecureChannel::new(RatchetSession::new(Role::Initiator, &root, StaticSecret::random_from_rng(rand::rngs::OsRng), bob_public), 1);
+        let mut bob = SecureChannel::new(RatchetSession::new(Role::Responder, &root, bob_secret, [0u8; 32]), 1);
+
+        for nonce in 0..REKEY_AFTER {
+            let sealed = alice.seal(Message::Ping { nonce }).unwrap();
+            assert_eq!(bob.open(&sealed).unwrap(), Message::Ping { nonce });
+        }
+        assert!(alice.wants_rekey());
+        assert!(!alice.wants_rekey());
+
+        // A resposta do par inicia cadeias novas nos dois sentidos
+        let reply = bob.seal(Message::Rekey { request: false }).unwrap();
+        alice.open(&reply).unwrap();
+        alice.seal(Message::Ping { nonce: 0 }).unwrap();
+        assert!(!alice.wants_rekey());
+    }
+}
@@ -1,79 +1,152 @@
-use chacha20poly1305::{aead::{Aead, KeyInit}, ChaCha20Poly1305, Key, Nonce};
+use chacha20poly1305::{aead::{Aead, KeyInit, Payload}, ChaCha20Poly1305, Key, Nonce};
 use hkdf::Hkdf;
+use rand::rngs::OsRng;
 use sha2::Sha256;
-use zeroize::{Zeroize, ZeroizeOnDrop};
+use std::collections::HashMap;
 use std::time::{SystemTime, UNIX_EPOCH};
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::{Zeroizing, ZeroizeOnDrop};
 
-const HKDF_INFO_SEND: &[u8] = b"sae-ratchet-send";
-const HKDF_INFO_RECV: &[u8] = b"sae-ratchet-recv";
-const MAX_SKIP: usize = 100; // Máximo de mensagens puladas antes de rejeitar
+const HKDF_INFO_ROOT: &[u8] = b"sae-ratchet-root";
+const HKDF_INFO_CHAIN: &[u8] = b"sae-ratchet-kdf";
+const HKDF_INFO_RESPONDER: &[u8] = b"sae-ratchet-responder-chain";
+const MAX_SKIP: usize = 100; // Máximo de mensagens puladas por cadeia antes de rejeitar
+const MAX_SKIPPED_KEYS: usize = 1000; // Limite total de chaves puladas armazenadas
 
-/// Double Ratchet implementação simplificada para Perfect Forward Secrecy
-#[derive(ZeroizeOnDrop)]
+/// Chave de mensagem pulada, indexada por (chave de ratchet do remetente, contador).
+type SkippedKeys = HashMap<([u8; 32], u64), Zeroizing<[u8; 32]>>;
+
+/// Papel de cada lado na sessão. Os dois pares precisam ter papéis opostos:
+/// a cadeia de envio de um é a cadeia de recebimento do outro.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Quem conecta ao convite; conhece a chave de ratchet inicial do par
+    Initiator,
+    /// Quem publicou o convite; sua chave X25519 é a chave de ratchet inicial
+    Responder,
+}
+
+/// Double Ratchet no estilo Signal: ratchet simétrico por mensagem e ratchet
+/// Diffie-Hellman a cada troca de turno, garantindo forward secrecy e
+/// segurança pós-comprometimento.
+///
+/// O iniciador conhece a chave de ratchet inicial do respondedor (a chave X25519
+/// do handshake). Para que o respondedor possa falar primeiro, ambos derivam da
+/// raiz uma cadeia inicial do respondedor, substituída no primeiro passo DH.
+#[derive(Clone, ZeroizeOnDrop)]
 pub struct RatchetSession {
+    /// Par DH de ratchet local atual
+    dh_self: StaticSecret,
+    #[zeroize(skip)]
+    dh_self_public: [u8; 32],
+    /// Última chave de ratchet recebida do par
+    #[zeroize(skip)]
+    dh_remote: Option<[u8; 32]>,
+    /// Chave raiz, atualizada a cada passo DH
+    root_key: [u8; 32],
     /// Chave de cadeia de envio
     send_chain_key: [u8; 32],
-    /// Chave de cadeia de recebimento
-    recv_chain_key: [u8; 32],
-    /// Contador de mensagens enviadas
+    /// Chave de cadeia de recebimento (ausente até o primeiro passo DH do respondedor)
+    recv_chain_key: Option<[u8; 32]>,
+    /// Contador de mensagens enviadas na cadeia atual
     send_count: u64,
-    /// Contador de mensagens recebidas
+    /// Contador de mensagens recebidas na cadeia atual
     recv_count: u64,
+    /// Tamanho da cadeia de envio anterior (PN no cabeçalho)
+    prev_send_count: u64,
     /// Cache de chaves puladas para mensagens fora de ordem
     #[zeroize(skip)]
-    skipped_keys: Vec<([u8; 32], u64)>,
+    skipped_keys: SkippedKeys,
 }
 
 impl RatchetSession {
-    /// Cria uma nova sessão de ratchet a partir de um segredo compartilhado
-    pub fn new(shared_secret: &[u8; 32]) -> Self {
-        // Deriva chaves de cadeia iniciais separadas para cada direção
-        let hkdf_send = Hkdf::<Sha256>::new(None, shared_secret);
-        let mut send_chain_key = [0u8; 32];
-        hkdf_send.expand(HKDF_INFO_SEND, &mut send_chain_key)
-            .expect("HKDF expand failed");
-
-        let hkdf_recv = Hkdf::<Sha256>::new(None, shared_secret);
-        let mut recv_chain_key = [0u8; 32];
-        hkdf_recv.expand(HKDF_INFO_RECV, &mut recv_chain_key)
-            .expect("HKDF expand failed");
+    /// Cria a sessão conforme o papel local.
+    ///
+    /// O iniciador usa `remote_public` como chave de ratchet inicial do par; o
+    /// respondedor usa `local_secret` como sua própria chave de ratchet inicial.
+    pub fn new(role: Role, shared_secret: &[u8; 32], local_secret: StaticSecret, remote_public: [u8; 32]) -> Self {
+        match role {
+            Role::Initiator => Self::initiator(shared_secret, remote_public),
+            Role::Responder => Self::responder(shared_secret, local_secret),
+        }
+    }
+
+    /// Inicia a sessão do lado que conhece a chave de ratchet do par.
+    pub fn initiator(shared_secret: &[u8; 32], remote_ratchet_key: [u8; 32]) -> Self {
+        let dh_self = StaticSecret::random_from_rng(OsRng);
+        let dh_self_public = PublicKey::from(&dh_self).to_bytes();
+
+        let dh_output = dh_self.diffie_hellman(&PublicKey::from(remote_ratchet_key));
+        let (root_key, send_chain_key) = kdf_rk(shared_secret, dh_output.as_bytes());
 
         Self {
+            dh_self,
+            dh_self_public,
+            dh_remote: Some(remote_ratchet_key),
+            root_key,
             send_chain_key,
-            recv_chain_key,
+            recv_chain_key: Some(responder_chain(shared_secret)),
             send_count: 0,
             recv_count: 0,
-            skipped_keys: Vec::new(),
+            prev_send_count: 0,
+            skipped_keys: HashMap::new(),
+        }
+    }
+
+    /// Inicia a sessão do lado cuja chave X25519 serve de ratchet inicial.
+    pub fn responder(shared_secret: &[u8; 32], local_ratchet_key: StaticSecret) -> Self {
+        let dh_self_public = PublicKey::from(&local_ratchet_key).to_bytes();
+
+        Self {
+            dh_self: local_ratchet_key,
+            dh_self_public,
+            dh_remote: None,
+            root_key: *shared_secret,
+            send_chain_key: responder_chain(shared_secret),
+            recv_chain_key: None,
+            send_count: 0,
+            recv_count: 0,
+            prev_send_count: 0,
+            skipped_keys: HashMap::new(),
         }
     }
 
     /// Criptografa uma mensagem e avança o ratchet de envio
     pub fn encrypt(&mut self, plaintext: &[u8]) -> Result<RatchetMessage, RatchetError> {
         // Deriva chave de mensagem da chave de cadeia
-        let (message_key, next_chain_key) = self.derive_key(&self.send_chain_key);
+        let (message_key, next_chain_key) = kdf_ck(&self.send_chain_key);
 
-        // Criptografa com ChaCha20-Poly1305
-        let cipher = ChaCha20Poly1305::new(Key::from_slice(&message_key));
-        let nonce = self.generate_nonce(self.send_count);
-
-        let ciphertext = cipher
-            .encrypt(&nonce, plaintext)
-            .map_err(|_| RatchetError::EncryptionFailed)?;
-
-        // Avança o ratchet
-        let msg = RatchetMessage {
+        let mut msg = RatchetMessage {
+            dh_public: self.dh_self_public,
+            prev_count: self.prev_send_count,
             counter: self.send_count,
-            ciphertext,
+            ciphertext: Vec::new(),
             timestamp: Self::current_timestamp(),
         };
 
+        // Criptografa com ChaCha20-Poly1305, autenticando o cabeçalho
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&message_key));
+        let nonce = Self::generate_nonce(msg.counter);
+        msg.ciphertext = cipher
+            .encrypt(&nonce, Payload { msg: plaintext, aad: &msg.header_bytes() })
+            .map_err(|_| RatchetError::EncryptionFailed)?;
+
+        // Avança o ratchet
         self.send_chain_key = next_chain_key;
         self.send_count += 1;
 
         Ok(msg)
     }
 
-    /// Descriptografa uma mensagem e avança o ratchet de recebimento
+    /// Mensagens enviadas desde o último passo DH (cresce enquanto o par não responde)
+    pub fn sending_chain_length(&self) -> u64 {
+        self.send_count
+    }
+
+    /// Descriptografa uma mensagem e avança o ratchet de recebimento.
+    ///
+    /// O estado só é atualizado se a mensagem autenticar: uma mensagem forjada
+    /// não consegue forçar passos DH nem consumir chaves puladas.
     pub fn decrypt(&mut self, message: &RatchetMessage) -> Result<Vec<u8>, RatchetError> {
         // Verifica timestamp para detectar replays
         let current_time = Self::current_timestamp();
@@ -81,83 +154,106 @@ impl RatchetSession {
             // Mensagem do futuro
             return Err(RatchetError::InvalidTimestamp);
         }
-        if current_time - message.timestamp > 300 {
+        if current_time.saturating_sub(message.timestamp) > 300 {
             // Mensagem muito antiga (> 5 minutos)
             return Err(RatchetError::MessageTooOld);
         }
 
-        // Verifica se a mensagem está na ordem esperada
-        if message.counter == self.recv_count {
-            // Mensagem em ordem
-            let (message_key, next_chain_key) = self.derive_key(&self.recv_chain_key);
-            let plaintext = self.decrypt_with_key(&message_key, message)?;
+        // Mensagem atrasada cuja chave foi guardada ao pular
+        if let Some(message_key) = self.skipped_keys.get(&(message.dh_public, message.counter)) {
+            let plaintext = Self::decrypt_with_key(message_key, message)?;
+            self.skipped_keys.remove(&(message.dh_public, message.counter));
+            return Ok(plaintext);
+        }
 
-            self.recv_chain_key = next_chain_key;
-            self.recv_count += 1;
+        let mut next = self.clone();
+        let plaintext = next.decrypt_in_order(message)?;
+        *self = next;
+        Ok(plaintext)
+    }
 
-            Ok(plaintext)
-        } else if message.counter > self.recv_count {
-            // Mensagem fora de ordem - armazena chaves puladas
-            let skip_count = (message.counter - self.recv_count) as usize;
+    fn decrypt_in_order(&mut self, message: &RatchetMessage) -> Result<Vec<u8>, RatchetError> {
+        if self.dh_remote != Some(message.dh_public) {
+            // Nova chave de ratchet do par: guarda o restante da cadeia atual e faz o passo DH
+            self.skip_message_keys(message.prev_count)?;
+            self.dh_ratchet(message.dh_public);
+        } else if message.counter < self.recv_count {
+            return Err(RatchetError::MessageAlreadyReceived);
+        }
 
-            if skip_count > MAX_SKIP {
-                return Err(RatchetError::TooManySkippedMessages);
-            }
+        self.skip_message_keys(message.counter)?;
 
-            // Deriva e armazena chaves para mensagens puladas
-            let mut chain_key = self.recv_chain_key;
-            for i in 0..skip_count {
-                let (msg_key, next_key) = self.derive_key(&chain_key);
-                self.skipped_keys.push((msg_key, self.recv_count + i as u64));
-                chain_key = next_key;
-            }
+        let chain_key = self.recv_chain_key.ok_or(RatchetError::DecryptionFailed)?;
+        let (message_key, next_chain_key) = kdf_ck(&chain_key);
+        let plaintext = Self::decrypt_with_key(&message_key, message)?;
 
-            // Descriptografa a mensagem atual
-            let (message_key, next_chain_key) = self.derive_key(&chain_key);
-            let plaintext = self.decrypt_with_key(&message_key, message)?;
-
-            self.recv_chain_key = next_chain_key;
-            self.recv_count = message.counter + 1;
-
-            Ok(plaintext)
-        } else {
-            // Mensagem antiga - verifica se temos a chave armazenada
-            if let Some(pos) = self.skipped_keys.iter().position(|(_, count)| *count == message.counter) {
-                let (message_key, _) = self.skipped_keys.remove(pos);
-                self.decrypt_with_key(&message_key, message)
-            } else {
-                Err(RatchetError::MessageAlreadyReceived)
-            }
-        }
+        self.recv_chain_key = Some(next_chain_key);
+        self.recv_count += 1;
+
+        Ok(plaintext)
     }
 
-    /// Deriva uma chave de mensagem e a próxima chave de cadeia usando HKDF
-    fn derive_key(&self, chain_key: &[u8; 32]) -> ([u8; 32], [u8; 32]) {
-        let hkdf = Hkdf::<Sha256>::new(None, chain_key);
-        let mut output = [0u8; 64]; // 32 bytes para message key + 32 para next chain key
-        hkdf.expand(b"sae-ratchet-kdf", &mut output)
-            .expect("HKDF expand failed");
+    /// Deriva e armazena as chaves da cadeia de recebimento até `until`.
+    fn skip_message_keys(&mut self, until: u64) -> Result<(), RatchetError> {
+        let Some(mut chain_key) = self.recv_chain_key else {
+            return Ok(());
+        };
+        let Some(dh_remote) = self.dh_remote else {
+            return Ok(());
+        };
 
-        let mut message_key = [0u8; 32];
-        let mut next_chain_key = [0u8; 32];
-        message_key.copy_from_slice(&output[..32]);
-        next_chain_key.copy_from_slice(&output[32..]);
+        if until > self.recv_count + MAX_SKIP as u64 {
+            return Err(RatchetError::TooManySkippedMessages);
+        }
+        if self.skipped_keys.len() + (until.saturating_sub(self.recv_count) as usize) > MAX_SKIPPED_KEYS {
+            return Err(RatchetError::TooManySkippedMessages);
+        }
 
-        (message_key, next_chain_key)
+        while self.recv_count < until {
+            let (message_key, next_chain_key) = kdf_ck(&chain_key);
+            self.skipped_keys.insert((dh_remote, self.recv_count), Zeroizing::new(message_key));
+            chain_key = next_chain_key;
+            self.recv_count += 1;
+        }
+        self.recv_chain_key = Some(chain_key);
+
+        Ok(())
+    }
+
+    /// Passo DH: nova cadeia de recebimento com a chave do par e nova cadeia
+    /// de envio com um par DH recém-gerado.
+    fn dh_ratchet(&mut self, remote_public: [u8; 32]) {
+        self.prev_send_count = self.send_count;
+        self.send_count = 0;
+        self.recv_count = 0;
+        self.dh_remote = Some(remote_public);
+
+        let remote = PublicKey::from(remote_public);
+        let dh_output = self.dh_self.diffie_hellman(&remote);
+        let (root_key, recv_chain_key) = kdf_rk(&self.root_key, dh_output.as_bytes());
+        self.root_key = root_key;
+        self.recv_chain_key = Some(recv_chain_key);
+
+        self.dh_self = StaticSecret::random_from_rng(OsRng);
+        self.dh_self_public = PublicKey::from(&self.dh_self).to_bytes();
+        let dh_output = self.dh_self.diffie_hellman(&remote);
+        let (root_key, send_chain_key) = kdf_rk(&self.root_key, dh_output.as_bytes());
+        self.root_key = root_key;
+        self.send_chain_key = send_chain_key;
     }
 
     /// Descriptografa com uma chave específica
-    fn decrypt_with_key(&self, key: &[u8; 32], message: &RatchetMessage) -> Result<Vec<u8>, RatchetError> {
+    fn decrypt_with_key(key: &[u8; 32], message: &RatchetMessage) -> Result<Vec<u8>, RatchetError> {
         let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
-        let nonce = self.generate_nonce(message.counter);
+        let nonce = Self::generate_nonce(message.counter);
 
         cipher
-            .decrypt(&nonce, message.ciphertext.as_slice())
+            .decrypt(&nonce, Payload { msg: &message.ciphertext, aad: &message.header_bytes() })
             .map_err(|_| RatchetError::DecryptionFailed)
     }
 
-    /// Gera um nonce único baseado no contador
-    fn generate_nonce(&self, counter: u64) -> Nonce {
+    /// Gera um nonce baseado no contador (cada chave de mensagem é usada uma única vez)
+    fn generate_nonce(counter: u64) -> Nonce {
         let mut nonce_bytes = [0u8; 12];
         nonce_bytes[4..12].copy_from_slice(&counter.to_le_bytes());
         nonce_bytes.into()
@@ -172,9 +268,50 @@ impl RatchetSession {
     }
 }
 
+/// KDF da chave raiz: (nova chave raiz, chave de cadeia) = HKDF(rk, saída DH)
+fn kdf_rk(root_key: &[u8; 32], dh_output: &[u8; 32]) -> ([u8; 32], [u8; 32]) {
+    let hkdf = Hkdf::<Sha256>::new(Some(root_key), dh_output);
+    let mut output = Zeroizing::new([0u8; 64]);
+    hkdf.expand(HKDF_INFO_ROOT, output.as_mut())
+        .expect("HKDF expand failed");
+
+    split_keys(&output)
+}
+
+/// KDF da cadeia: deriva uma chave de mensagem e a próxima chave de cadeia
+fn kdf_ck(chain_key: &[u8; 32]) -> ([u8; 32], [u8; 32]) {
+    let hkdf = Hkdf::<Sha256>::new(None, chain_key);
+    let mut output = Zeroizing::new([0u8; 64]); // 32 bytes para message key + 32 para next chain key
+    hkdf.expand(HKDF_INFO_CHAIN, output.as_mut())
+        .expect("HKDF expand failed");
+
+    split_keys(&output)
+}
+
+/// Cadeia usada pelo respondedor antes do primeiro passo DH
+fn responder_chain(shared_secret: &[u8; 32]) -> [u8; 32] {
+    let hkdf = Hkdf::<Sha256>::new(None, shared_secret);
+    let mut chain_key = [0u8; 32];
+    hkdf.expand(HKDF_INFO_RESPONDER, &mut chain_key)
+        .expect("HKDF expand failed");
+    chain_key
+}
+
+fn split_keys(output: &[u8; 64]) -> ([u8; 32], [u8; 32]) {
+    let mut first = [0u8; 32];
+    let mut second = [0u8; 32];
+    first.copy_from_slice(&output[..32]);
+    second.copy_from_slice(&output[32..]);
+    (first, second)
+}
+
 /// Mensagem criptografada pelo ratchet
 #[derive(Debug, Clone)]
 pub struct RatchetMessage {
+    /// Chave pública de ratchet atual do remetente
+    pub dh_public: [u8; 32],
+    /// Número de mensagens na cadeia de envio anterior do remetente
+    pub prev_count: u64,
     /// Contador da mensagem para ordenação
     pub counter: u64,
     /// Dados criptografados
@@ -183,12 +320,23 @@ pub struct RatchetMessage {
     pub timestamp: u64,
 }
 
+/// Tamanho do cabeçalho: 32 (chave DH) + 8 (PN) + 8 (contador) + 8 (timestamp)
+const HEADER_LEN: usize = 56;
+
 impl RatchetMessage {
-    /// Serializa a mensagem para bytes
-    pub fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::new();
+    /// Cabeçalho autenticado como dados associados do AEAD
+    fn header_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN);
+        bytes.extend_from_slice(&self.dh_public);
+        bytes.extend_from_slice(&self.prev_count.to_le_bytes());
         bytes.extend_from_slice(&self.counter.to_le_bytes());
         bytes.extend_from_slice(&self.timestamp.to_le_bytes());
+        bytes
+    }
+
+    /// Serializa a mensagem para bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.header_bytes();
         bytes.extend_from_slice(&(self.ciphertext.len() as u32).to_le_bytes());
         bytes.extend_from_slice(&self.ciphertext);
         bytes
@@ -196,22 +344,25 @@ impl RatchetMessage {
 
     /// Deserializa bytes para uma mensagem
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, RatchetError> {
-        if bytes.len() < 20 {
-            // 8 (counter) + 8 (timestamp) + 4 (length)
+        if bytes.len() < HEADER_LEN + 4 {
             return Err(RatchetError::InvalidMessage);
         }
 
-        let counter = u64::from_le_bytes(bytes[0..8].try_into().unwrap());
-        let timestamp = u64::from_le_bytes(bytes[8..16].try_into().unwrap());
-        let length = u32::from_le_bytes(bytes[16..20].try_into().unwrap()) as usize;
+        let dh_public: [u8; 32] = bytes[0..32].try_into().unwrap();
+        let prev_count = u64::from_le_bytes(bytes[32..40].try_into().unwrap());
+        let counter = u64::from_le_bytes(bytes[40..48].try_into().unwrap());
+        let timestamp = u64::from_le_bytes(bytes[48..56].try_into().unwrap());
+        let length = u32::from_le_bytes(bytes[56..60].try_into().unwrap()) as usize;
 
-        if bytes.len() < 20 + length {
+        if bytes.len() < HEADER_LEN + 4 + length {
             return Err(RatchetError::InvalidMessage);
         }
 
-        let ciphertext = bytes[20..20 + length].to_vec();
+        let ciphertext = bytes[HEADER_LEN + 4..HEADER_LEN + 4 + length].to_vec();
 
         Ok(Self {
+            dh_public,
+            prev_count,
             counter,
             ciphertext,
             timestamp,
@@ -251,11 +402,25 @@ impl std::error::Error for RatchetError {}
 mod tests {
     use super::*;
 
+    /// Cria o par (Alice iniciadora, Bob respondedor) como em `main.rs`: cada
+    /// lado faz o DH com a chave do outro e recebe seu papel da rede.
+    fn session_pair() -> (RatchetSession, RatchetSession) {
+        let alice_handshake = StaticSecret::random_from_rng(OsRng);
+        let bob_handshake = StaticSecret::random_from_rng(OsRng);
+        let alice_public = PublicKey::from(&alice_handshake).to_bytes();
+        let bob_public = PublicKey::from(&bob_handshake).to_bytes();
+
+        let alice_shared = alice_handshake.diffie_hellman(&PublicKey::from(bob_public));
+        let bob_shared = bob_handshake.diffie_hellman(&PublicKey::from(alice_public));
+
+        let alice = RatchetSession::new(Role::Initiator, alice_shared.as_bytes(), alice_handshake, bob_public);
+        let bob = RatchetSession::new(Role::Responder, bob_shared.as_bytes(), bob_handshake, alice_public);
+        (alice, bob)
+    }
+
     #[test]
     fn test_ratchet_basic() {
-        let secret = [42u8; 32];
-        let mut alice = RatchetSession::new(&secret);
-        let mut bob = RatchetSession::new(&secret);
+        let (mut alice, mut bob) = session_pair();
 
         // Alice envia mensagem
         let msg1 = alice.encrypt(b"Hello Bob!").unwrap();
@@ -270,9 +435,7 @@ mod tests {
 
     #[test]
     fn test_ratchet_forward_secrecy() {
-        let secret = [42u8; 32];
-        let mut alice = RatchetSession::new(&secret);
-        let mut bob = RatchetSession::new(&secret);
+        let (mut alice, mut bob) = session_pair();
 
         let msg1 = alice.encrypt(b"Message 1").unwrap();
         let msg2 = alice.encrypt(b"Message 2").unwrap();
@@ -284,4 +447,265 @@ mod tests {
         // Tentar descriptografar msg1 novamente deve falhar (forward secrecy)
         assert!(bob.decrypt(&msg1).is_err());
     }
+
+    #[test]
+    fn test_responder_can_send_first() {
+        let (mut alice, mut bob) = session_pair();
+
+        let msg = bob.encrypt(b"Bob primeiro").unwrap();
+        assert_eq!(alice.decrypt(&msg).unwrap(), b"Bob primeiro");
+
+        let reply = alice.encrypt(b"Oi Bob").unwrap();
+        assert_eq!(bob.decrypt(&reply).unwrap(), b"Oi Bob");
+    }
+
+    #[test]
+    fn test_dh_ratchet_rotates_keys_each_turn() {
+        let (mut alice, mut bob) = session_pair();
+
+        let a1 = alice.encrypt(b"a1").unwrap();
+        bob.decrypt(&a1).unwrap();
+        let b1 = bob.encrypt(b"b1").unwrap();
+        alice.decrypt(&b1).unwrap();
+        let a2 = alice.encrypt(b"a2").unwrap();
+        bob.decrypt(&a2).unwrap();
+
+        // Cada turno usa uma nova chave de ratchet
+        assert_ne!(a1.dh_public, a2.dh_public);
+        assert_ne!(a1.dh_public, b1.dh_public);
+        assert_eq!(a2.prev_count, 1);
+    }
+
+    #[test]
+    fn test_out_of_order_across_dh_steps() {
+        let (mut alice, mut bob) = session_pair();
+
+        let a1 = alice.encrypt(b"a1").unwrap();
+        let a2 = alice.encrypt(b"a2").unwrap();
+        bob.decrypt(&a2).unwrap();
+
+        let b1 = bob.encrypt(b"b1").unwrap();
+        alice.decrypt(&b1).unwrap();
+        let a3 = alice.encrypt(b"a3").unwrap();
+
+        // a3 vem de uma nova cadeia; a1, atrasada, ainda é aceita pela chave pulada
+        assert_eq!(bob.decrypt(&a3).unwrap(), b"a3");
+        assert_eq!(bob.decrypt(&a1).unwrap(), b"a1");
+        assert_eq!(bob.decrypt(&a1).unwrap_err(), RatchetError::DecryptionFailed);
+    }
+
+    #[test]
+    fn test_skipped_keys_are_per_ratchet_key() {
+        let (mut alice, mut bob) = session_pair();
+
+        let a1 = alice.encrypt(b"a1").unwrap();
+        let a2 = alice.encrypt(b"a2").unwrap();
+        bob.decrypt(&a2).unwrap();
+
+        // Mesmo contador, outra chave de ratchet: não pode usar a chave pulada de a1
+        let mut forged = a1.clone();
+        forged.dh_public = [9u8; 32];
+        assert!(bob.decrypt(&forged).is_err());
+        assert_eq!(bob.decrypt(&a1).unwrap(), b"a1");
+    }
+
+    #[test]
+    fn test_tampered_header_rejected_without_state_change() {
+        let (mut alice, mut bob) = session_pair();
+
+        let a1 = alice.encrypt(b"a1").unwrap();
+        let mut tampered = a1.clone();
+        tampered.counter = 5;
+        assert!(bob.decrypt(&tampered).is_err());
+
+        // O estado não avançou com a mensagem forjada
+        assert_eq!(bob.decrypt(&a1).unwrap(), b"a1");
+    }
+
+    #[test]
+    fn test_clock_slightly_ahead_accepted() {
+        let (mut alice, mut bob) = session_pair();
+
+        // Relógio de Alice 5 s adiantado: o timestamp entra no AAD, então
+        // sela de novo com a mesma chave de mensagem
+        let (message_key, _) = kdf_ck(&alice.send_chain_key);
+        let mut a1 = alice.encrypt(b"a1").unwrap();
+        a1.timestamp += 5;
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&message_key));
+        let nonce = RatchetSession::generate_nonce(a1.counter);
+        a1.ciphertext = cipher.encrypt(&nonce, Payload { msg: b"a1", aad: &a1.header_bytes() }).unwrap();
+
+        assert_eq!(bob.decrypt(&a1).unwrap(), b"a1");
+
+        let mut far_future = alice.encrypt(b"a2").unwrap();
+        far_future.timestamp += 120;
+        assert_eq!(bob.decrypt(&far_future), Err(RatchetError::InvalidTimestamp));
+    }
+
+    #[test]
+    fn test_too_many_skipped_messages() {
+        let (mut alice, mut bob) = session_pair();
+
+        let mut last = alice.encrypt(b"0").unwrap();
+        for _ in 0..=MAX_SKIP {
+            last = alice.encrypt(b"x").unwrap();
+        }
+        assert_eq!(bob.decrypt(&last).unwrap_err(), RatchetError::TooManySkippedMessages);
+    }
+
+    #[test]
+    fn test_post_compromise_healing() {
+        let (mut alice, mut bob) = session_pair();
+
+        let a1 = alice.encrypt(b"a1").unwrap();
+        bob.decrypt(&a1).unwrap();
+
+        // Atacante copia todo o estado de Bob (raiz, cadeias e chave DH privada)
+        let stolen = bob.clone();
+
+        // Enquanto Bob não gerar uma nova chave DH, o atacante acompanha a conversa
+        let a2 = alice.encrypt(b"a2").unwrap();
+        let b1 = bob.encrypt(b"b1").unwrap();
+        alice.decrypt(&b1).unwrap();
+        let a3 = alice.encrypt(b"a3").unwrap();
+        assert_eq!(stolen.clone().decrypt(&a2).unwrap(), b"a2");
+        assert_eq!(stolen.clone().decrypt(&a3).unwrap(), b"a3");
+        bob.decrypt(&a2).unwrap();
+        bob.decrypt(&a3).unwrap();
+
+        // Bob responde com uma chave DH nova; o próximo turno de Alice a usa
+        let b2 = bob.encrypt(b"b2").unwrap();
+        alice.decrypt(&b2).unwrap();
+        let a4 = alice.encrypt(b"a4 depois da cura").unwrap();
+        assert_eq!(bob.decrypt(&a4).unwrap(), b"a4 depois da cura");
+
+        // O estado roubado não descriptografa mais nada
+        assert!(stolen.clone().decrypt(&a4).is_err());
+    }
+
+    #[test]
+    fn test_message_serialization_roundtrip() {
+        let (mut alice, mut bob) = session_pair();
+
+        let msg = alice.encrypt(b"serializada").unwrap();
+        let parsed = RatchetMessage::from_bytes(&msg.to_bytes()).unwrap();
+        assert_eq!(parsed.dh_public, msg.dh_public);
+        assert_eq!(bob.decrypt(&parsed).unwrap(), b"serializada");
+
+        assert_eq!(RatchetMessage::from_bytes(&[0u8; 10]).unwrap_err(), RatchetError::InvalidMessage);
+    }
+
+    #[test]
+    fn test_kdf_vectors() {
+        // Vetores calculados independentemente com HKDF-SHA256 (RFC 5869)
+        let (root_key, chain_key) = kdf_rk(&[1u8; 32], &[2u8; 32]);
+        assert_eq!(hex::encode(root_key), "0ece30e1d96aa78c63aeb98ab18c87c93bb6f0ffd2aec8e271fa0a2825f42e9e");
+        assert_eq!(hex::encode(chain_key), "d1df089ec2bae769a176f370efaf066297cfc52af780a32e7ce5307519674ad0");
+
+        let (message_key, next_chain_key) = kdf_ck(&[3u8; 32]);
+        assert_eq!(hex::encode(message_key), "9f15dfbd36a4c828bd200d1b0859fe505fed551ebc44fd743ce4011980b19f1f");
+        assert_eq!(hex::encode(next_chain_key), "a38ab034a7760746841c3d6913c0c4ca11f89ee1d5ddd1775213c912ded26017");
+    }
+
+    #[test]
+    fn test_roles_use_opposite_chains() {
+        let (alice, bob) = session_pair();
+
+        // Antes de qualquer passo DH, a cadeia de envio de Bob é a de recebimento de Alice
+        assert_eq!(Some(bob.send_chain_key), alice.recv_chain_key);
+        assert_ne!(alice.send_chain_key, bob.send_chain_key);
+    }
+
+    #[test]
+    fn test_same_role_on_both_sides_fails() {
+        let alice_handshake = StaticSecret::random_from_rng(OsRng);
+        let bob_handshake = StaticSecret::random_from_rng(OsRng);
+        let alice_public = PublicKey::from(&alice_handshake).to_bytes();
+        let bob_public = PublicKey::from(&bob_handshake).to_bytes();
+        let shared = alice_handshake.diffie_hellman(&PublicKey::from(bob_public));
+
+        let mut alice = RatchetSession::new(Role::Responder, shared.as_bytes(), alice_handshake, bob_public);
+        let mut bob = RatchetSession::new(Role::Responder, shared.as_bytes(), bob_handshake, alice_public);
+
+        let msg = alice.encrypt(b"sem papel definido").unwrap();
+        assert!(bob.decrypt(&msg).is_err());
+    }
+
+    #[test]
+    fn test_two_party_interleaved() {
+        let (mut alice, mut bob) = session_pair();
+
+        // Envios simultâneos: ambos escrevem antes de receber a mensagem do outro
+        for round in 0..5 {
+            let from_alice: Vec<_> = (0..=round)
+                .map(|i| alice.encrypt(format!("a{}-{}", round, i).as_bytes()).unwrap())
+                .collect();
+            let from_bob: Vec<_> = (0..=round)
+                .map(|i| bob.encrypt(format!("b{}-{}", round, i).as_bytes()).unwrap())
+                .collect();
+
+            for (i, msg) in from_bob.iter().enumerate() {
+                assert_eq!(alice.decrypt(msg).unwrap(), format!("b{}-{}", round, i).as_bytes());
+            }
+            for (i, msg) in from_alice.iter().enumerate() {
+                assert_eq!(bob.decrypt(msg).unwrap(), format!("a{}-{}", round, i).as_bytes());
+            }
+        }
+    }
+
+    #[test]
+    fn test_two_party_out_of_order_both_directions() {
+        let (mut alice, mut bob) = session_pair();
+
+        let a: Vec<_> = (0..4).map(|i| alice.encrypt(&[b'a', i]).unwrap()).collect();
+        for i in [3, 0, 2, 1] {
+            assert_eq!(bob.decrypt(&a[i]).unwrap(), vec![b'a', i as u8]);
+        }
+
+        let b: Vec<_> = (0..4).map(|i| bob.encrypt(&[b'b', i]).unwrap()).collect();
+        for i in [2, 3, 1, 0] {
+            assert_eq!(alice.decrypt(&b[i]).unwrap(), vec![b'b', i as u8]);
+        }
+
+        // Atraso que atravessa uma troca de turno em cada direção
+        let late_from_alice = alice.encrypt(b"a-late").unwrap();
+        let next_from_alice = alice.encrypt(b"a-next").unwrap();
+        bob.decrypt(&next_from_alice).unwrap();
+        let from_bob = bob.encrypt(b"b-turn").unwrap();
+        let late_from_bob = bob.encrypt(b"b-late").unwrap();
+        alice.decrypt(&from_bob).unwrap();
+        let new_turn = alice.encrypt(b"a-turn").unwrap();
+        bob.decrypt(&new_turn).unwrap();
+        let bob_new_turn = bob.encrypt(b"b-new").unwrap();
+        alice.decrypt(&bob_new_turn).unwrap();
+
+        assert_eq!(bob.decrypt(&late_from_alice).unwrap(), b"a-late");
+        assert_eq!(alice.decrypt(&late_from_bob).unwrap(), b"b-late");
+    }
+
+    #[test]
+    fn test_two_party_duplicates_rejected_both_directions() {
+        let (mut alice, mut bob) = session_pair();
+
+        let a1 = alice.encrypt(b"a1").unwrap();
+        let a2 = alice.encrypt(b"a2").unwrap();
+        bob.decrypt(&a1).unwrap();
+        bob.decrypt(&a2).unwrap();
+        assert_eq!(bob.decrypt(&a1).unwrap_err(), RatchetError::MessageAlreadyReceived);
+        assert_eq!(bob.decrypt(&a2).unwrap_err(), RatchetError::MessageAlreadyReceived);
+
+        let b1 = bob.encrypt(b"b1").unwrap();
+        let b2 = bob.encrypt(b"b2").unwrap();
+        alice.decrypt(&b2).unwrap();
+        alice.decrypt(&b1).unwrap();
+        // Duplicatas de mensagens entregues fora de ordem também são rejeitadas
+        assert!(alice.decrypt(&b1).is_err());
+        assert_eq!(alice.decrypt(&b2).unwrap_err(), RatchetError::MessageAlreadyReceived);
+
+        // A sessão continua funcional depois das duplicatas
+        let a3 = alice.encrypt(b"a3").unwrap();
+        assert_eq!(bob.decrypt(&a3).unwrap(), b"a3");
+        let b3 = bob.encrypt(b"b3").unwrap();
+        assert_eq!(alice.decrypt(&b3).unwrap(), b"b3");
+    }
 }
@@ -0,0 +1,952 @@
+use crate::identity::{verify_signature, Identity};
+use crate::protocol::{ProtocolError, Reader, WireDecode, WireEncode, Writer};
+use chacha20poly1305::{aead::{Aead, KeyInit, Payload}, ChaCha20Poly1305, Key, Nonce};
+use ed25519_dalek::{Signature, VerifyingKey};
+use hkdf::Hkdf;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::Sha256;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+use zeroize::Zeroizing;
+
+const MEMBERS_CONTEXT: &[u8] = b"sae-room-members-v1";
+const SENDER_KEY_CONTEXT: &[u8] = b"sae-room-sender-key-v1";
+const MESSAGE_CONTEXT: &[u8] = b"sae-room-message-v1";
+const HKDF_INFO_SENDER_CHAIN: &[u8] = b"sae-room-sender-chain";
+const MAX_SKIP: u32 = 100; // Máximo de mensagens puladas por remetente
+
+/// Identificador aleatório de uma sala.
+pub type RoomId = [u8; 16];
+/// Chave Ed25519 de identidade de um membro.
+pub type MemberKey = [u8; 32];
+
+/// Forma curta do identificador, para digitar nos comandos.
+pub fn short_id(room_id: &RoomId) -> String {
+    hex::encode(&room_id[..4])
+}
+
+/// Lista de membros de uma época, assinada pelo criador da sala.
+///
+/// Toda mudança de membros gera uma nova época; cada membro troca sua chave
+/// de remetente ao receber a lista nova, então quem saiu não lê o que vem depois.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemberList {
+    pub room_id: RoomId,
+    pub epoch: u64,
+    pub creator: MemberKey,
+    pub members: BTreeSet<MemberKey>,
+    signature: [u8; 64],
+}
+
+impl MemberList {
+    fn signed(identity: &Identity, room_id: RoomId, epoch: u64, members: BTreeSet<MemberKey>) -> Self {
+        let mut list = Self {
+            room_id,
+            epoch,
+            creator: identity.public_key_bytes(),
+            members,
+            signature: [0u8; 64],
+        };
+        list.signature = identity.sign(&list.signed_data()).to_bytes();
+        list
+    }
+
+    /// Confere a assinatura do criador.
+    pub fn verify(&self) -> bool {
+        verify_by(&self.creator, &self.signed_data(), &self.signature) && self.members.contains(&self.creator)
+    }
+
+    fn signed_data(&self) -> Vec<u8> {
+        let mut data = MEMBERS_CONTEXT.to_vec();
+        data.extend_from_slice(&self.room_id);
+        data.extend_from_slice(&self.epoch.to_be_bytes());
+        data.extend_from_slice(&self.creator);
+        data.extend_from_slice(&(self.members.len() as u32).to_be_bytes());
+        for member in &self.members {
+            data.extend_from_slice(member);
+        }
+        data
+    }
+}
+
+/// Chave de remetente de um membro para a época atual, distribuída pelos
+/// canais par a par (já cifrados pelo ratchet de cada sessão).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SenderKeyDistribution {
+    pub room_id: RoomId,
+    pub epoch: u64,
+    pub sender: MemberKey,
+    chain_key: [u8; 32],
+    signature: [u8; 64],
+}
+
+impl SenderKeyDistribution {
+    fn signed_data(&self) -> Vec<u8> {
+        let mut data = SENDER_KEY_CONTEXT.to_vec();
+        data.extend_from_slice(&self.room_id);
+        data.extend_from_slice(&self.epoch.to_be_bytes());
+        data.extend_from_slice(&self.sender);
+        data.extend_from_slice(&self.chain_key);
+        data
+    }
+}
+
+/// Mensagem de sala cifrada com a chave de remetente e assinada pelo autor,
+/// para que o criador possa retransmiti-la sem conseguir forjá-la.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoomCiphertext {
+    pub room_id: RoomId,
+    pub epoch: u64,
+    pub sender: MemberKey,
+    pub iteration: u32,
+    ciphertext: Vec<u8>,
+    signature: [u8; 64],
+}
+
+impl RoomCiphertext {
+    fn header(&self) -> Vec<u8> {
+        let mut data = self.room_id.to_vec();
+        data.extend_from_slice(&self.epoch.to_be_bytes());
+        data.extend_from_slice(&self.sender);
+        data.extend_from_slice(&self.iteration.to_be_bytes());
+        data
+    }
+
+    fn signed_data(&self) -> Vec<u8> {
+        let mut data = MESSAGE_CONTEXT.to_vec();
+        data.extend_from_slice(&self.header());
+        data.extend_from_slice(&self.ciphertext);
+        data
+    }
+}
+
+/// Mensagens de controle e de conteúdo das salas.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RoomWire {
+    /// Convite do criador com a lista atual
+    Invite { name: String, members: MemberList },
+    /// O convidado aceita entrar
+    Join { room_id: RoomId },
+    /// Um membro sai; vindo do criador, a sala é encerrada
+    Leave { room_id: RoomId },
+    /// Nova época da lista de membros
+    Members { name: String, members: MemberList },
+    SenderKey(SenderKeyDistribution),
+    Message(RoomCiphertext),
+}
+
+/// Mensagem a enviar para um membro pelo canal par a par.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outgoing {
+    pub to: MemberKey,
+    pub message: RoomWire,
+}
+
+/// O que a aplicação deve mostrar após processar uma mensagem de sala.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RoomEvent {
+    Invited { room_id: RoomId, name: String },
+    Joined { room_id: RoomId },
+    MembersChanged { room_id: RoomId, added: Vec<MemberKey>, removed: Vec<MemberKey> },
+    /// Este membro foi removido pelo criador
+    Removed { room_id: RoomId },
+    /// O criador encerrou a sala
+    Closed { room_id: RoomId },
+    Message { room_id: RoomId, sender: MemberKey, text: String },
+}
+
+/// Resultado de uma operação: mensagens a enviar e o evento para a interface.
+#[derive(Debug, Default)]
+pub struct RoomOutput {
+    pub outgoing: Vec<Outgoing>,
+    pub event: Option<RoomEvent>,
+}
+
+/// Cadeia de envio de chaves de mensagem (KDF simétrico, como no ratchet).
+struct SenderChain {
+    chain_key: Zeroizing<[u8; 32]>,
+    iteration: u32,
+}
+
+impl SenderChain {
+    fn generate() -> Self {
+        let mut chain_key = Zeroizing::new([0u8; 32]);
+        OsRng.fill_bytes(chain_key.as_mut());
+        Self { chain_key, iteration: 0 }
+    }
+
+    fn next(&mut self) -> (u32, Zeroizing<[u8; 32]>) {
+        let (message_key, next) = kdf_sender(&self.chain_key);
+        let iteration = self.iteration;
+        self.chain_key = next;
+        self.iteration += 1;
+        (iteration, message_key)
+    }
+}
+
+/// Cópia da cadeia de outro membro, com as chaves puladas guardadas.
+struct ReceiverChain {
+    chain_key: Zeroizing<[u8; 32]>,
+    iteration: u32,
+    skipped: HashMap<u32, Zeroizing<[u8; 32]>>,
+}
+
+impl ReceiverChain {
+    fn new(chain_key: [u8; 32]) -> Self {
+        Self {
+            chain_key: Zeroizing::new(chain_key),
+            iteration: 0,
+            skipped: HashMap::new(),
+        }
+    }
+
+    fn message_key(&mut self, iteration: u32) -> Result<Zeroizing<[u8; 32]>, RoomError> {
+        if iteration < self.iteration {
+            return self.skipped.remove(&iteration).ok_or(RoomError::Replay);
+        }
+        if iteration - self.iteration > MAX_SKIP {
+            return Err(RoomError::TooManySkipped);
+        }
+        while self.iteration < iteration {
+            let (message_key, next) = kdf_sender(&self.chain_key);
+            self.skipped.insert(self.iteration, message_key);
+            self.chain_key = next;
+            self.iteration += 1;
+        }
+        let (message_key, next) = kdf_sender(&self.chain_key);
+        self.chain_key = next;
+        self.iteration += 1;
+        Ok(message_key)
+    }
+}
+
+/// Estado local de uma sala da qual este usuário é membro.
+pub struct Room {
+    pub name: String,
+    pub members: MemberList,
+    own_chain: SenderChain,
+    chains: HashMap<MemberKey, ReceiverChain>,
+    /// Convidados que ainda não entraram (apenas no criador)
+    invited: HashSet<MemberKey>,
+}
+
+impl Room {
+    /// Nome para exibição, com o identificador curto.
+    pub fn label(&self) -> String {
+        format!("{}#{}", self.name, short_id(&self.members.room_id))
+    }
+
+    pub fn is_member(&self, key: &MemberKey) -> bool {
+        self.members.members.contains(key)
+    }
+}
+
+struct PendingInvite {
+    name: String,
+    members: MemberList,
+    /// O usuário já pediu para entrar e aguarda a lista com o seu nome
+    requested: bool,
+}
+
+/// Salas em grupo sobre os canais autenticados par a par.
+///
+/// A topologia é estrela: o criador está conectado a todos os membros e
+/// retransmite o que cada um envia. As mensagens são cifradas com a chave de
+/// remetente do autor (Sender Keys) e assinadas com a sua identidade.
+pub struct RoomManager {
+    identity: Arc<Identity>,
+    rooms: HashMap<RoomId, Room>,
+    pending: HashMap<RoomId, PendingInvite>,
+}
+
+impl RoomManager {
+    pub fn new(identity: Arc<Identity>) -> Self {
+        Self {
+            identity,
+            rooms: HashMap::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    pub fn local_key(&self) -> MemberKey {
+        self.identity.public_key_bytes()
+    }
+
+    pub fn get(&self, room_id: &RoomId) -> Option<&Room> {
+        self.rooms.get(room_id)
+    }
+
+    /// Salas ativas e convites pendentes: (id, nome, é convite pendente).
+    pub fn list(&self) -> Vec<(RoomId, String, bool)> {
+        let mut list: Vec<_> = self.rooms.values()
+            .map(|room| (room.members.room_id, room.label(), false))
+            .chain(self.pending.iter().map(|(id, pending)| (*id, format!("{}#{}", pending.name, short_id(id)), true)))
+            .collect();
+        list.sort_by(|a, b| a.1.cmp(&b.1));
+        list
+    }
+
+    /// Procura uma sala (ou convite) pelo nome ou por um prefixo do identificador.
+    pub fn find(&self, query: &str) -> Result<RoomId, RoomError> {
+        let query = query.trim_start_matches('#');
+        let matches: Vec<RoomId> = self.list().into_iter()
+            .filter(|(id, label, _)| {
+                label.split('#').next() == Some(query) || label == query || hex::encode(id).starts_with(query)
+            })
+            .map(|(id, _, _)| id)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        match matches.as_slice() {
+            [room_id] => Ok(*room_id),
+            [] => Err(RoomError::UnknownRoom(query.to_string())),
+            _ => Err(RoomError::Ambiguous(query.to_string())),
+        }
+    }
+
+    /// Cria uma sala com este usuário como único membro.
+    pub fn create(&mut self, name: &str) -> RoomId {
+        let mut room_id = [0u8; 16];
+        OsRng.fill_bytes(&mut room_id);
+        let members = MemberList::signed(&self.identity, room_id, 0, BTreeSet::from([self.local_key()]));
+        self.rooms.insert(room_id, Room {
+            name: name.to_string(),
+            members,
+            own_chain: SenderChain::generate(),
+            chains: HashMap::new(),
+            invited: HashSet::new(),
+        });
+        room_id
+    }
+
+    /// Convida um par (apenas o criador convida).
+    pub fn invite(&mut self, room_id: &RoomId, invitee: MemberKey) -> Result<Outgoing, RoomError> {
+        let local_key = self.local_key();
+        let room = self.creator_room(room_id, &local_key)?;
+        if room.is_member(&invitee) {
+            return Err(RoomError::AlreadyMember);
+        }
+        room.invited.insert(invitee);
+        Ok(Outgoing {
+            to: invitee,
+            message: RoomWire::Invite { name: room.name.clone(), members: room.members.clone() },
+        })
+    }
+
+    /// Aceita um convite recebido; a sala passa a existir quando o criador
+    /// enviar a lista com este membro.
+    pub fn join(&mut self, room_id: &RoomId) -> Result<Outgoing, RoomError> {
+        let pending = self.pending.get_mut(room_id)
+            .ok_or_else(|| RoomError::UnknownRoom(short_id(room_id)))?;
+        pending.requested = true;
+        Ok(Outgoing {
+            to: pending.members.creator,
+            message: RoomWire::Join { room_id: *room_id },
+        })
+    }
+
+    /// Sai da sala. Se este usuário é o criador, a sala é encerrada para todos.
+    pub fn leave(&mut self, room_id: &RoomId) -> Result<Vec<Outgoing>, RoomError> {
+        if self.pending.remove(room_id).is_some() {
+            return Ok(Vec::new());
+        }
+        let room = self.rooms.remove(room_id)
+            .ok_or_else(|| RoomError::UnknownRoom(short_id(room_id)))?;
+        let local_key = self.local_key();
+        let message = RoomWire::Leave { room_id: *room_id };
+
+        if room.members.creator == local_key {
+            Ok(room.members.members.iter()
+                .filter(|member| **member != local_key)
+                .map(|member| Outgoing { to: *member, message: message.clone() })
+                .collect())
+        } else {
+            Ok(vec![Outgoing { to: room.members.creator, message }])
+        }
+    }
+
+    /// Remove um membro (apenas o criador) e inicia uma nova época.
+    pub fn kick(&mut self, room_id: &RoomId, member: &MemberKey) -> Result<RoomOutput, RoomError> {
+        let local_key = self.local_key();
+        let room = self.creator_room(room_id, &local_key)?;
+        if *member == local_key || !room.is_member(member) {
+            return Err(RoomError::NotMember);
+        }
+        let mut members = room.members.members.clone();
+        members.remove(member);
+        Ok(self.reconfigure(room_id, members, Some(*member)))
+    }
+
+    /// Cifra uma mensagem para a sala com a chave de remetente atual.
+    pub fn encrypt(&mut self, room_id: &RoomId, text: &str) -> Result<Vec<Outgoing>, RoomError> {
+        let sender = self.local_key();
+        let room = self.rooms.get_mut(room_id)
+            .ok_or_else(|| RoomError::UnknownRoom(short_id(room_id)))?;
+        let (iteration, message_key) = room.own_chain.next();
+
+        let mut message = RoomCiphertext {
+            room_id: *room_id,
+            epoch: room.members.epoch,
+            sender,
+            iteration,
+            ciphertext: Vec::new(),
+            signature: [0u8; 64],
+        };
+        // Cada chave de mensagem cifra uma única mensagem: o nonce pode ser fixo
+        message.ciphertext = ChaCha20Poly1305::new(Key::from_slice(message_key.as_ref()))
+            .encrypt(Nonce::from_slice(&[0u8; 12]), Payload { msg: text.as_bytes(), aad: &message.header() })
+            .map_err(|_| RoomError::Decrypt)?;
+        message.signature = self.identity.sign(&message.signed_data()).to_bytes();
+
+        let room = &self.rooms[room_id];
+        Ok(self.deliver(room, RoomWire::Message(message), None))
+    }
+
+    /// Processa uma mensagem de sala recebida do par `from`.
+    pub fn handle(&mut self, from: MemberKey, message: RoomWire) -> Result<RoomOutput, RoomError> {
+        match message {
+            RoomWire::Invite { name, members } => {
+                if !members.verify() || members.creator != from {
+                    return Err(RoomError::BadSignature);
+                }
+                let room_id = members.room_id;
+                if self.rooms.contains_key(&room_id) {
+                    return Err(RoomError::AlreadyMember);
+                }
+                self.pending.insert(room_id, PendingInvite { name: name.clone(), members, requested: false });
+                Ok(RoomOutput { outgoing: Vec::new(), event: Some(RoomEvent::Invited { room_id, name }) })
+            }
+            RoomWire::Join { room_id } => {
+                let local_key = self.local_key();
+                let room = self.creator_room(&room_id, &local_key)?;
+                if !room.invited.remove(&from) {
+                    return Err(RoomError::NotInvited);
+                }
+                let mut members = room.members.members.clone();
+                members.insert(from);
+                Ok(self.reconfigure(&room_id, members, None))
+            }
+            RoomWire::Leave { room_id } => self.handle_leave(from, room_id),
+            RoomWire::Members { name, members } => self.handle_members(from, name, members),
+            RoomWire::SenderKey(distribution) => self.handle_sender_key(from, distribution),
+            RoomWire::Message(message) => self.handle_message(from, message),
+        }
+    }
+
+    fn handle_leave(&mut self, from: MemberKey, room_id: RoomId) -> Result<RoomOutput, RoomError> {
+        let local_key = self.local_key();
+        let room = self.rooms.get(&room_id)
+            .ok_or_else(|| RoomError::UnknownRoom(short_id(&room_id)))?;
+
+        if room.members.creator == from {
+            self.rooms.remove(&room_id);
+            return Ok(RoomOutput { outgoing: Vec::new(), event: Some(RoomEvent::Closed { room_id }) });
+        }
+        if room.members.creator != local_key || !room.is_member(&from) {
+            return Err(RoomError::NotMember);
+        }
+        let mut members = room.members.members.clone();
+        members.remove(&from);
+        Ok(self.reconfigure(&room_id, members, None))
+    }
+
+    fn handle_members(&mut self, from: MemberKey, name: String, members: MemberList) -> Result<RoomOutput, RoomError> {
+        let local_key = self.local_key();
+        let room_id = members.room_id;
+        if !members.verify() || members.creator != from {
+            return Err(RoomError::BadSignature);
+        }
+
+        let event = match self.rooms.get(&room_id) {
+            Some(room) => {
+                if room.members.creator != members.creator {
+                    return Err(RoomError::BadSignature);
+                }
+                if members.epoch <= room.members.epoch {
+                    return Err(RoomError::StaleEpoch);
+                }
+                if !members.members.contains(&local_key) {
+                    self.rooms.remove(&room_id);
+                    return Ok(RoomOutput { outgoing: Vec::new(), event: Some(RoomEvent::Removed { room_id }) });
+                }
+                RoomEvent::MembersChanged {
+                    room_id,
+                    added: members.members.difference(&room.members.members).copied().collect(),
+                    removed: room.members.members.difference(&members.members).copied().collect(),
+                }
+            }
+            None => {
+                let accepted = self.pending.get(&room_id)
+                    .is_some_and(|pending| pending.requested && pending.members.creator == members.creator);
+                if !accepted || !members.members.contains(&local_key) {
+                    return Err(RoomError::NotInvited);
+                }
+                self.pending.remove(&room_id);
+                RoomEvent::Joined { room_id }
+            }
+        };
+
+        // Nova época: chaves antigas descartadas e chave de remetente nova
+        self.rooms.insert(room_id, Room {
+            name,
+            members,
+            own_chain: SenderChain::generate(),
+            chains: HashMap::new(),
+            invited: HashSet::new(),
+        });
+        let outgoing = self.distribute_sender_key(&room_id);
+        Ok(RoomOutput { outgoing, event: Some(event) })
+    }
+
+    fn handle_sender_key(&mut self, from: MemberKey, distribution: SenderKeyDistribution) -> Result<RoomOutput, RoomError> {
+        let local_key = self.local_key();
+        let room = self.authorized_room(from, &distribution.room_id, distribution.epoch, &distribution.sender)?;
+        if !verify_by(&distribution.sender, &distribution.signed_data(), &distribution.signature) {
+            return Err(RoomError::BadSignature);
+        }
+        room.chains.insert(distribution.sender, ReceiverChain::new(distribution.chain_key));
+
+        let outgoing = if room.members.creator == local_key {
+            let sender = distribution.sender;
+            let room = &self.rooms[&distribution.room_id];
+            self.deliver(room, RoomWire::SenderKey(distribution), Some(sender))
+        } else {
+            Vec::new()
+        };
+        Ok(RoomOutput { outgoing, event: None })
+    }
+
+    fn handle_message(&mut self, from: MemberKey, message: RoomCiphertext) -> Result<RoomOutput, RoomError> {
+        let local_key = self.local_key();
+        let room = self.authorized_room(from, &message.room_id, message.epoch, &message.sender)?;
+        if !verify_by(&message.sender, &message.signed_data(), &message.signature) {
+            return Err(RoomError::BadSignature);
+        }
+
+        let chain = room.chains.get_mut(&message.sender).ok_or(RoomError::MissingSenderKey)?;
+        let message_key = chain.message_key(message.iteration)?;
+        let plaintext = ChaCha20Poly1305::new(Key::from_slice(message_key.as_ref()))
+            .decrypt(Nonce::from_slice(&[0u8; 12]), Payload { msg: &message.ciphertext, aad: &message.header() })
+            .map_err(|_| RoomError::Decrypt)?;
+        let text = String::from_utf8(plaintext).map_err(|_| RoomError::Decrypt)?;
+
+        let event = RoomEvent::Message { room_id: message.room_id, sender: message.sender, text };
+        let outgoing = if room.members.creator == local_key {
+            let sender = message.sender;
+            let room = &self.rooms[&message.room_id];
+            self.deliver(room, RoomWire::Message(message), Some(sender))
+        } else {
+            Vec::new()
+        };
+        Ok(RoomOutput { outgoing, event: Some(event) })
+    }
+
+    /// Sala da qual este usuário é o criador.
+    fn creator_room(&mut self, room_id: &RoomId, local_key: &MemberKey) -> Result<&mut Room, RoomError> {
+        let room = self.rooms.get_mut(room_id)
+            .ok_or_else(|| RoomError::UnknownRoom(short_id(room_id)))?;
+        if room.members.creator != *local_key {
+            return Err(RoomError::NotCreator);
+        }
+        Ok(room)
+    }
+
+    /// Confere época, autoria e o caminho de uma mensagem de outro membro:
+    /// ela vem do próprio autor ou retransmitida pelo criador.
+    fn authorized_room(&mut self, from: MemberKey, room_id: &RoomId, epoch: u64, sender: &MemberKey) -> Result<&mut Room, RoomError> {
+        let local_key = self.local_key();
+        let room = self.rooms.get_mut(room_id)
+            .ok_or_else(|| RoomError::UnknownRoom(short_id(room_id)))?;
+        if epoch != room.members.epoch {
+            return Err(RoomError::StaleEpoch);
+        }
+        if *sender == local_key || !room.is_member(sender) {
+            return Err(RoomError::NotMember);
+        }
+        if from != *sender && from != room.members.creator {
+            return Err(RoomError::NotMember);
+        }
+        Ok(room)
+    }
+
+    /// Publica uma nova época (criador): assina a lista, troca a própria chave
+    /// e envia a lista a todos, inclusive a quem foi removido.
+    fn reconfigure(&mut self, room_id: &RoomId, members: BTreeSet<MemberKey>, removed: Option<MemberKey>) -> RoomOutput {
+        let local_key = self.local_key();
+        let room = self.rooms.get_mut(room_id).expect("sala verificada pelo chamador");
+        let previous = room.members.members.clone();
+        room.members = MemberList::signed(&self.identity, *room_id, room.members.epoch + 1, members);
+        room.own_chain = SenderChain::generate();
+        room.chains.clear();
+
+        let announcement = RoomWire::Members { name: room.name.clone(), members: room.members.clone() };
+        let mut outgoing: Vec<Outgoing> = room.members.members.iter()
+            .chain(removed.iter())
+            .filter(|member| **member != local_key)
+            .map(|member| Outgoing { to: *member, message: announcement.clone() })
+            .collect();
+        outgoing.extend(self.distribute_sender_key(room_id));
+
+        let room = &self.rooms[room_id];
+        let event = RoomEvent::MembersChanged {
+            room_id: *room_id,
+            added: room.members.members.difference(&previous).copied().collect(),
+            removed: previous.difference(&room.members.members).copied().collect(),
+        };
+        RoomOutput { outgoing, event: Some(event) }
+    }
+
+    /// Envia a chave de remetente atual deste usuário aos demais membros.
+    fn distribute_sender_key(&self, room_id: &RoomId) -> Vec<Outgoing> {
+        let room = &self.rooms[room_id];
+        let mut distribution = SenderKeyDistribution {
+            room_id: *room_id,
+            epoch: room.members.epoch,
+            sender: self.local_key(),
+            chain_key: *room.own_chain.chain_key,
+            signature: [0u8; 64],
+        };
+        distribution.signature = self.identity.sign(&distribution.signed_data()).to_bytes();
+        self.deliver(room, RoomWire::SenderKey(distribution), None)
+    }
+
+    /// Destinos de uma mensagem da sala: o criador entrega a cada membro
+    /// (exceto ao autor original); os demais entregam só ao criador.
+    fn deliver(&self, room: &Room, message: RoomWire, author: Option<MemberKey>) -> Vec<Outgoing> {
+        let local_key = self.local_key();
+        if room.members.creator != local_key {
+            return vec![Outgoing { to: room.members.creator, message }];
+        }
+        room.members.members.iter()
+            .filter(|member| **member != local_key && Some(**member) != author)
+            .map(|member| Outgoing { to: *member, message: message.clone() })
+            .collect()
+    }
+}
+
+/// KDF da cadeia de remetente: (chave de mensagem, próxima chave de cadeia)
+fn kdf_sender(chain_key: &[u8; 32]) -> (Zeroizing<[u8; 32]>, Zeroizing<[u8; 32]>) {
+    let hkdf = Hkdf::<Sha256>::new(None, chain_key);
+    let mut output = Zeroizing::new([0u8; 64]);
+    hkdf.expand(HKDF_INFO_SENDER_CHAIN, output.as_mut())
+        .expect("HKDF expand failed");
+
+    let mut message_key = Zeroizing::new([0u8; 32]);
+    let mut next = Zeroizing::new([0u8; 32]);
+    message_key.copy_from_slice(&output[..32]);
+    next.copy_from_slice(&output[32..]);
+    (message_key, next)
+}
+
+fn verify_by(key: &MemberKey, data: &[u8], signature: &[u8; 64]) -> bool {
+    VerifyingKey::from_bytes(key)
+        .is_ok_and(|key| verify_signature(&key, data, &Signature::from_bytes(signature)).is_ok())
+}
+
+impl WireEncode for MemberList {
+    fn encode(&self, w: &mut Writer) {
+        w.array(&self.room_id);
+        w.u64(self.epoch);
+        w.array(&self.creator);
+        w.u32(self.members.len() as u32);
+        for member in &self.members {
+            w.array(member);
+        }
+        w.array(&self.signature);
+    }
+}
+
+impl WireDecode for MemberList {
+    fn decode(r: &mut Reader) -> Result<Self, ProtocolError> {
+        let room_id = r.array()?;
+        let epoch = r.u64()?;
+        let creator = r.array()?;
+        let count = r.count(32)?;
+        let members = (0..count).map(|_| r.array()).collect::<Result<_, _>>()?;
+        Ok(Self { room_id, epoch, creator, members, signature: r.array()? })
+    }
+}
+
+impl WireEncode for SenderKeyDistribution {
+    fn encode(&self, w: &mut Writer) {
+        w.array(&self.room_id);
+        w.u64(self.epoch);
+        w.array(&self.sender);
+        w.array(&self.chain_key);
+        w.array(&self.signature);
+    }
+}
+
+impl WireDecode for SenderKeyDistribution {
+    fn decode(r: &mut Reader) -> Result<Self, ProtocolError> {
+        Ok(Self {
+            room_id: r.array()?,
+            epoch: r.u64()?,
+            sender: r.array()?,
+            chain_key: r.array()?,
+            signature: r.array()?,
+        })
+    }
+}
+
+impl WireEncode for RoomCiphertext {
+    fn encode(&self, w: &mut Writer) {
+        w.array(&self.header());
+        w.bytes(&self.ciphertext);
+        w.array(&self.signature);
+    }
+}
+
+impl WireDecode for RoomCiphertext {
+    fn decode(r: &mut Reader) -> Result<Self, ProtocolError> {
+        Ok(Self {
+            room_id: r.array()?,
+            epoch: r.u64()?,
+            sender: r.array()?,
+            iteration: r.u32()?,
+            ciphertext: r.bytes()?,
+            signature: r.array()?,
+        })
+    }
+}
+
+impl WireEncode for RoomWire {
+    fn encode(&self, w: &mut Writer) {
+        match self {
+            RoomWire::Invite { name, members } => {
+                w.u8(0);
+                w.string(name);
+                members.encode(w);
+            }
+            RoomWire::Join { room_id } => {
+                w.u8(1);
+                w.array(room_id);
+            }
+            RoomWire::Leave { room_id } => {
+                w.u8(2);
+                w.array(room_id);
+            }
+            RoomWire::Members { name, members } => {
+                w.u8(3);
+                w.string(name);
+                members.encode(w);
+            }
+            RoomWire::SenderKey(distribution) => {
+                w.u8(4);
+                distribution.encode(w);
+            }
+            RoomWire::Message(ciphertext) => {
+                w.u8(5);
+                ciphertext.encode(w);
+            }
+        }
+    }
+}
+
+impl WireDecode for RoomWire {
+    fn decode(r: &mut Reader) -> Result<Self, ProtocolError> {
+        Ok(match r.u8()? {
+            0 => RoomWire::Invite { name: r.string()?, members: MemberList::decode(r)? },
+            1 => RoomWire::Join { room_id: r.array()? },
+            2 => RoomWire::Leave { room_id: r.array()? },
+            3 => RoomWire::Members { name: r.string()?, members: MemberList::decode(r)? },
+            4 => RoomWire::SenderKey(SenderKeyDistribution::decode(r)?),
+            5 => RoomWire::Message(RoomCiphertext::decode(r)?),
+            _ => return Err(ProtocolError::Malformed),
+        })
+    }
+}
+
+/// Erros das salas em grupo.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RoomError {
+    UnknownRoom(String),
+    Ambiguous(String),
+    NotCreator,
+    NotInvited,
+    NotMember,
+    AlreadyMember,
+    BadSignature,
+    StaleEpoch,
+    MissingSenderKey,
+    TooManySkipped,
+    Replay,
+    Decrypt,
+}
+
+impl std::fmt::Display for RoomError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RoomError::UnknownRoom(room) => write!(f, "Sala desconhecida: {}", room),
+            RoomError::Ambiguous(room) => write!(f, "Mais de uma sala corresponde a '{}': use o identificador", room),
+            RoomError::NotCreator => write!(f, "Apenas o criador da sala pode fazer isso"),
+            RoomError::NotInvited => write!(f, "Entrada em sala sem convite"),
+            RoomError::NotMember => write!(f, "Remetente não é membro da sala"),
+            RoomError::AlreadyMember => write!(f, "Já é membro da sala"),
+            RoomError::BadSignature => write!(f, "Assinatura inválida em mensagem de sala"),
+            RoomError::StaleEpoch => write!(f, "Mensagem de uma época anterior da sala"),
+            RoomError::MissingSenderKey => write!(f, "Chave de remetente ainda não recebida"),
+            RoomError::TooManySkipped => write!(f, "Muitas mensagens puladas na sala"),
+            RoomError::Replay => write!(f, "Mensagem de sala repetida"),
+            RoomError::Decrypt => write!(f, "Falha ao decifrar mensagem de sala"),
+        }
+    }
+}
+
+impl std::error::Error for RoomError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    struct Member {
+        key: MemberKey,
+        rooms: RoomManager,
+        events: Vec<RoomEvent>,
+    }
+
+    fn member() -> Member {
+        let identity = Arc::new(Identity::generate());
+        Member { key: identity.public_key_bytes(), rooms: RoomManager::new(identity), events: Vec::new() }
+    }
+
+    /// Entrega as mensagens em ordem até esgotar, como fariam os canais par a par.
+    fn route(members: &mut [Member], from: MemberKey, outgoing: Vec<Outgoing>) {
+        let mut queue: VecDeque<(MemberKey, Outgoing)> = outgoing.into_iter().map(|o| (from, o)).collect();
+        while let Some((from, Outgoing { to, message })) = queue.pop_front() {
+            let Some(target) = members.iter_mut().find(|m| m.key == to) else { continue };
+            let output = target.rooms.handle(from, message).unwrap();
+            target.events.extend(output.event);
+            queue.extend(output.outgoing.into_iter().map(|o| (to, o)));
+        }
+    }
+
+    /// Sala criada por `members[0]` com todos os demais.
+    fn room_with(members: &mut [Member]) -> RoomId {
+        let room_id = members[0].rooms.create("geral");
+        for i in 1..members.len() {
+            let invite = members[0].rooms.invite(&room_id, members[i].key).unwrap();
+            let creator = members[0].key;
+            route(members, creator, vec![invite]);
+            let join = members[i].rooms.join(&room_id).unwrap();
+            let key = members[i].key;
+            route(members, key, vec![join]);
+        }
+        room_id
+    }
+
+    fn last_text(member: &Member) -> Option<&str> {
+        member.events.iter().rev().find_map(|e| match e {
+            RoomEvent::Message { text, .. } => Some(text.as_str()),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn test_messages_relayed_to_all_members() {
+        let mut members = vec![member(), member(), member()];
+        let room_id = room_with(&mut members);
+        assert!(members.iter().all(|m| m.rooms.get(&room_id).unwrap().members.members.len() == 3));
+
+        // Bob envia; o criador decifra e retransmite para Carol
+        let outgoing = members[1].rooms.encrypt(&room_id, "olá, sala").unwrap();
+        let bob = members[1].key;
+        route(&mut members, bob, outgoing);
+        assert_eq!(last_text(&members[0]), Some("olá, sala"));
+        assert_eq!(last_text(&members[2]), Some("olá, sala"));
+        assert_eq!(last_text(&members[1]), None);
+    }
+
+    #[test]
+    fn test_kicked_member_loses_access() {
+        let mut members = vec![member(), member(), member()];
+        let room_id = room_with(&mut members);
+        let carol = members[2].key;
+
+        let output = members[0].rooms.kick(&room_id, &carol).unwrap();
+        let creator = members[0].key;
+        route(&mut members, creator, output.outgoing);
+        assert!(members[2].events.contains(&RoomEvent::Removed { room_id }));
+        assert!(members[2].rooms.get(&room_id).is_none());
+
+        // Uma mensagem nova não chega a Carol, e a chave antiga não serve mais
+        let outgoing = members[1].rooms.encrypt(&room_id, "sem a Carol").unwrap();
+        assert!(outgoing.iter().all(|o| o.to != carol));
+        let bob = members[1].key;
+        route(&mut members, bob, outgoing);
+        assert_eq!(last_text(&members[0]), Some("sem a Carol"));
+        assert_eq!(members[1].rooms.get(&room_id).unwrap().members.epoch, 3);
+    }
+
+    #[test]
+    fn test_relay_cannot_forge_or_replay() {
+        let mut members = vec![member(), member(), member()];
+        let room_id = room_with(&mut members);
+        let outgoing = members[1].rooms.encrypt(&room_id, "original").unwrap();
+        let RoomWire::Message(message) = outgoing[0].message.clone() else { panic!() };
+        let creator = members[0].key;
+
+        // O criador entrega a Carol uma versão alterada: assinatura não confere
+        let mut forged = message.clone();
+        forged.ciphertext[0] ^= 1;
+        assert_eq!(members[2].rooms.handle(creator, RoomWire::Message(forged)).unwrap_err(), RoomError::BadSignature);
+
+        // A original passa uma vez, a repetição não
+        assert!(members[2].rooms.handle(creator, RoomWire::Message(message.clone())).is_ok());
+        assert_eq!(members[2].rooms.handle(creator, RoomWire::Message(message)).unwrap_err(), RoomError::Replay);
+    }
+
+    #[test]
+    fn test_member_list_requires_creator_signature() {
+        let mut members = vec![member(), member(), member()];
+        let room_id = room_with(&mut members);
+
+        // Bob assina uma lista sem Carol fingindo ser a nova época
+        let bob_identity = Identity::generate();
+        let mut list = members[2].rooms.get(&room_id).unwrap().members.clone();
+        list.epoch += 1;
+        list.members.remove(&members[2].key);
+        list.signature = bob_identity.sign(&list.signed_data()).to_bytes();
+        let creator = members[0].key;
+        assert_eq!(
+            members[2].rooms.handle(creator, RoomWire::Members { name: "geral".into(), members: list }).unwrap_err(),
+            RoomError::BadSignature
+        );
+        assert!(members[2].rooms.get(&room_id).is_some());
+    }
+
+    #[test]
+    fn test_join_requires_invite_and_creator_leave_closes() {
+        let mut members = vec![member(), member(), member()];
+        let room_id = members[0].rooms.create("geral");
+        let intruder = members[2].key;
+        assert_eq!(members[0].rooms.handle(intruder, RoomWire::Join { room_id }).unwrap_err(), RoomError::NotInvited);
+
+        let room_id = room_with(&mut members[..2]);
+        let outgoing = members[0].rooms.leave(&room_id).unwrap();
+        let creator = members[0].key;
+        route(&mut members, creator, outgoing);
+        assert!(members[1].events.contains(&RoomEvent::Closed { room_id }));
+        assert!(members[1].rooms.get(&room_id).is_none());
+    }
+
+    #[test]
+    fn test_wire_roundtrip() {
+        let mut members = vec![member(), member()];
+        let room_id = room_with(&mut members);
+        let mut outgoing = members[1].rooms.encrypt(&room_id, "binário").unwrap();
+        outgoing.push(members[0].rooms.invite(&room_id, [7u8; 32]).unwrap());
+        for out in outgoing {
+            let mut w = Writer::default();
+            out.message.encode(&mut w);
+            let bytes = w.into_bytes();
+            let mut r = Reader::new(&bytes);
+            assert_eq!(RoomWire::decode(&mut r).unwrap(), out.message);
+            r.finish().unwrap();
+        }
+        assert_eq!(members[0].rooms.find("geral"), Ok(room_id));
+        assert_eq!(members[0].rooms.find(&short_id(&room_id)), Ok(room_id));
+    }
+}
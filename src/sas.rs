@@ -0,0 +1,184 @@
+use crate::identity::{verify_signature, Identity};
+use crate::protocol::{ProtocolError, Reader, WireDecode, WireEncode, Writer};
+use ed25519_dalek::{Signature, VerifyingKey};
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+const SAS_INFO: &[u8] = b"sae-sas-v1";
+const VERIFIED_CONTEXT: &[u8] = b"sae-verified-v1";
+const EMOJI_COUNT: usize = 7;
+const SAFETY_NUMBER_GROUPS: usize = 6;
+
+/// Tabela de 64 emojis (6 bits cada), na ordem da especificação de SAS do Matrix,
+/// com nomes para leitura em voz alta.
+const EMOJI: [(&str, &str); 64] = [
+    ("🐶", "Cachorro"), ("🐱", "Gato"), ("🦁", "Leão"), ("🐎", "Cavalo"),
+    ("🦄", "Unicórnio"), ("🐷", "Porco"), ("🐘", "Elefante"), ("🐰", "Coelho"),
+    ("🐼", "Panda"), ("🐓", "Galo"), ("🐧", "Pinguim"), ("🐢", "Tartaruga"),
+    ("🐟", "Peixe"), ("🐙", "Polvo"), ("🦋", "Borboleta"), ("🌷", "Flor"),
+    ("🌳", "Árvore"), ("🌵", "Cacto"), ("🍄", "Cogumelo"), ("🌏", "Globo"),
+    ("🌙", "Lua"), ("☁️", "Nuvem"), ("🔥", "Fogo"), ("🍌", "Banana"),
+    ("🍎", "Maçã"), ("🍓", "Morango"), ("🌽", "Milho"), ("🍕", "Pizza"),
+    ("🎂", "Bolo"), ("❤️", "Coração"), ("😀", "Sorriso"), ("🤖", "Robô"),
+    ("🎩", "Chapéu"), ("👓", "Óculos"), ("🔧", "Chave inglesa"), ("🎅", "Papai Noel"),
+    ("👍", "Joinha"), ("☂️", "Guarda-chuva"), ("⌛", "Ampulheta"), ("⏰", "Despertador"),
+    ("🎁", "Presente"), ("💡", "Lâmpada"), ("📕", "Livro"), ("✏️", "Lápis"),
+    ("📎", "Clipe"), ("✂️", "Tesoura"), ("🔒", "Cadeado"), ("🔑", "Chave"),
+    ("🔨", "Martelo"), ("☎️", "Telefone"), ("🏁", "Bandeira"), ("🚂", "Trem"),
+    ("🚲", "Bicicleta"), ("✈️", "Avião"), ("🚀", "Foguete"), ("🏆", "Troféu"),
+    ("⚽", "Bola"), ("🎸", "Violão"), ("🎺", "Trompete"), ("🔔", "Sino"),
+    ("⚓", "Âncora"), ("🎧", "Fones"), ("📁", "Pasta"), ("📌", "Alfinete"),
+];
+
+/// Short Authentication String de uma sessão.
+///
+/// Derivada do hash do transcript Noise (que cobre as chaves efêmeras e
+/// estáticas) e das duas chaves de identidade. Um intermediário que tenha
+/// feito dois handshakes separados produz transcripts diferentes em cada
+/// lado, e portanto códigos diferentes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sas {
+    bytes: [u8; 32],
+}
+
+impl Sas {
+    /// Deriva o SAS; o resultado independe de qual lado é o local.
+    pub fn derive(handshake_hash: &[u8; 32], local_identity: &[u8; 32], remote_identity: &[u8; 32]) -> Self {
+        let (first, second) = if local_identity <= remote_identity {
+            (local_identity, remote_identity)
+        } else {
+            (remote_identity, local_identity)
+        };
+
+        let mut info = SAS_INFO.to_vec();
+        info.extend_from_slice(first);
+        info.extend_from_slice(second);
+
+        let hkdf = Hkdf::<Sha256>::new(None, handshake_hash);
+        let mut bytes = [0u8; 32];
+        hkdf.expand(&info, &mut bytes).expect("HKDF expand failed");
+        Self { bytes }
+    }
+
+    /// Sete emojis (42 bits) com seus nomes.
+    pub fn emoji(&self) -> Vec<(&'static str, &'static str)> {
+        let bits = u64::from_be_bytes(self.bytes[..8].try_into().unwrap());
+        (0..EMOJI_COUNT)
+            .map(|i| EMOJI[((bits >> (58 - 6 * i)) & 0x3f) as usize])
+            .collect()
+    }
+
+    /// Número de segurança: seis grupos de cinco dígitos.
+    pub fn safety_number(&self) -> String {
+        self.bytes[8..]
+            .chunks(4)
+            .take(SAFETY_NUMBER_GROUPS)
+            .map(|chunk| format!("{:05}", u32::from_be_bytes(chunk.try_into().unwrap()) % 100_000))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Mensagem de controle enviada quando o usuário confirma que o SAS confere.
+/// A assinatura cobre o SAS e a chave verificada, então não vale em outra sessão.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedNotice {
+    /// Chave Ed25519 que o remetente verificou (a do destinatário)
+    pub verified_key: [u8; 32],
+    pub signature: [u8; 64],
+}
+
+impl VerifiedNotice {
+    pub fn new(identity: &Identity, sas: &Sas, verified_key: &[u8; 32]) -> Self {
+        let signature = identity.sign(&notice_data(sas, verified_key));
+        Self {
+            verified_key: *verified_key,
+            signature: signature.to_bytes(),
+        }
+    }
+
+    /// Confere que o par `sender` assinou a verificação da nossa chave nesta sessão.
+    pub fn verify(&self, sas: &Sas, sender: &[u8; 32], local_identity: &[u8; 32]) -> bool {
+        let Ok(sender) = VerifyingKey::from_bytes(sender) else {
+            return false;
+        };
+        self.verified_key == *local_identity
+            && verify_signature(&sender, &notice_data(sas, local_identity), &Signature::from_bytes(&self.signature)).is_ok()
+    }
+}
+
+impl WireEncode for VerifiedNotice {
+    fn encode(&self, w: &mut Writer) {
+        w.array(&self.verified_key);
+        w.array(&self.signature);
+    }
+}
+
+impl WireDecode for VerifiedNotice {
+    fn decode(r: &mut Reader) -> Result<Self, ProtocolError> {
+        Ok(Self { verified_key: r.array()?, signature: r.array()? })
+    }
+}
+
+fn notice_data(sas: &Sas, verified_key: &[u8; 32]) -> Vec<u8> {
+    let mut data = VERIFIED_CONTEXT.to_vec();
+    data.extend_from_slice(&sas.bytes);
+    data.extend_from_slice(verified_key);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sas_symmetric() {
+        let hash = [7u8; 32];
+        let alice = Identity::generate().public_key_bytes();
+        let bob = Identity::generate().public_key_bytes();
+
+        let a = Sas::derive(&hash, &alice, &bob);
+        let b = Sas::derive(&hash, &bob, &alice);
+        assert_eq!(a, b);
+        assert_eq!(a.emoji(), b.emoji());
+        assert_eq!(a.safety_number(), b.safety_number());
+    }
+
+    #[test]
+    fn test_sas_depends_on_transcript_and_identities() {
+        let alice = Identity::generate().public_key_bytes();
+        let bob = Identity::generate().public_key_bytes();
+        let mallory = Identity::generate().public_key_bytes();
+
+        let honest = Sas::derive(&[1u8; 32], &alice, &bob);
+        assert_ne!(honest, Sas::derive(&[2u8; 32], &alice, &bob));
+        assert_ne!(honest, Sas::derive(&[1u8; 32], &alice, &mallory));
+    }
+
+    #[test]
+    fn test_sas_format() {
+        let sas = Sas::derive(&[3u8; 32], &[4u8; 32], &[5u8; 32]);
+        assert_eq!(sas.emoji().len(), 7);
+
+        let number = sas.safety_number();
+        let groups: Vec<&str> = number.split(' ').collect();
+        assert_eq!(groups.len(), 6);
+        assert!(groups.iter().all(|g| g.len() == 5 && g.chars().all(|c| c.is_ascii_digit())));
+    }
+
+    #[test]
+    fn test_verified_notice() {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let sas = Sas::derive(&[9u8; 32], &alice.public_key_bytes(), &bob.public_key_bytes());
+
+        // Alice verificou Bob e avisa; Bob confere com a chave de Alice
+        let notice = VerifiedNotice::new(&alice, &sas, &bob.public_key_bytes());
+        assert!(notice.verify(&sas, &alice.public_key_bytes(), &bob.public_key_bytes()));
+
+        // Outra sessão (outro SAS) ou outro remetente não valem
+        let other_sas = Sas::derive(&[8u8; 32], &alice.public_key_bytes(), &bob.public_key_bytes());
+        assert!(!notice.verify(&other_sas, &alice.public_key_bytes(), &bob.public_key_bytes()));
+        assert!(!notice.verify(&sas, &bob.public_key_bytes(), &bob.public_key_bytes()));
+    }
+}
@@ -0,0 +1,258 @@
+use std::io;
+use std::sync::Arc;
+use x25519_dalek::StaticSecret;
+
+use crate::handshake::{HandshakeError, HandshakeOutcome, NoiseHandshake};
+use crate::identity::Identity;
+use crate::invite::{Invite, TOKEN_LEN};
+use crate::protocol::{ChannelError, Message, SecureChannel};
+use crate::ratchet::{RatchetSession, Role};
+use crate::sas::Sas;
+use crate::transport::Transport;
+
+/// Conversa cifrada com um par sobre qualquer `Transport`: handshake Noise
+/// autenticado, Double Ratchet e padding, sem rede, reconexão nem interface.
+///
+/// É o que `NetworkManager` faz por baixo para cada conexão, exposto para
+/// bots e outras interfaces que cuidam do próprio transporte.
+pub struct Session<T: Transport> {
+    transport: T,
+    channel: SecureChannel,
+    outcome: HandshakeOutcome,
+    remote_token: Option<[u8; TOKEN_LEN]>,
+}
+
+impl<T: Transport> Session<T> {
+    /// Lado que conecta. Com `remote_static` (a chave Noise do convite) o
+    /// handshake é IK, senão XX; `token` segue cifrado até o host. Com
+    /// `post_quantum` a troca é híbrida, o que o par precisa suportar.
+    pub async fn initiate(
+        mut transport: T,
+        identity: Arc<Identity>,
+        local_static: StaticSecret,
+        remote_static: Option<[u8; 32]>,
+        token: Option<[u8; TOKEN_LEN]>,
+        post_quantum: bool,
+    ) -> Result<Self, SessionError> {
+        let handshake = NoiseHandshake::initiator(&local_static, remote_static, identity, token, post_quantum)?;
+        let (outcome, _) = run_handshake(handshake, &mut transport).await?;
+        Ok(Self::established(transport, outcome, Role::Initiator, local_static, None))
+    }
+
+    /// Conecta com um convite já validado por `Invite::parse`: usa a chave
+    /// Noise e o token dele e exige que o host seja quem assinou o convite.
+    /// Usa ML-KEM-768 quando o convite o anuncia.
+    pub async fn connect(transport: T, identity: Arc<Identity>, invite: &Invite) -> Result<Self, SessionError> {
+        let local_static = StaticSecret::random_from_rng(rand::rngs::OsRng);
+        let session = Self::initiate(
+            transport, identity, local_static, invite.noise_key, Some(invite.token), invite.post_quantum,
+        ).await?;
+        if session.remote_identity() != invite.identity_key {
+            return Err(SessionError::IdentityMismatch);
+        }
+        Ok(session)
+    }
+
+    /// Lado que aceita, com a chave estática anunciada nos convites. O token
+    /// apresentado pelo par fica em `remote_token`; cabe a quem chama
+    /// conferi-lo antes de confiar na sessão. Aceita tanto a troca clássica
+    /// quanto a híbrida; `post_quantum` diz qual foi usada.
+    pub async fn accept(mut transport: T, identity: Arc<Identity>, local_static: StaticSecret) -> Result<Self, SessionError> {
+        let handshake = NoiseHandshake::responder(&local_static, identity, false);
+        let (outcome, remote_token) = run_handshake(handshake, &mut transport).await?;
+        Ok(Self::established(transport, outcome, Role::Responder, local_static, remote_token))
+    }
+
+    fn established(
+        transport: T,
+        outcome: HandshakeOutcome,
+        role: Role,
+        local_static: StaticSecret,
+        remote_token: Option<[u8; TOKEN_LEN]>,
+    ) -> Self {
+        // A chave estática do respondedor é a chave de ratchet inicial
+        let ratchet = RatchetSession::new(role, &outcome.root_key, local_static, outcome.remote_static);
+        let channel = SecureChannel::new(ratchet, outcome.protocol_version);
+        Self { transport, channel, outcome, remote_token }
+    }
+
+    /// Cifra e envia uma mensagem; se a cadeia de envio ficou longa sem
+    /// resposta, pede em seguida um passo DH ao par.
+    pub async fn send(&mut self, message: Message) -> Result<(), SessionError> {
+        let mut messages = vec![message];
+        if self.channel.wants_rekey() {
+            messages.push(Message::Rekey { request: true });
+        }
+        for message in messages {
+            let frame = self.channel.seal(message)?;
+            self.transport.send(frame).await?;
+        }
+        Ok(())
+    }
+
+    /// Próxima mensagem do par; `None` quando a conexão fecha. Pedidos de
+    /// passo DH são respondidos aqui mesmo e não chegam a quem chama.
+    pub async fn recv(&mut self) -> Result<Option<Message>, SessionError> {
+        loop {
+            let Some(frame) = self.transport.recv().await? else {
+                return Ok(None);
+            };
+            match self.channel.open(&frame)? {
+                Message::Rekey { request: true } => self.send(Message::Rekey { request: false }).await?,
+                Message::Rekey { request: false } => {}
+                message => return Ok(Some(message)),
+            }
+        }
+    }
+
+    /// Avisa o par de que a sessão terminou de propósito.
+    pub async fn close(mut self, reason: &str) -> Result<(), SessionError> {
+        self.send(Message::Close { reason: reason.to_string() }).await
+    }
+
+    /// Chave de identidade Ed25519 do par, autenticada no handshake.
+    pub fn remote_identity(&self) -> [u8; 32] {
+        self.outcome.remote_identity
+    }
+
+    pub fn fingerprint(&self) -> &str {
+        &self.outcome.fingerprint
+    }
+
+    /// Código de verificação para comparar com o par por outro canal.
+    pub fn sas(&self) -> Sas {
+        self.outcome.sas
+    }
+
+    pub fn protocol_version(&self) -> u8 {
+        self.outcome.protocol_version
+    }
+
+    /// Chave raiz derivada também de ML-KEM-768.
+    pub fn post_quantum(&self) -> bool {
+        self.outcome.post_quantum
+    }
+
+    /// Token de convite apresentado pelo iniciador (só do lado que aceita).
+    pub fn remote_token(&self) -> Option<[u8; TOKEN_LEN]> {
+        self.remote_token
+    }
+}
+
+async fn run_handshake<T: Transport>(
+    mut handshake: NoiseHandshake,
+    transport: &mut T,
+) -> Result<(HandshakeOutcome, Option<[u8; TOKEN_LEN]>), SessionError> {
+    while !handshake.is_finished() {
+        if handshake.is_my_turn() {
+            let message = handshake.write_message()?;
+            transport.send(message).await?;
+        } else {
+            let message = transport.recv().await?.ok_or(SessionError::Closed)?;
+            handshake.read_message(&message)?;
+        }
+    }
+    let token = handshake.remote_token();
+    Ok((handshake.finish()?, token))
+}
+
+/// Erros de uma `Session`, por camada.
+#[derive(Debug)]
+pub enum SessionError {
+    Handshake(HandshakeError),
+    Channel(ChannelError),
+    Transport(io::Error),
+    /// O par fechou a conexão antes do fim do handshake
+    Closed,
+    /// O host autenticado não é o que assinou o convite
+    IdentityMismatch,
+}
+
+impl From<HandshakeError> for SessionError {
+    fn from(e: HandshakeError) -> Self {
+        SessionError::Handshake(e)
+    }
+}
+
+impl From<ChannelError> for SessionError {
+    fn from(e: ChannelError) -> Self {
+        SessionError::Channel(e)
+    }
+}
+
+impl From<io::Error> for SessionError {
+    fn from(e: io::Error) -> Self {
+        SessionError::Transport(e)
+    }
+}
+
+impl std::fmt::Display for SessionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionError::Handshake(e) => write!(f, "{}", e),
+            SessionError::Channel(e) => write!(f, "{}", e),
+            SessionError::Transport(e) => write!(f, "Erro no transporte: {}", e),
+            SessionError::Closed => write!(f, "Conexão fechada durante o handshake"),
+            SessionError::IdentityMismatch => write!(f, "Identidade do host não corresponde ao convite"),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{memory_pair, Connection};
+
+    async fn pair_with_invite() -> (Session<Connection>, Session<Connection>, Invite) {
+        let host = Arc::new(Identity::generate());
+        let host_static = StaticSecret::random_from_rng(rand::rngs::OsRng);
+        let noise_key = x25519_dalek::PublicKey::from(&host_static).to_bytes();
+        let invite = Invite::new(&host, "127.0.0.1", 9001, Some(noise_key), None, true, std::time::Duration::from_secs(60));
+
+        let (client_end, host_end) = memory_pair();
+        let accept = tokio::spawn(Session::accept(host_end, host, host_static));
+        let client = Session::connect(client_end, Arc::new(Identity::generate()), &invite).await.unwrap();
+        (client, accept.await.unwrap().unwrap(), invite)
+    }
+
+    #[tokio::test]
+    async fn test_session_over_memory_pair() {
+        let (mut client, mut host, invite) = pair_with_invite().await;
+        assert_eq!(host.remote_token(), Some(invite.token));
+        assert_eq!(client.sas(), host.sas());
+        assert!(client.post_quantum() && host.post_quantum());
+        assert_eq!(client.remote_identity(), invite.identity_key);
+        assert_ne!(host.remote_identity(), invite.identity_key);
+
+        client.send(Message::Chat { id: 1, sender: "bot".into(), content: "olá".into() }).await.unwrap();
+        assert_eq!(host.recv().await.unwrap(), Some(Message::Chat { id: 1, sender: "bot".into(), content: "olá".into() }));
+
+        // Muitas mensagens seguidas forçam pedidos de passo DH, tratados dentro de recv
+        for id in 0..200 {
+            host.send(Message::Ack { id }).await.unwrap();
+        }
+        for id in 0..200 {
+            assert_eq!(client.recv().await.unwrap(), Some(Message::Ack { id }));
+        }
+
+        host.close("fim").await.unwrap();
+        assert_eq!(client.recv().await.unwrap(), Some(Message::Close { reason: "fim".into() }));
+        assert_eq!(client.recv().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_connect_rejects_other_host() {
+        let impostor = Arc::new(Identity::generate());
+        let host_static = StaticSecret::random_from_rng(rand::rngs::OsRng);
+        let noise_key = x25519_dalek::PublicKey::from(&host_static).to_bytes();
+        // Convite assinado por outra identidade, com a chave Noise do impostor
+        let invite = Invite::new(&Identity::generate(), "127.0.0.1", 9001, Some(noise_key), None, false, std::time::Duration::from_secs(60));
+
+        let (client_end, host_end) = memory_pair();
+        tokio::spawn(Session::accept(host_end, impostor, host_static));
+        let result = Session::connect(client_end, Arc::new(Identity::generate()), &invite).await;
+        assert!(matches!(result, Err(SessionError::IdentityMismatch)));
+    }
+}
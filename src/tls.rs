@@ -0,0 +1,243 @@
+use sha2::{Digest, Sha256};
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio_native_tls::{TlsAcceptor, TlsConnector, TlsStream};
+
+/// Identidade TLS do host: certificado (DER) e a identidade usada pelo acceptor.
+pub struct TlsIdentity {
+    cert_der: Vec<u8>,
+    identity: native_tls::Identity,
+}
+
+impl TlsIdentity {
+    /// Gera um certificado autoassinado efêmero, válido apenas para esta sessão.
+    pub fn generate_self_signed() -> Result<Self, TlsError> {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])
+            .map_err(|e| TlsError::CertificateGeneration(e.to_string()))?;
+
+        let cert_pem = certified.cert.pem();
+        let key_pem = certified.key_pair.serialize_pem();
+        let identity = native_tls::Identity::from_pkcs8(cert_pem.as_bytes(), key_pem.as_bytes())
+            .map_err(|e| TlsError::InvalidIdentity(e.to_string()))?;
+
+        Ok(Self {
+            cert_der: certified.cert.der().to_vec(),
+            identity,
+        })
+    }
+
+    /// Carrega certificado e chave privada PKCS#8 a partir de arquivos PEM.
+    pub fn from_pem_files(cert_path: &Path, key_path: &Path) -> Result<Self, TlsError> {
+        let cert_pem = std::fs::read(cert_path)
+            .map_err(|e| TlsError::Io(format!("{}: {}", cert_path.display(), e)))?;
+        let key_pem = std::fs::read(key_path)
+            .map_err(|e| TlsError::Io(format!("{}: {}", key_path.display(), e)))?;
+
+        let cert = native_tls::Certificate::from_pem(&cert_pem)
+            .map_err(|e| TlsError::InvalidIdentity(e.to_string()))?;
+        let cert_der = cert.to_der()
+            .map_err(|e| TlsError::InvalidIdentity(e.to_string()))?;
+        let identity = native_tls::Identity::from_pkcs8(&cert_pem, &key_pem)
+            .map_err(|e| TlsError::InvalidIdentity(e.to_string()))?;
+
+        Ok(Self { cert_der, identity })
+    }
+
+    /// Fingerprint SHA256 completo do certificado, usado como pin no convite.
+    pub fn fingerprint(&self) -> String {
+        cert_fingerprint(&self.cert_der)
+    }
+
+    /// Cria o acceptor TLS do lado do host.
+    pub fn acceptor(&self) -> Result<TlsAcceptor, TlsError> {
+        let acceptor = native_tls::TlsAcceptor::new(self.identity.clone())
+            .map_err(|e| TlsError::Handshake(e.to_string()))?;
+        Ok(TlsAcceptor::from(acceptor))
+    }
+}
+
+/// Calcula o fingerprint SHA256 (hex) de um certificado em DER.
+pub fn cert_fingerprint(cert_der: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(cert_der);
+    hex::encode(hasher.finalize())
+}
+
+/// Abre uma conexão TLS sobre um stream TCP já conectado.
+///
+/// Com `pin`, a cadeia de certificados não é validada (certificados autoassinados
+/// são o caso comum), mas o fingerprint do certificado do host precisa coincidir
+/// exatamente com o pin recebido no convite. Sem pin, a validação padrão do
+/// sistema é aplicada.
+pub async fn connect(
+    stream: TcpStream,
+    domain: &str,
+    pin: Option<&str>,
+) -> Result<TlsStream<TcpStream>, TlsError> {
+    let mut builder = native_tls::TlsConnector::builder();
+    if pin.is_some() {
+        builder
+            .danger_accept_invalid_certs(true)
+            .danger_accept_invalid_hostnames(true);
+    }
+    let connector = builder.build()
+        .map_err(|e| TlsError::Handshake(e.to_string()))?;
+
+    let tls_stream = TlsConnector::from(connector)
+        .connect(domain, stream)
+        .await
+        .map_err(|e| TlsError::Handshake(e.to_string()))?;
+
+    if let Some(expected) = pin {
+        let cert = tls_stream.get_ref().peer_certificate()
+            .map_err(|e| TlsError::Handshake(e.to_string()))?
+            .ok_or(TlsError::MissingCertificate)?;
+        let der = cert.to_der()
+            .map_err(|e| TlsError::Handshake(e.to_string()))?;
+
+        if !cert_fingerprint(&der).eq_ignore_ascii_case(expected) {
+            return Err(TlsError::PinMismatch);
+        }
+    }
+
+    Ok(tls_stream)
+}
+
+/// Stream TCP que pode ou não estar envolvido em TLS.
+pub enum MaybeTlsStream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl AsyncRead for MaybeTlsStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(s) => Pin::new(s).poll_read(cx, buf),
+            MaybeTlsStream::Tls(s) => Pin::new(s).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for MaybeTlsStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(s) => Pin::new(s).poll_write(cx, buf),
+            MaybeTlsStream::Tls(s) => Pin::new(s).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(s) => Pin::new(s).poll_flush(cx),
+            MaybeTlsStream::Tls(s) => Pin::new(s).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(s) => Pin::new(s).poll_shutdown(cx),
+            MaybeTlsStream::Tls(s) => Pin::new(s).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Erros da camada TLS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TlsError {
+    CertificateGeneration(String),
+    InvalidIdentity(String),
+    Io(String),
+    Handshake(String),
+    MissingCertificate,
+    PinMismatch,
+}
+
+impl std::fmt::Display for TlsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlsError::CertificateGeneration(e) => write!(f, "Falha ao gerar certificado TLS: {}", e),
+            TlsError::InvalidIdentity(e) => write!(f, "Certificado ou chave TLS inválidos: {}", e),
+            TlsError::Io(e) => write!(f, "Erro ao ler arquivo TLS: {}", e),
+            TlsError::Handshake(e) => write!(f, "Falha no handshake TLS: {}", e),
+            TlsError::MissingCertificate => write!(f, "Host não apresentou certificado TLS"),
+            TlsError::PinMismatch => write!(f, "⚠️ Certificado TLS não corresponde ao pin do convite - possível ataque MITM!"),
+        }
+    }
+}
+
+impl std::error::Error for TlsError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Sobe um host TLS local que ecoa 4 bytes e retorna sua porta.
+    async fn spawn_echo_host(identity: &TlsIdentity) -> u16 {
+        let acceptor = identity.acceptor().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            if let Ok((stream, _)) = listener.accept().await {
+                if let Ok(mut tls) = acceptor.accept(stream).await {
+                    let mut buf = [0u8; 4];
+                    if tls.read_exact(&mut buf).await.is_ok() {
+                        let _ = tls.write_all(&buf).await;
+                    }
+                }
+            }
+        });
+
+        port
+    }
+
+    #[test]
+    fn test_self_signed_fingerprint() {
+        let identity = TlsIdentity::generate_self_signed().unwrap();
+        assert_eq!(identity.fingerprint().len(), 64);
+
+        // Cada sessão gera um certificado diferente
+        let other = TlsIdentity::generate_self_signed().unwrap();
+        assert_ne!(identity.fingerprint(), other.fingerprint());
+    }
+
+    #[tokio::test]
+    async fn test_pinned_connection() {
+        let identity = TlsIdentity::generate_self_signed().unwrap();
+        let port = spawn_echo_host(&identity).await;
+
+        let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let mut tls = connect(stream, "127.0.0.1", Some(&identity.fingerprint())).await.unwrap();
+
+        tls.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        tls.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+    }
+
+    #[tokio::test]
+    async fn test_pin_mismatch_rejected() {
+        let identity = TlsIdentity::generate_self_signed().unwrap();
+        let port = spawn_echo_host(&identity).await;
+
+        let wrong_pin = "00".repeat(32);
+        let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let result = connect(stream, "127.0.0.1", Some(&wrong_pin)).await;
+        assert_eq!(result.err(), Some(TlsError::PinMismatch));
+    }
+
+    #[tokio::test]
+    async fn test_unpinned_self_signed_rejected() {
+        let identity = TlsIdentity::generate_self_signed().unwrap();
+        let port = spawn_echo_host(&identity).await;
+
+        let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        assert!(connect(stream, "127.0.0.1", None).await.is_err());
+    }
+}
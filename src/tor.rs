@@ -1,13 +1,31 @@
+use hmac::{Hmac, Mac};
+use rand::{rngs::OsRng, RngCore};
+use sha2::Sha256;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::net::TcpStream;
 use tokio_socks::tcp::Socks5Stream;
-use std::io;
+
+const SAFECOOKIE_SERVER_KEY: &[u8] = b"Tor safe cookie authentication server-to-controller hash";
+const SAFECOOKIE_CLIENT_KEY: &[u8] = b"Tor safe cookie authentication controller-to-server hash";
 
 /// Configuração para conexões via Tor.
+#[derive(Debug, Clone)]
 pub struct TorConfig {
     /// Endereço do proxy SOCKS5 do Tor (geralmente 127.0.0.1:9050)
     pub socks_addr: String,
     /// Porta do proxy SOCKS5 do Tor
     pub socks_port: u16,
+    /// Endereço da porta de controle do Tor (geralmente 127.0.0.1:9051)
+    pub control_addr: String,
+    /// Porta de controle do Tor, usada para criar onion services
+    pub control_port: u16,
+    /// Senha da porta de controle (`HashedControlPassword` no torrc)
+    pub control_password: Option<String>,
+    /// Arquivo de cookie; quando ausente, usa o caminho informado pelo Tor
+    pub cookie_file: Option<PathBuf>,
 }
 
 impl Default for TorConfig {
@@ -15,6 +33,10 @@ impl Default for TorConfig {
         Self {
             socks_addr: "127.0.0.1".to_string(),
             socks_port: 9050,
+            control_addr: "127.0.0.1".to_string(),
+            control_port: 9051,
+            control_password: None,
+            cookie_file: None,
         }
     }
 }
@@ -24,6 +46,11 @@ impl TorConfig {
     pub fn proxy_addr(&self) -> String {
         format!("{}:{}", self.socks_addr, self.socks_port)
     }
+
+    /// Retorna o endereço completo da porta de controle.
+    pub fn control_endpoint(&self) -> String {
+        format!("{}:{}", self.control_addr, self.control_port)
+    }
 }
 
 /// Conecta a um host através do Tor usando SOCKS5.
@@ -50,10 +77,7 @@ pub async fn check_tor_available(tor_config: &TorConfig) -> bool {
     let proxy_addr = tor_config.proxy_addr();
 
     // Tenta conectar ao proxy Tor
-    match TcpStream::connect(proxy_addr).await {
-        Ok(_) => true,
-        Err(_) => false,
-    }
+    TcpStream::connect(proxy_addr).await.is_ok()
 }
 
 /// Retorna informações sobre o status do Tor.
@@ -73,6 +97,266 @@ pub async fn get_tor_status(tor_config: &TorConfig) -> TorStatus {
     }
 }
 
+/// Cliente mínimo do protocolo de controle do Tor (control-spec.txt).
+pub struct TorControl {
+    stream: BufReader<TcpStream>,
+}
+
+/// Resposta da porta de controle: linhas sem o prefixo de status.
+type ControlReply = Vec<String>;
+
+impl TorControl {
+    /// Conecta à porta de controle e autentica com o método anunciado pelo Tor.
+    pub async fn connect(tor_config: &TorConfig) -> Result<Self, TorControlError> {
+        let stream = TcpStream::connect(tor_config.control_endpoint()).await
+            .map_err(|e| TorControlError::Io(e.to_string()))?;
+        let mut control = Self { stream: BufReader::new(stream) };
+        control.authenticate(tor_config).await?;
+        Ok(control)
+    }
+
+    /// Escolhe o método de autenticação a partir do PROTOCOLINFO.
+    async fn authenticate(&mut self, tor_config: &TorConfig) -> Result<(), TorControlError> {
+        let info = self.command("PROTOCOLINFO 1").await?;
+        let methods = parse_auth_methods(&info);
+        let cookie_file = tor_config.cookie_file.clone()
+            .or_else(|| parse_cookie_file(&info).map(PathBuf::from));
+
+        if let Some(password) = &tor_config.control_password {
+            if methods.iter().any(|m| m == "HASHEDPASSWORD") {
+                self.command(&format!("AUTHENTICATE {}", quote(password))).await?;
+                return Ok(());
+            }
+        }
+
+        if methods.iter().any(|m| m == "SAFECOOKIE") {
+            if let Some(path) = &cookie_file {
+                let cookie = read_cookie(path).await?;
+                return self.authenticate_safecookie(&cookie).await;
+            }
+        }
+
+        if methods.iter().any(|m| m == "COOKIE") {
+            if let Some(path) = &cookie_file {
+                let cookie = read_cookie(path).await?;
+                self.command(&format!("AUTHENTICATE {}", hex::encode(cookie))).await?;
+                return Ok(());
+            }
+        }
+
+        if methods.iter().any(|m| m == "NULL") {
+            self.command("AUTHENTICATE").await?;
+            return Ok(());
+        }
+
+        Err(TorControlError::AuthUnavailable(methods.join(",")))
+    }
+
+    /// SAFECOOKIE: prova posse do cookie sem enviá-lo e verifica que o
+    /// servidor também o conhece.
+    async fn authenticate_safecookie(&mut self, cookie: &[u8]) -> Result<(), TorControlError> {
+        let mut client_nonce = [0u8; 32];
+        OsRng.fill_bytes(&mut client_nonce);
+
+        let reply = self.command(&format!("AUTHCHALLENGE SAFECOOKIE {}", hex::encode(client_nonce))).await?;
+        let line = reply.first().map(String::as_str).unwrap_or_default();
+        let server_hash = reply_field(line, "SERVERHASH")
+            .and_then(|v| hex::decode(v).ok())
+            .ok_or_else(|| TorControlError::Protocol(line.to_string()))?;
+        let server_nonce = reply_field(line, "SERVERNONCE")
+            .and_then(|v| hex::decode(v).ok())
+            .ok_or_else(|| TorControlError::Protocol(line.to_string()))?;
+
+        let mut mac = safecookie_mac(SAFECOOKIE_SERVER_KEY, cookie, &client_nonce, &server_nonce);
+        mac.verify_slice(&server_hash)
+            .map_err(|_| TorControlError::Protocol("SERVERHASH inválido - porta de controle não confiável".to_string()))?;
+
+        mac = safecookie_mac(SAFECOOKIE_CLIENT_KEY, cookie, &client_nonce, &server_nonce);
+        let client_hash = mac.finalize().into_bytes();
+        self.command(&format!("AUTHENTICATE {}", hex::encode(client_hash))).await?;
+        Ok(())
+    }
+
+    /// Cria um onion service v3 efêmero apontando para `target`.
+    ///
+    /// A chave privada é descartada pelo Tor (`DiscardPK`) e o serviço deixa de
+    /// existir quando esta conexão de controle é encerrada.
+    pub async fn add_onion(&mut self, virtual_port: u16, target: SocketAddr) -> Result<String, TorControlError> {
+        let reply = self.command(&format!(
+            "ADD_ONION NEW:ED25519-V3 Flags=DiscardPK Port={},{}",
+            virtual_port, target
+        )).await?;
+
+        reply.iter()
+            .find_map(|line| line.strip_prefix("ServiceID="))
+            .map(|id| id.to_string())
+            .ok_or_else(|| TorControlError::Protocol("ADD_ONION sem ServiceID".to_string()))
+    }
+
+    /// Remove um onion service criado por esta conexão.
+    pub async fn del_onion(&mut self, service_id: &str) -> Result<(), TorControlError> {
+        self.command(&format!("DEL_ONION {}", service_id)).await?;
+        Ok(())
+    }
+
+    /// Envia um comando e lê a resposta completa, falhando em status != 250.
+    async fn command(&mut self, command: &str) -> Result<ControlReply, TorControlError> {
+        self.stream.get_mut().write_all(format!("{}\r\n", command).as_bytes()).await
+            .map_err(|e| TorControlError::Io(e.to_string()))?;
+
+        let mut lines = Vec::new();
+        loop {
+            let line = self.read_line().await?;
+            if line.len() < 4 {
+                return Err(TorControlError::Protocol(line));
+            }
+            let (status, rest) = line.split_at(3);
+            let (separator, content) = rest.split_at(1);
+
+            if separator == "+" {
+                // Bloco de dados terminado por uma linha contendo apenas "."
+                lines.push(content.to_string());
+                loop {
+                    let data = self.read_line().await?;
+                    if data == "." {
+                        break;
+                    }
+                    lines.push(data);
+                }
+                continue;
+            }
+
+            lines.push(content.to_string());
+            if separator == " " {
+                let code: u16 = status.parse()
+                    .map_err(|_| TorControlError::Protocol(line.clone()))?;
+                if code != 250 {
+                    return Err(TorControlError::Rejected { code, message: content.to_string() });
+                }
+                return Ok(lines);
+            }
+        }
+    }
+
+    async fn read_line(&mut self) -> Result<String, TorControlError> {
+        let mut line = String::new();
+        let read = self.stream.read_line(&mut line).await
+            .map_err(|e| TorControlError::Io(e.to_string()))?;
+        if read == 0 {
+            return Err(TorControlError::Io("Conexão de controle encerrada pelo Tor".to_string()));
+        }
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+}
+
+/// Onion service efêmero mantido vivo enquanto a conexão de controle existir.
+pub struct OnionService {
+    control: TorControl,
+    service_id: String,
+}
+
+impl OnionService {
+    /// Publica um onion service que encaminha `virtual_port` para `target`.
+    pub async fn create(tor_config: &TorConfig, virtual_port: u16, target: SocketAddr) -> Result<Self, TorControlError> {
+        let mut control = TorControl::connect(tor_config).await?;
+        let service_id = control.add_onion(virtual_port, target).await?;
+        Ok(Self { control, service_id })
+    }
+
+    /// Endereço `.onion` do serviço.
+    pub fn onion_address(&self) -> String {
+        format!("{}.onion", self.service_id)
+    }
+
+    /// Remove o serviço explicitamente (DEL_ONION) antes de fechar o controle.
+    pub async fn close(mut self) -> Result<(), TorControlError> {
+        let service_id = std::mem::take(&mut self.service_id);
+        self.control.del_onion(&service_id).await
+    }
+}
+
+fn parse_auth_methods(info: &[String]) -> Vec<String> {
+    info.iter()
+        .find_map(|line| line.strip_prefix("AUTH METHODS="))
+        .map(|rest| {
+            rest.split_whitespace().next().unwrap_or_default()
+                .split(',')
+                .map(|m| m.to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn parse_cookie_file(info: &[String]) -> Option<String> {
+    let line = info.iter().find(|line| line.starts_with("AUTH "))?;
+    let start = line.find("COOKIEFILE=\"")? + "COOKIEFILE=\"".len();
+
+    let mut path = String::new();
+    let mut chars = line[start..].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => path.push(chars.next()?),
+            '"' => return Some(path),
+            _ => path.push(c),
+        }
+    }
+    None
+}
+
+/// Extrai `CHAVE=valor` de uma linha de resposta.
+fn reply_field<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    line.split_whitespace()
+        .find_map(|field| field.strip_prefix(key)?.strip_prefix('='))
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+async fn read_cookie(path: &PathBuf) -> Result<Vec<u8>, TorControlError> {
+    let cookie = tokio::fs::read(path).await
+        .map_err(|e| TorControlError::Io(format!("{}: {}", path.display(), e)))?;
+    if cookie.len() != 32 {
+        return Err(TorControlError::Protocol("Cookie de autenticação com tamanho inválido".to_string()));
+    }
+    Ok(cookie)
+}
+
+fn safecookie_mac(key: &[u8], cookie: &[u8], client_nonce: &[u8], server_nonce: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC aceita qualquer tamanho de chave");
+    mac.update(cookie);
+    mac.update(client_nonce);
+    mac.update(server_nonce);
+    mac
+}
+
+/// Erros da porta de controle do Tor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TorControlError {
+    Io(String),
+    Protocol(String),
+    Rejected { code: u16, message: String },
+    AuthUnavailable(String),
+}
+
+impl std::fmt::Display for TorControlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TorControlError::Io(e) => write!(f, "Falha na porta de controle do Tor: {}", e),
+            TorControlError::Protocol(line) => write!(f, "Resposta inesperada da porta de controle: {}", line),
+            TorControlError::Rejected { code, message } => write!(f, "Tor recusou o comando ({}): {}", code, message),
+            TorControlError::AuthUnavailable(methods) => write!(
+                f,
+                "Nenhum método de autenticação utilizável na porta de controle (oferecidos: {}). \
+                Configure CookieAuthentication ou informe a senha de controle",
+                methods
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TorControlError {}
+
 /// Status da disponibilidade do Tor.
 #[derive(Debug, Clone)]
 pub enum TorStatus {
@@ -93,6 +377,84 @@ impl TorStatus {
     }
 }
 
+/// Proxy SOCKS5 mínimo usado nos testes no lugar do Tor: registra cada
+/// destino solicitado e encaminha o tráfego diretamente.
+#[cfg(test)]
+pub(crate) mod test_socks {
+    use super::TorConfig;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    pub async fn spawn() -> (TorConfig, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let targets = Arc::new(Mutex::new(Vec::new()));
+
+        let seen = targets.clone();
+        tokio::spawn(async move {
+            while let Ok((client, _)) = listener.accept().await {
+                let seen = seen.clone();
+                tokio::spawn(async move {
+                    let _ = relay(client, seen).await;
+                });
+            }
+        });
+
+        let config = TorConfig {
+            socks_addr: "127.0.0.1".to_string(),
+            socks_port: port,
+            ..TorConfig::default()
+        };
+        (config, targets)
+    }
+
+    async fn relay(mut client: TcpStream, seen: Arc<Mutex<Vec<String>>>) -> std::io::Result<()> {
+        // Saudação: [versão, n_métodos, métodos...] -> sem autenticação
+        let mut header = [0u8; 2];
+        client.read_exact(&mut header).await?;
+        let mut methods = vec![0u8; header[1] as usize];
+        client.read_exact(&mut methods).await?;
+        client.write_all(&[5, 0]).await?;
+
+        // Pedido CONNECT: [versão, cmd, rsv, atyp, endereço, porta]
+        let mut request = [0u8; 4];
+        client.read_exact(&mut request).await?;
+        let host = match request[3] {
+            1 => {
+                let mut ip = [0u8; 4];
+                client.read_exact(&mut ip).await?;
+                std::net::Ipv4Addr::from(ip).to_string()
+            }
+            3 => {
+                let len = client.read_u8().await? as usize;
+                let mut name = vec![0u8; len];
+                client.read_exact(&mut name).await?;
+                String::from_utf8_lossy(&name).into_owned()
+            }
+            _ => {
+                let mut ip = [0u8; 16];
+                client.read_exact(&mut ip).await?;
+                std::net::Ipv6Addr::from(ip).to_string()
+            }
+        };
+        let port = client.read_u16().await?;
+        seen.lock().unwrap().push(format!("{}:{}", host, port));
+
+        let mut upstream = match TcpStream::connect((host.as_str(), port)).await {
+            Ok(upstream) => upstream,
+            Err(e) => {
+                // Resposta 0x04: host inacessível
+                client.write_all(&[5, 4, 0, 1, 0, 0, 0, 0, 0, 0]).await?;
+                return Err(e);
+            }
+        };
+        client.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).await?;
+        tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -102,4 +464,164 @@ mod tests {
         let config = TorConfig::default();
         assert_eq!(config.proxy_addr(), "127.0.0.1:9050");
     }
+
+    #[tokio::test]
+    async fn test_connect_via_socks_stand_in() {
+        let target = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target_port = target.local_addr().unwrap().port();
+        let (config, targets) = test_socks::spawn().await;
+
+        let stream = connect_via_tor("127.0.0.1", target_port, &config).await;
+        assert!(stream.is_ok());
+        assert_eq!(*targets.lock().unwrap(), vec![format!("127.0.0.1:{}", target_port)]);
+    }
+
+    /// Porta de controle falsa: responde cada comando via `respond` e registra
+    /// os comandos recebidos.
+    async fn spawn_fake_control(
+        respond: impl Fn(&str) -> String + Send + Sync + 'static,
+    ) -> (TorConfig, std::sync::Arc<std::sync::Mutex<Vec<String>>>) {
+        use tokio::io::AsyncBufReadExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let commands = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let seen = commands.clone();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut line = String::new();
+            while stream.read_line(&mut line).await.unwrap_or(0) > 0 {
+                let command = line.trim_end().to_string();
+                seen.lock().unwrap().push(command.clone());
+                let reply = respond(&command);
+                stream.get_mut().write_all(reply.as_bytes()).await.unwrap();
+                line.clear();
+            }
+        });
+
+        let config = TorConfig {
+            control_port: port,
+            ..TorConfig::default()
+        };
+        (config, commands)
+    }
+
+    fn temp_cookie(name: &str, cookie: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("sae-test-{}-{}", name, std::process::id()));
+        std::fs::write(&path, cookie).unwrap();
+        path
+    }
+
+    const SERVICE_ID: &str = "pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd";
+
+    #[tokio::test]
+    async fn test_onion_service_with_password() {
+        let (mut config, commands) = spawn_fake_control(|command| {
+            match command {
+                "PROTOCOLINFO 1" => "250-PROTOCOLINFO 1\r\n250-AUTH METHODS=HASHEDPASSWORD\r\n250-VERSION Tor=\"0.4.8.9\"\r\n250 OK\r\n".to_string(),
+                r#"AUTHENTICATE "s3cr\"et""# => "250 OK\r\n".to_string(),
+                c if c.starts_with("ADD_ONION ") => format!("250-ServiceID={}\r\n250 OK\r\n", SERVICE_ID),
+                c if c.starts_with("DEL_ONION ") => "250 OK\r\n".to_string(),
+                _ => "515 Authentication failed\r\n".to_string(),
+            }
+        }).await;
+        config.control_password = Some("s3cr\"et".to_string());
+
+        let target: SocketAddr = "127.0.0.1:9001".parse().unwrap();
+        let service = OnionService::create(&config, 9001, target).await.unwrap();
+        assert_eq!(service.onion_address(), format!("{}.onion", SERVICE_ID));
+        service.close().await.unwrap();
+
+        let commands = commands.lock().unwrap();
+        assert!(commands.contains(&"ADD_ONION NEW:ED25519-V3 Flags=DiscardPK Port=9001,127.0.0.1:9001".to_string()));
+        assert_eq!(commands.last().unwrap(), &format!("DEL_ONION {}", SERVICE_ID));
+    }
+
+    #[tokio::test]
+    async fn test_cookie_auth_uses_protocolinfo_path() {
+        let cookie = [7u8; 32];
+        let path = temp_cookie("cookie", &cookie);
+        let expected = format!("AUTHENTICATE {}", hex::encode(cookie));
+
+        let info = format!(
+            "250-PROTOCOLINFO 1\r\n250-AUTH METHODS=COOKIE COOKIEFILE=\"{}\"\r\n250 OK\r\n",
+            path.display()
+        );
+        let (config, _) = spawn_fake_control(move |command| {
+            if command == "PROTOCOLINFO 1" {
+                info.clone()
+            } else if command == expected {
+                "250 OK\r\n".to_string()
+            } else {
+                "515 Authentication failed\r\n".to_string()
+            }
+        }).await;
+
+        assert!(TorControl::connect(&config).await.is_ok());
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_safecookie_auth() {
+        let cookie = [9u8; 32];
+        let path = temp_cookie("safecookie", &cookie);
+        let server_nonce = [3u8; 32];
+        let client_nonce = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let nonce = client_nonce.clone();
+        let (mut config, _) = spawn_fake_control(move |command| {
+            if command == "PROTOCOLINFO 1" {
+                "250-PROTOCOLINFO 1\r\n250-AUTH METHODS=COOKIE,SAFECOOKIE\r\n250 OK\r\n".to_string()
+            } else if let Some(hex_nonce) = command.strip_prefix("AUTHCHALLENGE SAFECOOKIE ") {
+                let client = hex::decode(hex_nonce).unwrap();
+                let server_hash = safecookie_mac(SAFECOOKIE_SERVER_KEY, &cookie, &client, &server_nonce)
+                    .finalize().into_bytes();
+                *nonce.lock().unwrap() = client;
+                format!(
+                    "250 AUTHCHALLENGE SERVERHASH={} SERVERNONCE={}\r\n",
+                    hex::encode(server_hash),
+                    hex::encode(server_nonce)
+                )
+            } else if let Some(client_hash) = command.strip_prefix("AUTHENTICATE ") {
+                let client = nonce.lock().unwrap().clone();
+                let expected = safecookie_mac(SAFECOOKIE_CLIENT_KEY, &cookie, &client, &server_nonce)
+                    .finalize().into_bytes();
+                if client_hash == hex::encode(expected) {
+                    "250 OK\r\n".to_string()
+                } else {
+                    "515 Authentication failed\r\n".to_string()
+                }
+            } else {
+                "510 Unrecognized command\r\n".to_string()
+            }
+        }).await;
+        config.cookie_file = Some(path.clone());
+
+        assert!(TorControl::connect(&config).await.is_ok());
+        assert_eq!(client_nonce.lock().unwrap().len(), 32);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_control_rejection_is_reported() {
+        let (config, _) = spawn_fake_control(|command| {
+            if command == "PROTOCOLINFO 1" {
+                "250-PROTOCOLINFO 1\r\n250-AUTH METHODS=NULL\r\n250 OK\r\n".to_string()
+            } else {
+                "515 Authentication failed\r\n".to_string()
+            }
+        }).await;
+
+        let result = TorControl::connect(&config).await;
+        assert!(matches!(result, Err(TorControlError::Rejected { code: 515, .. })));
+    }
+
+    #[test]
+    fn test_parse_cookie_file_unescapes() {
+        let info = vec![r#"AUTH METHODS=COOKIE,SAFECOOKIE COOKIEFILE="/var/run/tor/con\"trol.authcookie""#.to_string()];
+        assert_eq!(parse_cookie_file(&info).as_deref(), Some("/var/run/tor/con\"trol.authcookie"));
+        assert_eq!(parse_auth_methods(&info), vec!["COOKIE", "SAFECOOKIE"]);
+    }
 }
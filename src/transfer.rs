@@ -0,0 +1,768 @@
+use crate::network_secure::SessionId;
+use crate::protocol::{ProtocolError, Reader, WireDecode, WireEncode, Writer};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Tamanho dos blocos enviados; cada bloco vai em uma mensagem do ratchet.
+pub const CHUNK_SIZE: u32 = 64 * 1024;
+/// Maior bloco aceito de um remetente
+const MAX_CHUNK_SIZE: u32 = 1024 * 1024;
+/// Blocos enviados por transferência a cada tick do loop principal
+pub const CHUNKS_PER_TICK: usize = 16;
+/// Máximo de blocos por arquivo (64 GiB com o tamanho de bloco padrão)
+const MAX_CHUNKS: u64 = 1 << 20;
+/// Máximo de índices em um pedido de reenvio
+const MAX_RESEND: usize = 1024;
+
+/// Identificador aleatório de uma transferência.
+pub type TransferId = [u8; 16];
+
+/// Forma curta do identificador, para digitar em `/accept` e `/reject`.
+pub fn short_id(id: &TransferId) -> String {
+    hex::encode(&id[..4])
+}
+
+/// Tamanho legível (B, KiB, MiB, GiB).
+pub fn format_size(size: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", size)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Proposta de envio: o destinatário decide se aceita antes de qualquer bloco.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileOffer {
+    pub id: TransferId,
+    pub name: String,
+    pub size: u64,
+    pub chunk_size: u32,
+    /// SHA256 do arquivo inteiro, conferido no fim
+    pub sha256: [u8; 32],
+}
+
+impl FileOffer {
+    pub fn chunk_count(&self) -> u32 {
+        self.size.div_ceil(self.chunk_size as u64) as u32
+    }
+
+    fn chunk_len(&self, index: u32) -> usize {
+        let start = index as u64 * self.chunk_size as u64;
+        (self.size - start).min(self.chunk_size as u64) as usize
+    }
+
+    fn validate(&self) -> Result<(), TransferError> {
+        validate_name(&self.name)?;
+        if self.chunk_size == 0 || self.chunk_size > MAX_CHUNK_SIZE
+            || self.size.div_ceil(self.chunk_size as u64) > MAX_CHUNKS
+        {
+            return Err(TransferError::InvalidOffer);
+        }
+        Ok(())
+    }
+}
+
+/// Bloco do arquivo; a posição vem do índice, então a ordem de chegada não importa.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileChunk {
+    pub id: TransferId,
+    pub index: u32,
+    pub data: Vec<u8>,
+}
+
+/// Mensagens de transferência de arquivo trocadas dentro do ratchet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileWire {
+    Offer(FileOffer),
+    Accept { id: TransferId },
+    Chunk(FileChunk),
+    /// O remetente terminou de enviar os blocos pedidos
+    End { id: TransferId },
+    /// O destinatário pede de novo os blocos que faltam
+    Resend {
+        id: TransferId,
+        chunks: Vec<u32>,
+    },
+    /// Resultado da verificação do hash do arquivo inteiro
+    Complete {
+        id: TransferId,
+        verified: bool,
+    },
+    /// Recusa ou cancelamento, por qualquer um dos lados
+    Cancel { id: TransferId },
+}
+
+/// O que a aplicação deve mostrar após processar uma mensagem de arquivo.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransferEvent {
+    /// O par propôs um arquivo; aguarda `/accept` ou `/reject`
+    Offered { id: TransferId, name: String, size: u64 },
+    Accepted { name: String },
+    Cancelled { name: String },
+    /// Fim da transferência; `path` é o arquivo gravado (apenas no destinatário)
+    Completed { name: String, path: Option<PathBuf>, verified: bool },
+}
+
+/// Resultado de uma mensagem recebida: respostas ao mesmo par e o evento.
+#[derive(Debug, Default)]
+pub struct TransferOutput {
+    pub replies: Vec<FileWire>,
+    pub event: Option<TransferEvent>,
+}
+
+/// Andamento de uma transferência, para a barra de status.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Progress {
+    pub name: String,
+    pub outgoing: bool,
+    pub done: u64,
+    pub total: u64,
+}
+
+impl Progress {
+    pub fn percent(&self) -> u64 {
+        (self.done * 100).checked_div(self.total).unwrap_or(100)
+    }
+}
+
+struct OutgoingTransfer {
+    session: SessionId,
+    offer: FileOffer,
+    file: File,
+    accepted: bool,
+    /// Blocos ainda a enviar, em ordem
+    queue: BTreeSet<u32>,
+    end_sent: bool,
+    sent: u64,
+}
+
+impl OutgoingTransfer {
+    fn read_chunk(&mut self, index: u32) -> Result<FileChunk, TransferError> {
+        let mut data = vec![0u8; self.offer.chunk_len(index)];
+        self.file.seek(SeekFrom::Start(index as u64 * self.offer.chunk_size as u64))?;
+        self.file.read_exact(&mut data)?;
+        Ok(FileChunk { id: self.offer.id, index, data })
+    }
+}
+
+struct IncomingTransfer {
+    session: SessionId,
+    offer: FileOffer,
+    dir: PathBuf,
+    part_path: PathBuf,
+    file: File,
+    received: Vec<bool>,
+    received_bytes: u64,
+}
+
+impl IncomingTransfer {
+    fn create(session: SessionId, offer: FileOffer, dir: &Path) -> Result<Self, TransferError> {
+        // Arquivo parcial oculto; só ganha o nome final depois do hash conferir
+        let part_path = dir.join(format!(".{}.{}.part", offer.name, short_id(&offer.id)));
+        let file = OpenOptions::new().read(true).write(true).create_new(true).open(&part_path)
+            .map_err(|e| TransferError::Io(format!("{}: {}", part_path.display(), e)))?;
+        file.set_len(offer.size)?;
+        Ok(Self {
+            session,
+            received: vec![false; offer.chunk_count() as usize],
+            offer,
+            dir: dir.to_path_buf(),
+            part_path,
+            file,
+            received_bytes: 0,
+        })
+    }
+
+    fn write_chunk(&mut self, chunk: &FileChunk) -> Result<(), TransferError> {
+        let index = chunk.index as usize;
+        if index >= self.received.len() || chunk.data.len() != self.offer.chunk_len(chunk.index) {
+            return Err(TransferError::InvalidChunk);
+        }
+        // Blocos repetidos (reenvio) são ignorados
+        if self.received[index] {
+            return Ok(());
+        }
+        self.file.seek(SeekFrom::Start(chunk.index as u64 * self.offer.chunk_size as u64))?;
+        self.file.write_all(&chunk.data)?;
+        self.received[index] = true;
+        self.received_bytes += chunk.data.len() as u64;
+        Ok(())
+    }
+
+    fn missing(&self) -> Vec<u32> {
+        self.received.iter().enumerate()
+            .filter(|(_, received)| !**received)
+            .map(|(index, _)| index as u32)
+            .take(MAX_RESEND)
+            .collect()
+    }
+
+    /// Confere o hash e move o arquivo para o nome final; se não conferir, apaga.
+    fn finish(mut self) -> Result<Option<PathBuf>, TransferError> {
+        self.file.flush()?;
+        self.file.seek(SeekFrom::Start(0))?;
+        if hash_reader(&mut self.file)? != self.offer.sha256 {
+            self.discard();
+            return Ok(None);
+        }
+        let destination = move_to_unique(&self.part_path, &self.dir, &self.offer.name)?;
+        Ok(Some(destination))
+    }
+
+    fn discard(self) {
+        let _ = fs::remove_file(&self.part_path);
+    }
+}
+
+/// Transferências de arquivo em andamento, em ambos os sentidos.
+///
+/// Os blocos são lidos do disco sob demanda (`pump`) e cada um segue como
+/// uma mensagem do ratchet da sessão; o destinatário grava pela posição,
+/// pede o que faltar ao fim de cada rodada e confere o SHA256 do arquivo.
+#[derive(Default)]
+pub struct TransferManager {
+    outgoing: HashMap<TransferId, OutgoingTransfer>,
+    incoming: HashMap<TransferId, IncomingTransfer>,
+    /// Propostas recebidas aguardando a decisão do usuário
+    offers: HashMap<TransferId, (SessionId, FileOffer)>,
+}
+
+impl TransferManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Prepara o envio de um arquivo e devolve a proposta para o par.
+    pub fn offer(&mut self, session: SessionId, path: &Path) -> Result<FileWire, TransferError> {
+        let name = path.file_name()
+            .and_then(|name| name.to_str())
+            .ok_or(TransferError::InvalidName)?
+            .to_string();
+        validate_name(&name)?;
+
+        let open_error = |e: std::io::Error| TransferError::Io(format!("{}: {}", path.display(), e));
+        let mut file = File::open(path).map_err(open_error)?;
+        let metadata = file.metadata().map_err(open_error)?;
+        if !metadata.is_file() {
+            return Err(TransferError::Io(format!("{}: não é um arquivo", path.display())));
+        }
+        let sha256 = hash_reader(&mut file)?;
+
+        let mut id = [0u8; 16];
+        OsRng.fill_bytes(&mut id);
+        let offer = FileOffer { id, name, size: metadata.len(), chunk_size: CHUNK_SIZE, sha256 };
+        offer.validate()?;
+
+        self.outgoing.insert(id, OutgoingTransfer {
+            session,
+            queue: (0..offer.chunk_count()).collect(),
+            offer: offer.clone(),
+            file,
+            accepted: false,
+            end_sent: false,
+            sent: 0,
+        });
+        Ok(FileWire::Offer(offer))
+    }
+
+    /// Aceita uma proposta, gravando em `dir`. Devolve a sessão e a resposta.
+    pub fn accept(&mut self, id: &TransferId, dir: &Path) -> Result<(SessionId, FileWire), TransferError> {
+        let (session, offer) = self.offers.remove(id)
+            .ok_or_else(|| TransferError::UnknownTransfer(short_id(id)))?;
+        match IncomingTransfer::create(session, offer.clone(), dir) {
+            Ok(incoming) => {
+                self.incoming.insert(*id, incoming);
+                Ok((session, FileWire::Accept { id: *id }))
+            }
+            Err(e) => {
+                // Mantém a proposta para o usuário tentar outro diretório
+                self.offers.insert(*id, (session, offer));
+                Err(e)
+            }
+        }
+    }
+
+    /// Recusa uma proposta ou cancela uma transferência em andamento.
+    pub fn cancel(&mut self, id: &TransferId) -> Result<(SessionId, FileWire, String), TransferError> {
+        let (session, name) = if let Some((session, offer)) = self.offers.remove(id) {
+            (session, offer.name)
+        } else if let Some(incoming) = self.incoming.remove(id) {
+            let result = (incoming.session, incoming.offer.name.clone());
+            incoming.discard();
+            result
+        } else if let Some(outgoing) = self.outgoing.remove(id) {
+            (outgoing.session, outgoing.offer.name)
+        } else {
+            return Err(TransferError::UnknownTransfer(short_id(id)));
+        };
+        Ok((session, FileWire::Cancel { id: *id }, name))
+    }
+
+    /// Procura uma transferência por um prefixo do identificador.
+    pub fn find(&self, query: &str) -> Result<TransferId, TransferError> {
+        let matches: Vec<&TransferId> = self.offers.keys()
+            .chain(self.incoming.keys())
+            .chain(self.outgoing.keys())
+            .filter(|id| hex::encode(id).starts_with(query))
+            .collect();
+        match matches.as_slice() {
+            [id] => Ok(**id),
+            [] => Err(TransferError::UnknownTransfer(query.to_string())),
+            _ => Err(TransferError::Ambiguous(query.to_string())),
+        }
+    }
+
+    /// Processa uma mensagem de arquivo recebida da sessão indicada.
+    pub fn handle(&mut self, session: SessionId, message: FileWire) -> Result<TransferOutput, TransferError> {
+        let mut output = TransferOutput::default();
+        match message {
+            FileWire::Offer(offer) => {
+                offer.validate()?;
+                let id = offer.id;
+                if self.offers.contains_key(&id) || self.incoming.contains_key(&id) || self.outgoing.contains_key(&id) {
+                    return Err(TransferError::InvalidOffer);
+                }
+                output.event = Some(TransferEvent::Offered { id, name: offer.name.clone(), size: offer.size });
+                self.offers.insert(id, (session, offer));
+            }
+            FileWire::Accept { id } => {
+                let outgoing = self.outgoing_for(session, &id)?;
+                outgoing.accepted = true;
+                output.event = Some(TransferEvent::Accepted { name: outgoing.offer.name.clone() });
+            }
+            FileWire::Chunk(chunk) => {
+                self.incoming_for(session, &chunk.id)?.write_chunk(&chunk)?;
+            }
+            FileWire::End { id } => {
+                let missing = self.incoming_for(session, &id)?.missing();
+                if !missing.is_empty() {
+                    output.replies.push(FileWire::Resend { id, chunks: missing });
+                    return Ok(output);
+                }
+                let incoming = self.incoming.remove(&id).expect("transferência verificada acima");
+                let name = incoming.offer.name.clone();
+                let path = incoming.finish()?;
+                output.replies.push(FileWire::Complete { id, verified: path.is_some() });
+                output.event = Some(TransferEvent::Completed { name, verified: path.is_some(), path });
+            }
+            FileWire::Resend { id, chunks } => {
+                let outgoing = self.outgoing_for(session, &id)?;
+                let count = outgoing.offer.chunk_count();
+                if chunks.len() > MAX_RESEND || chunks.iter().any(|index| *index >= count) {
+                    return Err(TransferError::InvalidChunk);
+                }
+                for index in chunks {
+                    if outgoing.queue.insert(index) {
+                        outgoing.sent = outgoing.sent.saturating_sub(outgoing.offer.chunk_len(index) as u64);
+                    }
+                }
+                outgoing.end_sent = false;
+            }
+            FileWire::Complete { id, verified } => {
+                self.outgoing_for(session, &id)?;
+                let outgoing = self.outgoing.remove(&id).expect("transferência verificada acima");
+                output.event = Some(TransferEvent::Completed { name: outgoing.offer.name, path: None, verified });
+            }
+            FileWire::Cancel { id } => {
+                let owned = |owner: SessionId| owner == session;
+                let name = if self.offers.get(&id).is_some_and(|(owner, _)| owned(*owner)) {
+                    self.offers.remove(&id).map(|(_, offer)| offer.name)
+                } else if self.incoming.get(&id).is_some_and(|t| owned(t.session)) {
+                    self.incoming.remove(&id).map(|incoming| {
+                        let name = incoming.offer.name.clone();
+                        incoming.discard();
+                        name
+                    })
+                } else if self.outgoing.get(&id).is_some_and(|t| owned(t.session)) {
+                    self.outgoing.remove(&id).map(|outgoing| outgoing.offer.name)
+                } else {
+                    None
+                };
+                let name = name.ok_or_else(|| TransferError::UnknownTransfer(short_id(&id)))?;
+                output.event = Some(TransferEvent::Cancelled { name });
+            }
+        }
+        Ok(output)
+    }
+
+    /// Lê os próximos blocos das transferências aceitas, até `budget` por
+    /// transferência, e avisa o fim de cada rodada.
+    pub fn pump(&mut self, budget: usize) -> Result<Vec<(SessionId, FileWire)>, TransferError> {
+        let mut messages = Vec::new();
+        for outgoing in self.outgoing.values_mut().filter(|t| t.accepted && !t.end_sent) {
+            for _ in 0..budget {
+                let Some(index) = outgoing.queue.pop_first() else {
+                    break;
+                };
+                let chunk = outgoing.read_chunk(index)?;
+                outgoing.sent += chunk.data.len() as u64;
+                messages.push((outgoing.session, FileWire::Chunk(chunk)));
+            }
+            if outgoing.queue.is_empty() {
+                outgoing.end_sent = true;
+                messages.push((outgoing.session, FileWire::End { id: outgoing.offer.id }));
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Andamento das transferências aceitas.
+    pub fn progress(&self) -> Vec<Progress> {
+        let outgoing = self.outgoing.values()
+            .filter(|t| t.accepted)
+            .map(|t| Progress { name: t.offer.name.clone(), outgoing: true, done: t.sent, total: t.offer.size });
+        let incoming = self.incoming.values()
+            .map(|t| Progress { name: t.offer.name.clone(), outgoing: false, done: t.received_bytes, total: t.offer.size });
+        outgoing.chain(incoming).collect()
+    }
+
+    /// Descarta as transferências de uma sessão encerrada; devolve os nomes.
+    pub fn drop_session(&mut self, session: SessionId) -> Vec<String> {
+        let mut names = Vec::new();
+        self.offers.retain(|_, (owner, offer)| {
+            *owner != session || { names.push(offer.name.clone()); false }
+        });
+        self.outgoing.retain(|_, t| t.session != session || { names.push(t.offer.name.clone()); false });
+        let ids: Vec<TransferId> = self.incoming.iter()
+            .filter(|(_, t)| t.session == session)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ids {
+            if let Some(incoming) = self.incoming.remove(&id) {
+                names.push(incoming.offer.name.clone());
+                incoming.discard();
+            }
+        }
+        names
+    }
+
+    fn outgoing_for(&mut self, session: SessionId, id: &TransferId) -> Result<&mut OutgoingTransfer, TransferError> {
+        self.outgoing.get_mut(id)
+            .filter(|t| t.session == session)
+            .ok_or_else(|| TransferError::UnknownTransfer(short_id(id)))
+    }
+
+    fn incoming_for(&mut self, session: SessionId, id: &TransferId) -> Result<&mut IncomingTransfer, TransferError> {
+        self.incoming.get_mut(id)
+            .filter(|t| t.session == session)
+            .ok_or_else(|| TransferError::UnknownTransfer(short_id(id)))
+    }
+}
+
+/// Nome de arquivo vindo do par: sem diretórios, caracteres de controle ou nomes especiais.
+fn validate_name(name: &str) -> Result<(), TransferError> {
+    let valid = !name.is_empty()
+        && name.len() <= 255
+        && name != "."
+        && name != ".."
+        && !name.chars().any(|c| c == '/' || c == '\\' || c.is_control());
+    if valid { Ok(()) } else { Err(TransferError::InvalidName) }
+}
+
+/// Move `from` para o primeiro nome livre em `dir` ("nome.ext", depois
+/// "nome (1).ext" e assim por diante) sem nunca sobrescrever: o nome é
+/// tomado de forma atômica, com um link ou `create_new`, e um arquivo que
+/// apareça no caminho só faz tentar o próximo nome.
+fn move_to_unique(from: &Path, dir: &Path, name: &str) -> std::io::Result<PathBuf> {
+    let path = Path::new(name);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or(name);
+    let extension = path.extension().and_then(|e| e.to_str());
+    let numbered = (1..).map(|n| match extension {
+        Some(extension) => dir.join(format!("{} ({}).{}", stem, n, extension)),
+        None => dir.join(format!("{} ({})", stem, n)),
+    });
+
+    for candidate in std::iter::once(dir.join(name)).chain(numbered) {
+        let taken = match fs::hard_link(from, &candidate) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Err(e),
+            // Sistemas de arquivos sem links (FAT, alguns compartilhamentos): copia
+            Err(_) => OpenOptions::new().write(true).create_new(true).open(&candidate).and_then(|mut file| {
+                let copied = File::open(from)
+                    .and_then(|mut source| std::io::copy(&mut source, &mut file))
+                    .and_then(|_| file.sync_all());
+                if copied.is_err() {
+                    let _ = fs::remove_file(&candidate);
+                }
+                copied
+            }),
+        };
+        match taken {
+            Ok(()) => {
+                fs::remove_file(from)?;
+                return Ok(candidate);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!("sequência infinita")
+}
+
+fn hash_reader(reader: &mut impl Read) -> Result<[u8; 32], TransferError> {
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; CHUNK_SIZE as usize];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize().into())
+}
+
+impl WireEncode for FileChunk {
+    fn encode(&self, w: &mut Writer) {
+        w.array(&self.id);
+        w.u32(self.index);
+        w.bytes(&self.data);
+    }
+}
+
+impl WireDecode for FileChunk {
+    fn decode(r: &mut Reader) -> Result<Self, ProtocolError> {
+        Ok(Self { id: r.array()?, index: r.u32()?, data: r.bytes()? })
+    }
+}
+
+impl WireEncode for FileWire {
+    fn encode(&self, w: &mut Writer) {
+        match self {
+            FileWire::Offer(offer) => {
+                w.u8(0);
+                w.array(&offer.id);
+                w.string(&offer.name);
+                w.u64(offer.size);
+                w.u32(offer.chunk_size);
+                w.array(&offer.sha256);
+            }
+            FileWire::Accept { id } => {
+                w.u8(1);
+                w.array(id);
+            }
+            FileWire::Chunk(chunk) => {
+                w.u8(2);
+                chunk.encode(w);
+            }
+            FileWire::End { id } => {
+                w.u8(3);
+                w.array(id);
+            }
+            FileWire::Resend { id, chunks } => {
+                w.u8(4);
+                w.array(id);
+                w.u32(chunks.len() as u32);
+                for index in chunks {
+                    w.u32(*index);
+                }
+            }
+            FileWire::Complete { id, verified } => {
+                w.u8(5);
+                w.array(id);
+                w.bool(*verified);
+            }
+            FileWire::Cancel { id } => {
+                w.u8(6);
+                w.array(id);
+            }
+        }
+    }
+}
+
+impl WireDecode for FileWire {
+    fn decode(r: &mut Reader) -> Result<Self, ProtocolError> {
+        Ok(match r.u8()? {
+            0 => FileWire::Offer(FileOffer {
+                id: r.array()?,
+                name: r.string()?,
+                size: r.u64()?,
+                chunk_size: r.u32()?,
+                sha256: r.array()?,
+            }),
+            1 => FileWire::Accept { id: r.array()? },
+            2 => FileWire::Chunk(FileChunk::decode(r)?),
+            3 => FileWire::End { id: r.array()? },
+            4 => {
+                let id = r.array()?;
+                let count = r.count(4)?;
+                FileWire::Resend { id, chunks: (0..count).map(|_| r.u32()).collect::<Result<_, _>>()? }
+            }
+            5 => FileWire::Complete { id: r.array()?, verified: r.bool()? },
+            6 => FileWire::Cancel { id: r.array()? },
+            _ => return Err(ProtocolError::Malformed),
+        })
+    }
+}
+
+/// Erros de transferência de arquivo.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransferError {
+    Io(String),
+    InvalidName,
+    InvalidOffer,
+    InvalidChunk,
+    UnknownTransfer(String),
+    Ambiguous(String),
+}
+
+impl std::fmt::Display for TransferError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransferError::Io(e) => write!(f, "Erro de arquivo: {}", e),
+            TransferError::InvalidName => write!(f, "Nome de arquivo inválido"),
+            TransferError::InvalidOffer => write!(f, "Proposta de arquivo inválida"),
+            TransferError::InvalidChunk => write!(f, "Bloco de arquivo inválido"),
+            TransferError::UnknownTransfer(id) => write!(f, "Transferência desconhecida: {}", id),
+            TransferError::Ambiguous(id) => write!(f, "Mais de uma transferência começa com '{}'", id),
+        }
+    }
+}
+
+impl std::error::Error for TransferError {}
+
+impl From<std::io::Error> for TransferError {
+    fn from(e: std::io::Error) -> Self {
+        TransferError::Io(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sae-transfer-{}", hex::encode(rand::random::<[u8; 8]>())));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Arquivo de três blocos e meio, oferecido pela sessão 1 e aceito na sessão 2.
+    fn accepted_transfer(dir: &Path) -> (TransferManager, TransferManager, TransferId, Vec<u8>) {
+        let content: Vec<u8> = (0..CHUNK_SIZE as usize * 7 / 2).map(|i| (i % 251) as u8).collect();
+        let source = dir.join("relatório.pdf");
+        fs::write(&source, &content).unwrap();
+        let inbox = dir.join("recebidos");
+        fs::create_dir_all(&inbox).unwrap();
+
+        let mut sender = TransferManager::new();
+        let mut receiver = TransferManager::new();
+        let offer = sender.offer(1, &source).unwrap();
+        let FileWire::Offer(FileOffer { id, .. }) = offer else { panic!() };
+        let output = receiver.handle(2, offer).unwrap();
+        assert!(matches!(output.event, Some(TransferEvent::Offered { size, .. }) if size == content.len() as u64));
+
+        let (session, accept) = receiver.accept(&id, &inbox).unwrap();
+        assert_eq!(session, 2);
+        sender.handle(1, accept).unwrap();
+        (sender, receiver, id, content)
+    }
+
+    #[test]
+    fn test_out_of_order_chunks_resume_and_verify() {
+        let dir = temp_dir();
+        let (mut sender, mut receiver, id, content) = accepted_transfer(&dir);
+
+        // Primeira rodada: blocos invertidos e o bloco 1 perdido
+        let mut round = sender.pump(CHUNKS_PER_TICK).unwrap();
+        let end = round.pop().unwrap().1;
+        assert_eq!(end, FileWire::End { id });
+        for (_, message) in round.into_iter().rev() {
+            if !matches!(&message, FileWire::Chunk(chunk) if chunk.index == 1) {
+                receiver.handle(2, message).unwrap();
+            }
+        }
+        let output = receiver.handle(2, end).unwrap();
+        assert_eq!(output.replies, vec![FileWire::Resend { id, chunks: vec![1] }]);
+
+        // Segunda rodada: só o bloco que faltou
+        sender.handle(1, output.replies[0].clone()).unwrap();
+        let round = sender.pump(CHUNKS_PER_TICK).unwrap();
+        assert_eq!(round.len(), 2);
+        let mut replies = Vec::new();
+        for (_, message) in round {
+            replies = receiver.handle(2, message).unwrap().replies;
+        }
+        assert_eq!(replies, vec![FileWire::Complete { id, verified: true }]);
+        assert_eq!(fs::read(dir.join("recebidos").join("relatório.pdf")).unwrap(), content);
+
+        let output = sender.handle(1, replies.remove(0)).unwrap();
+        assert!(matches!(output.event, Some(TransferEvent::Completed { verified: true, .. })));
+        assert!(sender.progress().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_hash_mismatch_discards_file() {
+        let dir = temp_dir();
+        let (mut sender, mut receiver, id, _) = accepted_transfer(&dir);
+
+        for (_, message) in sender.pump(CHUNKS_PER_TICK).unwrap() {
+            let message = match message {
+                FileWire::Chunk(mut chunk) if chunk.index == 2 => {
+                    chunk.data[0] ^= 0xff;
+                    FileWire::Chunk(chunk)
+                }
+                message => message,
+            };
+            let output = receiver.handle(2, message).unwrap();
+            if let Some(event) = output.event {
+                assert!(matches!(event, TransferEvent::Completed { path: None, verified: false, .. }));
+                assert_eq!(output.replies, vec![FileWire::Complete { id, verified: false }]);
+            }
+        }
+        assert_eq!(fs::read_dir(dir.join("recebidos")).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_offer_validation() {
+        let mut receiver = TransferManager::new();
+        let offer = |name: &str, chunk_size| FileWire::Offer(FileOffer {
+            id: rand::random(),
+            name: name.into(),
+            size: 10,
+            chunk_size,
+            sha256: [0u8; 32],
+        });
+        assert_eq!(receiver.handle(1, offer("../.ssh/authorized_keys", CHUNK_SIZE)).unwrap_err(), TransferError::InvalidName);
+        assert_eq!(receiver.handle(1, offer("..", CHUNK_SIZE)).unwrap_err(), TransferError::InvalidName);
+        assert_eq!(receiver.handle(1, offer("ok.txt", 0)).unwrap_err(), TransferError::InvalidOffer);
+        let FileWire::Offer(mut huge) = offer("grande.iso", 1) else { panic!() };
+        huge.size = u64::MAX;
+        assert_eq!(receiver.handle(1, FileWire::Offer(huge)).unwrap_err(), TransferError::InvalidOffer);
+        assert!(receiver.handle(1, offer("ok.txt", CHUNK_SIZE)).is_ok());
+    }
+
+    #[test]
+    fn test_existing_file_not_overwritten() {
+        let dir = temp_dir();
+        fs::write(dir.join("foto.png"), b"antiga").unwrap();
+        fs::write(dir.join("foto (1).png"), b"outra").unwrap();
+        let part = dir.join("foto.png.part");
+
+        fs::write(&part, b"recebida").unwrap();
+        assert_eq!(move_to_unique(&part, &dir, "foto.png").unwrap(), dir.join("foto (2).png"));
+        assert_eq!(fs::read(dir.join("foto.png")).unwrap(), b"antiga");
+        assert_eq!(fs::read(dir.join("foto (1).png")).unwrap(), b"outra");
+        assert_eq!(fs::read(dir.join("foto (2).png")).unwrap(), b"recebida");
+        assert!(!part.exists());
+
+        fs::write(&part, b"nova").unwrap();
+        assert_eq!(move_to_unique(&part, &dir, "novo").unwrap(), dir.join("novo"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
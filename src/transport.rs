@@ -0,0 +1,515 @@
+use futures::future::BoxFuture;
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio_native_tls::TlsAcceptor;
+use tokio_websockets::{CloseCode, Message, ServerBuilder, WebSocketStream};
+
+use crate::tls::MaybeTlsStream;
+
+/// Maior quadro aceito pelos transportes com prefixo de tamanho: a maior
+/// mensagem com padding cabe com folga.
+pub const MAX_FRAME_LEN: usize = 8 * 1024 * 1024;
+
+/// Quadro trocado pelos transportes: dados da sessão ou controle da conexão.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    Data(Vec<u8>),
+    /// Keepalive; o par responde com `Pong` de mesmo nonce
+    Ping(u64),
+    Pong(u64),
+    /// Fechamento explícito: a sessão não deve ser retomada
+    Close,
+}
+
+/// Lado de envio de uma conexão.
+pub trait FrameSink: Send {
+    fn send(&mut self, frame: Frame) -> BoxFuture<'_, io::Result<()>>;
+}
+
+/// Lado de recepção de uma conexão; `None` quando ela termina sem `Close`.
+pub trait FrameStream: Send {
+    fn recv(&mut self) -> BoxFuture<'_, io::Result<Option<Frame>>>;
+}
+
+/// Conexão estabelecida com um par, em qualquer backend.
+pub struct Connection {
+    pub sink: Box<dyn FrameSink>,
+    pub stream: Box<dyn FrameStream>,
+}
+
+impl Connection {
+    pub fn new(sink: impl FrameSink + 'static, stream: impl FrameStream + 'static) -> Self {
+        Self { sink: Box::new(sink), stream: Box::new(stream) }
+    }
+}
+
+/// Canal de quadros de dados entre dois pares. O handshake e as mensagens
+/// cifradas de uma `Session` passam por aqui sem saber o que há por baixo.
+pub trait Transport: Send {
+    /// Envia um quadro inteiro; o par o recebe de uma vez em `recv`.
+    fn send(&mut self, frame: Vec<u8>) -> impl Future<Output = io::Result<()>> + Send;
+
+    /// Próximo quadro; `None` quando o par fechou a conexão.
+    fn recv(&mut self) -> impl Future<Output = io::Result<Option<Vec<u8>>>> + Send;
+}
+
+impl Transport for Connection {
+    async fn send(&mut self, frame: Vec<u8>) -> io::Result<()> {
+        self.sink.send(Frame::Data(frame)).await
+    }
+
+    /// Responde aos pings do par no caminho; só dados chegam a quem chama.
+    async fn recv(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            match self.stream.recv().await? {
+                Some(Frame::Data(data)) => return Ok(Some(data)),
+                Some(Frame::Ping(nonce)) => self.sink.send(Frame::Pong(nonce)).await?,
+                Some(Frame::Pong(_)) => {}
+                Some(Frame::Close) | None => return Ok(None),
+            }
+        }
+    }
+}
+
+/// Conexão recebida por um `Listener`, ainda concluindo o handshake do
+/// próprio backend (TLS, WebSocket), que corre fora do laço de aceitação.
+pub struct Incoming {
+    /// Endereço do par, para os logs
+    pub peer: String,
+    pub connecting: BoxFuture<'static, Result<Connection, String>>,
+}
+
+/// Aceita conexões de entrada de um backend.
+pub trait Listener: Send {
+    fn accept(&mut self) -> BoxFuture<'_, io::Result<Incoming>>;
+
+    /// Onde o listener escuta, como aparece no log do host.
+    fn local_description(&self) -> String;
+}
+
+/// Abre conexões de saída, de novo a cada reconexão.
+pub trait Connector: Send + Sync {
+    fn connect(&self) -> BoxFuture<'_, Result<Connection, String>>;
+}
+
+// Quadros com prefixo de tamanho: u32 (big-endian) com o tamanho do resto,
+// um byte de tipo e o conteúdo.
+const KIND_DATA: u8 = 0;
+const KIND_PING: u8 = 1;
+const KIND_PONG: u8 = 2;
+const KIND_CLOSE: u8 = 3;
+
+struct FramedWriter<W> {
+    writer: W,
+}
+
+impl<W: AsyncWrite + Unpin + Send> FrameSink for FramedWriter<W> {
+    fn send(&mut self, frame: Frame) -> BoxFuture<'_, io::Result<()>> {
+        Box::pin(async move {
+            let (kind, payload) = match frame {
+                Frame::Data(data) => (KIND_DATA, data),
+                Frame::Ping(nonce) => (KIND_PING, nonce.to_be_bytes().to_vec()),
+                Frame::Pong(nonce) => (KIND_PONG, nonce.to_be_bytes().to_vec()),
+                Frame::Close => (KIND_CLOSE, Vec::new()),
+            };
+            if payload.len() >= MAX_FRAME_LEN {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "quadro grande demais"));
+            }
+            let mut bytes = Vec::with_capacity(5 + payload.len());
+            bytes.extend_from_slice(&(payload.len() as u32 + 1).to_be_bytes());
+            bytes.push(kind);
+            bytes.extend_from_slice(&payload);
+            self.writer.write_all(&bytes).await?;
+            self.writer.flush().await
+        })
+    }
+}
+
+struct FramedReader<R> {
+    reader: R,
+    /// Bytes lidos que ainda não formam um quadro inteiro
+    buffer: Vec<u8>,
+}
+
+impl<R> FramedReader<R> {
+    /// Tira do buffer o próximo quadro completo, se já houver um.
+    fn parse(&mut self) -> io::Result<Option<Frame>> {
+        let Some(header) = self.buffer.get(..4) else {
+            return Ok(None);
+        };
+        let len = u32::from_be_bytes(header.try_into().unwrap()) as usize;
+        if len == 0 || len > MAX_FRAME_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("quadro de {} bytes", len)));
+        }
+        if self.buffer.len() < 4 + len {
+            return Ok(None);
+        }
+        let mut bytes: Vec<u8> = self.buffer.drain(..4 + len).skip(4).collect();
+        let payload = bytes.split_off(1);
+        let nonce = || -> io::Result<u64> {
+            let bytes: [u8; 8] = payload.as_slice().try_into()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "nonce de keepalive inválido"))?;
+            Ok(u64::from_be_bytes(bytes))
+        };
+        match bytes[0] {
+            KIND_DATA => Ok(Some(Frame::Data(payload))),
+            KIND_PING => Ok(Some(Frame::Ping(nonce()?))),
+            KIND_PONG => Ok(Some(Frame::Pong(nonce()?))),
+            KIND_CLOSE => Ok(Some(Frame::Close)),
+            kind => Err(io::Error::new(io::ErrorKind::InvalidData, format!("tipo de quadro desconhecido: {}", kind))),
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin + Send> FrameStream for FramedReader<R> {
+    /// Pode ser cancelado no meio (`select!` com o keepalive): o que já foi
+    /// lido fica no buffer para a próxima chamada.
+    fn recv(&mut self) -> BoxFuture<'_, io::Result<Option<Frame>>> {
+        Box::pin(async move {
+            loop {
+                if let Some(frame) = self.parse()? {
+                    return Ok(Some(frame));
+                }
+                if self.reader.read_buf(&mut self.buffer).await? == 0 {
+                    if self.buffer.is_empty() {
+                        return Ok(None);
+                    }
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "conexão fechada no meio de um quadro"));
+                }
+            }
+        })
+    }
+}
+
+/// Quadros com prefixo de tamanho sobre qualquer fluxo de bytes: TCP, socket
+/// Unix, TLS ou um duplex em memória.
+pub fn framed<S: AsyncRead + AsyncWrite + Send + 'static>(stream: S) -> Connection {
+    let (reader, writer) = tokio::io::split(stream);
+    Connection::new(FramedWriter { writer }, FramedReader { reader, buffer: Vec::new() })
+}
+
+struct WsSink<S> {
+    sink: SplitSink<WebSocketStream<S>, Message>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> FrameSink for WsSink<S> {
+    fn send(&mut self, frame: Frame) -> BoxFuture<'_, io::Result<()>> {
+        let message = match frame {
+            Frame::Data(data) => Message::binary(data),
+            Frame::Ping(nonce) => Message::ping(nonce.to_be_bytes().to_vec()),
+            Frame::Pong(nonce) => Message::pong(nonce.to_be_bytes().to_vec()),
+            Frame::Close => Message::close(Some(CloseCode::NORMAL_CLOSURE), ""),
+        };
+        Box::pin(async move {
+            self.sink.send(message).await.map_err(io::Error::other)
+        })
+    }
+}
+
+struct WsStream<S> {
+    stream: SplitStream<WebSocketStream<S>>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> FrameStream for WsStream<S> {
+    fn recv(&mut self) -> BoxFuture<'_, io::Result<Option<Frame>>> {
+        Box::pin(async move {
+            loop {
+                let message = match self.stream.next().await {
+                    Some(message) => message.map_err(io::Error::other)?,
+                    None => return Ok(None),
+                };
+                if message.is_binary() {
+                    return Ok(Some(Frame::Data(message.as_payload().to_vec())));
+                } else if message.is_close() {
+                    return Ok(Some(Frame::Close));
+                } else if message.is_pong() {
+                    // Pongs que não vieram dos nossos pings não interessam
+                    if let Ok(nonce) = <[u8; 8]>::try_from(&message.as_payload()[..]) {
+                        return Ok(Some(Frame::Pong(u64::from_be_bytes(nonce))));
+                    }
+                }
+                // Pings são respondidos pelo próprio tokio-websockets
+            }
+        })
+    }
+}
+
+/// Quadros como mensagens WebSocket binárias; ping e pong viram os frames de
+/// controle do próprio WebSocket.
+pub fn websocket<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(ws: WebSocketStream<S>) -> Connection {
+    let (sink, stream) = ws.split();
+    Connection::new(WsSink { sink }, WsStream { stream })
+}
+
+/// Listener WebSocket (com TLS quando há `acceptor`), o transporte dos convites.
+pub struct WebSocketListener {
+    listener: TcpListener,
+    acceptor: Option<TlsAcceptor>,
+}
+
+impl WebSocketListener {
+    pub async fn bind(addr: SocketAddr, acceptor: Option<TlsAcceptor>) -> io::Result<Self> {
+        Ok(Self { listener: TcpListener::bind(addr).await?, acceptor })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+}
+
+impl Listener for WebSocketListener {
+    fn accept(&mut self) -> BoxFuture<'_, io::Result<Incoming>> {
+        Box::pin(async move {
+            let (stream, peer) = self.listener.accept().await?;
+            let acceptor = self.acceptor.clone();
+            let connecting = Box::pin(async move {
+                let stream = match acceptor {
+                    Some(acceptor) => {
+                        let tls_stream = acceptor.accept(stream).await
+                            .map_err(|e| format!("Falha no handshake TLS: {}", e))?;
+                        MaybeTlsStream::Tls(Box::new(tls_stream))
+                    }
+                    None => MaybeTlsStream::Plain(stream),
+                };
+                let ws = ServerBuilder::new()
+                    .accept(stream)
+                    .await
+                    .map_err(|e| format!("Erro no WebSocket: {}", e))?;
+                Ok(websocket(ws))
+            });
+            Ok(Incoming { peer: peer.to_string(), connecting })
+        })
+    }
+
+    fn local_description(&self) -> String {
+        let protocol = if self.acceptor.is_some() { "wss" } else { "ws" };
+        match self.listener.local_addr() {
+            Ok(addr) => format!("{}://{}", protocol, addr),
+            Err(_) => protocol.to_string(),
+        }
+    }
+}
+
+/// Listener TCP com quadros de prefixo de tamanho, sem WebSocket.
+pub struct TcpFrameListener {
+    listener: TcpListener,
+}
+
+impl TcpFrameListener {
+    pub async fn bind(addr: SocketAddr) -> io::Result<Self> {
+        Ok(Self { listener: TcpListener::bind(addr).await? })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+}
+
+impl Listener for TcpFrameListener {
+    fn accept(&mut self) -> BoxFuture<'_, io::Result<Incoming>> {
+        Box::pin(async move {
+            let (stream, peer) = self.listener.accept().await?;
+            Ok(Incoming { peer: peer.to_string(), connecting: Box::pin(async move { Ok(framed(stream)) }) })
+        })
+    }
+
+    fn local_description(&self) -> String {
+        self.listener.local_addr().map(|addr| format!("tcp://{}", addr)).unwrap_or_else(|_| "tcp".to_string())
+    }
+}
+
+/// Conecta a um `TcpFrameListener`.
+pub struct TcpFrameConnector {
+    pub addr: SocketAddr,
+}
+
+impl Connector for TcpFrameConnector {
+    fn connect(&self) -> BoxFuture<'_, Result<Connection, String>> {
+        Box::pin(async move {
+            let stream = TcpStream::connect(self.addr).await.map_err(|e| format!("Falha ao conectar: {}", e))?;
+            Ok(framed(stream))
+        })
+    }
+}
+
+/// Listener num socket Unix, para pares na mesma máquina.
+#[cfg(unix)]
+pub struct UnixFrameListener {
+    listener: tokio::net::UnixListener,
+}
+
+#[cfg(unix)]
+impl UnixFrameListener {
+    pub fn bind(path: &std::path::Path) -> io::Result<Self> {
+        Ok(Self { listener: tokio::net::UnixListener::bind(path)? })
+    }
+}
+
+#[cfg(unix)]
+impl Listener for UnixFrameListener {
+    fn accept(&mut self) -> BoxFuture<'_, io::Result<Incoming>> {
+        Box::pin(async move {
+            let (stream, _) = self.listener.accept().await?;
+            Ok(Incoming { peer: "socket Unix".to_string(), connecting: Box::pin(async move { Ok(framed(stream)) }) })
+        })
+    }
+
+    fn local_description(&self) -> String {
+        let path = self.listener.local_addr().ok().and_then(|addr| addr.as_pathname().map(|p| p.display().to_string()));
+        format!("unix://{}", path.unwrap_or_default())
+    }
+}
+
+/// Conecta a um `UnixFrameListener`.
+#[cfg(unix)]
+pub struct UnixFrameConnector {
+    pub path: std::path::PathBuf,
+}
+
+#[cfg(unix)]
+impl Connector for UnixFrameConnector {
+    fn connect(&self) -> BoxFuture<'_, Result<Connection, String>> {
+        Box::pin(async move {
+            let stream = tokio::net::UnixStream::connect(&self.path).await
+                .map_err(|e| format!("Falha ao conectar em {}: {}", self.path.display(), e))?;
+            Ok(framed(stream))
+        })
+    }
+}
+
+/// Tamanho do buffer de cada sentido do duplex em memória
+const MEMORY_BUFFER: usize = 64 * 1024;
+
+/// Par de conexões ligadas uma à outra no mesmo processo, sem socket.
+pub fn memory_pair() -> (Connection, Connection) {
+    let (a, b) = tokio::io::duplex(MEMORY_BUFFER);
+    (framed(a), framed(b))
+}
+
+/// Listener em memória; as conexões chegam pelo `MemoryConnector` do par.
+pub struct MemoryListener {
+    incoming: UnboundedReceiver<Connection>,
+}
+
+/// Lado que disca para um `MemoryListener`; pode ser clonado e reusado.
+#[derive(Clone)]
+pub struct MemoryConnector {
+    listener: UnboundedSender<Connection>,
+}
+
+/// Listener em memória e o conector que chega até ele.
+pub fn memory_listener() -> (MemoryListener, MemoryConnector) {
+    let (listener, incoming) = mpsc::unbounded_channel();
+    (MemoryListener { incoming }, MemoryConnector { listener })
+}
+
+impl Listener for MemoryListener {
+    fn accept(&mut self) -> BoxFuture<'_, io::Result<Incoming>> {
+        Box::pin(async move {
+            // Sem conectores, nenhuma conexão chega mais: espera para sempre,
+            // como um socket que ninguém procura
+            let Some(connection) = self.incoming.recv().await else {
+                return std::future::pending().await;
+            };
+            Ok(Incoming { peer: "memória".to_string(), connecting: Box::pin(async move { Ok(connection) }) })
+        })
+    }
+
+    fn local_description(&self) -> String {
+        "memória".to_string()
+    }
+}
+
+impl Connector for MemoryConnector {
+    fn connect(&self) -> BoxFuture<'_, Result<Connection, String>> {
+        Box::pin(async move {
+            let (local, remote) = memory_pair();
+            self.listener.send(remote).map_err(|_| "Listener em memória encerrado".to_string())?;
+            Ok(local)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn exchange(mut a: Connection, mut b: Connection) {
+        a.sink.send(Frame::Data(b"um".to_vec())).await.unwrap();
+        a.sink.send(Frame::Ping(7)).await.unwrap();
+        a.sink.send(Frame::Data(Vec::new())).await.unwrap();
+        assert_eq!(b.stream.recv().await.unwrap(), Some(Frame::Data(b"um".to_vec())));
+        assert_eq!(b.stream.recv().await.unwrap(), Some(Frame::Ping(7)));
+        assert_eq!(b.stream.recv().await.unwrap(), Some(Frame::Data(Vec::new())));
+
+        b.sink.send(Frame::Close).await.unwrap();
+        assert_eq!(a.stream.recv().await.unwrap(), Some(Frame::Close));
+        drop(b);
+        assert_eq!(a.stream.recv().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_framed_backends() {
+        let (a, b) = memory_pair();
+        exchange(a, b).await;
+
+        let (mut listener, connector) = memory_listener();
+        let a = connector.connect().await.unwrap();
+        let b = listener.accept().await.unwrap().connecting.await.unwrap();
+        exchange(a, b).await;
+
+        let mut listener = TcpFrameListener::bind("127.0.0.1:0".parse().unwrap()).await.unwrap();
+        let connector = TcpFrameConnector { addr: listener.local_addr().unwrap() };
+        let (a, incoming) = tokio::join!(connector.connect(), listener.accept());
+        exchange(a.unwrap(), incoming.unwrap().connecting.await.unwrap()).await;
+
+        let path = std::env::temp_dir().join(format!("sae-transport-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut listener = UnixFrameListener::bind(&path).unwrap();
+        let connector = UnixFrameConnector { path: path.clone() };
+        let (a, incoming) = tokio::join!(connector.connect(), listener.accept());
+        exchange(a.unwrap(), incoming.unwrap().connecting.await.unwrap()).await;
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_websocket_maps_control_frames() {
+        let mut listener = WebSocketListener::bind("127.0.0.1:0".parse().unwrap(), None).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = async {
+            let stream = TcpStream::connect(addr).await.unwrap();
+            let uri = format!("ws://{}", addr).parse().unwrap();
+            let (ws, _) = tokio_websockets::ClientBuilder::from_uri(uri).connect_on(stream).await.unwrap();
+            websocket(ws)
+        };
+        let server = async { listener.accept().await.unwrap().connecting.await.unwrap() };
+        let (mut a, mut b) = tokio::join!(client, server);
+        assert!(listener.local_description().starts_with("ws://127.0.0.1:"));
+
+        // O ping é respondido pelo WebSocket do par, que nem o vê
+        a.sink.send(Frame::Ping(42)).await.unwrap();
+        a.sink.send(Frame::Data(b"dados".to_vec())).await.unwrap();
+        assert_eq!(b.stream.recv().await.unwrap(), Some(Frame::Data(b"dados".to_vec())));
+        b.sink.send(Frame::Close).await.unwrap();
+        assert_eq!(a.stream.recv().await.unwrap(), Some(Frame::Pong(42)));
+        assert_eq!(a.stream.recv().await.unwrap(), Some(Frame::Close));
+    }
+
+    #[tokio::test]
+    async fn test_connection_transport_answers_pings() {
+        let (mut a, mut b) = memory_pair();
+        a.sink.send(Frame::Ping(3)).await.unwrap();
+        a.sink.send(Frame::Data(b"oi".to_vec())).await.unwrap();
+        assert_eq!(Transport::recv(&mut b).await.unwrap().as_deref(), Some(&b"oi"[..]));
+        assert_eq!(a.stream.recv().await.unwrap(), Some(Frame::Pong(3)));
+
+        a.sink.send(Frame::Close).await.unwrap();
+        assert_eq!(Transport::recv(&mut b).await.unwrap(), None);
+    }
+}
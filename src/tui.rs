@@ -1,7 +1,8 @@
 use crate::app::{App, AppMode, MessageState};
+use crate::delivery::DeliveryStatus;
 use ratatui::{
     prelude::*,
-    widgets::{Block, Borders, Paragraph, Wrap},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
 };
 
 pub fn ui(f: &mut Frame, app: &mut App) {
@@ -16,10 +17,31 @@ pub fn ui(f: &mut Frame, app: &mut App) {
     render_messages(f, app, chunks[0]);
     render_status_bar(f, app, chunks[1]);
     render_input_box(f, app, chunks[2]);
+
+    if app.invite_qr.is_some() {
+        render_invite_qr(f, app);
+    }
+    if app.verify_dialog.is_some() {
+        render_verify_dialog(f, app);
+    }
 }
 
 fn render_messages(f: &mut Frame, app: &mut App, area: Rect) {
-    let messages: Vec<Line> = app.messages.iter().map(|msg| {
+    // Avisos gerais aparecem sempre; mensagens de conversa ou sala, só quando em foco
+    let visible: Vec<_> = app.messages.iter()
+        .filter(|msg| match (msg.session, msg.room) {
+            (None, None) => true,
+            (_, Some(room)) => app.active_room == Some(room),
+            (session, None) => app.active_room.is_none() && session == app.active_session,
+        })
+        .collect();
+    let title = match (app.active_room.and_then(|room| app.rooms.get(&room)), app.active_conversation()) {
+        (Some(room), _) => format!("Log de Transmissão - sala {}", room.label),
+        (None, Some((session, conversation))) => format!("Log de Transmissão - {} [{}]", conversation.label, session),
+        (None, None) => "Log de Transmissão".to_string(),
+    };
+
+    let messages: Vec<Line> = visible.iter().map(|msg| {
         let sender_style = match msg.sender.as_str() {
             "Sistema" => Style::default().fg(Color::Yellow),
             "AVISO" => Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
@@ -33,16 +55,26 @@ fn render_messages(f: &mut Frame, app: &mut App, area: Rect) {
             MessageState::FadingOut => Style::default().fg(Color::Gray),
         };
 
-        Line::from(vec![
+        let mut spans = vec![
             Span::styled(format!("[{}] ", msg.sender), sender_style),
             Span::styled(&msg.content, content_style),
-        ])
+        ];
+        // Estado de entrega das nossas mensagens: pendente, enviada, entregue, lida
+        if let Some(status) = msg.status {
+            let status_style = match status {
+                DeliveryStatus::Pending => Style::default().fg(Color::DarkGray),
+                DeliveryStatus::Read => Style::default().fg(Color::Cyan),
+                _ => Style::default().fg(Color::Gray),
+            };
+            spans.push(Span::styled(format!(" {}", status.icon()), status_style));
+        }
+        Line::from(spans)
     }).collect();
 
     let messages_paragraph = Paragraph::new(messages)
-        .block(Block::default().borders(Borders::ALL).title("Log de Transmissão"))
+        .block(Block::default().borders(Borders::ALL).title(title))
         .wrap(Wrap { trim: true })
-        .scroll((app.messages.len().saturating_sub(area.height as usize - 2) as u16, 0));
+        .scroll((visible.len().saturating_sub(area.height as usize - 2) as u16, 0));
 
     f.render_widget(messages_paragraph, area);
 }
@@ -55,13 +87,48 @@ fn render_status_bar(f: &mut Frame, app: &App, area: Rect) {
         AppMode::Connected => "Conectado",
     };
     
-    let status_line = Line::from(vec![
+    let mut status_spans = vec![
         Span::styled(format!("Modo: {} | ", mode_text), Style::default().fg(Color::Green)),
-        Span::raw(&app.status_message),
-    ]);
+    ];
+    // Conversas fora de foco com mensagens novas
+    let unread: Vec<String> = app.conversations.iter()
+        .filter(|(_, conversation)| conversation.unread > 0)
+        .map(|(session, conversation)| format!("{}:{}({})", session, conversation.label, conversation.unread))
+        .chain(app.rooms.values()
+            .filter(|room| room.unread > 0)
+            .map(|room| format!("#{}({})", room.label, room.unread)))
+        .collect();
+    if !unread.is_empty() {
+        status_spans.push(Span::styled(format!("✉ {} | ", unread.join(" ")), Style::default().fg(Color::Magenta)));
+    }
+    // O par da conversa em foco está digitando
+    if let Some((_, conversation)) = app.active_conversation().filter(|(_, c)| c.typing && app.active_room.is_none()) {
+        status_spans.push(Span::styled(format!("✎ {} digitando... | ", conversation.label), Style::default().fg(Color::Yellow)));
+    }
+    // Saúde da conexão em foco: caída, instável ou o RTT do último ping
+    if let Some((_, conversation)) = app.active_conversation().filter(|_| app.active_room.is_none()) {
+        if conversation.reconnecting {
+            status_spans.push(Span::styled(format!("⟳ {} reconectando... | ", conversation.label), Style::default().fg(Color::Red)));
+        } else if conversation.degraded {
+            status_spans.push(Span::styled("⚠ conexão instável | ", Style::default().fg(Color::Yellow)));
+        } else if let Some(rtt) = conversation.rtt {
+            status_spans.push(Span::styled(format!("⏱ {}ms | ", rtt.as_millis()), Style::default().fg(Color::Green)));
+        }
+    }
+    // Transferências de arquivo em andamento
+    let transfers: Vec<String> = app.transfers.iter()
+        .map(|t| format!("{}{} {}%", if t.outgoing { "↑" } else { "↓" }, t.name, t.percent()))
+        .collect();
+    if !transfers.is_empty() {
+        status_spans.push(Span::styled(format!("{} | ", transfers.join(" ")), Style::default().fg(Color::Cyan)));
+    }
+    status_spans.push(Span::raw(&app.status_message));
+    let status_line = Line::from(status_spans);
 
     let your_fp = app.local_fingerprint.as_deref().unwrap_or("N/A");
-    let their_fp = app.remote_fingerprint.as_deref().unwrap_or("N/A");
+    let their_fp = app.active_conversation()
+        .map(|(_, conversation)| conversation.remote_fingerprint.as_str())
+        .unwrap_or("N/A");
 
     let fp_line = Line::from(vec![
         Span::styled("Seu FP: ", Style::default().fg(Color::Cyan)),
@@ -90,3 +157,117 @@ fn render_input_box(f: &mut Frame, app: &App, area: Rect) {
         area.y + 1,
     ));
 }
+
+/// Diálogo de verificação: mostra o SAS da sessão para comparação com o par.
+fn render_verify_dialog(f: &mut Frame, app: &App) {
+    let Some((session, petname)) = &app.verify_dialog else {
+        return;
+    };
+    let Some(conversation) = app.conversations.get(session) else {
+        return;
+    };
+    let sas = &conversation.sas;
+
+    let emoji = sas.emoji();
+    let emoji_line: Vec<Span> = emoji.iter()
+        .map(|(symbol, _)| Span::styled(format!(" {} ", symbol), Style::default().add_modifier(Modifier::BOLD)))
+        .collect();
+    let names = emoji.iter().map(|(_, name)| *name).collect::<Vec<_>>().join(" · ");
+
+    let peer_status = if conversation.peer_confirmed_sas {
+        Span::styled("✓ O par já confirmou", Style::default().fg(Color::Green))
+    } else {
+        Span::styled("Aguardando confirmação do par", Style::default().fg(Color::DarkGray))
+    };
+
+    let lines = vec![
+        Line::from(format!("Confirme com {} por voz ou pessoalmente:", petname)),
+        Line::from(""),
+        Line::from(emoji_line).alignment(Alignment::Center),
+        Line::from(names).alignment(Alignment::Center),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled("Número de segurança: ", Style::default().fg(Color::Cyan)),
+            Span::raw(sas.safety_number()),
+        ]).alignment(Alignment::Center),
+        Line::from(""),
+        Line::from(peer_status).alignment(Alignment::Center),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled("[S]", Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)),
+            Span::raw(" confere   "),
+            Span::styled("[N]", Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)),
+            Span::raw(" não confere   "),
+            Span::styled("[Esc]", Style::default().fg(Color::Yellow)),
+            Span::raw(" cancelar"),
+        ]).alignment(Alignment::Center),
+    ];
+
+    let area = centered_rect(f.area(), 72, lines.len() as u16 + 2);
+    let dialog = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title("Verificação de segurança"))
+        .wrap(Wrap { trim: true });
+
+    f.render_widget(Clear, area);
+    f.render_widget(dialog, area);
+}
+
+/// QR code do convite em meio-blocos. Se o terminal não comporta o código
+/// inteiro, mostra um aviso no lugar: um QR cortado não é lido pela câmera.
+fn render_invite_qr(f: &mut Frame, app: &App) {
+    let Some(qr) = &app.invite_qr else {
+        return;
+    };
+
+    let (qr_width, qr_height) = qr.terminal_size();
+    // Bordas do popup mais a linha de instrução
+    let (width, height) = (qr_width + 2, qr_height + 3);
+    let frame = f.area();
+
+    if width > frame.width || height > frame.height {
+        let lines = vec![
+            Line::from("Terminal pequeno demais para exibir o QR code."),
+            Line::from(format!(
+                "Necessário {}x{}, disponível {}x{}.",
+                width, height, frame.width, frame.height
+            )),
+            Line::from(""),
+            Line::from("Aumente a janela, reduza a fonte ou use a URI do log"),
+            Line::from("(ou --qr-png <arquivo> para gravar uma imagem)."),
+            Line::from(""),
+            Line::from(Span::styled("[Esc] fechar", Style::default().fg(Color::Yellow))).alignment(Alignment::Center),
+        ];
+        let area = centered_rect(frame, 60, lines.len() as u16 + 2);
+        let notice = Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title("QR code do convite"))
+            .wrap(Wrap { trim: true });
+        f.render_widget(Clear, area);
+        f.render_widget(notice, area);
+        return;
+    }
+
+    let qr_style = Style::default().fg(Color::White).bg(Color::Black);
+    let mut lines: Vec<Line> = qr.half_block_lines()
+        .into_iter()
+        .map(|line| Line::styled(line, qr_style))
+        .collect();
+    lines.push(Line::from(Span::styled("[Esc] fechar", Style::default().fg(Color::Yellow))).alignment(Alignment::Center));
+
+    let area = centered_rect(frame, width, height);
+    let popup = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title("Escaneie o convite"));
+    f.render_widget(Clear, area);
+    f.render_widget(popup, area);
+}
+
+/// Retângulo centralizado, limitado ao tamanho da área disponível.
+fn centered_rect(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}